@@ -0,0 +1,41 @@
+//! Records the commit and time the proxy was built from, reported by
+//! `GET /_admin/version`
+
+use std::path::Path;
+use std::process::Command;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+fn main() {
+    // Outside a git checkout, e.g. a source tarball, the commit is unknown
+    let commit = Command::new("git")
+        .args(["rev-parse", "--short=12", "HEAD"])
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .and_then(|output| String::from_utf8(output.stdout).ok())
+        .map(|commit| commit.trim().to_string())
+        .filter(|commit| !commit.is_empty())
+        .unwrap_or_else(|| "unknown".to_string());
+
+    // Reproducible builds pin the time through SOURCE_DATE_EPOCH
+    let built_at = std::env::var("SOURCE_DATE_EPOCH")
+        .ok()
+        .and_then(|epoch| epoch.parse::<u64>().ok())
+        .unwrap_or_else(|| {
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |since| since.as_secs())
+        });
+
+    println!("cargo:rustc-env=LOCAL_LAMBDAS_GIT_COMMIT={}", commit);
+    println!("cargo:rustc-env=LOCAL_LAMBDAS_BUILT_AT={}", built_at);
+
+    // Naming any path replaces Cargo's default of rerunning on every change
+    // in the package, so the sources are named along with the git state
+    println!("cargo:rerun-if-env-changed=SOURCE_DATE_EPOCH");
+    for path in ["build.rs", "Cargo.toml", "src", ".git/HEAD", ".git/refs"] {
+        if Path::new(path).exists() {
+            println!("cargo:rerun-if-changed={}", path);
+        }
+    }
+}
@@ -0,0 +1,36 @@
+//! Run the proxy from code instead of a manifest, in front of the echo
+//! service, until Ctrl+C
+//!
+//! ```bash
+//! cargo run --example embedded
+//! curl http://127.0.0.1:3000/echo/test
+//! ```
+
+use local_lambdas::domain::{Executable, PipeName, Process, ProcessId, Route};
+use local_lambdas::LocalLambdas;
+
+#[tokio::main]
+async fn main() -> Result<(), local_lambdas::embed::Error> {
+    tracing_subscriber::fmt::init();
+
+    let mut echo = Process::new(
+        ProcessId::new("echo-service")?,
+        Executable::new("python3")?,
+        Route::new("/echo/*")?,
+        PipeName::new("embedded_echo_pipe")?,
+    );
+    echo.arguments = vec!["examples/echo-service/echo_service.py".to_string()];
+
+    let proxy = LocalLambdas::builder()
+        .process(echo)
+        .cache_size(1000)
+        .bind("127.0.0.1:3000")
+        .build()
+        .start()
+        .await?;
+    println!("Proxying /echo/* on http://{}", proxy.local_addr());
+
+    tokio::signal::ctrl_c().await?;
+    proxy.shutdown().await?;
+    Ok(())
+}
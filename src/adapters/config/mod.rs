@@ -1,3 +1,3 @@
 pub mod xml_repository;
 
-pub use xml_repository::XmlProcessRepository;
+pub use xml_repository::{XmlProcessRepository, STDIN_MANIFEST};
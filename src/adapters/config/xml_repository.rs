@@ -2,43 +2,307 @@
 //! This is an infrastructure adapter
 
 use crate::domain::repositories::{ProcessRepository, RepositoryError};
-use crate::domain::entities::{Process, ProcessId, Executable, Route, PipeName, WorkingDirectory, CommunicationMode};
+use crate::domain::entities::{Process, ProcessId, Executable, Route, PipeName, WorkingDirectory, CommunicationMode,
+                              ConcurrencyLimit, OverflowPolicy, HealthCheck, NegativeCachePolicy, SerializationFormat,
+                              HttpMethod, WarmupRequest, RestartPolicy, RetryPolicy, ADDRESS_PLACEHOLDER,
+                              PORT_PLACEHOLDER};
 use async_trait::async_trait;
+use axum::http::{HeaderName, HeaderValue};
 use serde::Deserialize;
-use std::path::PathBuf;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 
 /// XML-based process repository
 pub struct XmlProcessRepository {
-    manifest_path: PathBuf,
+    /// Manifest files, or directories of them, merged in this order
+    manifest_paths: Vec<PathBuf>,
+    /// Working directory for processes that don't declare one
+    default_working_dir: Option<PathBuf>,
 }
 
 impl XmlProcessRepository {
-    pub fn new(manifest_path: impl Into<PathBuf>) -> Self {
+    /// Merge the processes of several manifests, e.g. one per team
+    ///
+    /// A directory stands for every `.xml` file in it, sorted by file name so
+    /// routing priority doesn't depend on the order the OS lists them in.
+    pub fn from_paths(manifest_paths: impl IntoIterator<Item = impl Into<PathBuf>>) -> Self {
         Self {
-            manifest_path: manifest_path.into(),
+            manifest_paths: manifest_paths.into_iter().map(Into::into).collect(),
+            default_working_dir: None,
         }
     }
+
+    /// Run processes without a `working_dir` of their own in `dir`
+    pub fn with_default_working_dir(mut self, dir: Option<PathBuf>) -> Self {
+        self.default_working_dir = dir;
+        self
+    }
+}
+
+/// Check a process's working directory exists, so a typo is reported when
+/// the manifest is loaded rather than as a failure to spawn
+fn validate_working_dir(process: &Process) -> Result<(), RepositoryError> {
+    let Some(dir) = &process.working_directory else {
+        return Ok(());
+    };
+    if std::path::Path::new(dir.as_str()).is_dir() {
+        return Ok(());
+    }
+    Err(RepositoryError::NotFound(format!(
+        "working directory '{}' for process '{}' does not exist or is not a directory",
+        dir.as_str(),
+        process.id.as_str()
+    )))
+}
+
+/// Check a static route's directory exists, so a typo is reported when the
+/// manifest is loaded rather than as a 404 for every file
+fn validate_static_dir(process: &Process) -> Result<(), RepositoryError> {
+    let Some(dir) = &process.static_dir else {
+        return Ok(());
+    };
+    if std::path::Path::new(dir).is_dir() {
+        return Ok(());
+    }
+    Err(RepositoryError::NotFound(format!(
+        "static directory '{}' for process '{}' does not exist or is not a directory",
+        dir,
+        process.id.as_str()
+    )))
+}
+
+/// Manifest path standing for the proxy's standard input, e.g. a generated
+/// manifest piped in
+pub const STDIN_MANIFEST: &str = "-";
+
+fn is_stdin(path: &Path) -> bool {
+    path.as_os_str() == STDIN_MANIFEST
+}
+
+/// Newest manifest schema this build understands, declared by a manifest
+/// with `<manifest version="N">`; one that doesn't declare it is taken to be
+/// written for the first
+pub const MANIFEST_VERSION: u32 = 1;
+
+/// Elements allowed directly under `<manifest>`
+const MANIFEST_ELEMENTS: &[&str] = &["process"];
+
+/// The version a manifest declares, refusing one written for a newer
+/// binary rather than ignoring the settings it doesn't know
+fn check_version(version: &str) -> Result<u32, String> {
+    let declared = version
+        .trim()
+        .parse::<u32>()
+        .ok()
+        .filter(|&v| v > 0)
+        .ok_or_else(|| format!("Invalid manifest version: '{}'", version))?;
+    if declared > MANIFEST_VERSION {
+        return Err(format!(
+            "Manifest version {} is newer than this local_lambdas supports (up to {}); upgrade local_lambdas to load it",
+            declared, MANIFEST_VERSION
+        ));
+    }
+    Ok(declared)
+}
+
+/// The `version` attribute of the root element and the names of the
+/// elements directly under it, in order; malformed XML is left for the
+/// parser to report
+fn manifest_outline(contents: &str) -> (Option<String>, Vec<String>) {
+    use xml::reader::{EventReader, XmlEvent};
+
+    let mut version = None;
+    let mut children = Vec::new();
+    let mut depth = 0;
+    for event in EventReader::from_str(contents) {
+        match event {
+            Ok(XmlEvent::StartElement { name, attributes, .. }) => {
+                match depth {
+                    0 => {
+                        version = attributes
+                            .into_iter()
+                            .find(|attribute| attribute.name.local_name == "version")
+                            .map(|attribute| attribute.value)
+                    }
+                    1 => children.push(name.local_name),
+                    _ => {}
+                }
+                depth += 1;
+            }
+            Ok(XmlEvent::EndElement { .. }) => depth -= 1,
+            Ok(_) => {}
+            Err(_) => break,
+        }
+    }
+    (version, children)
+}
+
+/// Each of `elements` that isn't part of the manifest schema, once
+fn unknown_elements(elements: &[String]) -> Vec<&str> {
+    let mut unknown = Vec::new();
+    for name in elements.iter().map(String::as_str) {
+        if !MANIFEST_ELEMENTS.contains(&name) && !unknown.contains(&name) {
+            unknown.push(name);
+        }
+    }
+    unknown
+}
+
+/// The manifest files `paths` stand for, in load order
+async fn manifest_files(paths: &[PathBuf]) -> Result<Vec<PathBuf>, RepositoryError> {
+    let mut files = Vec::new();
+    for path in paths {
+        if is_stdin(path) {
+            if files.iter().any(|file: &PathBuf| is_stdin(file)) {
+                return Err(RepositoryError::ParseError(
+                    "standard input ('-') can only be given as a manifest once".to_string(),
+                ));
+            }
+            files.push(path.clone());
+            continue;
+        }
+        if !path.is_dir() {
+            files.push(path.clone());
+            continue;
+        }
+
+        let mut entries = tokio::fs::read_dir(path)
+            .await
+            .map_err(|e| RepositoryError::IoError(format!("{}: {}", path.display(), e)))?;
+        let mut found = Vec::new();
+        while let Some(entry) = entries
+            .next_entry()
+            .await
+            .map_err(|e| RepositoryError::IoError(format!("{}: {}", path.display(), e)))?
+        {
+            let file = entry.path();
+            if file.is_file() && file.extension().is_some_and(|ext| ext == "xml") {
+                found.push(file);
+            }
+        }
+        if found.is_empty() {
+            return Err(RepositoryError::NotFound(format!("no .xml manifests in {}", path.display())));
+        }
+        found.sort_by(|a, b| a.file_name().cmp(&b.file_name()));
+        files.extend(found);
+    }
+    Ok(files)
+}
+
+/// Parse one manifest file into its processes
+async fn load_file(path: &Path) -> Result<Vec<Process>, RepositoryError> {
+    // Read file, or the whole of stdin
+    let contents = if is_stdin(path) {
+        use tokio::io::AsyncReadExt;
+        let mut contents = String::new();
+        tokio::io::stdin()
+            .read_to_string(&mut contents)
+            .await
+            .map_err(|e| RepositoryError::IoError(format!("standard input: {}", e)))?;
+        contents
+    } else {
+        tokio::fs::read_to_string(path)
+            .await
+            .map_err(|e| RepositoryError::IoError(e.to_string()))?
+    };
+
+    // A manifest for a newer binary could rely on settings this one would
+    // ignore, so it's refused before anything in it is read
+    let (version, elements) = manifest_outline(&contents);
+    if let Some(version) = version {
+        let version = check_version(&version).map_err(RepositoryError::ParseError)?;
+        for name in unknown_elements(&elements) {
+            tracing::warn!(
+                "{}: <{}> is not part of manifest version {} and is ignored",
+                path.display(),
+                name,
+                version
+            );
+        }
+    }
+
+    // Parse XML
+    let manifest: ManifestDto = serde_xml_rs::from_str(&contents)
+        .map_err(|e| RepositoryError::ParseError(e.to_string()))?;
+
+    // Convert DTOs to domain entities
+    manifest
+        .processes
+        .into_iter()
+        .map(|dto| dto.into_domain())
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| RepositoryError::ParseError(e.to_string()))
+}
+
+/// Reject an id or route declared in more than one file, naming both
+///
+/// Clashes within a single file are left to `--check`, as they always were.
+fn check_cross_file_duplicates(sources: &[(&Path, &Process)]) -> Result<(), RepositoryError> {
+    check_unique_across_files(sources, "id", |p| p.id.as_str())?;
+    check_unique_across_files(sources, "route", |p| p.route.as_str())
+}
+
+fn check_unique_across_files(
+    sources: &[(&Path, &Process)],
+    what: &str,
+    key: impl Fn(&Process) -> &str,
+) -> Result<(), RepositoryError> {
+    let mut first_seen: HashMap<&str, (&Path, &Process)> = HashMap::new();
+    for &(file, process) in sources {
+        let (first_file, first) = *first_seen.entry(key(process)).or_insert((file, process));
+        if first_file != file {
+            return Err(RepositoryError::ParseError(format!(
+                "Duplicate {} '{}' in {} (process '{}') and {} (process '{}')",
+                what,
+                key(process),
+                first_file.display(),
+                first.id.as_str(),
+                file.display(),
+                process.id.as_str()
+            )));
+        }
+    }
+    Ok(())
 }
 
 #[async_trait]
 impl ProcessRepository for XmlProcessRepository {
     async fn load_all(&self) -> Result<Vec<Process>, RepositoryError> {
-        // Read file
-        let contents = tokio::fs::read_to_string(&self.manifest_path)
-            .await
-            .map_err(|e| RepositoryError::IoError(e.to_string()))?;
+        let files = manifest_files(&self.manifest_paths).await?;
+        let mut loaded = Vec::new();
+        for file in &files {
+            loaded.push(load_file(file).await?);
+        }
 
-        // Parse XML
-        let manifest: ManifestDto = serde_xml_rs::from_str(&contents)
-            .map_err(|e| RepositoryError::ParseError(e.to_string()))?;
+        let sources: Vec<(&Path, &Process)> = files
+            .iter()
+            .zip(&loaded)
+            .flat_map(|(file, processes)| processes.iter().map(move |p| (file.as_path(), p)))
+            .collect();
+        check_cross_file_duplicates(&sources)?;
+        let mut processes: Vec<Process> = loaded.into_iter().flatten().collect();
 
-        // Convert DTOs to domain entities
-        manifest
-            .processes
-            .into_iter()
-            .map(|dto| dto.into_domain())
-            .collect::<Result<Vec<_>, _>>()
-            .map_err(|e| RepositoryError::ParseError(e.to_string()))
+        for process in &mut processes {
+            if process.working_directory.is_none() {
+                process.working_directory = self
+                    .default_working_dir
+                    .as_ref()
+                    .map(|dir| WorkingDirectory::new(dir.to_string_lossy()));
+            }
+            validate_working_dir(process)?;
+            validate_static_dir(process)?;
+        }
+
+        let defaults: Vec<&str> = processes.iter().filter(|p| p.is_default).map(|p| p.id.as_str()).collect();
+        if defaults.len() > 1 {
+            return Err(RepositoryError::ParseError(format!(
+                "Only one process can be the default, found: {}",
+                defaults.join(", ")
+            )));
+        }
+
+        Ok(processes)
     }
 }
 
@@ -53,34 +317,639 @@ struct ManifestDto {
 #[derive(Debug, Deserialize)]
 struct ProcessDto {
     id: String,
-    executable: String,
+    #[serde(default)]
+    executable: Option<String>,
     #[serde(rename = "arg", default)]
     args: Vec<String>,
     route: String,
-    pipe_name: String,
+    #[serde(default)]
+    methods: Option<String>,
+    #[serde(default)]
+    pipe_name: Option<String>,
+    #[serde(default)]
+    static_dir: Option<String>,
     #[serde(default)]
     working_dir: Option<String>,
     #[serde(default)]
     communication_mode: Option<String>,
+    #[serde(default)]
+    max_concurrency: Option<usize>,
+    #[serde(default)]
+    overflow_policy: Option<String>,
+    #[serde(default)]
+    queue_timeout_ms: Option<u64>,
+    #[serde(default)]
+    instances: Option<usize>,
+    #[serde(default)]
+    weights: Option<String>,
+    #[serde(default)]
+    timeout_ms: Option<u64>,
+    #[serde(default)]
+    http_fallback: bool,
+    #[serde(default)]
+    http_port: Option<u16>,
+    #[serde(default)]
+    max_body_bytes: Option<usize>,
+    #[serde(rename = "accept_content_type", default)]
+    accept_content_types: Vec<String>,
+    #[serde(default)]
+    max_response_bytes: Option<usize>,
+    #[serde(default)]
+    host_header: Option<String>,
+    #[serde(default)]
+    strip_prefix: Option<String>,
+    #[serde(default)]
+    prepend: Option<String>,
+    #[serde(default)]
+    head_from_get: bool,
+    #[serde(default)]
+    debug_body: bool,
+    #[serde(default)]
+    health_check: Option<HealthCheckDto>,
+    #[serde(rename = "warmup", default)]
+    warmups: Vec<String>,
+    #[serde(default)]
+    warmup_strict: bool,
+    #[serde(default)]
+    negative_cache: Option<NegativeCacheDto>,
+    #[serde(default)]
+    cache_vary: Option<String>,
+    #[serde(default)]
+    cache_ignore_query: Option<String>,
+    #[serde(default)]
+    shared_cache: Option<String>,
+    #[serde(default)]
+    protocol: Option<String>,
+    #[serde(rename = "env", default)]
+    env: Vec<EnvDto>,
+    #[serde(default)]
+    clean_env: bool,
+    #[serde(default)]
+    uid: Option<u32>,
+    #[serde(default)]
+    gid: Option<u32>,
+    #[serde(default)]
+    idle_timeout_ms: Option<u64>,
+    #[serde(default)]
+    auto_restart: Option<AutoRestartDto>,
+    #[serde(default)]
+    retry: Option<RetryDto>,
+    #[serde(default)]
+    default: bool,
+    #[serde(rename = "response_header", default)]
+    response_headers: Vec<ResponseHeaderDto>,
+}
+
+/// `<response_header name="X-Service">api</response_header>`
+#[derive(Debug, Deserialize)]
+struct ResponseHeaderDto {
+    name: String,
+    #[serde(rename = "$value", default)]
+    value: String,
+}
+
+impl ResponseHeaderDto {
+    /// Headers the HTTP layer couldn't send would fail every response
+    fn into_domain(self) -> Result<(String, String), String> {
+        let value = interpolate(&self.value)?;
+        if HeaderName::from_bytes(self.name.as_bytes()).is_err() {
+            return Err(format!("Invalid response header name: '{}'", self.name));
+        }
+        if HeaderValue::from_str(&value).is_err() {
+            return Err(format!("Invalid value for response header '{}': '{}'", self.name, value));
+        }
+        Ok((self.name, value))
+    }
+}
+
+/// `<warmup>GET /healthz</warmup>`: a method and a path
+fn parse_warmup(warmup: &str) -> Result<WarmupRequest, String> {
+    let invalid = || format!("Invalid warmup: '{}'. Must be a method and a path, like 'GET /healthz'", warmup);
+    let (method, path) = warmup.trim().split_once(char::is_whitespace).ok_or_else(invalid)?;
+    let path = path.trim();
+    if !path.starts_with('/') || path.contains(char::is_whitespace) {
+        return Err(invalid());
+    }
+    Ok(WarmupRequest {
+        method: method.parse().map_err(|_| invalid())?,
+        path: path.to_string(),
+    })
+}
+
+/// `<env name="LOG_LEVEL" value="debug"/>`
+#[derive(Debug, Deserialize)]
+struct EnvDto {
+    name: String,
+    #[serde(default)]
+    value: String,
+}
+
+/// `<health_check path="/healthz" interval_ms="5000"/>`
+#[derive(Debug, Deserialize)]
+struct HealthCheckDto {
+    path: String,
+    #[serde(default)]
+    interval_ms: Option<u64>,
+}
+
+impl HealthCheckDto {
+    /// Probe every five seconds unless configured otherwise
+    const DEFAULT_INTERVAL_MS: u64 = 5000;
+
+    fn into_domain(self) -> Result<HealthCheck, String> {
+        if !self.path.starts_with('/') {
+            return Err(format!("Health check path must start with '/': {}", self.path));
+        }
+        let interval_ms = self.interval_ms.unwrap_or(Self::DEFAULT_INTERVAL_MS);
+        if interval_ms == 0 {
+            return Err("Health check interval_ms must be greater than 0".to_string());
+        }
+        Ok(HealthCheck {
+            path: self.path,
+            interval: Duration::from_millis(interval_ms),
+        })
+    }
+}
+
+/// `<negative_cache ttl_ms="5000" statuses="502,503"/>`
+#[derive(Debug, Deserialize)]
+struct NegativeCacheDto {
+    #[serde(default)]
+    ttl_ms: Option<u64>,
+    #[serde(default)]
+    statuses: Option<String>,
+}
+
+impl NegativeCacheDto {
+    /// Keep error responses for five seconds unless configured otherwise
+    const DEFAULT_TTL_MS: u64 = 5000;
+
+    fn into_domain(self) -> Result<NegativeCachePolicy, String> {
+        let ttl_ms = self.ttl_ms.unwrap_or(Self::DEFAULT_TTL_MS);
+        if ttl_ms == 0 {
+            return Err("Negative cache ttl_ms must be greater than 0".to_string());
+        }
+
+        let server_errors = self
+            .statuses
+            .as_deref()
+            .unwrap_or("")
+            .split(',')
+            .map(str::trim)
+            .filter(|s| !s.is_empty())
+            .map(|s| match s.parse::<u16>() {
+                Ok(status) if (500..600).contains(&status) => Ok(status),
+                _ => Err(format!("Invalid negative cache status: {}. Must be a 5xx status code", s)),
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(NegativeCachePolicy {
+            ttl: Duration::from_millis(ttl_ms),
+            server_errors,
+        })
+    }
+}
+
+/// `<retry max_attempts="3" backoff_ms="100" methods="POST"/>`
+#[derive(Debug, Deserialize)]
+struct RetryDto {
+    #[serde(default)]
+    max_attempts: Option<u32>,
+    #[serde(default)]
+    backoff_ms: Option<u64>,
+    #[serde(default)]
+    methods: Option<String>,
+}
+
+impl RetryDto {
+    const DEFAULT_MAX_ATTEMPTS: u32 = 3;
+    const DEFAULT_BACKOFF_MS: u64 = 100;
+
+    fn into_domain(self) -> Result<RetryPolicy, String> {
+        let max_attempts = self.max_attempts.unwrap_or(Self::DEFAULT_MAX_ATTEMPTS);
+        if max_attempts == 0 {
+            return Err("Retry max_attempts must be greater than 0".to_string());
+        }
+        Ok(RetryPolicy {
+            max_attempts,
+            backoff: Duration::from_millis(self.backoff_ms.unwrap_or(Self::DEFAULT_BACKOFF_MS)),
+            methods: parse_methods(self.methods.as_deref())?,
+        })
+    }
+}
+
+/// `<auto_restart backoff_ms="100" max_backoff_ms="10000" max_crashes="5" window_ms="60000"/>`
+#[derive(Debug, Deserialize)]
+struct AutoRestartDto {
+    #[serde(default)]
+    backoff_ms: Option<u64>,
+    #[serde(default)]
+    max_backoff_ms: Option<u64>,
+    #[serde(default)]
+    max_crashes: Option<u32>,
+    #[serde(default)]
+    window_ms: Option<u64>,
+}
+
+impl AutoRestartDto {
+    const DEFAULT_BACKOFF_MS: u64 = 100;
+    const DEFAULT_MAX_BACKOFF_MS: u64 = 10_000;
+    /// Give up after five crashes within a minute unless configured otherwise
+    const DEFAULT_MAX_CRASHES: u32 = 5;
+    const DEFAULT_WINDOW_MS: u64 = 60_000;
+
+    fn into_domain(self) -> Result<RestartPolicy, String> {
+        let backoff_ms = self.backoff_ms.unwrap_or(Self::DEFAULT_BACKOFF_MS);
+        let max_backoff_ms = self.max_backoff_ms.unwrap_or(Self::DEFAULT_MAX_BACKOFF_MS.max(backoff_ms));
+        let max_crashes = self.max_crashes.unwrap_or(Self::DEFAULT_MAX_CRASHES);
+        let window_ms = self.window_ms.unwrap_or(Self::DEFAULT_WINDOW_MS);
+        if backoff_ms == 0 {
+            return Err("Auto restart backoff_ms must be greater than 0".to_string());
+        }
+        if max_backoff_ms < backoff_ms {
+            return Err("Auto restart max_backoff_ms must be at least backoff_ms".to_string());
+        }
+        if max_crashes == 0 || window_ms == 0 {
+            return Err("Auto restart max_crashes and window_ms must be greater than 0".to_string());
+        }
+        Ok(RestartPolicy {
+            initial_backoff: Duration::from_millis(backoff_ms),
+            max_backoff: Duration::from_millis(max_backoff_ms),
+            max_crashes,
+            window: Duration::from_millis(window_ms),
+        })
+    }
+}
+
+/// Substitute `${VAR}` and `${VAR:-default}` in a manifest value from the
+/// proxy's environment; `$${` stands for a literal `${`
+///
+/// `default` is used when `VAR` is unset or empty, as in the shell. An
+/// unset `VAR` without a default is an error naming it.
+fn interpolate(value: &str) -> Result<String, String> {
+    interpolate_except(value, &[])
+}
+
+/// [`interpolate`], leaving the placeholders in `kept` as they are to be
+/// filled in later
+fn interpolate_except(value: &str, kept: &[&str]) -> Result<String, String> {
+    let mut result = String::with_capacity(value.len());
+    let mut rest = value;
+    while let Some(start) = rest.find("${") {
+        if rest[..start].ends_with('$') {
+            result.push_str(&rest[..start]);
+            result.push('{');
+            rest = &rest[start + 2..];
+            continue;
+        }
+        result.push_str(&rest[..start]);
+        let end = rest[start..]
+            .find('}')
+            .map(|end| start + end)
+            .ok_or_else(|| format!("Unterminated '${{' in '{}'", value))?;
+        let expression = &rest[start + 2..end];
+        if kept.contains(&&rest[start..=end]) {
+            result.push_str(&rest[start..=end]);
+            rest = &rest[end + 1..];
+            continue;
+        }
+        let substituted = match expression.split_once(":-") {
+            Some((name, default)) => std::env::var(name).ok().filter(|v| !v.is_empty()).unwrap_or_else(|| default.to_string()),
+            None => std::env::var(expression)
+                .map_err(|_| format!("Environment variable '{}' is not set (used in '{}')", expression, value))?,
+        };
+        result.push_str(&substituted);
+        rest = &rest[end + 1..];
+    }
+    result.push_str(rest);
+    Ok(result)
+}
+
+/// `<accept_content_type>multipart/form-data</accept_content_type>`: a media
+/// type without parameters, or `type/*` for any of its subtypes, lowercased
+/// since media types are compared regardless of case
+fn parse_content_type(content_type: &str) -> Result<String, String> {
+    let is_token = |s: &str| {
+        !s.is_empty() && s.chars().all(|c| c.is_ascii_alphanumeric() || "!#$&-^_.+".contains(c))
+    };
+    let content_type = content_type.trim().to_ascii_lowercase();
+    match content_type.split_once('/') {
+        Some((kind, subtype)) if is_token(kind) && (subtype == "*" || is_token(subtype)) => Ok(content_type),
+        _ => Err(format!(
+            "Invalid accept_content_type: '{}'. Must be a media type such as 'application/json' or 'image/*'",
+            content_type
+        )),
+    }
+}
+
+/// Parse a comma-separated list of HTTP methods, e.g. `GET, POST`,
+/// uppercased since manifests tend to be written in either case
+fn parse_methods(methods: Option<&str>) -> Result<Vec<HttpMethod>, String> {
+    let Some(methods) = methods else {
+        return Ok(Vec::new());
+    };
+    let mut parsed = Vec::new();
+    for name in methods.split(',').map(str::trim) {
+        let method: HttpMethod = name
+            .to_ascii_uppercase()
+            .parse()
+            .map_err(|_| format!("Invalid method: '{}'", name))?;
+        if !parsed.contains(&method) {
+            parsed.push(method);
+        }
+    }
+    Ok(parsed)
 }
 
 impl ProcessDto {
-    fn into_domain(self) -> Result<Process, String> {
+    fn into_domain(mut self) -> Result<Process, String> {
+        self.route = interpolate(&self.route)?;
+        if let Some(dir) = self.static_dir.take() {
+            return self.into_static(interpolate(&dir)?);
+        }
+        let executable = interpolate(
+            self.executable
+                .as_deref()
+                .ok_or_else(|| format!("Process '{}' needs an executable or a static_dir", self.id))?,
+        )?;
+        let pipe_name = interpolate(
+            self.pipe_name
+                .as_deref()
+                .ok_or_else(|| format!("Process '{}' needs a pipe_name", self.id))?,
+        )?;
+        // The address placeholders are filled in when each instance is spawned
+        self.args = self
+            .args
+            .iter()
+            .map(|arg| interpolate_except(arg, &[ADDRESS_PLACEHOLDER, PORT_PLACEHOLDER]))
+            .collect::<Result<_, _>>()?;
+        self.working_dir = self.working_dir.as_deref().map(interpolate).transpose()?;
+        for env in &mut self.env {
+            env.value = interpolate(&env.value)?;
+        }
+
+        let methods = parse_methods(self.methods.as_deref())?;
+
         let communication_mode = match self.communication_mode.as_deref() {
             Some("http") => CommunicationMode::Http,
+            Some("grpc") => CommunicationMode::Grpc,
             Some("pipe") | None => CommunicationMode::Pipe,
-            Some(other) => return Err(format!("Invalid communication mode: {}. Must be 'pipe' or 'http'", other)),
+            Some(other) => return Err(format!("Invalid communication mode: {}. Must be 'pipe', 'http' or 'grpc'", other)),
+        };
+
+        let concurrency_limit = match self.max_concurrency {
+            None => None,
+            Some(0) => return Err("max_concurrency must be greater than 0".to_string()),
+            Some(max_concurrent) => {
+                let overflow = match self.overflow_policy.as_deref() {
+                    Some("reject") => OverflowPolicy::Reject,
+                    Some("queue") | None => self
+                        .queue_timeout_ms
+                        .map(|ms| OverflowPolicy::Queue(Duration::from_millis(ms)))
+                        .unwrap_or_default(),
+                    Some(other) => return Err(format!("Invalid overflow policy: {}. Must be 'queue' or 'reject'", other)),
+                };
+                Some(ConcurrencyLimit { max_concurrent, overflow })
+            }
+        };
+
+        let instances = match self.instances {
+            Some(0) => return Err("instances must be greater than 0".to_string()),
+            Some(n) => n,
+            None => 1,
+        };
+
+        let weights = match self.weights.as_deref() {
+            None => Vec::new(),
+            Some(weights) => {
+                let weights = weights
+                    .split(',')
+                    .map(str::trim)
+                    .map(|w| match w.parse::<u32>() {
+                        Ok(weight) if weight > 0 => Ok(weight),
+                        _ => Err(format!("Invalid weight: '{}'. Must be a positive integer", w)),
+                    })
+                    .collect::<Result<Vec<_>, _>>()?;
+                if weights.len() != instances {
+                    return Err(format!(
+                        "{} weight(s) given for {} instance(s); give one per instance",
+                        weights.len(),
+                        instances
+                    ));
+                }
+                weights
+            }
         };
         
-        Ok(Process {
-            id: ProcessId::new(self.id).map_err(|e| e.to_string())?,
-            executable: Executable::new(self.executable).map_err(|e| e.to_string())?,
-            arguments: self.args,
-            route: Route::new(self.route).map_err(|e| e.to_string())?,
-            pipe_name: PipeName::new(self.pipe_name).map_err(|e| e.to_string())?,
-            working_directory: self.working_dir.map(WorkingDirectory::new),
-            communication_mode,
-        })
+        if let Some(port) = self.http_port {
+            if communication_mode == CommunicationMode::Pipe && !self.http_fallback {
+                return Err("http_port requires communication_mode 'http' or 'grpc', or http_fallback".to_string());
+            }
+            // Each further instance listens on the next port
+            if port == 0 || port as usize + instances - 1 > u16::MAX as usize {
+                return Err(format!("Invalid http_port: {}. Must leave room for {} instance(s) below 65536", port, instances));
+            }
+        }
+
+        let listens_on_http = communication_mode != CommunicationMode::Pipe || self.http_fallback;
+        if !listens_on_http && self.args.iter().any(|arg| arg.contains(PORT_PLACEHOLDER)) {
+            return Err(format!(
+                "{} requires communication_mode 'http' or 'grpc', or http_fallback",
+                PORT_PLACEHOLDER
+            ));
+        }
+
+        // Only HTTP requests carry a Host header
+        let host_header = self.host_header.map(|host| host.trim().to_string());
+        if let Some(host) = &host_header {
+            if communication_mode != CommunicationMode::Http && !self.http_fallback {
+                return Err("host_header requires communication_mode 'http', or http_fallback".to_string());
+            }
+            if host.is_empty() || HeaderValue::from_str(host).is_err() {
+                return Err(format!("Invalid host_header: '{}'", host));
+            }
+        }
+
+        // gRPC calls are forwarded as they are, path included
+        let strip_prefix = self.strip_prefix.map(|prefix| prefix.trim().to_string());
+        let prepend_path = self.prepend.map(|path| path.trim().to_string());
+        for (element, path) in [("strip_prefix", &strip_prefix), ("prepend", &prepend_path)] {
+            let Some(path) = path else { continue };
+            if communication_mode == CommunicationMode::Grpc {
+                return Err(format!("{} is not supported in grpc communication mode", element));
+            }
+            if !path.starts_with('/') || path.contains(['?', '#']) {
+                return Err(format!("Invalid {}: '{}'. Must be a path starting with '/'", element, path));
+            }
+        }
+
+        let protocol = match self.protocol.as_deref() {
+            Some("json") | None => SerializationFormat::Json,
+            Some("msgpack") => SerializationFormat::MsgPack,
+            Some("raw") => SerializationFormat::Raw,
+            Some(other) => return Err(format!("Invalid protocol: {}. Must be 'json', 'msgpack' or 'raw'", other)),
+        };
+        // gRPC calls are forwarded as they are, with no envelope to encode
+        if protocol == SerializationFormat::MsgPack && communication_mode == CommunicationMode::Grpc {
+            return Err("The msgpack protocol is not supported in grpc communication mode".to_string());
+        }
+        // Plain HTTP requests need an HTTP server on the other end
+        if protocol == SerializationFormat::Raw && communication_mode != CommunicationMode::Http {
+            return Err("The raw protocol is only supported in http communication mode".to_string());
+        }
+
+        // Only Unix can spawn a child as another user
+        if !cfg!(unix) && (self.uid.is_some() || self.gid.is_some()) {
+            return Err("uid and gid are only supported on Unix".to_string());
+        }
+
+        if let Some(env) = self.env.iter().find(|e| e.name.is_empty() || e.name.contains('=')) {
+            return Err(format!("Invalid environment variable name: '{}'", env.name));
+        }
+
+        // Health checks are envelope requests, which a gRPC server can't answer
+        if self.health_check.is_some() && communication_mode == CommunicationMode::Grpc {
+            return Err("health_check is not supported in grpc communication mode".to_string());
+        }
+
+        // Warm-ups are envelope requests too
+        if !self.warmups.is_empty() && communication_mode == CommunicationMode::Grpc {
+            return Err("warmup is not supported in grpc communication mode".to_string());
+        }
+
+        // gRPC requests all have the same content type family
+        if !self.accept_content_types.is_empty() && communication_mode == CommunicationMode::Grpc {
+            return Err("accept_content_type is not supported in grpc communication mode".to_string());
+        }
+        let accepted_content_types = self
+            .accept_content_types
+            .iter()
+            .map(|content_type| parse_content_type(content_type))
+            .collect::<Result<_, _>>()?;
+
+        let shared_cache = self.shared_cache.map(|group| group.trim().to_string());
+        if shared_cache.as_deref() == Some("") {
+            return Err("shared_cache needs a group name".to_string());
+        }
+        // Entries are keyed by the path below the route, which an exact route doesn't have
+        if shared_cache.is_some() && !self.route.contains('*') && !self.route.ends_with('/') {
+            return Err(format!("shared_cache needs a prefix or wildcard route, not the exact route '{}'", self.route));
+        }
+
+        let health_check = self.health_check.map(HealthCheckDto::into_domain).transpose()?;
+        let warmup = self
+            .warmups
+            .iter()
+            .map(|warmup| parse_warmup(&interpolate(warmup)?))
+            .collect::<Result<_, _>>()?;
+        let response_headers = self
+            .response_headers
+            .into_iter()
+            .map(ResponseHeaderDto::into_domain)
+            .collect::<Result<_, _>>()?;
+        let negative_cache = self.negative_cache.map(NegativeCacheDto::into_domain).transpose()?;
+        let restart = self.auto_restart.map(AutoRestartDto::into_domain).transpose()?;
+        let retry = self.retry.map(RetryDto::into_domain).transpose()?;
+        
+        let mut process = Process::new(
+            ProcessId::new(self.id).map_err(|e| e.to_string())?,
+            Executable::new(executable).map_err(|e| e.to_string())?,
+            Route::new(self.route).map_err(|e| e.to_string())?,
+            PipeName::new(pipe_name).map_err(|e| e.to_string())?,
+        );
+        process.arguments = self.args;
+        process.methods = methods;
+        process.working_directory = self.working_dir.map(WorkingDirectory::new);
+        process.communication_mode = communication_mode;
+        process.concurrency_limit = concurrency_limit;
+        process.instances = instances;
+        process.weights = weights;
+        // 0 means no timeout, same as leaving it out
+        process.timeout = self.timeout_ms.filter(|&ms| ms > 0).map(Duration::from_millis);
+        process.http_fallback = self.http_fallback;
+        process.http_port = self.http_port;
+        process.max_body_bytes = self.max_body_bytes;
+        process.accepted_content_types = accepted_content_types;
+        process.max_response_bytes = self.max_response_bytes;
+        process.host_header = host_header;
+        process.strip_prefix = strip_prefix;
+        process.prepend_path = prepend_path;
+        process.head_from_get = self.head_from_get;
+        process.debug_body = self.debug_body;
+        process.health_check = health_check;
+        process.warmup = warmup;
+        process.warmup_strict = self.warmup_strict;
+        process.negative_cache = negative_cache;
+        process.cache_vary = self
+            .cache_vary
+            .iter()
+            .flat_map(|names| names.split(','))
+            .map(|name| name.trim().to_ascii_lowercase())
+            .filter(|name| !name.is_empty())
+            .collect();
+        // Unlike header names, query parameter names are case-sensitive
+        process.cache_ignore_query = self
+            .cache_ignore_query
+            .iter()
+            .flat_map(|names| names.split(','))
+            .map(|name| name.trim().to_string())
+            .filter(|name| !name.is_empty())
+            .collect();
+        process.shared_cache = shared_cache;
+        process.protocol = protocol;
+        process.environment = self.env.into_iter().map(|e| (e.name, e.value)).collect();
+        process.clean_env = self.clean_env;
+        process.uid = self.uid;
+        process.gid = self.gid;
+        process.is_default = self.default;
+        process.response_headers = response_headers;
+        process.idle_timeout = self.idle_timeout_ms.filter(|&ms| ms > 0).map(Duration::from_millis);
+        process.restart = restart;
+        process.retry = retry;
+
+        Ok(process)
+    }
+
+    /// A route served from `dir` by the proxy itself
+    ///
+    /// Only the settings that apply to serving files are kept; anything
+    /// describing a process to run is a mistake in the manifest.
+    fn into_static(self, dir: String) -> Result<Process, String> {
+        if self.executable.is_some() || self.pipe_name.is_some() {
+            return Err(format!(
+                "Process '{}' has a static_dir, so it can't also have an executable or pipe_name",
+                self.id
+            ));
+        }
+        if self.health_check.is_some() {
+            return Err(format!("Process '{}': health_check is not supported with static_dir", self.id));
+        }
+        if !self.warmups.is_empty() {
+            return Err(format!("Process '{}': warmup is not supported with static_dir", self.id));
+        }
+        if self.auto_restart.is_some() {
+            return Err(format!("Process '{}': auto_restart is not supported with static_dir", self.id));
+        }
+        if self.methods.is_some() {
+            return Err(format!("Process '{}': methods is not supported with static_dir", self.id));
+        }
+        if !self.accept_content_types.is_empty() {
+            return Err(format!("Process '{}': accept_content_type is not supported with static_dir", self.id));
+        }
+
+        let response_headers = self
+            .response_headers
+            .into_iter()
+            .map(ResponseHeaderDto::into_domain)
+            .collect::<Result<_, _>>()?;
+        let mut process = Process::new_static(
+            ProcessId::new(self.id).map_err(|e| e.to_string())?,
+            Route::new(self.route).map_err(|e| e.to_string())?,
+            dir,
+        );
+        process.is_default = self.default;
+        process.response_headers = response_headers;
+        Ok(process)
     }
 }
 
@@ -108,7 +977,7 @@ mod tests {
         temp_file.write_all(xml.as_bytes()).unwrap();
         temp_file.flush().unwrap();
 
-        let repo = XmlProcessRepository::new(temp_file.path());
+        let repo = XmlProcessRepository::from_paths([temp_file.path()]);
         let processes = repo.load_all().await.unwrap();
 
         assert_eq!(processes.len(), 1);
@@ -116,13 +985,1031 @@ mod tests {
         assert_eq!(processes[0].arguments.len(), 2);
     }
 
+    async fn load(xml: &str) -> Result<Vec<Process>, RepositoryError> {
+        let mut temp_file = NamedTempFile::new().unwrap();
+        temp_file.write_all(xml.as_bytes()).unwrap();
+        temp_file.flush().unwrap();
+
+        XmlProcessRepository::from_paths([temp_file.path()]).load_all().await
+    }
+
+    fn manifest_with(id: &str, route: &str) -> String {
+        format!(r#"<manifest>
+    <process>
+        <id>{id}</id>
+        <executable>./{id}</executable>
+        <route>{route}</route>
+        <pipe_name>{id}_pipe</pipe_name>
+    </process>
+</manifest>"#)
+    }
+
+    #[tokio::test]
+    async fn test_merge_manifest_directory() {
+        let dir = tempfile::TempDir::new().unwrap();
+        // Listed out of order on purpose: files load sorted by name
+        std::fs::write(dir.path().join("b-billing.xml"), manifest_with("billing", "/billing/*")).unwrap();
+        std::fs::write(dir.path().join("a-users.xml"), manifest_with("users", "/users/*")).unwrap();
+        std::fs::write(dir.path().join("notes.txt"), "not a manifest").unwrap();
+
+        let processes = XmlProcessRepository::from_paths([dir.path()]).load_all().await.unwrap();
+        let ids: Vec<&str> = processes.iter().map(|p| p.id.as_str()).collect();
+        assert_eq!(ids, vec!["users", "billing"]);
+    }
+
+    #[tokio::test]
+    async fn test_stdin_manifest_only_once() {
+        let error = XmlProcessRepository::from_paths(["-", "-"]).load_all().await.unwrap_err();
+        assert!(error.to_string().contains("can only be given as a manifest once"), "{}", error);
+    }
+
+    #[tokio::test]
+    async fn test_cross_file_duplicate_route_names_both_files() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let first = dir.path().join("team-a.xml");
+        let second = dir.path().join("team-b.xml");
+        std::fs::write(&first, manifest_with("a", "/api/*")).unwrap();
+        std::fs::write(&second, manifest_with("b", "/api/*")).unwrap();
+
+        let error = XmlProcessRepository::from_paths([&first, &second])
+            .load_all()
+            .await
+            .unwrap_err()
+            .to_string();
+        assert!(
+            error.contains(&format!(
+                "Duplicate route '/api/*' in {} (process 'a') and {} (process 'b')",
+                first.display(),
+                second.display()
+            )),
+            "{}",
+            error
+        );
+    }
+
+    #[tokio::test]
+    async fn test_values_are_interpolated_from_environment() {
+        std::env::set_var("LOCAL_LAMBDAS_TEST_BIN_DIR", "/opt/services");
+        std::env::set_var("LOCAL_LAMBDAS_TEST_PORT", "9001");
+        std::env::remove_var("LOCAL_LAMBDAS_TEST_UNSET_LEVEL");
+
+        let processes = load(r#"<manifest>
+    <process>
+        <id>api</id>
+        <executable>${LOCAL_LAMBDAS_TEST_BIN_DIR}/api</executable>
+        <arg>--port=${LOCAL_LAMBDAS_TEST_PORT}</arg>
+        <arg>--template=$${name}</arg>
+        <route>/api/*</route>
+        <pipe_name>api_${LOCAL_LAMBDAS_TEST_PORT}</pipe_name>
+        <env name="LOG_LEVEL" value="${LOCAL_LAMBDAS_TEST_UNSET_LEVEL:-info}"/>
+    </process>
+</manifest>"#).await.unwrap();
+
+        let process = &processes[0];
+        assert_eq!(process.executable.as_str(), "/opt/services/api");
+        assert_eq!(process.arguments, vec!["--port=9001", "--template=${name}"]);
+        assert_eq!(process.pipe_name.as_str(), "api_9001");
+        assert_eq!(process.environment, vec![("LOG_LEVEL".to_string(), "info".to_string())]);
+    }
+
+    #[tokio::test]
+    async fn test_unset_variable_is_rejected() {
+        std::env::remove_var("LOCAL_LAMBDAS_TEST_MISSING");
+
+        let result = load(r#"<manifest>
+    <process>
+        <id>api</id>
+        <executable>./api</executable>
+        <route>/api/*</route>
+        <pipe_name>api_pipe</pipe_name>
+        <working_dir>${LOCAL_LAMBDAS_TEST_MISSING}/api</working_dir>
+    </process>
+</manifest>"#).await;
+
+        let error = result.unwrap_err().to_string();
+        assert!(error.contains("Environment variable 'LOCAL_LAMBDAS_TEST_MISSING' is not set"), "{}", error);
+    }
+
+    #[tokio::test]
+    async fn test_load_concurrency_limit() {
+        let processes = load(r#"<manifest>
+    <process>
+        <id>queued</id>
+        <executable>./a</executable>
+        <route>/a/*</route>
+        <pipe_name>a_pipe</pipe_name>
+        <max_concurrency>4</max_concurrency>
+        <queue_timeout_ms>250</queue_timeout_ms>
+    </process>
+    <process>
+        <id>rejecting</id>
+        <executable>./b</executable>
+        <route>/b/*</route>
+        <pipe_name>b_pipe</pipe_name>
+        <max_concurrency>1</max_concurrency>
+        <overflow_policy>reject</overflow_policy>
+    </process>
+</manifest>"#).await.unwrap();
+
+        assert_eq!(
+            processes[0].concurrency_limit,
+            Some(ConcurrencyLimit { max_concurrent: 4, overflow: OverflowPolicy::Queue(Duration::from_millis(250)) })
+        );
+        assert_eq!(
+            processes[1].concurrency_limit,
+            Some(ConcurrencyLimit { max_concurrent: 1, overflow: OverflowPolicy::Reject })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_zero_concurrency_is_rejected() {
+        let result = load(r#"<manifest>
+    <process>
+        <id>a</id>
+        <executable>./a</executable>
+        <route>/a/*</route>
+        <pipe_name>a_pipe</pipe_name>
+        <max_concurrency>0</max_concurrency>
+    </process>
+</manifest>"#).await;
+
+        assert!(result.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_load_instances() {
+        let processes = load(r#"<manifest>
+    <process>
+        <id>a</id>
+        <executable>./a</executable>
+        <route>/a/*</route>
+        <pipe_name>a_pipe</pipe_name>
+        <instances>4</instances>
+    </process>
+    <process>
+        <id>b</id>
+        <executable>./b</executable>
+        <route>/b/*</route>
+        <pipe_name>b_pipe</pipe_name>
+    </process>
+</manifest>"#).await.unwrap();
+
+        assert_eq!(processes[0].instances, 4);
+        assert_eq!(processes[1].instances, 1);
+    }
+
+    #[tokio::test]
+    async fn test_load_weights() {
+        let manifest = |weights: &str| {
+            format!(
+                r#"<manifest>
+    <process>
+        <id>a</id>
+        <executable>./a</executable>
+        <route>/a/*</route>
+        <pipe_name>a_pipe</pipe_name>
+        <instances>3</instances>
+        <weights>{}</weights>
+    </process>
+</manifest>"#,
+                weights
+            )
+        };
+
+        let processes = load(&manifest("5, 3,1")).await.unwrap();
+        assert_eq!(processes[0].weights, vec![5, 3, 1]);
+
+        let zero = load(&manifest("5,0,1")).await.unwrap_err();
+        assert!(zero.to_string().contains("Invalid weight: '0'"), "{}", zero);
+        let negative = load(&manifest("5,-3,1")).await.unwrap_err();
+        assert!(negative.to_string().contains("Invalid weight: '-3'"), "{}", negative);
+        let too_few = load(&manifest("5,3")).await.unwrap_err();
+        assert!(too_few.to_string().contains("2 weight(s) given for 3 instance(s)"), "{}", too_few);
+    }
+
+    #[tokio::test]
+    async fn test_load_methods() {
+        let manifest = |methods: &str| {
+            format!(
+                r#"<manifest>
+    <process>
+        <id>a</id>
+        <executable>./a</executable>
+        <route>/a/*</route>
+        <pipe_name>a_pipe</pipe_name>
+        <methods>{}</methods>
+    </process>
+</manifest>"#,
+                methods
+            )
+        };
+
+        let processes = load(&manifest("get, POST,Get,PROPFIND")).await.unwrap();
+        assert_eq!(
+            processes[0].methods,
+            vec![HttpMethod::Get, HttpMethod::Post, HttpMethod::Other("PROPFIND".to_string())]
+        );
+
+        let empty = load(&manifest("GET,,POST")).await.unwrap_err();
+        assert!(empty.to_string().contains("Invalid method: ''"), "{}", empty);
+        let spaced = load(&manifest("GET POST")).await.unwrap_err();
+        assert!(spaced.to_string().contains("Invalid method: 'GET POST'"), "{}", spaced);
+    }
+
+    #[tokio::test]
+    async fn test_load_http_port() {
+        let processes = load(r#"<manifest>
+    <process>
+        <id>a</id>
+        <executable>./a</executable>
+        <route>/a/*</route>
+        <pipe_name>a_pipe</pipe_name>
+        <communication_mode>http</communication_mode>
+        <http_port>8085</http_port>
+    </process>
+</manifest>"#).await.unwrap();
+        assert_eq!(processes[0].http_port, Some(8085));
+        assert_eq!(processes[0].instance_addresses(), vec!["127.0.0.1:8085"]);
+
+        let pipe_only = load(r#"<manifest>
+    <process>
+        <id>a</id>
+        <executable>./a</executable>
+        <route>/a/*</route>
+        <pipe_name>a_pipe</pipe_name>
+        <http_port>8085</http_port>
+    </process>
+</manifest>"#).await.unwrap_err();
+        assert!(pipe_only.to_string().contains("http_port requires"), "{}", pipe_only);
+
+        let no_room = load(r#"<manifest>
+    <process>
+        <id>a</id>
+        <executable>./a</executable>
+        <route>/a/*</route>
+        <pipe_name>a_pipe</pipe_name>
+        <communication_mode>http</communication_mode>
+        <http_port>65535</http_port>
+        <instances>2</instances>
+    </process>
+</manifest>"#).await.unwrap_err();
+        assert!(no_room.to_string().contains("Invalid http_port: 65535"), "{}", no_room);
+    }
+
+    #[tokio::test]
+    async fn test_manifest_version() {
+        let manifest = |version: &str| {
+            format!(
+                r#"<manifest version="{}">
+    <process>
+        <id>a</id>
+        <executable>./a</executable>
+        <route>/a/*</route>
+        <pipe_name>a_pipe</pipe_name>
+    </process>
+</manifest>"#,
+                version
+            )
+        };
+
+        let processes = load(&manifest(&MANIFEST_VERSION.to_string())).await.unwrap();
+        assert_eq!(processes[0].id.as_str(), "a");
+
+        let future = load(&manifest(&(MANIFEST_VERSION + 1).to_string())).await.unwrap_err();
+        assert!(
+            future.to_string().contains(&format!(
+                "Manifest version {} is newer than this local_lambdas supports (up to {})",
+                MANIFEST_VERSION + 1,
+                MANIFEST_VERSION
+            )),
+            "{}",
+            future
+        );
+        for invalid in ["0", "two", ""] {
+            let error = load(&manifest(invalid)).await.unwrap_err();
+            assert!(error.to_string().contains("Invalid manifest version"), "{}", error);
+        }
+    }
+
+    #[test]
+    fn test_unknown_top_level_elements_are_found() {
+        let (version, elements) = manifest_outline(
+            r#"<?xml version="1.0"?>
+<manifest version="1">
+    <process><id>a</id><route>/a</route></process>
+    <defaults><timeout_ms>5</timeout_ms></defaults>
+    <process><id>b</id><route>/b</route></process>
+    <defaults/>
+</manifest>"#,
+        );
+        assert_eq!(version.as_deref(), Some("1"));
+        assert_eq!(elements, ["process", "defaults", "process", "defaults"]);
+        assert_eq!(unknown_elements(&elements), ["defaults"]);
+    }
+
+    #[tokio::test]
+    async fn test_load_shared_cache() {
+        let manifest = |route: &str, group: &str| {
+            format!(
+                r#"<manifest>
+    <process>
+        <id>a</id>
+        <executable>./a</executable>
+        <route>{}</route>
+        <pipe_name>a_pipe</pipe_name>
+        <shared_cache>{}</shared_cache>
+    </process>
+</manifest>"#,
+                route, group
+            )
+        };
+
+        let processes = load(&manifest("/a/*", " assets ")).await.unwrap();
+        assert_eq!(processes[0].shared_cache.as_deref(), Some("assets"));
+        let unnamed = load(&manifest("/a/*", " ")).await.unwrap_err();
+        assert!(unnamed.to_string().contains("shared_cache needs a group name"), "{}", unnamed);
+        let exact = load(&manifest("/favicon.ico", "assets")).await.unwrap_err();
+        assert!(exact.to_string().contains("not the exact route '/favicon.ico'"), "{}", exact);
+    }
+
+    #[tokio::test]
+    async fn test_load_host_header() {
+        let manifest = |mode: &str, host: &str| {
+            format!(
+                r#"<manifest>
+    <process>
+        <id>a</id>
+        <executable>./a</executable>
+        <route>/a/*</route>
+        <pipe_name>a_pipe</pipe_name>
+        <communication_mode>{}</communication_mode>
+        <host_header>{}</host_header>
+    </process>
+</manifest>"#,
+                mode, host
+            )
+        };
+
+        let processes = load(&manifest("http", " api.internal ")).await.unwrap();
+        assert_eq!(processes[0].host_header.as_deref(), Some("api.internal"));
+
+        let pipe_only = load(&manifest("pipe", "api.internal")).await.unwrap_err();
+        assert!(pipe_only.to_string().contains("host_header requires"), "{}", pipe_only);
+        let empty = load(&manifest("http", "")).await.unwrap_err();
+        assert!(empty.to_string().contains("Invalid host_header: ''"), "{}", empty);
+    }
+
+    #[tokio::test]
+    async fn test_load_path_rewrite() {
+        let manifest = |mode: &str, rewrite: &str| {
+            format!(
+                r#"<manifest>
+    <process>
+        <id>a</id>
+        <executable>./a</executable>
+        <route>/api/v1/*</route>
+        <pipe_name>a_pipe</pipe_name>
+        <communication_mode>{}</communication_mode>
+        {}
+    </process>
+</manifest>"#,
+                mode, rewrite
+            )
+        };
+
+        let processes = load(&manifest("pipe", "<strip_prefix> /api/v1 </strip_prefix><prepend>/v1</prepend>"))
+            .await
+            .unwrap();
+        assert_eq!(processes[0].strip_prefix.as_deref(), Some("/api/v1"));
+        assert_eq!(processes[0].prepend_path.as_deref(), Some("/v1"));
+        assert_eq!(processes[0].rewrite_path("/api/v1/users"), "/v1/users");
+
+        let relative = load(&manifest("pipe", "<strip_prefix>api</strip_prefix>")).await.unwrap_err();
+        assert!(relative.to_string().contains("Invalid strip_prefix: 'api'"), "{}", relative);
+        let grpc = load(&manifest("grpc", "<prepend>/v1</prepend>")).await.unwrap_err();
+        assert!(grpc.to_string().contains("prepend is not supported in grpc"), "{}", grpc);
+    }
+
+    #[tokio::test]
+    async fn test_load_grpc_mode() {
+        let processes = load(r#"<manifest>
+    <process>
+        <id>a</id>
+        <executable>./a</executable>
+        <route>/a.Service/*</route>
+        <pipe_name>a_pipe</pipe_name>
+        <communication_mode>grpc</communication_mode>
+        <http_port>50051</http_port>
+    </process>
+</manifest>"#).await.unwrap();
+        assert_eq!(processes[0].communication_mode, CommunicationMode::Grpc);
+        assert_eq!(processes[0].instance_addresses(), vec!["127.0.0.1:50051"]);
+
+        let checked = load(r#"<manifest>
+    <process>
+        <id>a</id>
+        <executable>./a</executable>
+        <route>/a.Service/*</route>
+        <pipe_name>a_pipe</pipe_name>
+        <communication_mode>grpc</communication_mode>
+        <health_check path="/healthz"/>
+    </process>
+</manifest>"#).await.unwrap_err();
+        assert!(checked.to_string().contains("health_check is not supported"), "{}", checked);
+    }
+
+    #[tokio::test]
+    async fn test_load_static_dir() {
+        let dir = tempfile::tempdir().unwrap();
+        let processes = load(&format!(r#"<manifest>
+    <process>
+        <id>site</id>
+        <route>/site/*</route>
+        <static_dir>{}</static_dir>
+    </process>
+</manifest>"#, dir.path().display())).await.unwrap();
+        assert_eq!(processes[0].static_dir.as_deref(), Some(dir.path().to_str().unwrap()));
+        assert_eq!(processes[0].executable.as_str(), dir.path().to_str().unwrap());
+
+        let missing = load(r#"<manifest>
+    <process>
+        <id>site</id>
+        <route>/site/*</route>
+        <static_dir>/definitely/not/a/dir</static_dir>
+    </process>
+</manifest>"#).await.unwrap_err();
+        assert!(missing.to_string().contains("static directory '/definitely/not/a/dir'"), "{}", missing);
+
+        let both = load(&format!(r#"<manifest>
+    <process>
+        <id>site</id>
+        <executable>./site</executable>
+        <route>/site/*</route>
+        <static_dir>{}</static_dir>
+    </process>
+</manifest>"#, dir.path().display())).await.unwrap_err();
+        assert!(both.to_string().contains("can't also have an executable"), "{}", both);
+
+        let neither = load(r#"<manifest>
+    <process>
+        <id>site</id>
+        <route>/site/*</route>
+        <pipe_name>site_pipe</pipe_name>
+    </process>
+</manifest>"#).await.unwrap_err();
+        assert!(neither.to_string().contains("needs an executable or a static_dir"), "{}", neither);
+    }
+
+    #[tokio::test]
+    async fn test_load_timeout() {
+        let processes = load(r#"<manifest>
+    <process>
+        <id>a</id>
+        <executable>./a</executable>
+        <route>/a/*</route>
+        <pipe_name>a_pipe</pipe_name>
+        <timeout_ms>1500</timeout_ms>
+    </process>
+    <process>
+        <id>b</id>
+        <executable>./b</executable>
+        <route>/b/*</route>
+        <pipe_name>b_pipe</pipe_name>
+        <timeout_ms>0</timeout_ms>
+    </process>
+    <process>
+        <id>c</id>
+        <executable>./c</executable>
+        <route>/c/*</route>
+        <pipe_name>c_pipe</pipe_name>
+    </process>
+</manifest>"#).await.unwrap();
+
+        assert_eq!(processes[0].timeout, Some(Duration::from_millis(1500)));
+        assert_eq!(processes[1].timeout, None);
+        assert_eq!(processes[2].timeout, None);
+    }
+
+    #[tokio::test]
+    async fn test_load_default_process() {
+        let processes = load(r#"<manifest>
+    <process>
+        <id>api</id>
+        <executable>./api</executable>
+        <route>/api/*</route>
+        <pipe_name>api_pipe</pipe_name>
+    </process>
+    <process>
+        <id>spa</id>
+        <executable>./spa</executable>
+        <route>/</route>
+        <pipe_name>spa_pipe</pipe_name>
+        <default>true</default>
+    </process>
+</manifest>"#).await.unwrap();
+
+        assert!(!processes[0].is_default);
+        assert!(processes[1].is_default);
+    }
+
+    #[tokio::test]
+    async fn test_multiple_defaults_are_rejected() {
+        let result = load(r#"<manifest>
+    <process>
+        <id>a</id>
+        <executable>./a</executable>
+        <route>/a</route>
+        <pipe_name>a_pipe</pipe_name>
+        <default>true</default>
+    </process>
+    <process>
+        <id>b</id>
+        <executable>./b</executable>
+        <route>/b</route>
+        <pipe_name>b_pipe</pipe_name>
+        <default>true</default>
+    </process>
+</manifest>"#).await;
+
+        let error = result.unwrap_err().to_string();
+        assert!(error.contains("Only one process can be the default, found: a, b"), "{}", error);
+    }
+
+    #[tokio::test]
+    async fn test_missing_working_dir_is_rejected() {
+        let result = load(r#"<manifest>
+    <process>
+        <id>api</id>
+        <executable>./api</executable>
+        <route>/api/*</route>
+        <pipe_name>api_pipe</pipe_name>
+        <working_dir>./definitely/not/here</working_dir>
+    </process>
+</manifest>"#).await;
+
+        let error = result.unwrap_err().to_string();
+        assert!(error.contains("working directory './definitely/not/here' for process 'api'"), "{}", error);
+    }
+
+    #[tokio::test]
+    async fn test_default_working_dir_is_applied() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let own_dir = tempfile::TempDir::new().unwrap();
+        let xml = format!(r#"<manifest>
+    <process>
+        <id>a</id>
+        <executable>./a</executable>
+        <route>/a/*</route>
+        <pipe_name>a_pipe</pipe_name>
+    </process>
+    <process>
+        <id>b</id>
+        <executable>./b</executable>
+        <route>/b/*</route>
+        <pipe_name>b_pipe</pipe_name>
+        <working_dir>{}</working_dir>
+    </process>
+</manifest>"#, own_dir.path().display());
+        let mut temp_file = NamedTempFile::new().unwrap();
+        temp_file.write_all(xml.as_bytes()).unwrap();
+
+        let processes = XmlProcessRepository::from_paths([temp_file.path()])
+            .with_default_working_dir(Some(dir.path().to_path_buf()))
+            .load_all()
+            .await
+            .unwrap();
+
+        assert_eq!(processes[0].working_directory, Some(WorkingDirectory::new(dir.path().to_string_lossy())));
+        assert_eq!(processes[1].working_directory, Some(WorkingDirectory::new(own_dir.path().to_string_lossy())));
+
+        // The default is validated too
+        let missing = XmlProcessRepository::from_paths([temp_file.path()])
+            .with_default_working_dir(Some(dir.path().join("missing")))
+            .load_all()
+            .await;
+        assert!(matches!(missing, Err(RepositoryError::NotFound(_))));
+    }
+
+    #[tokio::test]
+    async fn test_load_idle_timeout() {
+        let processes = load(r#"<manifest>
+    <process>
+        <id>a</id>
+        <executable>./a</executable>
+        <route>/a/*</route>
+        <pipe_name>a_pipe</pipe_name>
+        <idle_timeout_ms>60000</idle_timeout_ms>
+    </process>
+    <process>
+        <id>b</id>
+        <executable>./b</executable>
+        <route>/b/*</route>
+        <pipe_name>b_pipe</pipe_name>
+        <idle_timeout_ms>0</idle_timeout_ms>
+    </process>
+</manifest>"#).await.unwrap();
+
+        assert_eq!(processes[0].idle_timeout, Some(Duration::from_secs(60)));
+        assert_eq!(processes[1].idle_timeout, None);
+    }
+
+    #[tokio::test]
+    async fn test_load_health_check() {
+        let processes = load(r#"<manifest>
+    <process>
+        <id>a</id>
+        <executable>./a</executable>
+        <route>/a/*</route>
+        <pipe_name>a_pipe</pipe_name>
+        <health_check path="/healthz" interval_ms="250"/>
+    </process>
+    <process>
+        <id>b</id>
+        <executable>./b</executable>
+        <route>/b/*</route>
+        <pipe_name>b_pipe</pipe_name>
+        <health_check path="/ping"/>
+    </process>
+</manifest>"#).await.unwrap();
+
+        assert_eq!(
+            processes[0].health_check,
+            Some(HealthCheck { path: "/healthz".to_string(), interval: Duration::from_millis(250) })
+        );
+        assert_eq!(processes[1].health_check.as_ref().unwrap().interval, Duration::from_secs(5));
+    }
+
+    #[tokio::test]
+    async fn test_load_protocol() {
+        let processes = load(r#"<manifest>
+    <process>
+        <id>a</id>
+        <executable>./a</executable>
+        <route>/a/*</route>
+        <pipe_name>a_pipe</pipe_name>
+        <protocol>msgpack</protocol>
+    </process>
+    <process>
+        <id>b</id>
+        <executable>./b</executable>
+        <route>/b/*</route>
+        <pipe_name>b_pipe</pipe_name>
+    </process>
+</manifest>"#).await.unwrap();
+
+        assert_eq!(processes[0].protocol, SerializationFormat::MsgPack);
+        assert_eq!(processes[1].protocol, SerializationFormat::Json);
+
+        // HTTP backends are told the format in X-Proxy-Protocol
+        let manifest = |mode: &str| {
+            format!(
+                r#"<manifest>
+    <process>
+        <id>a</id>
+        <executable>./a</executable>
+        <route>/a/*</route>
+        <pipe_name>a_pipe</pipe_name>
+        <communication_mode>{}</communication_mode>
+        <protocol>msgpack</protocol>
+    </process>
+</manifest>"#,
+                mode
+            )
+        };
+        let processes = load(&manifest("http")).await.unwrap();
+        assert_eq!(processes[0].protocol, SerializationFormat::MsgPack);
+        let error = load(&manifest("grpc")).await.unwrap_err();
+        assert!(error.to_string().contains("not supported in grpc communication mode"), "{}", error);
+    }
+
+    #[tokio::test]
+    async fn test_load_raw_protocol() {
+        let processes = load(r#"<manifest>
+    <process>
+        <id>a</id>
+        <executable>./a</executable>
+        <route>/a/*</route>
+        <pipe_name>a_pipe</pipe_name>
+        <communication_mode>http</communication_mode>
+        <protocol>raw</protocol>
+    </process>
+</manifest>"#).await.unwrap();
+        assert_eq!(processes[0].protocol, SerializationFormat::Raw);
+
+        let error = load(r#"<manifest>
+    <process>
+        <id>a</id>
+        <executable>./a</executable>
+        <route>/a/*</route>
+        <pipe_name>a_pipe</pipe_name>
+        <protocol>raw</protocol>
+    </process>
+</manifest>"#).await.unwrap_err();
+        assert!(error.to_string().contains("only supported in http communication mode"), "{}", error);
+    }
+
+    #[tokio::test]
+    async fn test_load_environment() {
+        let processes = load(r#"<manifest>
+    <process>
+        <id>a</id>
+        <executable>./a</executable>
+        <route>/a/*</route>
+        <pipe_name>a_pipe</pipe_name>
+        <env name="LOG_LEVEL" value="debug"/>
+        <env name="EMPTY"/>
+        <clean_env>true</clean_env>
+    </process>
+    <process>
+        <id>b</id>
+        <executable>./b</executable>
+        <route>/b/*</route>
+        <pipe_name>b_pipe</pipe_name>
+    </process>
+</manifest>"#).await.unwrap();
+
+        assert_eq!(
+            processes[0].environment,
+            vec![("LOG_LEVEL".to_string(), "debug".to_string()), ("EMPTY".to_string(), String::new())]
+        );
+        assert!(processes[0].clean_env);
+        assert!(processes[1].environment.is_empty());
+        assert!(!processes[1].clean_env);
+    }
+
+    #[tokio::test]
+    async fn test_load_user_and_group() {
+        let loaded = load(r#"<manifest>
+    <process>
+        <id>a</id>
+        <executable>./a</executable>
+        <route>/a/*</route>
+        <pipe_name>a_pipe</pipe_name>
+        <uid>65534</uid>
+        <gid>65533</gid>
+    </process>
+</manifest>"#).await;
+
+        if cfg!(unix) {
+            let processes = loaded.unwrap();
+            assert_eq!(processes[0].uid, Some(65534));
+            assert_eq!(processes[0].gid, Some(65533));
+        } else {
+            let error = loaded.unwrap_err();
+            assert!(error.to_string().contains("only supported on Unix"), "{}", error);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_load_accepted_content_types() {
+        let processes = load(r#"<manifest>
+    <process>
+        <id>upload</id>
+        <executable>./upload</executable>
+        <route>/upload/*</route>
+        <pipe_name>upload_pipe</pipe_name>
+        <accept_content_type>Multipart/Form-Data</accept_content_type>
+        <accept_content_type>image/*</accept_content_type>
+    </process>
+</manifest>"#).await.unwrap();
+        assert_eq!(processes[0].accepted_content_types, vec!["multipart/form-data", "image/*"]);
+
+        let error = load(r#"<manifest>
+    <process>
+        <id>upload</id>
+        <executable>./upload</executable>
+        <route>/upload/*</route>
+        <pipe_name>upload_pipe</pipe_name>
+        <accept_content_type>text/plain; charset=utf-8</accept_content_type>
+    </process>
+</manifest>"#).await.unwrap_err();
+        assert!(error.to_string().contains("Invalid accept_content_type: 'text/plain; charset=utf-8'"), "{}", error);
+    }
+
+    #[tokio::test]
+    async fn test_address_placeholders_are_kept_in_args() {
+        let processes = load(r#"<manifest>
+    <process>
+        <id>a</id>
+        <executable>./a</executable>
+        <arg>--listen=${ADDRESS}</arg>
+        <arg>--port=${PORT}</arg>
+        <route>/a/*</route>
+        <pipe_name>a_pipe</pipe_name>
+        <communication_mode>http</communication_mode>
+    </process>
+</manifest>"#).await.unwrap();
+        assert_eq!(processes[0].arguments, vec!["--listen=${ADDRESS}", "--port=${PORT}"]);
+
+        // A pipe process doesn't listen on a port
+        let error = load(r#"<manifest>
+    <process>
+        <id>a</id>
+        <executable>./a</executable>
+        <arg>--port=${PORT}</arg>
+        <route>/a/*</route>
+        <pipe_name>a_pipe</pipe_name>
+    </process>
+</manifest>"#).await.unwrap_err();
+        assert!(error.to_string().contains("${PORT} requires communication_mode 'http'"), "{}", error);
+    }
+
+    #[tokio::test]
+    async fn test_load_response_headers() {
+        let processes = load(r#"<manifest>
+    <process>
+        <id>a</id>
+        <executable>./a</executable>
+        <route>/a/*</route>
+        <pipe_name>a_pipe</pipe_name>
+        <response_header name="X-Service">api</response_header>
+        <response_header name="Cache-Control">no-store, max-age=0</response_header>
+    </process>
+</manifest>"#).await.unwrap();
+        assert_eq!(
+            processes[0].response_headers,
+            vec![
+                ("X-Service".to_string(), "api".to_string()),
+                ("Cache-Control".to_string(), "no-store, max-age=0".to_string()),
+            ]
+        );
+
+        let bad_name = load(r#"<manifest>
+    <process>
+        <id>a</id>
+        <executable>./a</executable>
+        <route>/a/*</route>
+        <pipe_name>a_pipe</pipe_name>
+        <response_header name="X Service">api</response_header>
+    </process>
+</manifest>"#).await.unwrap_err();
+        assert!(bad_name.to_string().contains("Invalid response header name: 'X Service'"), "{}", bad_name);
+    }
+
+    #[tokio::test]
+    async fn test_load_negative_cache() {
+        let processes = load(r#"<manifest>
+    <process>
+        <id>a</id>
+        <executable>./a</executable>
+        <route>/a/*</route>
+        <pipe_name>a_pipe</pipe_name>
+        <negative_cache ttl_ms="250" statuses="502, 503"/>
+    </process>
+    <process>
+        <id>b</id>
+        <executable>./b</executable>
+        <route>/b/*</route>
+        <pipe_name>b_pipe</pipe_name>
+        <negative_cache/>
+    </process>
+</manifest>"#).await.unwrap();
+
+        assert_eq!(
+            processes[0].negative_cache,
+            Some(NegativeCachePolicy { ttl: Duration::from_millis(250), server_errors: vec![502, 503] })
+        );
+        assert_eq!(
+            processes[1].negative_cache,
+            Some(NegativeCachePolicy { ttl: Duration::from_secs(5), server_errors: vec![] })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_load_cache_vary() {
+        let processes = load(r#"<manifest>
+    <process>
+        <id>a</id>
+        <executable>./a</executable>
+        <route>/a/*</route>
+        <pipe_name>a_pipe</pipe_name>
+        <cache_vary>Accept, Accept-Language</cache_vary>
+    </process>
+</manifest>"#).await.unwrap();
+
+        assert_eq!(processes[0].cache_vary, vec!["accept", "accept-language"]);
+    }
+
+    #[tokio::test]
+    async fn test_load_warmup() {
+        let xml = r#"<?xml version="1.0" encoding="UTF-8"?>
+<manifest>
+    <process>
+        <id>jit</id>
+        <executable>./jit</executable>
+        <route>/jit/*</route>
+        <pipe_name>jit_pipe</pipe_name>
+        <warmup>GET /healthz</warmup>
+        <warmup>POST  /cache/prime</warmup>
+        <warmup_strict>true</warmup_strict>
+    </process>
+</manifest>"#;
+
+        let processes = load(xml).await.unwrap();
+        assert_eq!(
+            processes[0].warmup,
+            vec![
+                WarmupRequest { method: HttpMethod::Get, path: "/healthz".to_string() },
+                WarmupRequest { method: HttpMethod::Post, path: "/cache/prime".to_string() },
+            ]
+        );
+        assert!(processes[0].warmup_strict);
+
+        let invalid = load(&xml.replace("GET /healthz", "/healthz")).await.unwrap_err();
+        assert!(invalid.to_string().contains("Invalid warmup: '/healthz'"), "{}", invalid);
+    }
+
+    #[tokio::test]
+    async fn test_load_auto_restart() {
+        let xml = r#"<manifest>
+    <process>
+        <id>a</id>
+        <executable>./a</executable>
+        <route>/a/*</route>
+        <pipe_name>a_pipe</pipe_name>
+        <auto_restart backoff_ms="50" max_crashes="3"/>
+    </process>
+    <process>
+        <id>b</id>
+        <executable>./b</executable>
+        <route>/b/*</route>
+        <pipe_name>b_pipe</pipe_name>
+    </process>
+</manifest>"#;
+
+        let processes = load(xml).await.unwrap();
+        assert_eq!(
+            processes[0].restart,
+            Some(RestartPolicy {
+                initial_backoff: Duration::from_millis(50),
+                max_backoff: Duration::from_secs(10),
+                max_crashes: 3,
+                window: Duration::from_secs(60),
+            })
+        );
+        assert_eq!(processes[1].restart, None);
+
+        let inverted = load(&xml.replace(r#"max_crashes="3""#, r#"max_backoff_ms="10""#)).await.unwrap_err();
+        assert!(inverted.to_string().contains("max_backoff_ms must be at least backoff_ms"), "{}", inverted);
+    }
+
+    #[tokio::test]
+    async fn test_load_retry() {
+        let manifest = |retry: &str| {
+            format!(
+                r#"<manifest>
+    <process>
+        <id>a</id>
+        <executable>./a</executable>
+        <route>/a/*</route>
+        <pipe_name>a_pipe</pipe_name>
+        {}
+    </process>
+</manifest>"#,
+                retry
+            )
+        };
+
+        let processes = load(&manifest(r#"<retry methods="post"/>"#)).await.unwrap();
+        assert_eq!(
+            processes[0].retry,
+            Some(RetryPolicy {
+                max_attempts: 3,
+                backoff: Duration::from_millis(100),
+                methods: vec![HttpMethod::Post],
+            })
+        );
+        let processes = load(&manifest(r#"<retry max_attempts="5" backoff_ms="20"/>"#)).await.unwrap();
+        assert_eq!(processes[0].retry.as_ref().unwrap().max_attempts, 5);
+        assert!(processes[0].retry.as_ref().unwrap().methods.is_empty());
+        assert_eq!(load(&manifest("")).await.unwrap()[0].retry, None);
+
+        let never = load(&manifest(r#"<retry max_attempts="0"/>"#)).await.unwrap_err();
+        assert!(never.to_string().contains("max_attempts must be greater than 0"), "{}", never);
+    }
+
+    #[tokio::test]
+    async fn test_load_cache_ignore_query() {
+        let processes = load(r#"<manifest>
+    <process>
+        <id>a</id>
+        <executable>./a</executable>
+        <route>/a/*</route>
+        <pipe_name>a_pipe</pipe_name>
+        <cache_ignore_query>_, utm_Source,</cache_ignore_query>
+    </process>
+</manifest>"#).await.unwrap();
+
+        assert_eq!(processes[0].cache_ignore_query, vec!["_", "utm_Source"]);
+    }
+
     #[tokio::test]
     async fn test_load_invalid_xml() {
         let mut temp_file = NamedTempFile::new().unwrap();
         temp_file.write_all(b"invalid xml").unwrap();
         temp_file.flush().unwrap();
 
-        let repo = XmlProcessRepository::new(temp_file.path());
+        let repo = XmlProcessRepository::from_paths([temp_file.path()]);
         let result = repo.load_all().await;
 
         assert!(result.is_err());
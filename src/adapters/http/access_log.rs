@@ -0,0 +1,192 @@
+//! Access log - one line per request with the method, path, process that
+//! served it, status, request and response sizes and duration
+
+use axum::{
+    extract::{Request, State},
+    middleware::Next,
+    response::Response,
+};
+use axum::body::HttpBody as _;
+use std::time::{Duration, Instant};
+
+/// Log target for access log lines, so they can be filtered separately
+/// (e.g. `RUST_LOG=access_log=info`)
+pub const TARGET: &str = "access_log";
+
+/// How access log lines are written
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AccessLogFormat {
+    /// `GET /api/users 200 512 3.2ms api`, with `-` for unknown fields
+    Plain,
+    /// One JSON object per line
+    Json,
+}
+
+impl std::str::FromStr for AccessLogFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "plain" => Ok(AccessLogFormat::Plain),
+            "json" => Ok(AccessLogFormat::Json),
+            other => Err(format!("invalid access log format '{}', expected 'plain' or 'json'", other)),
+        }
+    }
+}
+
+/// Response extension naming the process a request was routed to
+#[derive(Debug, Clone)]
+pub struct MatchedProcess(pub String);
+
+/// Response extension with the length of the request body the backend was
+/// sent, when it was buffered
+#[derive(Debug, Clone, Copy)]
+pub struct RequestBytes(pub u64);
+
+/// What is logged about one request
+#[derive(Debug)]
+struct AccessLogEntry {
+    method: String,
+    path: String,
+    process: Option<String>,
+    status: u16,
+    /// Size of the request body; `None` when streamed or never read
+    request_bytes: Option<u64>,
+    /// Size of the response body before compression; `None` when streamed
+    bytes: Option<u64>,
+    duration: Duration,
+}
+
+impl AccessLogEntry {
+    fn format(&self, format: AccessLogFormat) -> String {
+        let duration_ms = self.duration.as_secs_f64() * 1000.0;
+        match format {
+            AccessLogFormat::Plain => format!(
+                "{} {} {} {} {} {:.1}ms {}",
+                self.method,
+                self.path,
+                self.status,
+                self.request_bytes.map_or("-".to_string(), |b| b.to_string()),
+                self.bytes.map_or("-".to_string(), |b| b.to_string()),
+                duration_ms,
+                self.process.as_deref().unwrap_or("-"),
+            ),
+            AccessLogFormat::Json => serde_json::json!({
+                "method": self.method,
+                "path": self.path,
+                "process": self.process,
+                "status": self.status,
+                "request_bytes": self.request_bytes,
+                "bytes": self.bytes,
+                "duration_ms": (duration_ms * 10.0).round() / 10.0,
+            })
+            .to_string(),
+        }
+    }
+}
+
+/// Middleware writing an access log line once each response is ready
+pub async fn log_access(State(format): State<AccessLogFormat>, request: Request, next: Next) -> Response {
+    let started = Instant::now();
+    let method = request.method().to_string();
+    let path = request.uri().path().to_string();
+
+    let response = next.run(request).await;
+
+    let entry = AccessLogEntry {
+        method,
+        path,
+        process: response.extensions().get::<MatchedProcess>().map(|p| p.0.clone()),
+        status: response.status().as_u16(),
+        request_bytes: response.extensions().get::<RequestBytes>().map(|b| b.0),
+        bytes: response.body().size_hint().exact(),
+        duration: started.elapsed(),
+    };
+    tracing::info!(target: TARGET, "{}", entry.format(format));
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::adapters::http::{HttpServerState, ServerOptions};
+    use crate::domain::{Executable, PipeName, Process, ProcessId, Route};
+    use crate::test_support::{CapturedLogs, MockPipeCommunicationService};
+    use crate::use_cases::ProxyHttpRequestUseCase;
+    use std::sync::Arc;
+
+    /// The access log entries among `captured`'s lines
+    fn access_lines(captured: &CapturedLogs) -> Vec<String> {
+        captured
+            .lines()
+            .iter()
+            .filter_map(|line| line.split_once("access_log: ").map(|(_, entry)| entry.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_plain_format() {
+        let entry = AccessLogEntry {
+            method: "GET".to_string(),
+            path: "/api/users".to_string(),
+            process: Some("api".to_string()),
+            status: 200,
+            request_bytes: Some(128),
+            bytes: Some(512),
+            duration: Duration::from_micros(3_240),
+        };
+        assert_eq!(entry.format(AccessLogFormat::Plain), "GET /api/users 200 128 512 3.2ms api");
+
+        let unrouted = AccessLogEntry {
+            process: None,
+            request_bytes: None,
+            bytes: None,
+            status: 404,
+            ..entry
+        };
+        assert_eq!(unrouted.format(AccessLogFormat::Plain), "GET /api/users 404 - - 3.2ms -");
+    }
+
+    // The subscriber is thread-local, so the server has to run on this thread
+    #[tokio::test(flavor = "current_thread")]
+    async fn test_proxied_request_is_logged_as_json() {
+        let (captured, _guard) = CapturedLogs::capture();
+
+        let process = Process::new(
+            ProcessId::new("api").unwrap(),
+            Executable::new("./api").unwrap(),
+            Route::new("/api/*").unwrap(),
+            PipeName::new("api_pipe").unwrap(),
+        );
+        let backend = MockPipeCommunicationService::new();
+        backend.respond("POST", "/api/users", 201, "hello");
+        let use_case = ProxyHttpRequestUseCase::new(Arc::new(backend), Arc::new(vec![process]));
+        let options = ServerOptions {
+            access_log: Some(AccessLogFormat::Json),
+            ..ServerOptions::default()
+        };
+        let app = HttpServerState::with_options(Arc::new(use_case), options).create_router();
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let response = reqwest::Client::new()
+            .post(format!("http://{}/api/users", addr))
+            .body("{\"name\":\"ada\"}")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::CREATED);
+
+        let lines = access_lines(&captured);
+        assert_eq!(lines.len(), 1, "{:?}", lines);
+        let entry: serde_json::Value = serde_json::from_str(&lines[0]).unwrap();
+        assert_eq!(entry["method"], "POST");
+        assert_eq!(entry["path"], "/api/users");
+        assert_eq!(entry["process"], "api");
+        assert_eq!(entry["status"], 201);
+        assert_eq!(entry["request_bytes"], 14);
+        assert_eq!(entry["bytes"], 5);
+        assert!(entry["duration_ms"].is_number());
+    }
+}
@@ -0,0 +1,783 @@
+//! Admin endpoints - report on and manage the processes behind the proxy
+//! These are served by the proxy itself and never reach a backend
+
+use super::server::{error_response, json_error, HttpServerState};
+use crate::domain::PipeCommunicationService;
+use axum::extract::{Path, State};
+use axum::http::{header, HeaderMap, HeaderValue, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use serde_json::{json, Value};
+use std::time::UNIX_EPOCH;
+
+/// `GET /_admin/status` - every process with its route, transport and
+/// health, and its lifecycle state and how it last exited when the proxy
+/// manages it
+pub async fn status<P: PipeCommunicationService + Clone>(
+    State(state): State<HttpServerState<P>>,
+) -> Json<Value> {
+    let health = state.use_case.health();
+    let mut processes = Vec::new();
+    for p in state.use_case.processes() {
+        let mut entry = json!({
+            "id": p.id.as_str(),
+            "route": p.route.as_str(),
+            "mode": p.mode_name(),
+            "health": health.get(p.id.as_str()).as_str(),
+        });
+        if let Some(process_state) = state.use_case.state(p).await {
+            entry["state"] = process_state.as_str().into();
+        }
+        if let Some(status) = state.use_case.process_status(p).await {
+            let exited_at = status
+                .last_exit_at
+                .and_then(|at| at.duration_since(UNIX_EPOCH).ok())
+                .map(|since| since.as_secs());
+            entry["last_exit_code"] = status.last_exit_code.into();
+            entry["last_exit_at"] = exited_at.into();
+            entry["last_error"] = status.last_error.into();
+        }
+        processes.push(entry);
+    }
+
+    Json(json!({ "processes": processes }))
+}
+
+/// `GET /_admin/version` - the proxy's version, the commit and time (in
+/// seconds since the epoch) it was built from, and the manifests it loaded
+pub async fn version<P: PipeCommunicationService + Clone>(
+    State(state): State<HttpServerState<P>>,
+) -> Json<Value> {
+    Json(json!({
+        "version": env!("CARGO_PKG_VERSION"),
+        "commit": env!("LOCAL_LAMBDAS_GIT_COMMIT"),
+        "built_at": env!("LOCAL_LAMBDAS_BUILT_AT").parse::<u64>().ok(),
+        "manifests": state.options.manifests,
+    }))
+}
+
+/// `GET /health` - readiness: 200 once every process that isn't started on
+/// demand is running and passing its health check, 503 listing the rest
+/// otherwise
+pub async fn health<P: PipeCommunicationService + Clone>(State(state): State<HttpServerState<P>>) -> Response {
+    let unready: Vec<Value> = state
+        .use_case
+        .unready_processes()
+        .await
+        .into_iter()
+        .map(|(p, reason)| json!({ "process": p.id.as_str(), "reason": reason }))
+        .collect();
+
+    if unready.is_empty() {
+        Json(json!({ "status": "ok" })).into_response()
+    } else {
+        let body = json!({ "status": "unavailable", "unhealthy": unready });
+        (StatusCode::SERVICE_UNAVAILABLE, Json(body)).into_response()
+    }
+}
+
+/// `GET /livez` - liveness: 200 whenever the proxy can answer at all,
+/// whatever the state of its backends
+pub async fn livez() -> Json<Value> {
+    Json(json!({ "status": "ok" }))
+}
+
+/// `GET /_admin/routes` - routes in the order requests are matched against
+/// them, with the process and addresses each one resolves to
+pub async fn routes<P: PipeCommunicationService + Clone>(
+    State(state): State<HttpServerState<P>>,
+) -> Json<Value> {
+    let routes: Vec<Value> = state
+        .use_case
+        .processes()
+        .iter()
+        .enumerate()
+        .map(|(i, p)| {
+            json!({
+                "priority": i + 1,
+                "route": p.route.as_str(),
+                "process": p.id.as_str(),
+                "mode": p.mode_name(),
+                "addresses": state.use_case.addresses(p),
+            })
+        })
+        .collect();
+
+    Json(json!({ "routes": routes }))
+}
+
+/// `POST /_admin/processes/{id}/reload` - restart a process without
+/// dropping requests, answering once the new instances serve its route
+pub async fn reload<P: PipeCommunicationService + Clone>(
+    State(state): State<HttpServerState<P>>,
+    Path(id): Path<String>,
+    headers: HeaderMap,
+) -> Response {
+    if let Some(refused) = refuse_unauthorized(&state.options.admin_token, &headers, state.options.dev_mode) {
+        return refused;
+    }
+    match state.use_case.reload(&id).await {
+        Ok(()) => Json(json!({ "process": id, "status": "reloaded" })).into_response(),
+        Err(e) => {
+            tracing::error!("Reload of '{}' failed: {}", id, e);
+            error_response(e, state.options.dev_mode)
+        }
+    }
+}
+
+/// The answer to a request to an admin endpoint that changes the proxy's
+/// state, such as reloading a process or clearing the cache, if it lacks the
+/// admin token: 403 when none is configured, as the endpoint is disabled, and
+/// 401 when it's missing or wrong
+fn refuse_unauthorized(token: &Option<String>, headers: &HeaderMap, dev_mode: bool) -> Option<Response> {
+    let Some(token) = token else {
+        return Some(json_error(
+            StatusCode::FORBIDDEN,
+            "admin_disabled",
+            "set --admin-token (ADMIN_TOKEN) to enable this endpoint".to_string(),
+            None,
+            dev_mode,
+        ));
+    };
+    let presented = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "));
+    match presented {
+        Some(presented) if constant_time_eq(presented.as_bytes(), token.as_bytes()) => None,
+        _ => {
+            let mut response = json_error(
+                StatusCode::UNAUTHORIZED,
+                "unauthorized",
+                "missing or wrong admin token".to_string(),
+                None,
+                dev_mode,
+            );
+            response
+                .headers_mut()
+                .insert(header::WWW_AUTHENTICATE, HeaderValue::from_static("Bearer"));
+            Some(response)
+        }
+    }
+}
+
+/// Compare tokens in time that only depends on their length, so it doesn't
+/// reveal how much of a guess was right
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+/// `GET /_admin/cache/stats` - how many responses are cached and how often
+/// the cache has answered requests since startup
+pub async fn cache_stats<P: PipeCommunicationService + Clone>(
+    State(state): State<HttpServerState<P>>,
+) -> Json<Value> {
+    match state.use_case.cache_stats().await {
+        Some(stats) => Json(json!({
+            "enabled": true,
+            "entries": stats.entries,
+            "hits": stats.hits,
+            "misses": stats.misses,
+            "evictions": stats.evictions,
+        })),
+        None => Json(json!({ "enabled": false })),
+    }
+}
+
+/// `GET /_admin/metrics` - request and response body sizes per process,
+/// as Prometheus histograms
+pub async fn metrics<P: PipeCommunicationService + Clone>(State(state): State<HttpServerState<P>>) -> Response {
+    let text = state.use_case.payload_metrics().to_prometheus();
+    ([(header::CONTENT_TYPE, "text/plain; version=0.0.4")], text).into_response()
+}
+
+/// `DELETE /_admin/cache` - drop every cached response
+pub async fn clear_cache<P: PipeCommunicationService + Clone>(
+    State(state): State<HttpServerState<P>>,
+    headers: HeaderMap,
+) -> Response {
+    if let Some(refused) = refuse_unauthorized(&state.options.admin_token, &headers, state.options.dev_mode) {
+        return refused;
+    }
+    match state.use_case.clear_cache().await {
+        Some(cleared) => Json(json!({ "enabled": true, "cleared": cleared })).into_response(),
+        None => Json(json!({ "enabled": false, "cleared": 0 })).into_response(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::adapters::http::{HttpServerState, ServerOptions};
+    use crate::domain::{CommunicationError, Executable, HealthCheck, PipeCommunicationService, PipeName, Process, ProcessId, Route};
+    use crate::use_cases::ProxyHttpRequestUseCase;
+    use crate::adapters::TokioProcessOrchestrator;
+    use crate::domain::ProcessOrchestrationService;
+    use async_trait::async_trait;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::{Arc, Mutex};
+    use std::time::{Duration, Instant};
+    use tokio::sync::RwLock;
+
+    #[derive(Clone)]
+    struct NoopService;
+
+    #[async_trait]
+    impl PipeCommunicationService for NoopService {
+        async fn send_request(&self, _address: &str, _request: Vec<u8>) -> Result<Vec<u8>, CommunicationError> {
+            Ok(br#"{"status": 200}"#.to_vec())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_status_lists_processes_with_health() {
+        let mut checked = Process::new(
+            ProcessId::new("api").unwrap(),
+            Executable::new("./api").unwrap(),
+            Route::new("/api/*").unwrap(),
+            PipeName::new("api_pipe").unwrap(),
+        );
+        checked.health_check = Some(HealthCheck {
+            path: "/healthz".to_string(),
+            interval: Duration::from_secs(60),
+        });
+        let plain = Process::new(
+            ProcessId::new("web").unwrap(),
+            Executable::new("./web").unwrap(),
+            Route::new("/*").unwrap(),
+            PipeName::new("web_pipe").unwrap(),
+        );
+        let use_case = ProxyHttpRequestUseCase::new(Arc::new(NoopService), Arc::new(vec![checked, plain]));
+        let app = HttpServerState::new(Arc::new(use_case)).create_router();
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let body: serde_json::Value = reqwest::get(format!("http://{}/_admin/status", addr))
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+
+        assert_eq!(
+            body,
+            serde_json::json!({"processes": [
+                {"id": "api", "route": "/api/*", "mode": "pipe", "health": "starting"},
+                {"id": "web", "route": "/*", "mode": "pipe", "health": "healthy"},
+            ]})
+        );
+    }
+
+    #[tokio::test]
+    async fn test_version_is_reported_without_healthy_backends() {
+        let mut process = Process::new(
+            ProcessId::new("api").unwrap(),
+            Executable::new("./api").unwrap(),
+            Route::new("/api/*").unwrap(),
+            PipeName::new("api_pipe").unwrap(),
+        );
+        process.health_check = Some(HealthCheck {
+            path: "/healthz".to_string(),
+            interval: Duration::from_secs(60),
+        });
+        let use_case = ProxyHttpRequestUseCase::new(Arc::new(NoopService), Arc::new(vec![process]));
+        let options = crate::adapters::ServerOptions {
+            manifests: vec!["manifest.xml".to_string()],
+            ..crate::adapters::ServerOptions::default()
+        };
+        let app = HttpServerState::with_options(Arc::new(use_case), options).create_router();
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let health = reqwest::get(format!("http://{}/health", addr)).await.unwrap();
+        assert_eq!(health.status(), reqwest::StatusCode::SERVICE_UNAVAILABLE);
+        let response = reqwest::get(format!("http://{}/_admin/version", addr)).await.unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::OK);
+        let body: serde_json::Value = response.json().await.unwrap();
+
+        assert_eq!(body["version"], env!("CARGO_PKG_VERSION"));
+        assert!(!body["commit"].as_str().unwrap().is_empty(), "{}", body);
+        assert!(body["built_at"].as_u64().unwrap() > 0, "{}", body);
+        assert_eq!(body["manifests"], serde_json::json!(["manifest.xml"]));
+    }
+
+    #[tokio::test]
+    async fn test_metrics_are_served_as_prometheus_text() {
+        let process = Process::new(
+            ProcessId::new("api").unwrap(),
+            Executable::new("./api").unwrap(),
+            Route::new("/api/*").unwrap(),
+            PipeName::new("api_pipe").unwrap(),
+        );
+        let use_case = ProxyHttpRequestUseCase::new(Arc::new(NoopService), Arc::new(vec![process]));
+        let app = HttpServerState::new(Arc::new(use_case)).create_router();
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let client = reqwest::Client::new();
+        client.post(format!("http://{}/api/x", addr)).body("hello").send().await.unwrap();
+        let response = client.get(format!("http://{}/_admin/metrics", addr)).send().await.unwrap();
+
+        assert!(response.headers()["content-type"].to_str().unwrap().starts_with("text/plain"));
+        let text = response.text().await.unwrap();
+        assert!(text.contains("request_bytes_sum{process=\"api\"} 5\n"), "{}", text);
+        assert!(text.contains("response_bytes_count{process=\"api\"} 1\n"), "{}", text);
+    }
+
+    #[tokio::test]
+    async fn test_status_reports_exit_code_and_spawn_error() {
+        let mut crashed = Process::new(
+            ProcessId::new("crashed").unwrap(),
+            Executable::new("sh").unwrap(),
+            Route::new("/crashed/*").unwrap(),
+            PipeName::new("crashed_pipe").unwrap(),
+        );
+        crashed.arguments = vec!["-c".to_string(), "exit 1".to_string()];
+        let never_started = Process::new(
+            ProcessId::new("missing").unwrap(),
+            Executable::new("./definitely/not/here").unwrap(),
+            Route::new("/missing/*").unwrap(),
+            PipeName::new("missing_pipe").unwrap(),
+        );
+
+        let mut orchestrator = TokioProcessOrchestrator::new();
+        orchestrator.register(crashed.clone());
+        orchestrator.register(never_started.clone());
+        orchestrator.start_process(&crashed.id).await.unwrap();
+        assert!(orchestrator.start_process(&never_started.id).await.is_err());
+        let orchestrator = Arc::new(RwLock::new(orchestrator));
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        let use_case = ProxyHttpRequestUseCase::new(Arc::new(NoopService), Arc::new(vec![crashed, never_started]))
+            .with_orchestrator(orchestrator.clone());
+        let app = HttpServerState::new(Arc::new(use_case)).create_router();
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let body: serde_json::Value = reqwest::get(format!("http://{}/_admin/status", addr))
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+
+        let crashed = &body["processes"][0];
+        assert_eq!(crashed["state"], "failed");
+        assert_eq!(crashed["last_exit_code"], 1);
+        assert!(crashed["last_exit_at"].is_u64());
+        assert_eq!(crashed["last_error"], serde_json::Value::Null);
+
+        let never_started = &body["processes"][1];
+        assert_eq!(never_started["state"], "stopped");
+        assert_eq!(never_started["last_exit_code"], serde_json::Value::Null);
+        assert!(never_started["last_exit_at"].is_u64());
+        assert!(never_started["last_error"].as_str().unwrap().contains("not found"), "{}", never_started);
+
+        orchestrator.write().await.stop_all().await.unwrap();
+    }
+
+    /// Backend whose health check fails while `healthy` is false
+    #[derive(Clone, Default)]
+    struct ToggleHealthService {
+        healthy: Arc<AtomicBool>,
+    }
+
+    #[async_trait]
+    impl PipeCommunicationService for ToggleHealthService {
+        async fn send_request(&self, _address: &str, _request: Vec<u8>) -> Result<Vec<u8>, CommunicationError> {
+            if self.healthy.load(Ordering::SeqCst) {
+                Ok(br#"{"status": 200}"#.to_vec())
+            } else {
+                Ok(br#"{"status": 500}"#.to_vec())
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_health_reflects_backends_and_livez_does_not() {
+        let mut checked = Process::new(
+            ProcessId::new("api").unwrap(),
+            Executable::new("./api").unwrap(),
+            Route::new("/api/*").unwrap(),
+            PipeName::new("api_pipe").unwrap(),
+        );
+        checked.health_check = Some(HealthCheck {
+            path: "/healthz".to_string(),
+            interval: Duration::from_secs(60),
+        });
+        // A catch-all route must not swallow the probe paths
+        let plain = Process::new(
+            ProcessId::new("web").unwrap(),
+            Executable::new("./web").unwrap(),
+            Route::new("/*").unwrap(),
+            PipeName::new("web_pipe").unwrap(),
+        );
+        let service = ToggleHealthService::default();
+        service.healthy.store(true, Ordering::SeqCst);
+        let use_case = Arc::new(ProxyHttpRequestUseCase::new(
+            Arc::new(service.clone()),
+            Arc::new(vec![checked.clone(), plain]),
+        ));
+        let app = HttpServerState::new(use_case.clone()).create_router();
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        // All healthy
+        use_case.check_health(&checked).await;
+        let response = reqwest::get(format!("http://{}/health", addr)).await.unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::OK);
+        let body: serde_json::Value = response.json().await.unwrap();
+        assert_eq!(body, serde_json::json!({"status": "ok"}));
+
+        // One unhealthy
+        service.healthy.store(false, Ordering::SeqCst);
+        use_case.check_health(&checked).await;
+        let response = reqwest::get(format!("http://{}/health", addr)).await.unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::SERVICE_UNAVAILABLE);
+        let body: serde_json::Value = response.json().await.unwrap();
+        assert_eq!(
+            body,
+            serde_json::json!({"status": "unavailable", "unhealthy": [{"process": "api", "reason": "unhealthy"}]})
+        );
+
+        let response = reqwest::get(format!("http://{}/livez", addr)).await.unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::OK);
+    }
+
+    /// Backend that takes a while to answer and records which address
+    /// served each request and when it finished
+    #[derive(Clone, Default)]
+    struct SlowRecordingService {
+        served: Arc<Mutex<Vec<(String, Instant)>>>,
+    }
+
+    #[async_trait]
+    impl PipeCommunicationService for SlowRecordingService {
+        async fn send_request(&self, address: &str, _request: Vec<u8>) -> Result<Vec<u8>, CommunicationError> {
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            self.served.lock().unwrap().push((address.to_string(), Instant::now()));
+            Ok(br#"{"status": 200}"#.to_vec())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_reload_under_load_drops_no_requests() {
+        let mut process = Process::new(
+            ProcessId::new("api").unwrap(),
+            Executable::new("sleep").unwrap(),
+            Route::new("/api/*").unwrap(),
+            PipeName::new("api_pipe").unwrap(),
+        );
+        process.arguments = vec!["30".to_string()];
+        process.health_check = Some(HealthCheck {
+            path: "/healthz".to_string(),
+            interval: Duration::from_secs(1),
+        });
+        let old_address = process.instance_addresses()[0].clone();
+        let new_address = process.for_generation(1).instance_addresses()[0].clone();
+
+        let mut orchestrator = TokioProcessOrchestrator::new();
+        orchestrator.register(process.clone());
+        orchestrator.start_process(&process.id).await.unwrap();
+        let orchestrator = Arc::new(RwLock::new(orchestrator));
+
+        let service = SlowRecordingService::default();
+        let use_case = ProxyHttpRequestUseCase::new(Arc::new(service.clone()), Arc::new(vec![process.clone()]))
+            .with_orchestrator(orchestrator.clone());
+        use_case.check_health(&process).await;
+        let options = ServerOptions {
+            admin_token: Some("s3cret".to_string()),
+            ..ServerOptions::default()
+        };
+        let app = HttpServerState::with_options(Arc::new(use_case), options).create_router();
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        // Several clients sending requests back to back for the whole reload
+        let running = Arc::new(AtomicBool::new(true));
+        let clients: Vec<_> = (0..4)
+            .map(|_| {
+                let running = running.clone();
+                tokio::spawn(async move {
+                    let client = reqwest::Client::new();
+                    let mut statuses = Vec::new();
+                    while running.load(Ordering::Relaxed) {
+                        let response = client.get(format!("http://{}/api/x", addr)).send().await.unwrap();
+                        statuses.push(response.status());
+                    }
+                    statuses
+                })
+            })
+            .collect();
+
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        let response = reqwest::Client::new()
+            .post(format!("http://{}/_admin/processes/api/reload", addr))
+            .bearer_auth("s3cret")
+            .send()
+            .await
+            .unwrap();
+        let reloaded_at = Instant::now();
+        assert_eq!(response.status(), reqwest::StatusCode::OK);
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        running.store(false, Ordering::Relaxed);
+
+        for client in clients {
+            let statuses = client.await.unwrap();
+            assert!(!statuses.is_empty());
+            assert!(statuses.iter().all(|s| *s == reqwest::StatusCode::OK), "{:?}", statuses);
+        }
+
+        // The old instance finished everything sent to it before being stopped
+        let served = service.served.lock().unwrap().clone();
+        assert!(served.iter().any(|(a, _)| *a == old_address));
+        assert!(served.iter().any(|(a, _)| *a == new_address));
+        assert!(served
+            .iter()
+            .filter(|(a, _)| *a == old_address)
+            .all(|(_, finished)| *finished <= reloaded_at));
+
+        let status = orchestrator.read().await.status(&process.id).unwrap();
+        assert!(status.running);
+        assert_eq!(status.restart_count, 0);
+
+        let body: serde_json::Value = reqwest::get(format!("http://{}/_admin/routes", addr))
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        assert_eq!(body["routes"][0]["addresses"], serde_json::json!([new_address]));
+
+        let response = reqwest::Client::new()
+            .post(format!("http://{}/_admin/processes/nope/reload", addr))
+            .bearer_auth("s3cret")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::NOT_FOUND);
+
+        orchestrator.write().await.stop_all().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_reload_requires_the_admin_token() {
+        use tower::Service;
+
+        let process = Process::new(
+            ProcessId::new("api").unwrap(),
+            Executable::new("./api").unwrap(),
+            Route::new("/api/*").unwrap(),
+            PipeName::new("api_pipe").unwrap(),
+        );
+        let router = |admin_token: Option<&str>| {
+            let use_case = ProxyHttpRequestUseCase::new(Arc::new(NoopService), Arc::new(vec![process.clone()]));
+            let options = ServerOptions {
+                admin_token: admin_token.map(String::from),
+                ..ServerOptions::default()
+            };
+            HttpServerState::with_options(Arc::new(use_case), options).create_router()
+        };
+        let reload = |mut router: axum::Router, authorization: Option<&str>| {
+            let mut request = axum::http::Request::post("/_admin/processes/api/reload");
+            if let Some(authorization) = authorization {
+                request = request.header("authorization", authorization);
+            }
+            async move { router.call(request.body(axum::body::Body::empty()).unwrap()).await.unwrap() }
+        };
+
+        // Without a token configured, reloads are disabled
+        let response = reload(router(None), Some("Bearer anything")).await;
+        assert_eq!(response.status(), axum::http::StatusCode::FORBIDDEN);
+
+        for authorization in [None, Some("Bearer wrong"), Some("s3cret"), Some("Basic czNjcmV0")] {
+            let response = reload(router(Some("s3cret")), authorization).await;
+            assert_eq!(response.status(), axum::http::StatusCode::UNAUTHORIZED, "{:?}", authorization);
+            assert_eq!(response.headers()["www-authenticate"], "Bearer");
+        }
+
+        // With the token the reload goes ahead, and fails only because
+        // nothing manages the process
+        let response = reload(router(Some("s3cret")), Some("Bearer s3cret")).await;
+        assert_eq!(response.status(), axum::http::StatusCode::INTERNAL_SERVER_ERROR);
+    }
+
+    #[tokio::test]
+    async fn test_routes_in_match_order() {
+        let mut api = Process::new(
+            ProcessId::new("api").unwrap(),
+            Executable::new("./api").unwrap(),
+            Route::new("/api/*").unwrap(),
+            PipeName::new("api_pipe").unwrap(),
+        );
+        api.communication_mode = crate::domain::CommunicationMode::Http;
+        let web = Process::new(
+            ProcessId::new("web").unwrap(),
+            Executable::new("./web").unwrap(),
+            Route::new("/*").unwrap(),
+            PipeName::new("web_pipe").unwrap(),
+        );
+        let api_addresses = api.instance_addresses();
+        let web_addresses = web.instance_addresses();
+        let use_case = ProxyHttpRequestUseCase::new(Arc::new(NoopService), Arc::new(vec![api, web]));
+        let app = HttpServerState::new(Arc::new(use_case)).create_router();
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let body: serde_json::Value = reqwest::get(format!("http://{}/_admin/routes", addr))
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+
+        assert_eq!(
+            body,
+            serde_json::json!({"routes": [
+                {"priority": 1, "route": "/api/*", "process": "api", "mode": "http", "addresses": api_addresses},
+                {"priority": 2, "route": "/*", "process": "web", "mode": "pipe", "addresses": web_addresses},
+            ]})
+        );
+    }
+
+    /// Serve a router for `use_case` on a free port, with `admin_token` as
+    /// the admin token, returning its address
+    async fn serve(use_case: ProxyHttpRequestUseCase<NoopService>, admin_token: Option<&str>) -> std::net::SocketAddr {
+        let options = ServerOptions {
+            admin_token: admin_token.map(String::from),
+            ..ServerOptions::default()
+        };
+        let app = HttpServerState::with_options(Arc::new(use_case), options).create_router();
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        addr
+    }
+
+    fn api() -> Process {
+        Process::new(
+            ProcessId::new("api").unwrap(),
+            Executable::new("./api").unwrap(),
+            Route::new("/api/*").unwrap(),
+            PipeName::new("api_pipe").unwrap(),
+        )
+    }
+
+    #[tokio::test]
+    async fn test_cache_stats_count_hits_and_misses_until_cleared() {
+        let addr = serve(
+            ProxyHttpRequestUseCase::new_with_cache(Arc::new(NoopService), Arc::new(vec![api()]), Some(10)),
+            Some("s3cret"),
+        )
+        .await;
+        let client = reqwest::Client::new();
+        let stats = || async {
+            let body: serde_json::Value = client
+                .get(format!("http://{}/_admin/cache/stats", addr))
+                .send()
+                .await
+                .unwrap()
+                .json()
+                .await
+                .unwrap();
+            body
+        };
+
+        for path in ["/api/a", "/api/a", "/api/b", "/api/a"] {
+            client.get(format!("http://{}{}", addr, path)).send().await.unwrap();
+        }
+        assert_eq!(
+            stats().await,
+            serde_json::json!({"enabled": true, "entries": 2, "hits": 2, "misses": 2, "evictions": 0})
+        );
+
+        let response = client
+            .delete(format!("http://{}/_admin/cache", addr))
+            .bearer_auth("s3cret")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::OK);
+        let body: serde_json::Value = response.json().await.unwrap();
+        assert_eq!(body, serde_json::json!({"enabled": true, "cleared": 2}));
+
+        // Clearing empties the cache without resetting the counts, and the
+        // next request goes back to the backend
+        client.get(format!("http://{}/api/a", addr)).send().await.unwrap();
+        assert_eq!(
+            stats().await,
+            serde_json::json!({"enabled": true, "entries": 1, "hits": 2, "misses": 3, "evictions": 0})
+        );
+    }
+
+    #[tokio::test]
+    async fn test_cache_endpoints_report_disabled_cache() {
+        let addr = serve(ProxyHttpRequestUseCase::new(Arc::new(NoopService), Arc::new(vec![api()])), Some("s3cret")).await;
+        let client = reqwest::Client::new();
+
+        let response = client.get(format!("http://{}/_admin/cache/stats", addr)).send().await.unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::OK);
+        let body: serde_json::Value = response.json().await.unwrap();
+        assert_eq!(body, serde_json::json!({"enabled": false}));
+
+        let response = client
+            .delete(format!("http://{}/_admin/cache", addr))
+            .bearer_auth("s3cret")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::OK);
+        let body: serde_json::Value = response.json().await.unwrap();
+        assert_eq!(body, serde_json::json!({"enabled": false, "cleared": 0}));
+    }
+
+    #[tokio::test]
+    async fn test_clearing_the_cache_requires_the_admin_token() {
+        let client = reqwest::Client::new();
+        let cached = || ProxyHttpRequestUseCase::new_with_cache(Arc::new(NoopService), Arc::new(vec![api()]), Some(10));
+
+        // Without a token configured, clearing is disabled
+        let addr = serve(cached(), None).await;
+        let response = client
+            .delete(format!("http://{}/_admin/cache", addr))
+            .bearer_auth("anything")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::FORBIDDEN);
+
+        let addr = serve(cached(), Some("s3cret")).await;
+        client.get(format!("http://{}/api/a", addr)).send().await.unwrap();
+        for authorization in [None, Some("Bearer wrong"), Some("s3cret")] {
+            let mut request = client.delete(format!("http://{}/_admin/cache", addr));
+            if let Some(authorization) = authorization {
+                request = request.header("authorization", authorization);
+            }
+            let response = request.send().await.unwrap();
+            assert_eq!(response.status(), reqwest::StatusCode::UNAUTHORIZED, "{:?}", authorization);
+            assert_eq!(response.headers()["www-authenticate"], "Bearer");
+        }
+
+        // Turned away without touching the cache
+        let body: serde_json::Value = client
+            .get(format!("http://{}/_admin/cache/stats", addr))
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        assert_eq!(body["entries"], 1);
+    }
+}
@@ -0,0 +1,188 @@
+//! Serving the connections the proxy accepts, closing those whose client
+//! sits on them without sending a request
+
+use async_trait::async_trait;
+use axum::extract::{ConnectInfo, FromRequestParts};
+use axum::http::request::Parts;
+use axum::Router;
+use hyper_util::rt::{TokioExecutor, TokioIo, TokioTimer};
+use hyper_util::server::conn::auto;
+use hyper_util::server::graceful::Watcher;
+use std::convert::Infallible;
+use std::future::Future;
+use std::io;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio::time::Sleep;
+use tower::Service;
+
+/// Connection builder for every listener
+///
+/// With a `client_timeout`, an HTTP/1 connection is closed when its client
+/// takes longer than that to send a request's headers, whether the first
+/// request or the next one on a kept-alive connection. hyper has no such
+/// timeout for HTTP/2, so an HTTP/2 connection that has sent its first
+/// bytes is only closed by its client or at shutdown.
+pub(super) fn builder(client_timeout: Option<Duration>) -> auto::Builder<TokioExecutor> {
+    let mut builder = auto::Builder::new(TokioExecutor::new());
+    if let Some(timeout) = client_timeout {
+        builder.http1().timer(TokioTimer::new()).header_read_timeout(timeout);
+    }
+    builder
+}
+
+/// Note a failure to accept a connection, pausing before the next attempt
+/// unless only that one connection was at fault
+///
+/// Errors such as running out of file descriptors would otherwise fail
+/// every retry straight away, spinning the accept loop.
+pub(super) async fn accept_failed(e: io::Error) {
+    let connection_error = matches!(
+        e.kind(),
+        io::ErrorKind::ConnectionRefused | io::ErrorKind::ConnectionAborted | io::ErrorKind::ConnectionReset
+    );
+    if connection_error {
+        tracing::debug!("Failed to accept connection: {}", e);
+    } else {
+        tracing::warn!("Failed to accept connection: {}", e);
+        tokio::time::sleep(Duration::from_secs(1)).await;
+    }
+}
+
+/// Marks requests that arrived over TLS, so backends can be told the
+/// client used `https`
+#[derive(Clone, Copy, Debug)]
+struct Secure;
+
+/// What a handler knows of the connection a request came in on
+#[derive(Clone, Copy, Debug)]
+pub(super) struct Client {
+    /// The client's address, when there is one
+    pub(super) peer: Option<SocketAddr>,
+    /// Whether the connection is TLS the proxy terminated
+    pub(super) secure: bool,
+}
+
+impl Client {
+    /// Scheme the client connected with
+    pub(super) fn scheme(&self) -> &'static str {
+        if self.secure {
+            "https"
+        } else {
+            "http"
+        }
+    }
+}
+
+#[async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for Client {
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        Ok(Client {
+            peer: parts.extensions.get::<ConnectInfo<SocketAddr>>().map(|ConnectInfo(peer)| *peer),
+            secure: parts.extensions.get::<Secure>().is_some(),
+        })
+    }
+}
+
+/// Serve `app` on one connection until it closes, telling handlers the
+/// client's address when there is one and whether the connection is `secure`
+pub(super) async fn serve_connection<I>(
+    builder: &auto::Builder<TokioExecutor>,
+    io: I,
+    app: Router,
+    peer: Option<SocketAddr>,
+    secure: bool,
+    watcher: Watcher,
+) where
+    I: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    let service = hyper::service::service_fn(move |mut request: hyper::Request<hyper::body::Incoming>| {
+        if let Some(peer) = peer {
+            request.extensions_mut().insert(ConnectInfo(peer));
+        }
+        if secure {
+            request.extensions_mut().insert(Secure);
+        }
+        // A router is always ready, so it can be called without polling first
+        app.clone().call(request)
+    });
+    // Upgrades are kept so WebSocket passthrough works on every listener
+    let connection = builder.serve_connection_with_upgrades(TokioIo::new(io), service);
+    if let Err(e) = watcher.watch(connection.into_owned()).await {
+        tracing::debug!("Connection error: {}", e);
+    }
+}
+
+/// A stream whose reads fail once `timeout` passes without the client
+/// sending a single byte
+///
+/// This covers the start of a connection, where hyper is still waiting to
+/// see whether the client speaks HTTP/1 or HTTP/2 and its own timeout
+/// doesn't apply yet.
+pub(super) struct FirstByteTimeout<S> {
+    inner: S,
+    /// Cleared by the first byte read
+    deadline: Option<Pin<Box<Sleep>>>,
+}
+
+impl<S> FirstByteTimeout<S> {
+    pub(super) fn new(inner: S, timeout: Option<Duration>) -> Self {
+        Self {
+            inner,
+            deadline: timeout.map(|timeout| Box::pin(tokio::time::sleep(timeout))),
+        }
+    }
+}
+
+impl<S: AsyncRead + Unpin> AsyncRead for FirstByteTimeout<S> {
+    fn poll_read(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<io::Result<()>> {
+        let filled = buf.filled().len();
+        let polled = Pin::new(&mut self.inner).poll_read(cx, buf);
+        match &polled {
+            Poll::Ready(Ok(())) if buf.filled().len() > filled => self.deadline = None,
+            Poll::Pending => {
+                if let Some(deadline) = &mut self.deadline {
+                    if deadline.as_mut().poll(cx).is_ready() {
+                        return Poll::Ready(Err(io::Error::new(
+                            io::ErrorKind::TimedOut,
+                            "client sent nothing before the timeout",
+                        )));
+                    }
+                }
+            }
+            _ => {}
+        }
+        polled
+    }
+}
+
+impl<S: AsyncWrite + Unpin> AsyncWrite for FirstByteTimeout<S> {
+    fn poll_write(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.inner).poll_write(cx, buf)
+    }
+
+    fn poll_write_vectored(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[io::IoSlice<'_>],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.inner).poll_write_vectored(cx, bufs)
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        self.inner.is_write_vectored()
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_shutdown(cx)
+    }
+}
@@ -0,0 +1,251 @@
+//! CORS handling - answers preflight requests and adds `Access-Control-*`
+//! headers in the proxy so backends don't each have to
+
+use super::server::json_error;
+use axum::{
+    extract::{Request, State},
+    http::{header, HeaderName, HeaderValue, Method, StatusCode},
+    middleware::Next,
+    response::Response,
+};
+use tower_http::cors::{AllowHeaders, AllowMethods, AllowOrigin, CorsLayer};
+
+/// Cross-origin settings applied to every route
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CorsOptions {
+    /// Origins allowed to call the proxy; `*` allows any origin
+    pub allowed_origins: Vec<String>,
+    /// Methods allowed in cross-origin requests; `*` allows any method
+    pub allowed_methods: Vec<String>,
+    /// Request headers allowed in cross-origin requests; `*` allows any header
+    pub allowed_headers: Vec<String>,
+    /// Allow cookies and other credentials on cross-origin requests
+    pub allow_credentials: bool,
+}
+
+impl CorsOptions {
+    fn any_origin(&self) -> bool {
+        self.allowed_origins.iter().any(|o| o == "*")
+    }
+
+    /// Whether a request carrying this `Origin` header may proceed
+    pub fn is_origin_allowed(&self, origin: &HeaderValue) -> bool {
+        self.any_origin()
+            || origin
+                .to_str()
+                .is_ok_and(|origin| self.allowed_origins.iter().any(|o| o == origin))
+    }
+
+    /// Build the layer that answers preflight requests and decorates responses
+    ///
+    /// Wildcards can't be combined with credentials, so in that case the
+    /// request's own origin, method or headers are mirrored back instead.
+    pub fn layer(&self) -> CorsLayer {
+        let origin = if self.any_origin() {
+            if self.allow_credentials {
+                AllowOrigin::mirror_request()
+            } else {
+                AllowOrigin::any()
+            }
+        } else {
+            AllowOrigin::list(self.allowed_origins.iter().filter_map(|o| HeaderValue::from_str(o).ok()))
+        };
+
+        let methods = if self.allowed_methods.iter().any(|m| m == "*") {
+            if self.allow_credentials {
+                AllowMethods::mirror_request()
+            } else {
+                AllowMethods::any()
+            }
+        } else {
+            AllowMethods::list(self.allowed_methods.iter().filter_map(|m| m.parse::<Method>().ok()))
+        };
+
+        let headers = if self.allowed_headers.iter().any(|h| h == "*") {
+            if self.allow_credentials {
+                AllowHeaders::mirror_request()
+            } else {
+                AllowHeaders::any()
+            }
+        } else {
+            AllowHeaders::list(self.allowed_headers.iter().filter_map(|h| h.parse::<HeaderName>().ok()))
+        };
+
+        CorsLayer::new()
+            .allow_origin(origin)
+            .allow_methods(methods)
+            .allow_headers(headers)
+            .allow_credentials(self.allow_credentials)
+    }
+}
+
+/// Middleware rejecting requests from origins that aren't allowed, before
+/// they reach the CORS layer or a backend; in dev mode the error names the
+/// origin
+pub async fn reject_disallowed_origin(
+    State((options, dev_mode)): State<(CorsOptions, bool)>,
+    request: Request,
+    next: Next,
+) -> Response {
+    if let Some(origin) = request.headers().get(header::ORIGIN) {
+        if !options.is_origin_allowed(origin) {
+            tracing::debug!("Rejecting request from disallowed origin {:?}", origin);
+            return json_error(
+                StatusCode::FORBIDDEN,
+                "origin_not_allowed",
+                format!("Origin {:?} is not allowed", origin),
+                None,
+                dev_mode,
+            );
+        }
+    }
+
+    next.run(request).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::adapters::http::{HttpServerState, ServerOptions};
+    use crate::domain::{CommunicationError, Executable, PipeCommunicationService, PipeName, Process, ProcessId, Route};
+    use crate::use_cases::ProxyHttpRequestUseCase;
+    use async_trait::async_trait;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    fn options(origins: &[&str]) -> CorsOptions {
+        CorsOptions {
+            allowed_origins: origins.iter().map(|o| o.to_string()).collect(),
+            allowed_methods: vec!["GET".to_string(), "POST".to_string()],
+            allowed_headers: vec!["*".to_string()],
+            allow_credentials: false,
+        }
+    }
+
+    #[test]
+    fn test_origin_list() {
+        let options = options(&["https://app.example.com"]);
+        assert!(options.is_origin_allowed(&HeaderValue::from_static("https://app.example.com")));
+        assert!(!options.is_origin_allowed(&HeaderValue::from_static("https://evil.example.com")));
+    }
+
+    #[test]
+    fn test_wildcard_origin() {
+        let options = options(&["*"]);
+        assert!(options.is_origin_allowed(&HeaderValue::from_static("https://anything.example.com")));
+    }
+
+    #[test]
+    fn test_wildcards_with_credentials_build() {
+        // tower-http panics when wildcards are combined with credentials
+        let mut options = options(&["*"]);
+        options.allowed_methods = vec!["*".to_string()];
+        options.allow_credentials = true;
+        let _ = options.layer();
+    }
+
+    /// Backend that counts how many requests reach it
+    #[derive(Clone, Default)]
+    struct CountingService {
+        calls: Arc<AtomicUsize>,
+    }
+
+    #[async_trait]
+    impl PipeCommunicationService for CountingService {
+        async fn send_request(&self, _address: &str, _request: Vec<u8>) -> Result<Vec<u8>, CommunicationError> {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            Ok(br#"{"status": 200}"#.to_vec())
+        }
+    }
+
+    async fn spawn_proxy(service: CountingService, dev_mode: bool) -> String {
+        let process = Process::new(
+            ProcessId::new("api").unwrap(),
+            Executable::new("./api").unwrap(),
+            Route::new("/api/*").unwrap(),
+            PipeName::new("api_pipe").unwrap(),
+        );
+        let use_case = ProxyHttpRequestUseCase::new(Arc::new(service), Arc::new(vec![process]));
+        let server_options = ServerOptions {
+            cors: Some(options(&["https://app.example.com"])),
+            dev_mode,
+            ..ServerOptions::default()
+        };
+        let app = HttpServerState::with_options(Arc::new(use_case), server_options).create_router();
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        format!("http://{}/api/x", addr)
+    }
+
+    #[tokio::test]
+    async fn test_preflight_is_answered_by_proxy() {
+        let service = CountingService::default();
+        let url = spawn_proxy(service.clone(), false).await;
+
+        let response = reqwest::Client::new()
+            .request(reqwest::Method::OPTIONS, &url)
+            .header("Origin", "https://app.example.com")
+            .header("Access-Control-Request-Method", "POST")
+            .send()
+            .await
+            .unwrap();
+
+        assert!(response.status().is_success());
+        assert_eq!(response.headers()["access-control-allow-origin"], "https://app.example.com");
+        assert_eq!(service.calls.load(Ordering::SeqCst), 0);
+    }
+
+    #[tokio::test]
+    async fn test_allowed_origin_gets_cors_headers() {
+        let service = CountingService::default();
+        let url = spawn_proxy(service.clone(), false).await;
+
+        let response = reqwest::Client::new()
+            .get(&url)
+            .header("Origin", "https://app.example.com")
+            .send()
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), reqwest::StatusCode::OK);
+        assert_eq!(response.headers()["access-control-allow-origin"], "https://app.example.com");
+        assert_eq!(service.calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn test_disallowed_origin_is_forbidden() {
+        let service = CountingService::default();
+        let url = spawn_proxy(service.clone(), false).await;
+
+        let response = reqwest::Client::new()
+            .get(&url)
+            .header("Origin", "https://evil.example.com")
+            .send()
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), reqwest::StatusCode::FORBIDDEN);
+        let body: serde_json::Value = response.json().await.unwrap();
+        assert_eq!(body["error"]["message"], "Forbidden");
+        assert_eq!(service.calls.load(Ordering::SeqCst), 0);
+    }
+
+    #[tokio::test]
+    async fn test_disallowed_origin_is_named_in_dev_mode() {
+        let url = spawn_proxy(CountingService::default(), true).await;
+
+        let response = reqwest::Client::new()
+            .get(&url)
+            .header("Origin", "https://evil.example.com")
+            .send()
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), reqwest::StatusCode::FORBIDDEN);
+        let body: serde_json::Value = response.json().await.unwrap();
+        assert_eq!(body["error"]["code"], "origin_not_allowed");
+        assert_eq!(body["error"]["message"], "Origin \"https://evil.example.com\" is not allowed");
+    }
+}
@@ -0,0 +1,129 @@
+//! gRPC passthrough - forwards calls to gRPC-mode backends over cleartext
+//! HTTP/2 (h2c)
+//! gRPC streams messages both ways and reports each call's outcome in
+//! trailers, neither of which the envelope protocol can carry, so these
+//! requests bypass the proxy use case once the backend has been resolved
+
+use super::server::status_for_error;
+use crate::use_cases::{UpgradeTarget, UseCaseError};
+use axum::body::Body;
+use axum::http::{header, HeaderValue, Request, StatusCode, Uri, Version};
+use axum::response::{IntoResponse, Response};
+use hyper_util::client::legacy::connect::HttpConnector;
+use hyper_util::client::legacy::Client;
+use hyper_util::rt::TokioExecutor;
+
+/// HTTP/2-only client shared by every gRPC call, so calls to a backend
+/// reuse its connection
+pub type GrpcClient = Client<HttpConnector, Body>;
+
+/// `UNAVAILABLE`: the backend couldn't be reached
+const UNAVAILABLE: u16 = 14;
+
+pub fn client() -> GrpcClient {
+    Client::builder(TokioExecutor::new()).http2_only(true).build_http()
+}
+
+/// Forward a gRPC call to `target` and stream its response back, trailers
+/// and all
+pub async fn proxy_grpc(client: &GrpcClient, target: UpgradeTarget, request: Request<Body>, dev_mode: bool) -> Response {
+    let (mut parts, body) = request.into_parts();
+    let path = parts.uri.path_and_query().map(|p| p.as_str()).unwrap_or("/");
+    let url = format!("http://{}{}", target.address, path);
+    tracing::debug!("Proxying gRPC call for '{}' to {}", target.process, url);
+
+    parts.uri = match url.parse::<Uri>() {
+        Ok(uri) => uri,
+        Err(e) => {
+            let detail = format!("Invalid backend address {}: {}", url, e);
+            return status_response(StatusCode::BAD_GATEWAY, &detail, dev_mode);
+        }
+    };
+    // The backend's authority comes from the URI
+    parts.version = Version::HTTP_2;
+    parts.headers.remove(header::HOST);
+
+    match client.request(Request::from_parts(parts, body)).await {
+        Ok(response) => response.map(Body::new),
+        Err(e) => {
+            tracing::error!("gRPC call to '{}' failed: {}", target.process, e);
+            status_response(StatusCode::BAD_GATEWAY, &format!("gRPC call to {} failed: {}", url, e), dev_mode)
+        }
+    }
+}
+
+/// Report a failure to resolve the backend as a gRPC status
+pub fn error_response(error: UseCaseError, dev_mode: bool) -> Response {
+    let (status, _) = status_for_error(&error);
+    status_response(status, &error.to_string(), dev_mode)
+}
+
+/// gRPC status code for the HTTP status the proxy would otherwise answer
+/// with, as gRPC clients map them
+fn grpc_code(status: StatusCode) -> u16 {
+    match status {
+        StatusCode::BAD_REQUEST => 13,
+        StatusCode::UNAUTHORIZED => 16,
+        StatusCode::FORBIDDEN => 7,
+        StatusCode::NOT_FOUND => 12,
+        StatusCode::TOO_MANY_REQUESTS
+        | StatusCode::BAD_GATEWAY
+        | StatusCode::SERVICE_UNAVAILABLE
+        | StatusCode::GATEWAY_TIMEOUT => UNAVAILABLE,
+        _ => 2,
+    }
+}
+
+/// A trailers-only gRPC response standing in for an HTTP error `status`:
+/// 200 with the outcome in `grpc-status` and `grpc-message` headers and no
+/// messages
+fn status_response(status: StatusCode, detail: &str, dev_mode: bool) -> Response {
+    let message = if dev_mode {
+        detail
+    } else {
+        status.canonical_reason().unwrap_or("Error")
+    };
+    let mut response = StatusCode::OK.into_response();
+    let headers = response.headers_mut();
+    headers.insert(header::CONTENT_TYPE, HeaderValue::from_static("application/grpc"));
+    headers.insert("grpc-status", HeaderValue::from(grpc_code(status)));
+    if let Ok(message) = HeaderValue::from_str(&percent_encode(message)) {
+        headers.insert("grpc-message", message);
+    }
+    response
+}
+
+/// Percent-encode a `grpc-message`, which may only hold printable ASCII
+fn percent_encode(message: &str) -> String {
+    message
+        .bytes()
+        .map(|b| match b {
+            b' '..=b'~' if b != b'%' => (b as char).to_string(),
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_grpc_message_is_percent_encoded() {
+        assert_eq!(percent_encode("no route: /x"), "no route: /x");
+        assert_eq!(percent_encode("100% ✓\n"), "100%25 %E2%9C%93%0A");
+    }
+
+    #[test]
+    fn test_failures_map_to_grpc_codes() {
+        let response = error_response(UseCaseError::NoRouteFound("/x".to_string()), true);
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()["content-type"], "application/grpc");
+        assert_eq!(response.headers()["grpc-status"], "12");
+        assert_eq!(response.headers()["grpc-message"], "No route found for path: /x");
+
+        let response = error_response(UseCaseError::ProcessUnavailable("api".to_string()), false);
+        assert_eq!(response.headers()["grpc-status"], "14");
+        assert_eq!(response.headers()["grpc-message"], "Service Unavailable");
+    }
+}
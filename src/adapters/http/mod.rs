@@ -1,3 +1,18 @@
+pub mod access_log;
+mod admin;
+mod connection;
+pub mod cors;
+mod grpc;
+mod request_id;
 pub mod server;
+mod static_files;
+pub mod tcp;
+pub mod tls;
+#[cfg(unix)]
+pub mod unix_socket;
+mod websocket;
 
-pub use server::HttpServerState;
+pub use access_log::AccessLogFormat;
+pub use cors::CorsOptions;
+pub use server::{HttpServerState, ServerOptions, DEFAULT_MAX_BODY_BYTES, DEFAULT_MAX_HEADERS, DEFAULT_MAX_HEADER_BYTES};
+pub use tcp::TcpOptions;
@@ -0,0 +1,127 @@
+//! Request ids - every request carries an `X-Request-Id` that is forwarded to
+//! the backend, echoed in the response and attached to the request's log span
+
+use axum::{
+    extract::Request,
+    http::{HeaderName, HeaderValue},
+    middleware::Next,
+    response::Response,
+};
+use tracing::Span;
+
+pub static X_REQUEST_ID: HeaderName = HeaderName::from_static("x-request-id");
+
+/// Middleware keeping the client's request id, or assigning a new UUID when
+/// it didn't send one, and echoing it back on the response
+///
+/// The id is set on the request before it is converted, so it reaches the
+/// backend along with the other headers.
+pub async fn assign_request_id(mut request: Request, next: Next) -> Response {
+    let id = match request.headers().get(&X_REQUEST_ID) {
+        Some(id) if !id.is_empty() => id.clone(),
+        _ => {
+            let id = HeaderValue::from_str(&uuid::Uuid::new_v4().to_string())
+                .expect("a UUID is a valid header value");
+            request.headers_mut().insert(X_REQUEST_ID.clone(), id.clone());
+            id
+        }
+    };
+
+    let mut response = next.run(request).await;
+    response.headers_mut().insert(X_REQUEST_ID.clone(), id);
+    response
+}
+
+/// Span for one request, tagged with its id so every log line written while
+/// handling it can be correlated
+pub fn request_span<B>(request: &axum::http::Request<B>) -> Span {
+    let id = request
+        .headers()
+        .get(&X_REQUEST_ID)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or("-");
+    tracing::info_span!(
+        "request",
+        method = %request.method(),
+        uri = %request.uri(),
+        request_id = %id,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::adapters::http::HttpServerState;
+    use crate::domain::{CommunicationError, Executable, PipeCommunicationService, PipeName, Process, ProcessId, Route};
+    use crate::use_cases::ProxyHttpRequestUseCase;
+    use async_trait::async_trait;
+    use std::sync::{Arc, Mutex};
+
+    /// Backend that records the request id it was sent
+    #[derive(Clone, Default)]
+    struct IdCapturingService {
+        ids: Arc<Mutex<Vec<String>>>,
+    }
+
+    #[async_trait]
+    impl PipeCommunicationService for IdCapturingService {
+        async fn send_request(&self, _address: &str, request: Vec<u8>) -> Result<Vec<u8>, CommunicationError> {
+            let request: serde_json::Value = serde_json::from_slice(&request).unwrap();
+            let id = request["headers"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .find(|h| h[0] == "x-request-id")
+                .map(|h| h[1].as_str().unwrap().to_string());
+            self.ids.lock().unwrap().extend(id);
+            Ok(br#"{"status": 200}"#.to_vec())
+        }
+    }
+
+    async fn spawn_proxy(service: IdCapturingService) -> String {
+        let process = Process::new(
+            ProcessId::new("api").unwrap(),
+            Executable::new("./api").unwrap(),
+            Route::new("/api/*").unwrap(),
+            PipeName::new("api_pipe").unwrap(),
+        );
+        let use_case = ProxyHttpRequestUseCase::new(Arc::new(service), Arc::new(vec![process]));
+        let app = HttpServerState::new(Arc::new(use_case)).create_router();
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        format!("http://{}", addr)
+    }
+
+    #[tokio::test]
+    async fn test_request_id_is_generated_and_forwarded() {
+        let service = IdCapturingService::default();
+        let base = spawn_proxy(service.clone()).await;
+
+        let response = reqwest::get(format!("{}/api/x", base)).await.unwrap();
+        let id = response.headers()["x-request-id"].to_str().unwrap().to_string();
+
+        assert!(uuid::Uuid::parse_str(&id).is_ok());
+        assert_eq!(*service.ids.lock().unwrap(), vec![id]);
+    }
+
+    #[tokio::test]
+    async fn test_client_request_id_is_kept() {
+        let service = IdCapturingService::default();
+        let base = spawn_proxy(service.clone()).await;
+
+        let response = reqwest::Client::new()
+            .get(format!("{}/api/x", base))
+            .header("X-Request-Id", "abc-123")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.headers()["x-request-id"], "abc-123");
+        assert_eq!(*service.ids.lock().unwrap(), vec!["abc-123".to_string()]);
+
+        // Errors produced by the proxy itself carry the id too
+        let response = reqwest::get(format!("{}/nowhere", base)).await.unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::NOT_FOUND);
+        assert!(response.headers().contains_key("x-request-id"));
+    }
+}
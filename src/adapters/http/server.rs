@@ -1,43 +1,203 @@
 //! HTTP adapter - Axum-based HTTP server controller
 //! This is an interface adapter that translates HTTP requests to use cases
 
-use crate::domain::entities::{HttpRequest, HttpResponse, HttpMethod};
-use crate::use_cases::ProxyHttpRequestUseCase;
-use crate::domain::PipeCommunicationService;
+use crate::domain::entities::{HttpRequest, HttpResponse, HttpMethod, StreamingRequest};
+use crate::use_cases::{ProxyHttpRequestUseCase, RequestTimings, UseCaseError};
+use crate::domain::{PipeCommunicationService, CommunicationError};
+use super::access_log::{log_access, AccessLogFormat, MatchedProcess, RequestBytes};
+use super::admin;
+use super::connection::Client;
+use super::cors::{reject_disallowed_origin, CorsOptions};
+use super::grpc::{self, GrpcClient};
+use super::request_id::{assign_request_id, request_span};
+use super::static_files::serve_static;
+use super::websocket::proxy_websocket;
 use axum::{
     body::Body,
-    extract::State,
-    http::{Method, StatusCode, Uri, HeaderMap},
+    extract::{ws::WebSocketUpgrade, State},
+    http::{header, Method, StatusCode, Uri, HeaderMap, HeaderValue},
     response::{IntoResponse, Response},
-    routing::any,
-    Router,
+    routing::{any, delete, get, post},
+    Json, Router,
 };
+use bytes::Bytes;
+use http_body_util::StreamBody;
+use hyper::body::Frame;
+use std::convert::Infallible;
+use std::net::SocketAddr;
 use std::sync::Arc;
+use tokio::sync::Semaphore;
+use tower_http::compression::predicate::{DefaultPredicate, NotForContentType, Predicate};
+use tower_http::compression::CompressionLayer;
 use tower_http::trace::TraceLayer;
 
+/// Options controlling the behaviour of the HTTP adapter
+#[derive(Debug, Clone)]
+pub struct ServerOptions {
+    /// Include detailed error messages in error responses (development only)
+    pub dev_mode: bool,
+    /// Cross-origin handling; `None` leaves CORS entirely to the backends
+    pub cors: Option<CorsOptions>,
+    /// Largest request body accepted unless the matched process sets its own limit
+    pub max_body_bytes: usize,
+    /// Largest total size of a request's header names and values; larger
+    /// requests get 431
+    pub max_header_bytes: usize,
+    /// Most headers a request may carry; more get 431
+    pub max_headers: usize,
+    /// Add a `Server-Timing` header breaking down where each request's time went
+    pub server_timing: bool,
+    /// Gzip or deflate responses for clients that accept it
+    pub compression: bool,
+    /// Write an access log line for every request, in this format
+    pub access_log: Option<AccessLogFormat>,
+    /// Most requests proxied at once, across every process; more are
+    /// answered with 503. `None` leaves them unbounded
+    pub max_in_flight: Option<usize>,
+    /// Pass requests no route matches to the proxy, which reads their body
+    /// before answering with a JSON 404; when off they get a bare 404 up front
+    pub catch_all: bool,
+    /// Manifests the processes were loaded from, reported by `/_admin/version`
+    pub manifests: Vec<String>,
+    /// Bearer token required by admin endpoints that change the proxy's
+    /// state, reloads and clearing the cache; `None` disables them
+    pub admin_token: Option<String>,
+}
+
+/// Default request body limit (16 MiB)
+pub const DEFAULT_MAX_BODY_BYTES: usize = 16 * 1024 * 1024;
+
+/// Default limit on the total size of request headers (64 KiB)
+pub const DEFAULT_MAX_HEADER_BYTES: usize = 64 * 1024;
+
+/// Default limit on the number of request headers, hyper's own
+pub const DEFAULT_MAX_HEADERS: usize = 100;
+
+impl Default for ServerOptions {
+    fn default() -> Self {
+        Self {
+            dev_mode: false,
+            cors: None,
+            max_body_bytes: DEFAULT_MAX_BODY_BYTES,
+            max_header_bytes: DEFAULT_MAX_HEADER_BYTES,
+            max_headers: DEFAULT_MAX_HEADERS,
+            server_timing: false,
+            compression: true,
+            access_log: None,
+            max_in_flight: None,
+            catch_all: true,
+            manifests: Vec::new(),
+            admin_token: None,
+        }
+    }
+}
+
 /// HTTP server state
 #[derive(Clone)]
 pub struct HttpServerState<P: PipeCommunicationService + Clone> {
-    use_case: Arc<ProxyHttpRequestUseCase<P>>,
+    pub(super) use_case: Arc<ProxyHttpRequestUseCase<P>>,
+    pub(super) options: ServerOptions,
+    grpc_client: GrpcClient,
+    /// One permit per request that may be proxied at once
+    in_flight: Option<Arc<Semaphore>>,
 }
 
 impl<P: PipeCommunicationService + Clone + 'static> HttpServerState<P> {
+    #[allow(dead_code)]
     pub fn new(use_case: Arc<ProxyHttpRequestUseCase<P>>) -> Self {
-        Self { use_case }
+        Self::with_options(use_case, ServerOptions::default())
+    }
+
+    pub fn with_options(use_case: Arc<ProxyHttpRequestUseCase<P>>, options: ServerOptions) -> Self {
+        Self {
+            use_case,
+            in_flight: options.max_in_flight.map(|limit| Arc::new(Semaphore::new(limit))),
+            options,
+            grpc_client: grpc::client(),
+        }
     }
 
     pub fn create_router(self) -> Router {
-        Router::new()
+        let cors = self.options.cors.clone();
+        let mut router = Router::new()
+            .route("/health", get(admin::health::<P>))
+            .route("/livez", get(admin::livez))
+            .route("/_admin/status", get(admin::status::<P>))
+            .route("/_admin/version", get(admin::version::<P>))
+            .route("/_admin/routes", get(admin::routes::<P>))
+            .route("/_admin/cache/stats", get(admin::cache_stats::<P>))
+            .route("/_admin/metrics", get(admin::metrics::<P>))
+            .route("/_admin/cache", delete(admin::clear_cache::<P>))
+            .route("/_admin/processes/:id/reload", post(admin::reload::<P>));
+
+        // Registered after the reserved endpoints above, which take precedence
+        let mut proxy = Router::new()
             .route("/*path", any(proxy_handler::<P>))
-            .fallback(proxy_handler::<P>)
-            .layer(TraceLayer::new_for_http())
+            .fallback(proxy_handler::<P>);
+        if !self.options.catch_all {
+            proxy = proxy.layer(axum::middleware::from_fn_with_state(self.clone(), reject_unrouted::<P>));
+        }
+        router = router.merge(proxy);
+
+        if let Some(cors) = cors {
+            router = router
+                .layer(cors.layer())
+                .layer(axum::middleware::from_fn_with_state((cors, self.options.dev_mode), reject_disallowed_origin));
+        }
+
+        // Inside compression, so logged sizes are known up front
+        if let Some(format) = self.options.access_log {
+            router = router.layer(axum::middleware::from_fn_with_state(format, log_access));
+        }
+
+        if self.options.compression {
+            router = router.layer(compression_layer());
+        }
+
+        // The request id is assigned outside the trace layer so its span can
+        // include it
+        router
+            .layer(TraceLayer::new_for_http().make_span_with(request_span))
+            .layer(axum::middleware::from_fn(assign_request_id))
             .with_state(self)
     }
 }
 
+/// Compress responses according to the client's `Accept-Encoding`
+///
+/// On top of tower-http's defaults (tiny bodies, images, gRPC and event
+/// streams are left alone), formats that are already compressed are skipped.
+fn compression_layer() -> CompressionLayer<impl Predicate> {
+    let predicate = DefaultPredicate::new()
+        .and(NotForContentType::const_new("application/zip"))
+        .and(NotForContentType::const_new("application/gzip"))
+        .and(NotForContentType::const_new("application/x-gzip"))
+        .and(NotForContentType::const_new("audio/"))
+        .and(NotForContentType::const_new("video/"))
+        .and(NotForContentType::const_new("font/woff"));
+
+    CompressionLayer::new().gzip(true).deflate(true).compress_when(predicate)
+}
+
+/// Middleware answering requests that no route matches with a plain 404,
+/// without reading their body
+async fn reject_unrouted<P: PipeCommunicationService + Clone>(
+    State(state): State<HttpServerState<P>>,
+    request: axum::extract::Request,
+    next: axum::middleware::Next,
+) -> Response {
+    if !state.use_case.has_route(request.uri().path()) {
+        tracing::debug!("No route for {} {}", request.method(), request.uri().path());
+        return StatusCode::NOT_FOUND.into_response();
+    }
+    next.run(request).await
+}
+
 /// Handle incoming HTTP requests
 async fn proxy_handler<P: PipeCommunicationService + Clone>(
     State(state): State<HttpServerState<P>>,
+    client: Client,
+    upgrade: Option<WebSocketUpgrade>,
     method: Method,
     uri: Uri,
     headers: HeaderMap,
@@ -45,55 +205,479 @@ async fn proxy_handler<P: PipeCommunicationService + Clone>(
 ) -> Response {
     tracing::debug!("Received {} request for {}", method, uri.path());
 
+    // Held until the response is ready, so a spike can't pile up handlers
+    // without bound
+    let _in_flight = match &state.in_flight {
+        Some(limit) => match limit.try_acquire() {
+            Ok(permit) => Some(permit),
+            Err(_) => {
+                tracing::warn!("Turning away {} {}: too many requests in flight", method, uri.path());
+                return overloaded_response(state.options.dev_mode);
+            }
+        },
+        None => None,
+    };
+
+    if let Err(e) = check_header_limits(&headers, state.options.max_header_bytes, state.options.max_headers) {
+        return conversion_error_response(e, state.options.dev_mode);
+    }
+
+    // Processes sharing a route by method are told apart by it
+    let routed_method = HttpMethod::from_name(method.as_str());
+
+    // WebSocket upgrades to HTTP-mode backends are spliced directly rather
+    // than going through the request/response envelope
+    if let Some(upgrade) = upgrade {
+        if let Some(target) = state.use_case.upgrade_target(&routed_method, uri.path()) {
+            let process = MatchedProcess(target.process.clone());
+            let mut response = proxy_websocket(upgrade, target, uri, headers, state.options.dev_mode).await;
+            response.extensions_mut().insert(process);
+            return response;
+        }
+    }
+
+    // Static routes are answered from their directory, never by a process
+    if let Some(mount) = state.use_case.static_mount(uri.path()) {
+        let process = MatchedProcess(mount.process.clone());
+        let mut request = axum::http::Request::new(body);
+        *request.method_mut() = method;
+        *request.uri_mut() = uri;
+        *request.headers_mut() = headers;
+        let mut response = serve_static(mount, request).await;
+        response.extensions_mut().insert(process);
+        return response;
+    }
+
+    // gRPC calls are forwarded as they are, streams and trailers included
+    if state.use_case.serves_grpc(&routed_method, uri.path()) {
+        let dev_mode = state.options.dev_mode;
+        let target = match state.use_case.grpc_target(uri.path()).await {
+            Ok(target) => target,
+            Err(e) => {
+                tracing::error!("Use case failed: {}", e);
+                let process = e.process().map(|p| MatchedProcess(p.to_string()));
+                let mut response = grpc::error_response(e, dev_mode);
+                if let Some(process) = process {
+                    response.extensions_mut().insert(process);
+                }
+                return response;
+            }
+        };
+        let process = MatchedProcess(target.process.clone());
+        let mut request = axum::http::Request::new(body);
+        *request.method_mut() = method;
+        *request.uri_mut() = uri;
+        *request.headers_mut() = headers;
+        let mut response = grpc::proxy_grpc(&state.grpc_client, target, request, dev_mode).await;
+        response.extensions_mut().insert(process);
+        return response;
+    }
+
+    // Before any of the body is read, so an upload the process won't take
+    // isn't buffered only to be turned away
+    if let Err(e) = check_content_type(&state.use_case, &method, uri.path(), &headers) {
+        return conversion_error_response(e, state.options.dev_mode);
+    }
+
     // Convert Axum types to domain types
-    let domain_request = match convert_to_domain_request(method, uri, headers, body).await {
-        Ok(req) => req,
-        Err(e) => {
-            tracing::error!("Failed to convert request: {}", e);
-            return (StatusCode::BAD_REQUEST, format!("Invalid request: {}", e)).into_response();
+    let is_head = method == Method::HEAD;
+    let peer = client.peer;
+    let scheme = client.scheme();
+    let body_limit = state
+        .use_case
+        .max_body_bytes(&routed_method, uri.path())
+        .unwrap_or(state.options.max_body_bytes);
+
+    // Raw-protocol processes get the body as it arrives instead of buffered
+    let result = if state.use_case.streams_requests(uri.path()) {
+        match convert_to_streaming_request(method, uri, headers, peer, scheme, body, body_limit) {
+            // Only found out once the body has been streamed past the limit
+            Ok(request) => match state.use_case.execute_streaming(request).await {
+                Err(UseCaseError::CommunicationError { source: CommunicationError::PayloadTooLarge(_), .. }) => {
+                    return conversion_error_response(ConversionError::PayloadTooLarge(body_limit), state.options.dev_mode);
+                }
+                result => result,
+            },
+            Err(e) => return conversion_error_response(e, state.options.dev_mode),
+        }
+    } else {
+        match convert_to_domain_request(method, uri, headers, peer, scheme, body, body_limit).await {
+            Ok(request) => state.use_case.execute_timed(request).await,
+            Err(e) => return conversion_error_response(e, state.options.dev_mode),
         }
     };
 
     // Execute use case
-    match state.use_case.execute(domain_request).await {
-        Ok(domain_response) => convert_to_axum_response(domain_response),
+    match result {
+        Ok(timed) => {
+            let mut response = convert_to_axum_response(timed.response, is_head);
+            if state.options.server_timing {
+                if let Ok(value) = HeaderValue::from_str(&server_timing_header(&timed.timings)) {
+                    response.headers_mut().insert("server-timing", value);
+                }
+            }
+            if let Some(process) = timed.process {
+                response.extensions_mut().insert(MatchedProcess(process));
+            }
+            if let Some(bytes) = timed.request_bytes {
+                response.extensions_mut().insert(RequestBytes(bytes));
+            }
+            response
+        }
         Err(e) => {
             tracing::error!("Use case failed: {}", e);
-            let status = match e {
-                crate::use_cases::UseCaseError::NoRouteFound(_) => StatusCode::NOT_FOUND,
-                _ => StatusCode::BAD_GATEWAY,
-            };
-            (status, e.to_string()).into_response()
+            let process = e.process().map(|p| MatchedProcess(p.to_string()));
+            let mut response = error_response(e, state.options.dev_mode);
+            if let Some(process) = process {
+                response.extensions_mut().insert(process);
+            }
+            response
+        }
+    }
+}
+
+/// Response for a request that couldn't be converted to a domain request
+fn conversion_error_response(error: ConversionError, dev_mode: bool) -> Response {
+    match error {
+        e @ ConversionError::PayloadTooLarge(_) => {
+            tracing::warn!("Rejecting request: {}", e);
+            json_error(StatusCode::PAYLOAD_TOO_LARGE, "payload_too_large", e.to_string(), None, dev_mode)
+        }
+        e @ ConversionError::HeadersTooLarge(_) => {
+            tracing::warn!("Rejecting request: {}", e);
+            json_error(
+                StatusCode::REQUEST_HEADER_FIELDS_TOO_LARGE,
+                "request_header_fields_too_large",
+                e.to_string(),
+                None,
+                dev_mode,
+            )
+        }
+        e @ ConversionError::UnsupportedMediaType(_) => {
+            tracing::warn!("Rejecting request: {}", e);
+            json_error(
+                StatusCode::UNSUPPORTED_MEDIA_TYPE,
+                "unsupported_media_type",
+                e.to_string(),
+                None,
+                dev_mode,
+            )
+        }
+        e => {
+            tracing::error!("Failed to convert request: {}", e);
+            json_error(
+                StatusCode::BAD_REQUEST,
+                "invalid_request",
+                format!("Invalid request: {}", e),
+                None,
+                dev_mode,
+            )
+        }
+    }
+}
+
+/// Format timings as a `Server-Timing` header value, e.g.
+/// `serialize;dur=0.3, backend;dur=12.1, deserialize;dur=0.2`
+fn server_timing_header(timings: &RequestTimings) -> String {
+    let mut entries = Vec::new();
+    if timings.cache_hit {
+        entries.push("cache;desc=hit".to_string());
+    }
+    let stages = [
+        ("serialize", timings.serialize),
+        ("backend", timings.backend),
+        ("deserialize", timings.deserialize),
+    ];
+    for (name, duration) in stages {
+        if let Some(duration) = duration {
+            entries.push(format!("{};dur={:.1}", name, duration.as_secs_f64() * 1000.0));
+        }
+    }
+    entries.join(", ")
+}
+
+/// Map a use case failure to a status code and, where the status alone is
+/// ambiguous, a more specific reason phrase
+pub(super) fn status_for_error(error: &UseCaseError) -> (StatusCode, Option<&'static str>) {
+    match error {
+        UseCaseError::NoRouteFound(_) | UseCaseError::ProcessNotFound(_) => (StatusCode::NOT_FOUND, None),
+        UseCaseError::MethodNotAllowed { .. } => (StatusCode::METHOD_NOT_ALLOWED, None),
+        UseCaseError::ProcessUnavailable(_)
+        | UseCaseError::WarmupFailed(_)
+        | UseCaseError::ProcessStarting { .. }
+        | UseCaseError::ConcurrencyLimitReached(_) => (StatusCode::SERVICE_UNAVAILABLE, None),
+        UseCaseError::CommunicationError { source, .. } => match source {
+            CommunicationError::Timeout(_) => (StatusCode::GATEWAY_TIMEOUT, None),
+            CommunicationError::ConnectionFailed(_) => (StatusCode::BAD_GATEWAY, None),
+            CommunicationError::SendFailed(_) => (StatusCode::BAD_GATEWAY, Some("Backend Send Failed")),
+            CommunicationError::ReceiveFailed(_) => (StatusCode::BAD_GATEWAY, Some("Backend Receive Failed")),
+            CommunicationError::PayloadTooLarge(_) => (StatusCode::PAYLOAD_TOO_LARGE, None),
+            CommunicationError::RequestBodyFailed(_) => (StatusCode::BAD_REQUEST, None),
+        },
+        UseCaseError::DeserializationError(_) => (StatusCode::BAD_GATEWAY, None),
+        UseCaseError::SerializationError(_)
+        | UseCaseError::RepositoryError(_)
+        | UseCaseError::OrchestrationError(_) => (StatusCode::INTERNAL_SERVER_ERROR, None),
+    }
+}
+
+/// Machine-readable code identifying the kind of failure
+fn error_code(error: &UseCaseError) -> &'static str {
+    match error {
+        UseCaseError::NoRouteFound(_) => "no_route",
+        UseCaseError::MethodNotAllowed { .. } => "method_not_allowed",
+        UseCaseError::ProcessNotFound(_) => "unknown_process",
+        UseCaseError::ProcessUnavailable(_) | UseCaseError::WarmupFailed(_) => "backend_unhealthy",
+        UseCaseError::ProcessStarting { .. } => "backend_starting",
+        UseCaseError::ConcurrencyLimitReached(_) => "backend_busy",
+        UseCaseError::CommunicationError { source, .. } => match source {
+            CommunicationError::Timeout(_) => "backend_timeout",
+            CommunicationError::ConnectionFailed(_) => "backend_unavailable",
+            CommunicationError::SendFailed(_) => "backend_send_failed",
+            CommunicationError::ReceiveFailed(_) => "backend_receive_failed",
+            CommunicationError::PayloadTooLarge(_) => "payload_too_large",
+            CommunicationError::RequestBodyFailed(_) => "invalid_request",
+        },
+        UseCaseError::SerializationError(_) => "serialization_error",
+        UseCaseError::DeserializationError(_) => "deserialization_error",
+        UseCaseError::RepositoryError(_) | UseCaseError::OrchestrationError(_) => "internal_error",
+    }
+}
+
+/// Build a JSON error envelope:
+/// `{"error": {"code": "...", "message": "...", "process": "..."}}`
+///
+/// The detailed message is only included in dev mode; otherwise the status's
+/// canonical reason is used so internal error strings don't reach clients.
+pub(super) fn json_error(
+    status: StatusCode,
+    code: &str,
+    detail: String,
+    process: Option<&str>,
+    dev_mode: bool,
+) -> Response {
+    let message = if dev_mode {
+        detail
+    } else {
+        status.canonical_reason().unwrap_or("Error").to_string()
+    };
+
+    let mut error = serde_json::json!({
+        "code": code,
+        "message": message,
+    });
+    if let Some(process) = process {
+        error["process"] = serde_json::Value::from(process);
+    }
+
+    (status, Json(serde_json::json!({ "error": error }))).into_response()
+}
+
+/// 503 for a request turned away because the most requests allowed are
+/// already being proxied
+fn overloaded_response(dev_mode: bool) -> Response {
+    let detail = "Too many requests in flight; try again shortly".to_string();
+    json_error(StatusCode::SERVICE_UNAVAILABLE, "proxy_overloaded", detail, None, dev_mode)
+}
+
+/// Convert a use case failure into an HTTP response
+pub(super) fn error_response(error: UseCaseError, dev_mode: bool) -> Response {
+    let (status, reason) = status_for_error(&error);
+    let mut response = json_error(
+        status,
+        error_code(&error),
+        error.to_string(),
+        error.process(),
+        dev_mode,
+    );
+    if let Some(reason) = reason {
+        response
+            .extensions_mut()
+            .insert(hyper::ext::ReasonPhrase::from_static(reason.as_bytes()));
+    }
+    if let UseCaseError::ProcessStarting { retry_after, .. } = error {
+        // Whole seconds, rounded up so clients never retry too early
+        let seconds = retry_after.as_secs() + u64::from(retry_after.subsec_nanos() > 0);
+        response.headers_mut().insert(header::RETRY_AFTER, HeaderValue::from(seconds.max(1)));
+    } else if let UseCaseError::MethodNotAllowed { allowed, .. } = &error {
+        let allowed: Vec<&str> = allowed.iter().map(HttpMethod::as_str).collect();
+        if let Ok(value) = HeaderValue::from_str(&allowed.join(", ")) {
+            response.headers_mut().insert(header::ALLOW, value);
+        }
+    }
+    response
+}
+
+/// Reasons an incoming request can't be turned into a domain request
+#[derive(Debug)]
+enum ConversionError {
+    /// The body is larger than the given limit in bytes
+    PayloadTooLarge(usize),
+    /// The headers are over one of the limits, described
+    HeadersTooLarge(String),
+    /// The matched process doesn't take bodies of the given `Content-Type`
+    UnsupportedMediaType(String),
+    Invalid(String),
+}
+
+impl std::fmt::Display for ConversionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConversionError::PayloadTooLarge(limit) => write!(f, "Request body exceeds {} bytes", limit),
+            ConversionError::HeadersTooLarge(limit) => write!(f, "Request headers exceed {}", limit),
+            ConversionError::UnsupportedMediaType(content_type) => {
+                write!(f, "Content-Type '{}' is not accepted by this route", content_type)
+            }
+            ConversionError::Invalid(msg) => write!(f, "{}", msg),
         }
     }
 }
 
 /// Convert Axum request to domain request
+///
+/// At most `body_limit` bytes of body are read; a larger declared
+/// `Content-Length` is rejected before reading anything.
 async fn convert_to_domain_request(
     method: Method,
     uri: Uri,
     headers: HeaderMap,
+    peer: Option<SocketAddr>,
+    scheme: &str,
     body: Body,
-) -> Result<HttpRequest, String> {
+    body_limit: usize,
+) -> Result<HttpRequest, ConversionError> {
     use axum::body::to_bytes;
+    use std::error::Error as _;
 
-    let body_bytes = to_bytes(body, usize::MAX)
+    check_declared_length(&headers, body_limit)?;
+
+    let body_bytes = to_bytes(body, body_limit)
         .await
-        .map_err(|e| format!("Failed to read body: {}", e))?
+        .map_err(|e| {
+            if e.source().is_some_and(|cause| cause.is::<http_body_util::LengthLimitError>()) {
+                ConversionError::PayloadTooLarge(body_limit)
+            } else {
+                ConversionError::Invalid(format!("Failed to read body: {}", e))
+            }
+        })?
         .to_vec();
 
-    let domain_method = match method {
-        Method::GET => HttpMethod::Get,
-        Method::POST => HttpMethod::Post,
-        Method::PUT => HttpMethod::Put,
-        Method::DELETE => HttpMethod::Delete,
-        Method::PATCH => HttpMethod::Patch,
-        Method::HEAD => HttpMethod::Head,
-        Method::OPTIONS => HttpMethod::Options,
-        _ => return Err(format!("Unsupported method: {}", method)),
+    let (method, headers) = convert_request_head(method, headers, peer, scheme);
+    Ok(HttpRequest {
+        method,
+        path: request_target(&uri),
+        headers,
+        body: body_bytes,
+    })
+}
+
+/// Like [`convert_to_domain_request`], but leaving the body to be read as it
+/// is sent on
+///
+/// A body that turns out to exceed `body_limit` fails the stream part way.
+fn convert_to_streaming_request(
+    method: Method,
+    uri: Uri,
+    headers: HeaderMap,
+    peer: Option<SocketAddr>,
+    scheme: &str,
+    body: Body,
+    body_limit: usize,
+) -> Result<StreamingRequest, ConversionError> {
+    use futures_util::TryStreamExt;
+
+    check_declared_length(&headers, body_limit)?;
+
+    let body = Body::new(http_body_util::Limited::new(body, body_limit))
+        .into_data_stream()
+        .map_err(std::io::Error::other);
+    let (method, headers) = convert_request_head(method, headers, peer, scheme);
+    Ok(StreamingRequest {
+        method,
+        path: request_target(&uri),
+        headers,
+        body: Box::pin(body),
+    })
+}
+
+/// Path and query of `uri`, as the backend is asked for them
+fn request_target(uri: &Uri) -> String {
+    uri.path_and_query().map_or(uri.path(), |target| target.as_str()).to_string()
+}
+
+/// Reject a request up front when its `Content-Length` is over the limit
+fn check_declared_length(headers: &HeaderMap, body_limit: usize) -> Result<(), ConversionError> {
+    let declared_length = headers
+        .get(axum::http::header::CONTENT_LENGTH)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse::<usize>().ok());
+    if declared_length.is_some_and(|length| length > body_limit) {
+        return Err(ConversionError::PayloadTooLarge(body_limit));
+    }
+    Ok(())
+}
+
+/// Reject a request carrying more headers, or more header bytes, than allowed
+///
+/// Names and values are counted as received, before any are copied into the
+/// domain request.
+fn check_header_limits(headers: &HeaderMap, max_bytes: usize, max_count: usize) -> Result<(), ConversionError> {
+    if headers.len() > max_count {
+        return Err(ConversionError::HeadersTooLarge(format!("{} headers", max_count)));
+    }
+    let total: usize = headers.iter().map(|(name, value)| name.as_str().len() + value.len()).sum();
+    if total > max_bytes {
+        return Err(ConversionError::HeadersTooLarge(format!("{} bytes", max_bytes)));
+    }
+    Ok(())
+}
+
+/// Reject a body the process a `method` request for `path` goes to doesn't
+/// take, going by its `Content-Type`
+///
+/// A request without one is let through unless it declares a body, as a
+/// plain `GET` or `DELETE` doesn't. One that isn't text is malformed.
+fn check_content_type<P: PipeCommunicationService>(
+    use_case: &ProxyHttpRequestUseCase<P>,
+    method: &Method,
+    path: &str,
+    headers: &HeaderMap,
+) -> Result<(), ConversionError> {
+    let content_type = match headers.get(header::CONTENT_TYPE) {
+        Some(value) => value
+            .to_str()
+            .map_err(|_| ConversionError::Invalid("Content-Type header is not valid ASCII".to_string()))?,
+        None => {
+            let declares_body = headers.contains_key(header::TRANSFER_ENCODING)
+                || headers
+                    .get(header::CONTENT_LENGTH)
+                    .and_then(|v| v.to_str().ok())
+                    .is_some_and(|v| v.trim() != "0");
+            if !declares_body {
+                return Ok(());
+            }
+            ""
+        }
     };
+    if use_case.accepts_content_type(&HttpMethod::from_name(method.as_str()), path, content_type) {
+        Ok(())
+    } else if content_type.is_empty() {
+        Err(ConversionError::UnsupportedMediaType("(none)".to_string()))
+    } else {
+        Err(ConversionError::UnsupportedMediaType(content_type.to_string()))
+    }
+}
 
-    let domain_headers = headers
+/// Convert the method and headers of a request, adding forwarding headers
+fn convert_request_head(
+    method: Method,
+    headers: HeaderMap,
+    peer: Option<SocketAddr>,
+    scheme: &str,
+) -> (HttpMethod, Vec<(String, String)>) {
+    let domain_method = HttpMethod::from_name(method.as_str());
+
+    let mut domain_headers = headers
         .iter()
         .filter_map(|(k, v)| {
             v.to_str()
@@ -101,28 +685,1153 @@ async fn convert_to_domain_request(
                 .map(|v| (k.as_str().to_string(), v.to_string()))
         })
         .collect();
+    add_forwarded_headers(&mut domain_headers, peer, scheme);
+    (domain_method, domain_headers)
+}
 
-    Ok(HttpRequest {
-        method: domain_method,
-        path: uri.path().to_string(),
-        headers: domain_headers,
-        body: body_bytes,
-    })
+/// Tell the backend who the original client was
+///
+/// The peer address is appended to any `X-Forwarded-For` chain set by
+/// upstream proxies. `X-Forwarded-Proto` (the `scheme` the client connected
+/// with) and `X-Forwarded-Host` are only set when no upstream proxy has
+/// already recorded them.
+fn add_forwarded_headers(headers: &mut Vec<(String, String)>, peer: Option<SocketAddr>, scheme: &str) {
+    let has = |headers: &Vec<(String, String)>, name: &str| {
+        headers.iter().any(|(k, _)| k.eq_ignore_ascii_case(name))
+    };
+
+    if let Some(peer) = peer {
+        let mut chain: Vec<String> = headers
+            .iter()
+            .filter(|(k, _)| k.eq_ignore_ascii_case("x-forwarded-for"))
+            .map(|(_, v)| v.clone())
+            .collect();
+        chain.push(peer.ip().to_string());
+        headers.retain(|(k, _)| !k.eq_ignore_ascii_case("x-forwarded-for"));
+        headers.push(("x-forwarded-for".to_string(), chain.join(", ")));
+    }
+
+    if !has(headers, "x-forwarded-proto") {
+        headers.push(("x-forwarded-proto".to_string(), scheme.to_string()));
+    }
+
+    if !has(headers, "x-forwarded-host") {
+        let host = headers
+            .iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case("host"))
+            .map(|(_, v)| v.clone());
+        if let Some(host) = host {
+            headers.push(("x-forwarded-host".to_string(), host));
+        }
+    }
 }
 
 /// Convert domain response to Axum response
-fn convert_to_axum_response(domain_response: HttpResponse) -> Response {
-    let mut response_builder = Response::builder()
-        .status(StatusCode::from_u16(domain_response.status_code).unwrap_or(StatusCode::OK));
+///
+/// The backend's `Content-Length` is dropped so the length sent is always
+/// that of the decoded body; a stale one would leave clients waiting for
+/// bytes that never come, or cut the body short. Responses to HEAD have no
+/// body, so there the backend's value is the only one and is kept, as it is
+/// for `304 Not Modified`, where it describes the unchanged resource.
+/// Statuses that can't have a body (1xx, 204 and 304) lose any the backend
+/// sent, and 1xx and 204 responses never carry a `Content-Length`.
+fn convert_to_axum_response(domain_response: HttpResponse, is_head: bool) -> Response {
+    let status = StatusCode::from_u16(domain_response.status_code).unwrap_or(StatusCode::OK);
+    let forbids_length = status.is_informational() || status == StatusCode::NO_CONTENT;
+    let keeps_length = (is_head || status == StatusCode::NOT_MODIFIED) && !forbids_length;
+    let mut response_builder = Response::builder().status(status);
 
     for (key, value) in domain_response.headers {
+        if !keeps_length && key.eq_ignore_ascii_case("content-length") {
+            continue;
+        }
         response_builder = response_builder.header(key, value);
     }
 
+    let body = if forbids_length || status == StatusCode::NOT_MODIFIED {
+        if !domain_response.body.is_empty() {
+            tracing::debug!("Dropping {}-byte body of a {} response", domain_response.body.len(), status);
+        }
+        Body::empty()
+    } else if domain_response.trailers.is_empty() || is_head {
+        Body::from(domain_response.body)
+    } else {
+        body_with_trailers(domain_response.body, &domain_response.trailers)
+    };
+
     response_builder
-        .body(Body::from(domain_response.body))
+        .body(body)
         .unwrap_or_else(|e| {
             tracing::error!("Failed to build response: {}", e);
-            (StatusCode::INTERNAL_SERVER_ERROR, "Internal server error").into_response()
+            json_error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "internal_error",
+                format!("Failed to build response: {}", e),
+                None,
+                false,
+            )
         })
 }
+
+/// A body of `data` followed by `trailers`; ones that aren't valid headers
+/// are dropped
+fn body_with_trailers(data: Vec<u8>, trailers: &[(String, String)]) -> Body {
+    let trailers: HeaderMap = trailers
+        .iter()
+        .filter_map(|(name, value)| {
+            let name = header::HeaderName::from_bytes(name.as_bytes()).ok()?;
+            Some((name, HeaderValue::from_str(value).ok()?))
+        })
+        .collect();
+    let frames = [Frame::data(Bytes::from(data)), Frame::trailers(trailers)];
+    Body::new(StreamBody::new(futures_util::stream::iter(frames.map(Ok::<_, Infallible>))))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn communication_error(source: CommunicationError) -> UseCaseError {
+        UseCaseError::CommunicationError {
+            process: "api".to_string(),
+            source,
+        }
+    }
+
+    fn reason_of(response: &Response) -> Option<&[u8]> {
+        response
+            .extensions()
+            .get::<hyper::ext::ReasonPhrase>()
+            .map(|r| r.as_bytes())
+    }
+
+    async fn json_body(response: Response) -> serde_json::Value {
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        serde_json::from_slice(&bytes).unwrap()
+    }
+
+    #[test]
+    fn test_no_route_maps_to_not_found() {
+        let response = error_response(UseCaseError::NoRouteFound("/missing".to_string()), false);
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        assert!(reason_of(&response).is_none());
+    }
+
+    #[test]
+    fn test_timeout_maps_to_gateway_timeout() {
+        let error = communication_error(CommunicationError::Timeout("slow".to_string()));
+        let response = error_response(error, false);
+        assert_eq!(response.status(), StatusCode::GATEWAY_TIMEOUT);
+    }
+
+    #[test]
+    fn test_connection_failed_maps_to_bad_gateway() {
+        let error = communication_error(CommunicationError::ConnectionFailed("refused".to_string()));
+        let response = error_response(error, false);
+        assert_eq!(response.status(), StatusCode::BAD_GATEWAY);
+        assert!(reason_of(&response).is_none());
+    }
+
+    #[test]
+    fn test_send_and_receive_failures_have_distinct_reasons() {
+        let send = error_response(
+            communication_error(CommunicationError::SendFailed("broken pipe".to_string())),
+            false,
+        );
+        let receive = error_response(
+            communication_error(CommunicationError::ReceiveFailed("reset".to_string())),
+            false,
+        );
+
+        assert_eq!(send.status(), StatusCode::BAD_GATEWAY);
+        assert_eq!(receive.status(), StatusCode::BAD_GATEWAY);
+        assert_eq!(reason_of(&send), Some(&b"Backend Send Failed"[..]));
+        assert_eq!(reason_of(&receive), Some(&b"Backend Receive Failed"[..]));
+    }
+
+    #[tokio::test]
+    async fn test_concurrency_limit_maps_to_service_unavailable() {
+        let response = error_response(UseCaseError::ConcurrencyLimitReached("api".to_string()), false);
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+
+        let body = json_body(response).await;
+        assert_eq!(body["error"]["code"], "backend_busy");
+        assert_eq!(body["error"]["process"], "api");
+    }
+
+    #[test]
+    fn test_server_timing_header() {
+        use std::time::Duration;
+
+        let timings = RequestTimings {
+            cache_hit: false,
+            serialize: Some(Duration::from_micros(300)),
+            backend: Some(Duration::from_micros(12_140)),
+            deserialize: Some(Duration::from_micros(200)),
+        };
+        assert_eq!(
+            server_timing_header(&timings),
+            "serialize;dur=0.3, backend;dur=12.1, deserialize;dur=0.2"
+        );
+
+        let hit = RequestTimings { cache_hit: true, ..RequestTimings::default() };
+        assert_eq!(server_timing_header(&hit), "cache;desc=hit");
+    }
+
+    #[tokio::test]
+    async fn test_unhealthy_process_maps_to_service_unavailable() {
+        let response = error_response(UseCaseError::ProcessUnavailable("api".to_string()), false);
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+
+        let body = json_body(response).await;
+        assert_eq!(body["error"]["code"], "backend_unhealthy");
+        assert_eq!(body["error"]["process"], "api");
+    }
+
+    #[tokio::test]
+    async fn test_starting_process_answers_with_retry_after() {
+        use crate::adapters::TokioProcessOrchestrator;
+        use crate::domain::{Executable, HealthCheck, PipeName, Process, ProcessId, ProcessOrchestrationService, Route};
+        use crate::use_cases::ProxyOptions;
+        use std::time::Duration;
+        use tokio::sync::RwLock;
+
+        let mut process = Process::new(
+            ProcessId::new("api").unwrap(),
+            Executable::new("sleep").unwrap(),
+            Route::new("/api/*").unwrap(),
+            PipeName::new("api_pipe").unwrap(),
+        );
+        process.arguments = vec!["30".to_string()];
+        process.health_check = Some(HealthCheck {
+            path: "/healthz".to_string(),
+            interval: Duration::from_secs(60),
+        });
+        let mut orchestrator = TokioProcessOrchestrator::new();
+        orchestrator.register(process.clone());
+        orchestrator.start_process(&process.id).await.unwrap();
+        let orchestrator = Arc::new(RwLock::new(orchestrator));
+
+        let options = ProxyOptions {
+            starting_retry_after: Some(Duration::from_millis(1500)),
+            ..ProxyOptions::default()
+        };
+        let use_case = ProxyHttpRequestUseCase::with_options(Arc::new(CapturingService::default()), Arc::new(vec![process]), options)
+            .with_orchestrator(orchestrator.clone());
+        let app = HttpServerState::new(Arc::new(use_case)).create_router();
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        // Still within the startup window: the health check hasn't passed yet
+        let response = reqwest::get(format!("http://{}/api/x", addr)).await.unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(response.headers()["retry-after"], "2");
+        let body: serde_json::Value = response.json().await.unwrap();
+        assert_eq!(body["error"]["code"], "backend_starting");
+
+        orchestrator.write().await.stop_all().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_requests_over_global_limit_get_service_unavailable() {
+        use crate::domain::{Executable, PipeName, Process, ProcessId, Route};
+        use crate::test_support::MockPipeCommunicationService;
+        use tower::Service;
+
+        let process = Process::new(
+            ProcessId::new("api").unwrap(),
+            Executable::new("./api").unwrap(),
+            Route::new("/api/*").unwrap(),
+            PipeName::new("api_pipe").unwrap(),
+        );
+        let backend = MockPipeCommunicationService::new();
+        backend.respond("GET", "/api/a", 200, "");
+        backend.respond("GET", "/api/b", 200, "");
+        backend.respond("GET", "/api/c", 200, "");
+        let gate = backend.gate();
+        let use_case = ProxyHttpRequestUseCase::new(Arc::new(backend), Arc::new(vec![process]));
+        let options = ServerOptions {
+            max_in_flight: Some(2),
+            ..ServerOptions::default()
+        };
+        let router = HttpServerState::with_options(Arc::new(use_case), options).create_router();
+        let get = |path: &str| {
+            let request = axum::http::Request::get(path).body(Body::empty()).unwrap();
+            router.clone().call(request)
+        };
+
+        let held = [tokio::spawn(get("/api/a")), tokio::spawn(get("/api/b"))];
+        gate.arrived(2).await;
+
+        let response = get("/api/c").await.unwrap();
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(json_body(response).await["error"]["code"], "proxy_overloaded");
+        // The proxy's own endpoints aren't limited
+        assert_eq!(get("/livez").await.unwrap().status(), StatusCode::OK);
+
+        // The requests in flight still complete, and free their places
+        gate.release(3);
+        for request in held {
+            assert_eq!(request.await.unwrap().unwrap().status(), StatusCode::OK);
+        }
+        assert_eq!(get("/api/c").await.unwrap().status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_disallowed_method_answers_405_with_allow() {
+        use crate::domain::{Executable, PipeName, Process, ProcessId, Route};
+        use tower::Service;
+
+        let mut reads = Process::new(
+            ProcessId::new("reads").unwrap(),
+            Executable::new("./reads").unwrap(),
+            Route::new("/api/*").unwrap(),
+            PipeName::new("reads_pipe").unwrap(),
+        );
+        reads.methods = vec![HttpMethod::Get, HttpMethod::Head];
+        let mut writes = Process::new(
+            ProcessId::new("writes").unwrap(),
+            Executable::new("./writes").unwrap(),
+            Route::new("/api/*").unwrap(),
+            PipeName::new("writes_pipe").unwrap(),
+        );
+        writes.methods = vec![HttpMethod::Post];
+        let service = CapturingService::default();
+        let use_case = ProxyHttpRequestUseCase::new(Arc::new(service.clone()), Arc::new(vec![reads, writes]));
+        let router = HttpServerState::new(Arc::new(use_case)).create_router();
+
+        let request = axum::http::Request::delete("/api/users/7").body(Body::empty()).unwrap();
+        let response = router.clone().call(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::METHOD_NOT_ALLOWED);
+        assert_eq!(response.headers()[header::ALLOW], "GET, HEAD, POST");
+        assert_eq!(json_body(response).await["error"]["code"], "method_not_allowed");
+        assert!(service.last_request.lock().unwrap().is_none());
+
+        // Each allowed method reaches the process serving it
+        for (method, process) in [("POST", "writes"), ("GET", "reads")] {
+            let request = axum::http::Request::builder()
+                .method(method)
+                .uri("/api/users")
+                .body(Body::empty())
+                .unwrap();
+            let response = router.clone().call(request).await.unwrap();
+            assert_eq!(response.status(), StatusCode::OK);
+            assert_eq!(response.extensions().get::<MatchedProcess>().unwrap().0, process);
+        }
+
+        let request = axum::http::Request::delete("/other").body(Body::empty()).unwrap();
+        let response = router.clone().call(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        assert!(response.headers().get(header::ALLOW).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_unrouted_request_without_catch_all_is_rejected_unread() {
+        use crate::domain::{Executable, PipeName, Process, ProcessId, Route};
+        use std::sync::atomic::{AtomicBool, Ordering};
+        use tower::Service;
+
+        let process = Process::new(
+            ProcessId::new("api").unwrap(),
+            Executable::new("./api").unwrap(),
+            Route::new("/api/*").unwrap(),
+            PipeName::new("api_pipe").unwrap(),
+        );
+        let service = CapturingService::default();
+        let use_case = ProxyHttpRequestUseCase::new(Arc::new(service.clone()), Arc::new(vec![process]));
+        let options = ServerOptions {
+            catch_all: false,
+            ..ServerOptions::default()
+        };
+        let router = HttpServerState::with_options(Arc::new(use_case), options).create_router();
+
+        let read = Arc::new(AtomicBool::new(false));
+        let body = {
+            let read = read.clone();
+            futures_util::stream::once(async move {
+                read.store(true, Ordering::SeqCst);
+                Ok::<_, Infallible>(Bytes::from_static(b"payload"))
+            })
+        };
+        let request = axum::http::Request::post("/wp-login.php").body(Body::from_stream(body)).unwrap();
+        let response = router.clone().call(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        assert!(!read.load(Ordering::SeqCst), "the body was read");
+        assert!(service.last_request.lock().unwrap().is_none());
+
+        // Routed paths and the proxy's own endpoints are unaffected
+        let request = axum::http::Request::post("/api/x").body(Body::from("payload")).unwrap();
+        assert_eq!(router.clone().call(request).await.unwrap().status(), StatusCode::OK);
+        let request = axum::http::Request::get("/livez").body(Body::empty()).unwrap();
+        assert_eq!(router.clone().call(request).await.unwrap().status(), StatusCode::OK);
+    }
+
+    /// Route `/slow/*` to a backend that never answers, send it a request
+    /// and abort the client once the backend has it, returning what the
+    /// backend reads from its connection afterwards
+    async fn read_after_client_disconnects<S>(
+        mode: crate::domain::CommunicationMode,
+        pipe_name: &str,
+        accept: impl std::future::Future<Output = S> + Send + 'static,
+    ) -> std::io::Result<usize>
+    where
+        S: tokio::io::AsyncRead + Unpin + Send + 'static,
+    {
+        use crate::domain::{Executable, PipeName, Process, ProcessId, Route};
+        use crate::infrastructure::{HttpClient, NamedPipeClient};
+        use std::time::Duration;
+        use tokio::io::AsyncReadExt;
+
+        let (received, request_seen) = tokio::sync::oneshot::channel();
+        let backend = tokio::spawn(async move {
+            let mut stream = accept.await;
+            let mut request = [0u8; 4096];
+            let _ = stream.read(&mut request).await?;
+            received.send(()).unwrap();
+            // Never answer; only the proxy closing the connection ends this
+            stream.read(&mut request).await
+        });
+
+        let mut process = Process::new(
+            ProcessId::new("slow").unwrap(),
+            Executable::new("./slow").unwrap(),
+            Route::new("/slow/*").unwrap(),
+            PipeName::new(pipe_name).unwrap(),
+        );
+        process.communication_mode = mode;
+        let use_case = ProxyHttpRequestUseCase::new(Arc::new(NamedPipeClient::new()), Arc::new(vec![process]))
+            .with_http_service(Arc::new(HttpClient::new()));
+        let app = HttpServerState::new(Arc::new(use_case)).create_router();
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let client = tokio::spawn(reqwest::get(format!("http://{}/slow/work", addr)));
+        request_seen.await.unwrap();
+        client.abort();
+
+        tokio::time::timeout(Duration::from_secs(5), backend)
+            .await
+            .expect("backend connection was left open after the client went away")
+            .unwrap()
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_client_disconnect_closes_pipe_connection() {
+        let pipe_name = format!("disconnect_test_{}", std::process::id());
+        let path = crate::domain::utils::get_pipe_address_from_name(&pipe_name);
+        let _ = std::fs::remove_file(&path);
+        let listener = tokio::net::UnixListener::bind(&path).unwrap();
+
+        let read = read_after_client_disconnects(crate::domain::CommunicationMode::Pipe, &pipe_name, async move {
+            listener.accept().await.unwrap().0
+        })
+        .await;
+
+        let _ = std::fs::remove_file(&path);
+        assert_eq!(read.unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_client_disconnect_closes_http_connection() {
+        let pipe_name = "disconnect_test_http";
+        let address = crate::domain::utils::get_http_address_from_name(pipe_name);
+        let listener = tokio::net::TcpListener::bind(&address).await.unwrap();
+
+        let read = read_after_client_disconnects(crate::domain::CommunicationMode::Http, pipe_name, async move {
+            listener.accept().await.unwrap().0
+        })
+        .await;
+
+        // A reset is as good as an orderly close here
+        assert!(matches!(read, Ok(0) | Err(_)), "{:?}", read);
+    }
+
+    #[test]
+    fn test_serialization_error_maps_to_internal_server_error() {
+        let response = error_response(UseCaseError::SerializationError("bad".to_string()), false);
+        assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+    }
+
+    #[test]
+    fn test_malformed_backend_response_maps_to_bad_gateway() {
+        let response = error_response(UseCaseError::DeserializationError("missing status".to_string()), false);
+        assert_eq!(response.status(), StatusCode::BAD_GATEWAY);
+    }
+
+    #[tokio::test]
+    async fn test_error_envelope_hides_detail_in_production() {
+        let error = communication_error(CommunicationError::ConnectionFailed("/tmp/api: refused".to_string()));
+        let body = json_body(error_response(error, false)).await;
+
+        assert_eq!(body["error"]["code"], "backend_unavailable");
+        assert_eq!(body["error"]["message"], "Bad Gateway");
+        assert_eq!(body["error"]["process"], "api");
+    }
+
+    #[tokio::test]
+    async fn test_error_envelope_includes_detail_in_dev_mode() {
+        let error = communication_error(CommunicationError::ConnectionFailed("/tmp/api: refused".to_string()));
+        let body = json_body(error_response(error, true)).await;
+
+        assert_eq!(body["error"]["code"], "backend_unavailable");
+        assert!(body["error"]["message"].as_str().unwrap().contains("/tmp/api: refused"));
+    }
+
+    #[tokio::test]
+    async fn test_error_envelope_omits_unknown_process() {
+        let body = json_body(error_response(UseCaseError::NoRouteFound("/missing".to_string()), true)).await;
+
+        assert_eq!(body["error"]["code"], "no_route");
+        assert!(body["error"].get("process").is_none());
+    }
+
+    fn header<'a>(headers: &'a [(String, String)], name: &str) -> Option<&'a str> {
+        headers.iter().find(|(k, _)| k == name).map(|(_, v)| v.as_str())
+    }
+
+    #[test]
+    fn test_forwarded_headers_are_added() {
+        let mut headers = vec![("host".to_string(), "proxy.local:3000".to_string())];
+        add_forwarded_headers(&mut headers, Some("192.0.2.7:51000".parse().unwrap()), "http");
+
+        assert_eq!(header(&headers, "x-forwarded-for"), Some("192.0.2.7"));
+        assert_eq!(header(&headers, "x-forwarded-proto"), Some("http"));
+        assert_eq!(header(&headers, "x-forwarded-host"), Some("proxy.local:3000"));
+    }
+
+    #[test]
+    fn test_forwarded_for_chain_is_appended() {
+        let mut headers = vec![
+            ("x-forwarded-for".to_string(), "203.0.113.1".to_string()),
+            ("x-forwarded-for".to_string(), "198.51.100.2".to_string()),
+            ("x-forwarded-proto".to_string(), "https".to_string()),
+        ];
+        add_forwarded_headers(&mut headers, Some("192.0.2.7:51000".parse().unwrap()), "http");
+
+        assert_eq!(
+            header(&headers, "x-forwarded-for"),
+            Some("203.0.113.1, 198.51.100.2, 192.0.2.7")
+        );
+        assert_eq!(headers.iter().filter(|(k, _)| k == "x-forwarded-for").count(), 1);
+        assert_eq!(header(&headers, "x-forwarded-proto"), Some("https"));
+    }
+
+    /// Backend that keeps the last request envelope it received
+    #[derive(Clone, Default)]
+    struct CapturingService {
+        last_request: Arc<std::sync::Mutex<Option<serde_json::Value>>>,
+    }
+
+    #[async_trait::async_trait]
+    impl PipeCommunicationService for CapturingService {
+        async fn send_request(&self, _address: &str, request: Vec<u8>) -> Result<Vec<u8>, CommunicationError> {
+            *self.last_request.lock().unwrap() = Some(serde_json::from_slice(&request).unwrap());
+            Ok(br#"{"status": 200}"#.to_vec())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_response_headers_are_injected() {
+        use crate::domain::{Executable, PipeName, Process, ProcessId, Route};
+        use crate::test_support::MockPipeCommunicationService;
+        use tower::Service;
+
+        let mut process = Process::new(
+            ProcessId::new("api").unwrap(),
+            Executable::new("./api").unwrap(),
+            Route::new("/api/*").unwrap(),
+            PipeName::new("api_pipe").unwrap(),
+        );
+        process.response_headers = vec![
+            ("X-Service".to_string(), "api".to_string()),
+            ("Cache-Control".to_string(), "no-store".to_string()),
+        ];
+        let mock = MockPipeCommunicationService::new();
+        mock.respond_with(
+            "GET",
+            "/api/x",
+            HttpResponse {
+                status_code: 200,
+                headers: vec![
+                    ("cache-control".to_string(), "max-age=3600".to_string()),
+                    ("x-backend".to_string(), "kept".to_string()),
+                ],
+                body: b"ok".to_vec(),
+                trailers: Vec::new(),
+            },
+        );
+
+        let request = axum::http::Request::get("/api/x").body(Body::empty()).unwrap();
+        let response = mock.router(vec![process]).call(request).await.unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+        let headers = response.headers();
+        assert_eq!(headers["x-service"], "api");
+        assert_eq!(headers.get_all("cache-control").iter().collect::<Vec<_>>(), ["no-store"]);
+        assert_eq!(headers["x-backend"], "kept");
+    }
+
+    #[tokio::test]
+    async fn test_static_route_serves_files_from_its_directory() {
+        use crate::domain::{Process, ProcessId, Route};
+        use crate::test_support::MockPipeCommunicationService;
+        use tower::Service;
+
+        let root = tempfile::tempdir().unwrap();
+        let site = root.path().join("site");
+        std::fs::create_dir_all(site.join("css")).unwrap();
+        std::fs::write(site.join("css/app.css"), "body {}").unwrap();
+        std::fs::write(root.path().join("secret.txt"), "secret").unwrap();
+
+        let mut process = Process::new_static(
+            ProcessId::new("site").unwrap(),
+            Route::new("/site/*").unwrap(),
+            site.to_str().unwrap(),
+        );
+        process.response_headers = vec![("X-Served-By".to_string(), "site".to_string())];
+        let mut router = MockPipeCommunicationService::new().router(vec![process]);
+        let mut get = |path: &str| {
+            let request = axum::http::Request::get(path).body(Body::empty()).unwrap();
+            router.call(request)
+        };
+
+        let response = get("/site/css/app.css").await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()["content-type"], "text/css");
+        assert_eq!(response.headers()["x-served-by"], "site");
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        assert_eq!(&body[..], b"body {}");
+
+        assert_eq!(get("/site/missing.css").await.unwrap().status(), StatusCode::NOT_FOUND);
+
+        // Nothing outside the directory can be reached
+        assert_eq!(get("/site/../secret.txt").await.unwrap().status(), StatusCode::NOT_FOUND);
+        assert_eq!(get("/site/..%2Fsecret.txt").await.unwrap().status(), StatusCode::NOT_FOUND);
+
+        // A directory redirects to itself with a slash, under the route
+        let response = get("/site/css").await.unwrap();
+        assert_eq!(response.status(), StatusCode::TEMPORARY_REDIRECT);
+        assert_eq!(response.headers()["location"], "/site/css/");
+    }
+
+    #[tokio::test]
+    async fn test_client_address_reaches_backend() {
+        use crate::domain::{Executable, PipeName, Process, ProcessId, Route};
+
+        let service = CapturingService::default();
+        let process = Process::new(
+            ProcessId::new("api").unwrap(),
+            Executable::new("./api").unwrap(),
+            Route::new("/api/*").unwrap(),
+            PipeName::new("api_pipe").unwrap(),
+        );
+        let use_case = ProxyHttpRequestUseCase::new(Arc::new(service.clone()), Arc::new(vec![process]));
+        let app = HttpServerState::new(Arc::new(use_case)).create_router();
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            axum::serve(listener, app.into_make_service_with_connect_info::<SocketAddr>())
+                .await
+                .unwrap()
+        });
+
+        reqwest::Client::new()
+            .get(format!("http://{}/api/x", addr))
+            .header("X-Forwarded-For", "203.0.113.1")
+            .send()
+            .await
+            .unwrap();
+
+        let request = service.last_request.lock().unwrap().clone().unwrap();
+        let headers: Vec<(String, String)> = serde_json::from_value(request["headers"].clone()).unwrap();
+        assert_eq!(header(&headers, "x-forwarded-for"), Some("203.0.113.1, 127.0.0.1"));
+        assert_eq!(header(&headers, "x-forwarded-host"), Some(addr.to_string().as_str()));
+    }
+
+    #[tokio::test]
+    async fn test_extension_method_reaches_backend() {
+        let (service, addr) = spawn_limited_proxy(1024, None).await;
+
+        let propfind = reqwest::Method::from_bytes(b"PROPFIND").unwrap();
+        let response = reqwest::Client::new()
+            .request(propfind, format!("http://{}/api/dav/", addr))
+            .header("Depth", "1")
+            .send()
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), reqwest::StatusCode::OK);
+        let request = service.last_request.lock().unwrap().clone().unwrap();
+        assert_eq!(request["method"], "PROPFIND");
+        assert_eq!(request["uri"], "/api/dav/");
+    }
+
+    #[tokio::test]
+    async fn test_query_string_reaches_backend() {
+        let (service, addr) = spawn_limited_proxy(1024, None).await;
+
+        let response = reqwest::get(format!("http://{}/api/search?q=rust&page=2", addr)).await.unwrap();
+
+        assert_eq!(response.status(), reqwest::StatusCode::OK);
+        let request = service.last_request.lock().unwrap().clone().unwrap();
+        assert_eq!(request["uri"], "/api/search?q=rust&page=2");
+    }
+
+    async fn spawn_limited_proxy(max_body_bytes: usize, process_limit: Option<usize>) -> (CapturingService, SocketAddr) {
+        use crate::domain::{Executable, PipeName, Process, ProcessId, Route};
+
+        let service = CapturingService::default();
+        let mut process = Process::new(
+            ProcessId::new("api").unwrap(),
+            Executable::new("./api").unwrap(),
+            Route::new("/api/*").unwrap(),
+            PipeName::new("api_pipe").unwrap(),
+        );
+        process.max_body_bytes = process_limit;
+        let use_case = ProxyHttpRequestUseCase::new(Arc::new(service.clone()), Arc::new(vec![process]));
+        let options = ServerOptions {
+            max_body_bytes,
+            ..ServerOptions::default()
+        };
+        let app = HttpServerState::with_options(Arc::new(use_case), options).create_router();
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        (service, addr)
+    }
+
+    #[tokio::test]
+    async fn test_oversized_body_is_rejected() {
+        let (service, addr) = spawn_limited_proxy(1024, None).await;
+
+        let response = reqwest::Client::new()
+            .post(format!("http://{}/api/upload", addr))
+            .body(vec![0u8; 1024 * 1024])
+            .send()
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), reqwest::StatusCode::PAYLOAD_TOO_LARGE);
+        let body: serde_json::Value = response.json().await.unwrap();
+        assert_eq!(body["error"]["code"], "payload_too_large");
+        assert!(service.last_request.lock().unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_oversized_headers_are_rejected() {
+        let (service, addr) = spawn_limited_proxy(1024, None).await;
+
+        let response = reqwest::Client::new()
+            .get(format!("http://{}/api/items", addr))
+            .header("x-padding", "a".repeat(DEFAULT_MAX_HEADER_BYTES))
+            .send()
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), reqwest::StatusCode::REQUEST_HEADER_FIELDS_TOO_LARGE);
+        let body: serde_json::Value = response.json().await.unwrap();
+        assert_eq!(body["error"]["code"], "request_header_fields_too_large");
+        assert!(service.last_request.lock().unwrap().is_none());
+
+        let mut headers = HeaderMap::new();
+        for i in 0..4 {
+            headers.insert(format!("x-header-{}", i).parse::<axum::http::HeaderName>().unwrap(), HeaderValue::from_static("1"));
+        }
+        assert!(check_header_limits(&headers, 1024, 4).is_ok());
+        assert!(matches!(check_header_limits(&headers, 1024, 3), Err(ConversionError::HeadersTooLarge(_))));
+        assert!(matches!(check_header_limits(&headers, 16, 4), Err(ConversionError::HeadersTooLarge(_))));
+    }
+
+    #[tokio::test]
+    async fn test_disallowed_content_type_is_rejected_before_the_body() {
+        use crate::domain::{Executable, PipeName, Process, ProcessId, Route};
+        use std::time::Duration;
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let service = CapturingService::default();
+        let mut process = Process::new(
+            ProcessId::new("upload").unwrap(),
+            Executable::new("./upload").unwrap(),
+            Route::new("/upload/*").unwrap(),
+            PipeName::new("upload_pipe").unwrap(),
+        );
+        process.accepted_content_types = vec!["multipart/form-data".to_string(), "text/plain".to_string()];
+        let use_case = ProxyHttpRequestUseCase::new(Arc::new(service.clone()), Arc::new(vec![process]));
+        let app = HttpServerState::new(Arc::new(use_case)).create_router();
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        // The answer comes without any of the declared body having been sent
+        let mut stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+        stream
+            .write_all(
+                b"POST /upload/file HTTP/1.1\r\nHost: localhost\r\nContent-Type: application/json; charset=utf-8\r\n\
+                  Content-Length: 10000000\r\n\r\n",
+            )
+            .await
+            .unwrap();
+        let mut response = vec![0u8; 1024];
+        let read = tokio::time::timeout(Duration::from_secs(5), stream.read(&mut response)).await.unwrap().unwrap();
+        let response = String::from_utf8_lossy(&response[..read]);
+        assert!(response.starts_with("HTTP/1.1 415 Unsupported Media Type"), "{}", response);
+        assert!(response.contains("unsupported_media_type"), "{}", response);
+        assert!(service.last_request.lock().unwrap().is_none());
+
+        // Parameters don't get in the way of an accepted type
+        let response = reqwest::Client::new()
+            .post(format!("http://{}/upload/file", addr))
+            .header("content-type", "Text/Plain; charset=utf-8")
+            .body("hello")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::OK);
+        assert!(service.last_request.lock().unwrap().is_some());
+
+        // A body needs a type; a request without a body doesn't
+        let response = reqwest::Client::new()
+            .post(format!("http://{}/upload/file", addr))
+            .body("hello")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::UNSUPPORTED_MEDIA_TYPE);
+        let response = reqwest::get(format!("http://{}/upload/file", addr)).await.unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::OK);
+
+        // A type that isn't text is malformed rather than missing
+        let response = reqwest::Client::new()
+            .post(format!("http://{}/upload/file", addr))
+            .header("content-type", HeaderValue::from_bytes(b"text/pl\xe4in").unwrap())
+            .body("hello")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_body_limit_applies_without_content_length() {
+        let body = Body::from(vec![0u8; 2048]);
+        let result = convert_to_domain_request(Method::POST, Uri::from_static("/api/x"), HeaderMap::new(), None, "http", body, 1024).await;
+        assert!(matches!(result, Err(ConversionError::PayloadTooLarge(1024))));
+    }
+
+    #[tokio::test]
+    async fn test_streamed_body_over_the_limit_is_rejected() {
+        use crate::domain::{CommunicationMode, Executable, PipeName, Process, ProcessId, Route, SerializationFormat};
+        use crate::test_support::MockPipeCommunicationService;
+        use tower::Service;
+
+        let mock = MockPipeCommunicationService::new();
+        mock.respond("POST", "/upload/file", 200, "stored");
+        let mut process = Process::new(
+            ProcessId::new("upload").unwrap(),
+            Executable::new("./upload").unwrap(),
+            Route::new("/upload/*").unwrap(),
+            PipeName::new("upload_pipe").unwrap(),
+        );
+        process.communication_mode = CommunicationMode::Http;
+        process.protocol = SerializationFormat::Raw;
+        process.max_body_bytes = Some(1024);
+        let mut router = mock.router(vec![process]);
+
+        // Chunked, so the limit is only reached part way through streaming
+        let chunks = (0..4).map(|_| Ok::<_, std::io::Error>(bytes::Bytes::from(vec![0u8; 512])));
+        let request = axum::http::Request::post("/upload/file")
+            .body(Body::from_stream(futures_util::stream::iter(chunks)))
+            .unwrap();
+        let response = router.call(request).await.unwrap();
+
+        assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+        let body = http_body_util::BodyExt::collect(response.into_body()).await.unwrap().to_bytes();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["error"]["code"], "payload_too_large");
+    }
+
+    #[tokio::test]
+    async fn test_process_body_limit_overrides_default() {
+        let (service, addr) = spawn_limited_proxy(1024, Some(4096)).await;
+
+        let response = reqwest::Client::new()
+            .post(format!("http://{}/api/upload", addr))
+            .body(vec![0u8; 2048])
+            .send()
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), reqwest::StatusCode::OK);
+        assert!(service.last_request.lock().unwrap().is_some());
+    }
+
+    #[tokio::test]
+    async fn test_body_limit_is_that_of_the_process_serving_the_method() {
+        use crate::domain::{Executable, PipeName, Process, ProcessId, Route};
+        use crate::test_support::MockPipeCommunicationService;
+        use tower::Service;
+
+        let mut reads = Process::new(
+            ProcessId::new("reads").unwrap(),
+            Executable::new("./reads").unwrap(),
+            Route::new("/api/*").unwrap(),
+            PipeName::new("reads_pipe").unwrap(),
+        );
+        reads.methods = vec![HttpMethod::Get];
+        reads.max_body_bytes = Some(16);
+        let mut writes = Process::new(
+            ProcessId::new("writes").unwrap(),
+            Executable::new("./writes").unwrap(),
+            Route::new("/api/*").unwrap(),
+            PipeName::new("writes_pipe").unwrap(),
+        );
+        writes.methods = vec![HttpMethod::Post];
+        writes.max_body_bytes = Some(4096);
+        let mock = MockPipeCommunicationService::new();
+        mock.respond("POST", "/api/upload", 200, "stored");
+        let mut router = mock.router(vec![reads, writes]);
+
+        // Within the limit of the process that takes POSTs, not the first declared
+        let request = axum::http::Request::post("/api/upload").body(Body::from(vec![0u8; 2048])).unwrap();
+        let response = router.call(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.extensions().get::<MatchedProcess>().unwrap().0, "writes");
+
+        let request = axum::http::Request::get("/api/upload").body(Body::from(vec![0u8; 2048])).unwrap();
+        let response = router.call(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+        assert_eq!(mock.received().len(), 1);
+    }
+
+    /// Backend whose `Content-Length` doesn't match the body it sends
+    #[derive(Clone)]
+    struct WrongLengthService {
+        content_length: &'static str,
+    }
+
+    #[async_trait::async_trait]
+    impl PipeCommunicationService for WrongLengthService {
+        async fn send_request(&self, _address: &str, _request: Vec<u8>) -> Result<Vec<u8>, CommunicationError> {
+            // "hello world" in base64
+            let envelope = serde_json::json!({
+                "status": 200,
+                "headers": { "Content-Length": self.content_length },
+                "body": "aGVsbG8gd29ybGQ=",
+            });
+            Ok(serde_json::to_vec(&envelope).unwrap())
+        }
+    }
+
+    async fn serve_wrong_length(content_length: &'static str) -> SocketAddr {
+        use crate::domain::{Executable, PipeName, Process, ProcessId, Route};
+
+        let process = Process::new(
+            ProcessId::new("api").unwrap(),
+            Executable::new("./api").unwrap(),
+            Route::new("/api/*").unwrap(),
+            PipeName::new("api_pipe").unwrap(),
+        );
+        let service = WrongLengthService { content_length };
+        let use_case = ProxyHttpRequestUseCase::new(Arc::new(service), Arc::new(vec![process]));
+        let app = HttpServerState::new(Arc::new(use_case)).create_router();
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        addr
+    }
+
+    #[tokio::test]
+    async fn test_backend_content_length_is_replaced() {
+        use std::time::Duration;
+
+        for content_length in ["4", "500"] {
+            let addr = serve_wrong_length(content_length).await;
+            let response = tokio::time::timeout(Duration::from_secs(5), reqwest::get(format!("http://{}/api/x", addr)))
+                .await
+                .unwrap()
+                .unwrap();
+
+            // Either the real length or none at all (chunked) is fine
+            if let Some(length) = response.headers().get("content-length") {
+                assert_eq!(length, "11");
+            }
+            let body = tokio::time::timeout(Duration::from_secs(5), response.text())
+                .await
+                .unwrap()
+                .unwrap();
+            assert_eq!(body, "hello world");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_head_response_keeps_backend_content_length() {
+        let addr = serve_wrong_length("500").await;
+        let response = reqwest::Client::new()
+            .head(format!("http://{}/api/x", addr))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.headers()["content-length"], "500");
+    }
+
+    /// Send `GET /api/x` to a proxy whose backend answers with `response`,
+    /// returning the response head and whatever bytes followed it before the
+    /// connection closed
+    async fn fetch_raw(response: HttpResponse) -> (String, Vec<u8>) {
+        use crate::domain::{Executable, PipeName, Process, ProcessId, Route};
+        use crate::test_support::MockPipeCommunicationService;
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let process = Process::new(
+            ProcessId::new("api").unwrap(),
+            Executable::new("./api").unwrap(),
+            Route::new("/api/*").unwrap(),
+            PipeName::new("api_pipe").unwrap(),
+        );
+        let mock = MockPipeCommunicationService::new();
+        mock.respond_with("GET", "/api/x", response);
+        let app = mock.router(vec![process]);
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let mut stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+        stream
+            .write_all(b"GET /api/x HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n")
+            .await
+            .unwrap();
+        let mut raw = Vec::new();
+        tokio::time::timeout(std::time::Duration::from_secs(5), stream.read_to_end(&mut raw))
+            .await
+            .unwrap()
+            .unwrap();
+
+        let end = raw.windows(4).position(|w| w == b"\r\n\r\n").unwrap() + 4;
+        let head = String::from_utf8(raw[..end].to_vec()).unwrap().to_ascii_lowercase();
+        (head, raw[end..].to_vec())
+    }
+
+    #[tokio::test]
+    async fn test_no_content_response_is_bodiless() {
+        for body in [Vec::new(), b"stray".to_vec()] {
+            let (head, rest) = fetch_raw(HttpResponse {
+                status_code: 204,
+                headers: vec![("content-length".to_string(), body.len().to_string())],
+                body,
+                trailers: Vec::new(),
+            })
+            .await;
+
+            assert!(head.starts_with("http/1.1 204"), "{}", head);
+            assert!(!head.contains("content-length"), "{}", head);
+            assert!(!head.contains("transfer-encoding"), "{}", head);
+            assert!(rest.is_empty());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_not_modified_response_is_bodiless() {
+        let (head, rest) = fetch_raw(HttpResponse {
+            status_code: 304,
+            headers: vec![
+                ("etag".to_string(), "\"v1\"".to_string()),
+                ("content-length".to_string(), "5".to_string()),
+            ],
+            body: b"stale".to_vec(),
+            trailers: Vec::new(),
+        })
+        .await;
+
+        assert!(head.starts_with("http/1.1 304"), "{}", head);
+        assert!(head.contains("etag: \"v1\""), "{}", head);
+        assert!(head.contains("content-length: 5\r\n"), "{}", head);
+        assert!(!head.contains("transfer-encoding"), "{}", head);
+        assert!(rest.is_empty());
+
+        // Without a body or length from the backend, none is made up
+        let (head, rest) = fetch_raw(HttpResponse {
+            status_code: 304,
+            headers: Vec::new(),
+            body: Vec::new(),
+            trailers: Vec::new(),
+        })
+        .await;
+        assert!(!head.contains("content-length"), "{}", head);
+        assert!(rest.is_empty());
+    }
+
+    /// Backend answering every request with a large body of the given type
+    #[derive(Clone)]
+    struct LargeBodyService {
+        content_type: &'static str,
+    }
+
+    #[async_trait::async_trait]
+    impl PipeCommunicationService for LargeBodyService {
+        async fn send_request(&self, _address: &str, _request: Vec<u8>) -> Result<Vec<u8>, CommunicationError> {
+            use base64::{engine::general_purpose, Engine as _};
+
+            let body = format!("[{}]", vec![r#"{"name":"item"}"#; 500].join(","));
+            let envelope = serde_json::json!({
+                "status": 200,
+                "headers": { "content-type": self.content_type },
+                "body": general_purpose::STANDARD.encode(body),
+            });
+            Ok(serde_json::to_vec(&envelope).unwrap())
+        }
+    }
+
+    async fn fetch_with_gzip(content_type: &'static str, compression: bool) -> reqwest::Response {
+        use crate::domain::{Executable, PipeName, Process, ProcessId, Route};
+
+        let process = Process::new(
+            ProcessId::new("api").unwrap(),
+            Executable::new("./api").unwrap(),
+            Route::new("/api/*").unwrap(),
+            PipeName::new("api_pipe").unwrap(),
+        );
+        let service = LargeBodyService { content_type };
+        let use_case = ProxyHttpRequestUseCase::new(Arc::new(service), Arc::new(vec![process]));
+        let options = ServerOptions {
+            compression,
+            ..ServerOptions::default()
+        };
+        let app = HttpServerState::with_options(Arc::new(use_case), options).create_router();
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        reqwest::Client::new()
+            .get(format!("http://{}/api/items", addr))
+            .header("Accept-Encoding", "gzip")
+            .send()
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_large_response_is_gzipped() {
+        let response = fetch_with_gzip("application/json", true).await;
+        assert_eq!(response.headers()["content-encoding"], "gzip");
+        assert!(response.bytes().await.unwrap().len() < 1000);
+    }
+
+    #[tokio::test]
+    async fn test_compression_can_be_disabled() {
+        let response = fetch_with_gzip("application/json", false).await;
+        assert!(!response.headers().contains_key("content-encoding"));
+    }
+
+    #[tokio::test]
+    async fn test_compressed_formats_are_left_alone() {
+        let response = fetch_with_gzip("application/zip", true).await;
+        assert!(!response.headers().contains_key("content-encoding"));
+    }
+}
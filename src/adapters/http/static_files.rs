@@ -0,0 +1,51 @@
+//! Static routes - files served by the proxy itself from a directory
+//! tower-http's `ServeDir` does the serving: content types, ranges,
+//! conditional requests, and refusing paths that would escape the directory
+
+use crate::use_cases::StaticMount;
+use axum::body::Body;
+use axum::http::{header, HeaderName, HeaderValue, Request, Uri};
+use axum::response::{IntoResponse, Response};
+use tower::Service;
+use tower_http::services::ServeDir;
+
+/// Serve the file `mount` resolves a request to
+///
+/// `ServeDir` sees only the path below the route, so a redirect it sends
+/// (from a directory to the same path with a trailing slash) gets the
+/// route's prefix put back.
+pub async fn serve_static(mount: StaticMount, request: Request<Body>) -> Response {
+    let (mut parts, body) = request.into_parts();
+    let original_path = parts.uri.path().to_string();
+    let path_and_query = match parts.uri.query() {
+        Some(query) => format!("{}?{}", mount.path, query),
+        None => mount.path.clone(),
+    };
+    parts.uri = match path_and_query.parse::<Uri>() {
+        Ok(uri) => uri,
+        Err(_) => return axum::http::StatusCode::NOT_FOUND.into_response(),
+    };
+
+    let response = match ServeDir::new(&mount.dir).call(Request::from_parts(parts, body)).await {
+        Ok(response) => response.map(Body::new),
+        Err(never) => match never {},
+    };
+    let mut response = response.into_response();
+
+    if let Some(prefix) = original_path.strip_suffix(mount.path.as_str()).filter(|p| !p.is_empty()) {
+        let location = response.headers().get(header::LOCATION).and_then(|l| l.to_str().ok());
+        if let Some(location) = location.filter(|l| l.starts_with('/')) {
+            if let Ok(value) = HeaderValue::from_str(&format!("{}{}", prefix, location)) {
+                response.headers_mut().insert(header::LOCATION, value);
+            }
+        }
+    }
+
+    // Names and values were checked when the manifest was loaded
+    for (name, value) in &mount.response_headers {
+        if let (Ok(name), Ok(value)) = (HeaderName::from_bytes(name.as_bytes()), HeaderValue::from_str(value)) {
+            response.headers_mut().insert(name, value);
+        }
+    }
+    response
+}
@@ -0,0 +1,179 @@
+//! Binding the proxy's TCP listeners, with socket options suited to the
+//! many small requests and responses it relays
+
+use super::connection::{self, FirstByteTimeout};
+use axum::Router;
+use hyper_util::server::graceful::GracefulShutdown;
+use std::future::Future;
+use std::io;
+use std::net::SocketAddr;
+use std::time::Duration;
+use tokio::net::{TcpListener, TcpSocket};
+
+/// Connections queued by the OS before the proxy accepts them, the same as
+/// tokio's `TcpListener::bind`
+pub const DEFAULT_LISTEN_BACKLOG: u32 = 1024;
+
+/// How long a client may keep a connection open without sending a request
+/// before it's closed
+pub const DEFAULT_CLIENT_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// How TCP listeners are bound and their connections set up
+#[derive(Debug, Clone, Copy)]
+pub struct TcpOptions {
+    pub backlog: u32,
+    /// Send each response as soon as it's written (`TCP_NODELAY`) rather
+    /// than holding small ones back to batch them
+    pub nodelay: bool,
+    /// Close connections whose client sends no request headers within this
+    /// long, whether it's just connected or kept the connection alive
+    /// after a response; `None` waits forever
+    pub client_timeout: Option<Duration>,
+}
+
+impl Default for TcpOptions {
+    fn default() -> Self {
+        Self {
+            backlog: DEFAULT_LISTEN_BACKLOG,
+            nodelay: true,
+            client_timeout: Some(DEFAULT_CLIENT_TIMEOUT),
+        }
+    }
+}
+
+/// Bind the TCP `addr`, trying each address a host name resolves to in turn
+pub async fn bind(addr: &str, options: &TcpOptions) -> io::Result<TcpListener> {
+    let mut last_error = None;
+    for address in tokio::net::lookup_host(addr).await? {
+        match bind_address(address, options.backlog) {
+            Ok(listener) => return Ok(listener),
+            Err(e) => last_error = Some(e),
+        }
+    }
+    Err(last_error.unwrap_or_else(|| {
+        io::Error::new(io::ErrorKind::InvalidInput, "could not resolve to any address")
+    }))
+}
+
+fn bind_address(address: SocketAddr, backlog: u32) -> io::Result<TcpListener> {
+    let socket = match address {
+        SocketAddr::V4(_) => TcpSocket::new_v4()?,
+        SocketAddr::V6(_) => TcpSocket::new_v6()?,
+    };
+    // Lets a restarted proxy take its port back while connections from the
+    // last run linger in TIME_WAIT; on Windows it would let two servers
+    // share a port instead
+    #[cfg(not(windows))]
+    socket.set_reuseaddr(true)?;
+    socket.bind(address)?;
+    socket.listen(backlog)
+}
+
+/// Serve `app` on `listener` until `shutdown` completes, then wait for open
+/// connections to finish
+pub async fn serve(
+    listener: TcpListener,
+    app: Router,
+    options: &TcpOptions,
+    shutdown: impl Future<Output = ()>,
+) -> io::Result<()> {
+    let builder = connection::builder(options.client_timeout);
+    let graceful = GracefulShutdown::new();
+    tokio::pin!(shutdown);
+
+    loop {
+        let (stream, peer) = tokio::select! {
+            accepted = listener.accept() => match accepted {
+                Ok(accepted) => accepted,
+                Err(e) => {
+                    connection::accept_failed(e).await;
+                    continue;
+                }
+            },
+            _ = &mut shutdown => break,
+        };
+        if let Err(e) = stream.set_nodelay(options.nodelay) {
+            tracing::debug!("Failed to set TCP_NODELAY for {}: {}", peer, e);
+        }
+
+        let stream = FirstByteTimeout::new(stream, options.client_timeout);
+        let builder = builder.clone();
+        let watcher = graceful.watcher();
+        let app = app.clone();
+        // Connect info lets the proxy tell backends the client's address
+        tokio::spawn(async move { connection::serve_connection(&builder, stream, app, Some(peer), false, watcher).await });
+    }
+
+    drop(listener);
+    graceful.shutdown().await;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::adapters::http::HttpServerState;
+    use crate::infrastructure::HttpClient;
+    use crate::use_cases::ProxyHttpRequestUseCase;
+    use std::sync::Arc;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    #[tokio::test]
+    async fn test_bind_resolves_host_names() {
+        let options = TcpOptions {
+            backlog: 8,
+            ..TcpOptions::default()
+        };
+        let listener = bind("localhost:0", &options).await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        assert!(addr.ip().is_loopback());
+        assert_ne!(addr.port(), 0);
+
+        let connecting = tokio::net::TcpStream::connect(addr);
+        let (connected, accepted) = tokio::join!(connecting, listener.accept());
+        connected.unwrap();
+        accepted.unwrap();
+
+        assert!(bind("not an address", &options).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_silent_connections_are_closed_after_the_client_timeout() {
+        let options = TcpOptions {
+            client_timeout: Some(Duration::from_millis(200)),
+            ..TcpOptions::default()
+        };
+        let listener = bind("127.0.0.1:0", &options).await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let use_case = ProxyHttpRequestUseCase::new(Arc::new(HttpClient::new()), Arc::new(Vec::new()));
+        let app = HttpServerState::new(Arc::new(use_case)).create_router();
+        tokio::spawn(async move { serve(listener, app, &options, std::future::pending()).await });
+
+        // Nothing is ever sent, yet the server hangs up
+        let mut silent = tokio::net::TcpStream::connect(addr).await.unwrap();
+        let mut buf = Vec::new();
+        let closed = tokio::time::timeout(Duration::from_secs(5), silent.read_to_end(&mut buf)).await;
+        assert!(closed.is_ok(), "connection still open after the client timeout");
+
+        // A client that does send a request is still served
+        let mut stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+        stream
+            .write_all(b"GET /health HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n")
+            .await
+            .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        assert!(response.starts_with("HTTP/1.1 "), "{}", response);
+
+        // As is one that waits less than the timeout before sending it
+        let mut stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        stream
+            .write_all(b"GET /health HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n")
+            .await
+            .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        assert!(response.starts_with("HTTP/1.1 "), "{}", response);
+    }
+}
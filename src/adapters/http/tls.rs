@@ -0,0 +1,185 @@
+//! Terminating TLS in the proxy, so backends can be reached over HTTPS
+//! without implementing it themselves
+
+use super::connection::{self, FirstByteTimeout};
+use super::TcpOptions;
+use axum::Router;
+use hyper_util::server::graceful::GracefulShutdown;
+use std::future::Future;
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+use tokio::net::TcpListener;
+use tokio_rustls::rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use tokio_rustls::rustls::ServerConfig;
+use tokio_rustls::TlsAcceptor;
+
+/// Build the server configuration from a PEM certificate chain and private key
+///
+/// The errors name the file at fault, since they end up as startup errors.
+pub fn load_config(cert_path: &Path, key_path: &Path) -> Result<ServerConfig, String> {
+    let open = |path: &Path, what: &str| {
+        std::fs::File::open(path)
+            .map(io::BufReader::new)
+            .map_err(|e| format!("Failed to read TLS {} {}: {}", what, path.display(), e))
+    };
+
+    let certs: Vec<CertificateDer<'static>> = rustls_pemfile::certs(&mut open(cert_path, "certificate")?)
+        .collect::<Result<_, _>>()
+        .map_err(|e| format!("Invalid TLS certificate {}: {}", cert_path.display(), e))?;
+    if certs.is_empty() {
+        return Err(format!("No certificates found in {}", cert_path.display()));
+    }
+
+    let key: PrivateKeyDer<'static> = rustls_pemfile::private_key(&mut open(key_path, "private key")?)
+        .map_err(|e| format!("Invalid TLS private key {}: {}", key_path.display(), e))?
+        .ok_or_else(|| format!("No private key found in {}", key_path.display()))?;
+
+    let mut config = ServerConfig::builder()
+        .with_no_client_auth()
+        .with_single_cert(certs, key)
+        .map_err(|e| format!("TLS certificate and private key don't work together: {}", e))?;
+    config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+    Ok(config)
+}
+
+/// Serve `app` over TLS on `listener` until `shutdown` completes, then wait
+/// for open connections to finish
+///
+/// Accepted connections get `TCP_NODELAY` when `options.nodelay` is set,
+/// and the client timeout covers the handshake too.
+pub async fn serve(
+    listener: TcpListener,
+    config: ServerConfig,
+    app: Router,
+    options: &TcpOptions,
+    shutdown: impl Future<Output = ()>,
+) -> io::Result<()> {
+    let acceptor = TlsAcceptor::from(Arc::new(config));
+    let builder = connection::builder(options.client_timeout);
+    let graceful = GracefulShutdown::new();
+    tokio::pin!(shutdown);
+
+    loop {
+        let (stream, peer) = tokio::select! {
+            accepted = listener.accept() => match accepted {
+                Ok(accepted) => accepted,
+                Err(e) => {
+                    connection::accept_failed(e).await;
+                    continue;
+                }
+            },
+            _ = &mut shutdown => break,
+        };
+        if let Err(e) = stream.set_nodelay(options.nodelay) {
+            tracing::debug!("Failed to set TCP_NODELAY for {}: {}", peer, e);
+        }
+
+        // Handshakes happen off the accept loop so a slow client can't hold
+        // up everyone else
+        let acceptor = acceptor.clone();
+        let builder = builder.clone();
+        let watcher = graceful.watcher();
+        let app = app.clone();
+        let client_timeout = options.client_timeout;
+        tokio::spawn(async move {
+            let handshake = acceptor.accept(FirstByteTimeout::new(stream, client_timeout));
+            let handshake = match client_timeout {
+                Some(timeout) => match tokio::time::timeout(timeout, handshake).await {
+                    Ok(handshake) => handshake,
+                    Err(_) => {
+                        tracing::debug!("TLS handshake with {} timed out", peer);
+                        return;
+                    }
+                },
+                None => handshake.await,
+            };
+            let stream = match handshake {
+                Ok(stream) => stream,
+                Err(e) => {
+                    tracing::debug!("TLS handshake with {} failed: {}", peer, e);
+                    return;
+                }
+            };
+
+            // Same connect info as plain HTTP, so backends still see the client's address,
+            // and marked secure so they're told it came over https.
+            // The first request must follow the handshake within the timeout too
+            let stream = FirstByteTimeout::new(stream, client_timeout);
+            connection::serve_connection(&builder, stream, app, Some(peer), true, watcher).await;
+        });
+    }
+
+    drop(listener);
+    graceful.shutdown().await;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::{Executable, PipeName, Process, ProcessId, Route};
+    use crate::test_support::MockPipeCommunicationService;
+
+    /// Write a self-signed certificate for `localhost` and its key, returning
+    /// the certificate's PEM along with both paths
+    fn self_signed(dir: &Path) -> (String, std::path::PathBuf, std::path::PathBuf) {
+        let certified = rcgen::generate_simple_self_signed(vec!["localhost".to_string()]).unwrap();
+        let cert_pem = certified.cert.pem();
+        let cert_path = dir.join("cert.pem");
+        let key_path = dir.join("key.pem");
+        std::fs::write(&cert_path, &cert_pem).unwrap();
+        std::fs::write(&key_path, certified.key_pair.serialize_pem()).unwrap();
+        (cert_pem, cert_path, key_path)
+    }
+
+    #[tokio::test]
+    async fn test_proxies_over_https() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let (cert_pem, cert_path, key_path) = self_signed(dir.path());
+        let config = load_config(&cert_path, &key_path).unwrap();
+
+        let process = Process::new(
+            ProcessId::new("api").unwrap(),
+            Executable::new("./api").unwrap(),
+            Route::new("/api/*").unwrap(),
+            PipeName::new("api_pipe").unwrap(),
+        );
+        let backend = MockPipeCommunicationService::new();
+        backend.respond("GET", "/api/x", 200, "hello");
+        let app = backend.router(vec![process]);
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move { serve(listener, config, app, &TcpOptions::default(), std::future::pending()).await });
+
+        let client = reqwest::Client::builder()
+            .add_root_certificate(reqwest::Certificate::from_pem(cert_pem.as_bytes()).unwrap())
+            .build()
+            .unwrap();
+        let response = client
+            .get(format!("https://localhost:{}/api/x", port))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::OK);
+        assert_eq!(response.text().await.unwrap(), "hello");
+        assert_eq!(backend.received()[0].header("x-forwarded-proto"), Some("https"));
+    }
+
+    #[test]
+    fn test_load_errors_name_the_file() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let (_, cert_path, key_path) = self_signed(dir.path());
+
+        let missing = dir.path().join("missing.pem");
+        let error = load_config(&missing, &key_path).unwrap_err();
+        assert!(error.starts_with(&format!("Failed to read TLS certificate {}", missing.display())), "{}", error);
+
+        // A certificate file holds no private key
+        let error = load_config(&cert_path, &cert_path).unwrap_err();
+        assert_eq!(error, format!("No private key found in {}", cert_path.display()));
+
+        let error = load_config(&key_path, &key_path).unwrap_err();
+        assert_eq!(error, format!("No certificates found in {}", key_path.display()));
+    }
+}
@@ -0,0 +1,129 @@
+//! Serving the proxy on a Unix domain socket instead of a TCP port, e.g. as
+//! an nginx upstream
+
+use super::connection::{self, FirstByteTimeout};
+use axum::Router;
+use hyper_util::server::graceful::GracefulShutdown;
+use std::future::Future;
+use std::io;
+use std::os::unix::fs::FileTypeExt;
+use std::path::Path;
+use std::time::Duration;
+use tokio::net::{UnixListener, UnixStream};
+
+/// Bind `path`, replacing a socket file left behind by an earlier run
+///
+/// A socket that still accepts connections belongs to a running server and
+/// is left alone, as is any file that isn't a socket.
+pub async fn bind(path: &Path) -> io::Result<UnixListener> {
+    if let Ok(metadata) = std::fs::symlink_metadata(path) {
+        if !metadata.file_type().is_socket() {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("{} exists and is not a socket", path.display()),
+            ));
+        }
+        if UnixStream::connect(path).await.is_ok() {
+            return Err(io::Error::new(
+                io::ErrorKind::AddrInUse,
+                format!("{} is in use by another server", path.display()),
+            ));
+        }
+        tracing::info!("Removing stale socket {}", path.display());
+        std::fs::remove_file(path)?;
+    }
+    UnixListener::bind(path)
+}
+
+/// Serve `app` on `listener` until `shutdown` completes, then wait for open
+/// connections to finish and remove the socket file
+///
+/// Connections whose client sends no request within `client_timeout` are
+/// closed, as on TCP.
+pub async fn serve(
+    listener: UnixListener,
+    app: Router,
+    client_timeout: Option<Duration>,
+    shutdown: impl Future<Output = ()>,
+) -> io::Result<()> {
+    let path = listener.local_addr()?.as_pathname().map(Path::to_path_buf);
+    let builder = connection::builder(client_timeout);
+    let graceful = GracefulShutdown::new();
+    tokio::pin!(shutdown);
+
+    loop {
+        let stream = tokio::select! {
+            accepted = listener.accept() => match accepted {
+                Ok((stream, _)) => stream,
+                Err(e) => {
+                    connection::accept_failed(e).await;
+                    continue;
+                }
+            },
+            _ = &mut shutdown => break,
+        };
+
+        // There's no client address to pass on over a socket file
+        let stream = FirstByteTimeout::new(stream, client_timeout);
+        let builder = builder.clone();
+        let watcher = graceful.watcher();
+        let app = app.clone();
+        tokio::spawn(async move { connection::serve_connection(&builder, stream, app, None, false, watcher).await });
+    }
+
+    drop(listener);
+    graceful.shutdown().await;
+    if let Some(path) = path {
+        let _ = std::fs::remove_file(path);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::{Executable, PipeName, Process, ProcessId, Route};
+    use crate::test_support::MockPipeCommunicationService;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    #[tokio::test]
+    async fn test_proxies_over_unix_socket() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("proxy.sock");
+
+        // A leftover socket from a previous run doesn't prevent binding
+        drop(std::os::unix::net::UnixListener::bind(&path).unwrap());
+        let listener = bind(&path).await.unwrap();
+
+        let process = Process::new(
+            ProcessId::new("api").unwrap(),
+            Executable::new("./api").unwrap(),
+            Route::new("/api/*").unwrap(),
+            PipeName::new("api_pipe").unwrap(),
+        );
+        let backend = MockPipeCommunicationService::new();
+        backend.respond("GET", "/api/x", 200, "hello");
+        let app = backend.router(vec![process]);
+        let (stop, stopped) = tokio::sync::oneshot::channel::<()>();
+        let server = tokio::spawn(serve(listener, app, None, async {
+            stopped.await.ok();
+        }));
+
+        // A second server can't take over a socket that is in use
+        assert_eq!(bind(&path).await.unwrap_err().kind(), io::ErrorKind::AddrInUse);
+
+        let mut stream = UnixStream::connect(&path).await.unwrap();
+        stream
+            .write_all(b"GET /api/x HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n")
+            .await
+            .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        assert!(response.starts_with("HTTP/1.1 200 OK"), "{}", response);
+        assert!(response.contains("\r\nhello\r\n"), "{}", response);
+
+        stop.send(()).unwrap();
+        server.await.unwrap().unwrap();
+        assert!(!path.exists());
+    }
+}
@@ -0,0 +1,225 @@
+//! WebSocket passthrough - splices an upgraded client connection onto a
+//! WebSocket opened to an HTTP-mode backend
+//! The JSON envelope protocol can't carry a bidirectional stream, so upgrade
+//! requests bypass the proxy use case once the backend has been resolved
+
+use crate::use_cases::UpgradeTarget;
+use axum::extract::ws::{self, WebSocket, WebSocketUpgrade};
+use axum::http::{header, HeaderMap, StatusCode, Uri};
+use axum::response::Response;
+use futures_util::{SinkExt, StreamExt};
+use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+use tokio_tungstenite::tungstenite::protocol::{frame::coding::CloseCode, CloseFrame};
+use tokio_tungstenite::tungstenite::Message as BackendMessage;
+
+use super::server::json_error;
+
+/// Connect to the backend, then accept the client's upgrade and relay
+/// messages in both directions until either side closes
+pub async fn proxy_websocket(
+    upgrade: WebSocketUpgrade,
+    target: UpgradeTarget,
+    uri: Uri,
+    headers: HeaderMap,
+    dev_mode: bool,
+) -> Response {
+    let path = uri.path_and_query().map(|p| p.as_str()).unwrap_or("/");
+    let url = format!("ws://{}{}", target.address, path);
+    tracing::debug!("Proxying WebSocket for '{}' to {}", target.process, url);
+
+    let mut request = match url.as_str().into_client_request() {
+        Ok(request) => request,
+        Err(e) => {
+            return json_error(
+                StatusCode::BAD_REQUEST,
+                "invalid_request",
+                format!("Invalid WebSocket request: {}", e),
+                Some(&target.process),
+                dev_mode,
+            );
+        }
+    };
+    if let Some(protocols) = headers.get(header::SEC_WEBSOCKET_PROTOCOL) {
+        request
+            .headers_mut()
+            .insert(header::SEC_WEBSOCKET_PROTOCOL, protocols.clone());
+    }
+
+    // Connect before accepting so an unreachable backend is reported as a
+    // normal error response rather than an immediately closed socket
+    let (backend, response) = match tokio_tungstenite::connect_async(request).await {
+        Ok(connected) => connected,
+        Err(e) => {
+            tracing::error!("WebSocket connection to '{}' failed: {}", target.process, e);
+            return json_error(
+                StatusCode::BAD_GATEWAY,
+                "backend_unavailable",
+                format!("WebSocket connection to {} failed: {}", url, e),
+                Some(&target.process),
+                dev_mode,
+            );
+        }
+    };
+
+    // Echo back whichever subprotocol the backend agreed to
+    let upgrade = match response
+        .headers()
+        .get(header::SEC_WEBSOCKET_PROTOCOL)
+        .and_then(|v| v.to_str().ok())
+    {
+        Some(protocol) => upgrade.protocols([protocol.to_string()]),
+        None => upgrade,
+    };
+
+    let process = target.process;
+    upgrade.on_upgrade(move |client| async move {
+        splice(client, backend).await;
+        tracing::debug!("WebSocket for '{}' closed", process);
+    })
+}
+
+/// Relay messages between the client and backend sockets
+async fn splice<S>(client: WebSocket, backend: tokio_tungstenite::WebSocketStream<S>)
+where
+    S: tokio::io::AsyncRead + tokio::io::AsyncWrite + Unpin,
+{
+    let (mut client_tx, mut client_rx) = client.split();
+    let (mut backend_tx, mut backend_rx) = backend.split();
+
+    let client_to_backend = async {
+        while let Some(Ok(message)) = client_rx.next().await {
+            let closing = matches!(message, ws::Message::Close(_));
+            if backend_tx.send(to_backend(message)).await.is_err() || closing {
+                break;
+            }
+        }
+    };
+
+    let backend_to_client = async {
+        while let Some(Ok(message)) = backend_rx.next().await {
+            let closing = matches!(message, BackendMessage::Close(_));
+            let Some(message) = to_client(message) else { continue };
+            if client_tx.send(message).await.is_err() || closing {
+                break;
+            }
+        }
+    };
+
+    tokio::select! {
+        _ = client_to_backend => {}
+        _ = backend_to_client => {}
+    }
+}
+
+fn to_backend(message: ws::Message) -> BackendMessage {
+    match message {
+        ws::Message::Text(text) => BackendMessage::Text(text),
+        ws::Message::Binary(data) => BackendMessage::Binary(data),
+        ws::Message::Ping(data) => BackendMessage::Ping(data),
+        ws::Message::Pong(data) => BackendMessage::Pong(data),
+        ws::Message::Close(frame) => BackendMessage::Close(frame.map(|f| CloseFrame {
+            code: CloseCode::from(f.code),
+            reason: f.reason,
+        })),
+    }
+}
+
+/// Raw frames are never produced when reading, so they have no client equivalent
+fn to_client(message: BackendMessage) -> Option<ws::Message> {
+    Some(match message {
+        BackendMessage::Text(text) => ws::Message::Text(text),
+        BackendMessage::Binary(data) => ws::Message::Binary(data),
+        BackendMessage::Ping(data) => ws::Message::Ping(data),
+        BackendMessage::Pong(data) => ws::Message::Pong(data),
+        BackendMessage::Close(frame) => ws::Message::Close(frame.map(|f| ws::CloseFrame {
+            code: f.code.into(),
+            reason: f.reason,
+        })),
+        BackendMessage::Frame(_) => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::adapters::http::HttpServerState;
+    use crate::domain::{CommunicationMode, Executable, PipeName, Process, ProcessId, Route};
+    use crate::infrastructure::HttpClient;
+    use crate::use_cases::ProxyHttpRequestUseCase;
+    use axum::extract::ws::{Message, WebSocketUpgrade};
+    use axum::routing::any;
+    use axum::Router;
+    use futures_util::{SinkExt, StreamExt};
+    use std::sync::Arc;
+    use tokio_tungstenite::tungstenite::Message as ClientMessage;
+
+    /// Backend that echoes every text message prefixed with the request path
+    async fn spawn_echo_backend(address: &str) {
+        let app = Router::new().route(
+            "/*path",
+            any(|upgrade: WebSocketUpgrade, uri: axum::http::Uri| async move {
+                upgrade.on_upgrade(move |mut socket| async move {
+                    while let Some(Ok(Message::Text(text))) = socket.recv().await {
+                        let reply = format!("{} {}", uri.path(), text);
+                        if socket.send(Message::Text(reply)).await.is_err() {
+                            break;
+                        }
+                    }
+                })
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind(address).await.unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+    }
+
+    async fn spawn_proxy(process: Process) -> std::net::SocketAddr {
+        let use_case = ProxyHttpRequestUseCase::new(Arc::new(HttpClient::new()), Arc::new(vec![process]));
+        let app = HttpServerState::new(Arc::new(use_case)).create_router();
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        addr
+    }
+
+    fn ws_process(pipe_name: &str) -> Process {
+        let mut process = Process::new(
+            ProcessId::new("chat").unwrap(),
+            Executable::new("./chat").unwrap(),
+            Route::new("/chat/*").unwrap(),
+            PipeName::new(pipe_name).unwrap(),
+        );
+        process.communication_mode = CommunicationMode::Http;
+        process
+    }
+
+    #[tokio::test]
+    async fn test_websocket_is_spliced_to_backend() {
+        let process = ws_process("ws_passthrough_test");
+        spawn_echo_backend(&process.instance_addresses()[0]).await;
+        let proxy = spawn_proxy(process).await;
+
+        let (mut socket, _) = tokio_tungstenite::connect_async(format!("ws://{}/chat/room", proxy))
+            .await
+            .unwrap();
+
+        for text in ["hello", "world"] {
+            socket.send(ClientMessage::Text(text.to_string())).await.unwrap();
+            let reply = socket.next().await.unwrap().unwrap();
+            assert_eq!(reply, ClientMessage::Text(format!("/chat/room {}", text)));
+        }
+        socket.close(None).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_unreachable_backend_rejects_upgrade() {
+        // Nothing listens on this process's port
+        let proxy = spawn_proxy(ws_process("ws_passthrough_down")).await;
+
+        let result = tokio_tungstenite::connect_async(format!("ws://{}/chat/room", proxy)).await;
+        match result {
+            Err(tokio_tungstenite::tungstenite::Error::Http(response)) => {
+                assert_eq!(response.status(), axum::http::StatusCode::BAD_GATEWAY);
+            }
+            other => panic!("expected HTTP error, got {:?}", other.map(|_| ())),
+        }
+    }
+}
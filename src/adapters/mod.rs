@@ -4,5 +4,5 @@ pub mod http;
 pub mod process;
 
 pub use config::XmlProcessRepository;
-pub use http::HttpServerState;
+pub use http::{CorsOptions, HttpServerState, ServerOptions};
 pub use process::TokioProcessOrchestrator;
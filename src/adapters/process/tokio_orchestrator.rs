@@ -2,11 +2,18 @@
 //! This manages the lifecycle of child processes
 
 use crate::domain::repositories::{ProcessOrchestrationService, OrchestrationError};
-use crate::domain::entities::{Process, ProcessId};
+use crate::domain::entities::{Process, ProcessId, ProcessState, ProcessStatus, RestartPolicy};
+use crate::use_cases::STARTUP_GRACE;
 use async_trait::async_trait;
 use std::collections::HashMap;
-use std::process::Stdio;
-use tokio::process::{Child, Command};
+use std::path::{Path, PathBuf};
+use std::process::{ExitStatus, Stdio};
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
+use tokio::process::{Child, ChildStderr, ChildStdin, ChildStdout, Command};
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
 
 /// Implementation of process orchestration using tokio processes
 pub struct TokioProcessOrchestrator {
@@ -15,7 +22,133 @@ pub struct TokioProcessOrchestrator {
 
 struct ManagedProcess {
     config: Process,
-    child: Option<Child>,
+    /// One child per configured instance; empty while stopped
+    children: Vec<Instance>,
+    /// Children being replaced by a reload, kept running until requests
+    /// already sent to them have completed
+    replaced: Vec<Instance>,
+    /// Number of reloads so far, which decides the children's addresses
+    generation: u32,
+    /// When the current children were spawned
+    started_at: Option<Instant>,
+    /// How the process last exited or failed to start, shared with the
+    /// supervisors of its children
+    last_exit: Arc<Mutex<LastExit>>,
+    /// When a request was last routed to the process
+    last_activity: Option<Instant>,
+    /// Whether the current children are known to accept requests
+    ready: bool,
+}
+
+/// A spawned child, owned by a supervisor task that waits for it to exit
+/// and restarts it if the process says to
+struct Instance {
+    /// The current child's id, updated by the supervisor when it restarts
+    /// the child; 0 once it has exited
+    pid: Arc<AtomicU32>,
+    /// Set by the supervisor when the child crashed too often to restart
+    failed: Arc<AtomicBool>,
+    /// The child's pipes, only held so they stay open for as long as it
+    /// runs; the proxy never reads or writes them
+    _stdin: Option<ChildStdin>,
+    _stdout: Option<ChildStdout>,
+    _stderr: Option<ChildStderr>,
+    /// Tells the supervisor to stop the child; dropping it kills the child
+    stop: mpsc::UnboundedSender<StopSignal>,
+    /// The supervisor, finishing with the child's exit status
+    exited: JoinHandle<std::io::Result<ExitStatus>>,
+}
+
+impl Instance {
+    fn pid(&self) -> Option<u32> {
+        Some(self.pid.load(Ordering::Relaxed)).filter(|&pid| pid != 0)
+    }
+
+    /// Whether the supervisor has seen the child exit and won't restart it
+    fn has_exited(&self) -> bool {
+        self.exited.is_finished()
+    }
+}
+
+/// How a supervisor is told to stop its child
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum StopSignal {
+    /// Ask the child to exit (SIGTERM), leaving it time to clean up
+    Terminate,
+    /// Kill the child outright
+    Kill,
+}
+
+/// The last time a process exited or failed to start
+#[derive(Debug, Default)]
+struct LastExit {
+    code: Option<i32>,
+    at: Option<SystemTime>,
+    /// Spawn error, or the signal for a child that was killed from outside
+    error: Option<String>,
+    /// Children restarted after exiting by themselves
+    restarts: u32,
+}
+
+impl LastExit {
+    /// Record a child's exit; children killed by the proxy itself only
+    /// count when they still exit with a code
+    fn record_exit(&mut self, status: ExitStatus, stopped: bool) {
+        match status.code() {
+            Some(code) => {
+                self.code = Some(code);
+                self.error = None;
+            }
+            None if stopped => return,
+            None => {
+                self.code = None;
+                self.error = Some(format!("terminated by {}", status));
+            }
+        }
+        self.at = Some(SystemTime::now());
+    }
+
+    fn record_error(&mut self, error: &OrchestrationError) {
+        self.code = None;
+        self.error = Some(error.to_string());
+        self.at = Some(SystemTime::now());
+    }
+}
+
+impl ManagedProcess {
+    /// Whether the process is running and has had no requests, since it was
+    /// started, for longer than its idle timeout
+    fn is_idle(&self, now: Instant) -> bool {
+        let (Some(timeout), Some(started_at)) = (self.config.idle_timeout, self.started_at) else {
+            return false;
+        };
+        let last_used = self.last_activity.map_or(started_at, |at| at.max(started_at));
+        now.duration_since(last_used) >= timeout
+    }
+
+    /// Processes leave `Starting` when marked ready; those without a health
+    /// check to tell are also assumed ready after [`STARTUP_GRACE`]
+    ///
+    /// Once every child has exited for good the process is down until
+    /// started again: `Stopped` if the last one exited cleanly, `Failed`
+    /// otherwise.
+    fn state(&self) -> ProcessState {
+        let Some(started_at) = self.started_at else {
+            return ProcessState::Stopped;
+        };
+        if self.children.iter().any(|c| c.failed.load(Ordering::Relaxed)) {
+            ProcessState::Failed
+        } else if self.children.iter().all(Instance::has_exited) {
+            match self.last_exit.lock().unwrap().code {
+                Some(0) => ProcessState::Stopped,
+                _ => ProcessState::Failed,
+            }
+        } else if self.ready || (self.config.health_check.is_none() && started_at.elapsed() >= STARTUP_GRACE) {
+            ProcessState::Running
+        } else {
+            ProcessState::Starting
+        }
+    }
 }
 
 impl Default for TokioProcessOrchestrator {
@@ -37,89 +170,547 @@ impl TokioProcessOrchestrator {
             id,
             ManagedProcess {
                 config: process,
-                child: None,
+                children: Vec::new(),
+                replaced: Vec::new(),
+                generation: 0,
+                started_at: None,
+                last_exit: Arc::default(),
+                last_activity: None,
+                ready: false,
             },
         );
     }
 }
 
+/// Resolve a process's executable to a file, the same way spawning it would
+///
+/// Paths are looked up on `PATH` when they are bare names, and relative paths
+/// are resolved against the process's working directory if it has one. The
+/// result is absolute, so it doesn't depend on the directory it is run from,
+/// nor on the environment the process is given: a `PATH` the process
+/// declares is searched instead of the proxy's, and a `clean_env` process
+/// that doesn't declare one still finds what the proxy would.
+pub fn resolve_executable(process: &Process) -> Result<PathBuf, OrchestrationError> {
+    let cwd = match &process.working_directory {
+        Some(dir) => std::path::absolute(dir.as_str()).unwrap_or_else(|_| PathBuf::from(dir.as_str())),
+        None => std::env::current_dir().unwrap_or_else(|_| PathBuf::from(".")),
+    };
+    let executable = process.executable.as_str();
+    // The last declaration wins, as it does when the process is spawned
+    let path = match process.environment.iter().rev().find(|(name, _)| name == "PATH") {
+        Some((_, declared)) => Some(declared.into()),
+        None => std::env::var_os("PATH"),
+    };
+
+    which::which_in(executable, path, &cwd).map_err(|e| {
+        let searched = if Path::new(executable).components().count() > 1 {
+            format!("relative to {}", cwd.display())
+        } else {
+            "on PATH".to_string()
+        };
+        OrchestrationError::ExecutableNotFound(format!(
+            "'{}' for process '{}' not found {} ({})",
+            executable,
+            process.id.as_str(),
+            searched,
+            e
+        ))
+    })
+}
+
+/// What a supervisor needs to restart its child once it exits by itself
+struct Restart {
+    crashes: CrashLoop,
+    /// Builds the command the child was spawned with
+    command: Box<dyn Fn() -> Command + Send>,
+    /// Set when giving up on the child
+    failed: Arc<AtomicBool>,
+}
+
+/// Recent crashes of one instance, deciding how long to wait before
+/// restarting it and when to stop trying
+struct CrashLoop {
+    policy: RestartPolicy,
+    crashes: Vec<Instant>,
+}
+
+impl CrashLoop {
+    fn new(policy: RestartPolicy) -> Self {
+        Self { policy, crashes: Vec::new() }
+    }
+
+    /// Record a crash at `now`, returning how long to wait before
+    /// restarting, or `None` once there were too many within the window
+    fn crashed(&mut self, now: Instant) -> Option<Duration> {
+        let window = self.policy.window;
+        self.crashes.retain(|at| now.duration_since(*at) < window);
+        self.crashes.push(now);
+        let crashes = self.crashes.len() as u32;
+        (crashes < self.policy.max_crashes).then(|| self.policy.backoff(crashes))
+    }
+}
+
+/// Wait for `child` to exit, or stop it when told to, recording how it
+/// exited in `last_exit`
+///
+/// With a `restart`, a child that exits by itself is spawned again after
+/// a backoff, until it crashes too often and the supervisor gives up.
+async fn supervise(
+    id: ProcessId,
+    mut child: Child,
+    mut stop: mpsc::UnboundedReceiver<StopSignal>,
+    last_exit: Arc<Mutex<LastExit>>,
+    pid: Arc<AtomicU32>,
+    mut restart: Option<Restart>,
+) -> std::io::Result<ExitStatus> {
+    let mut terminating = false;
+    // A restarted child's pipes, held open like the first child's
+    let mut _pipes = None;
+    loop {
+        tokio::select! {
+            status = child.wait() => {
+                let status = status?;
+                if !terminating {
+                    tracing::warn!("Process '{}' exited unexpectedly ({})", id.as_str(), status);
+                }
+                last_exit.lock().unwrap().record_exit(status, terminating);
+                pid.store(0, Ordering::Relaxed);
+                let Some(restart) = restart.as_mut().filter(|_| !terminating) else {
+                    return Ok(status);
+                };
+                match respawn(&id, restart, &mut stop, &last_exit).await {
+                    Some(mut respawned) => {
+                        pid.store(respawned.id().unwrap_or(0), Ordering::Relaxed);
+                        _pipes = Some((respawned.stdin.take(), respawned.stdout.take(), respawned.stderr.take()));
+                        child = respawned;
+                    }
+                    None => return Ok(status),
+                }
+            }
+            signal = stop.recv() => match signal {
+                Some(StopSignal::Terminate) if !terminating => {
+                    terminating = true;
+                    terminate(&mut child)?;
+                }
+                Some(StopSignal::Terminate) => {}
+                Some(StopSignal::Kill) | None => {
+                    child.start_kill()?;
+                    let status = child.wait().await?;
+                    last_exit.lock().unwrap().record_exit(status, true);
+                    return Ok(status);
+                }
+            },
+        }
+    }
+}
+
+/// Spawn a crashed child again once its backoff has passed, retrying
+/// spawns that fail the same way
+///
+/// Returns `None` when told to stop while waiting, or when giving up
+/// after too many crashes.
+async fn respawn(
+    id: &ProcessId,
+    restart: &mut Restart,
+    stop: &mut mpsc::UnboundedReceiver<StopSignal>,
+    last_exit: &Mutex<LastExit>,
+) -> Option<Child> {
+    loop {
+        let Some(backoff) = restart.crashes.crashed(Instant::now()) else {
+            tracing::error!(
+                "Process '{}' crashed {} times within {:?}; not restarting it until it is reloaded",
+                id.as_str(),
+                restart.crashes.policy.max_crashes,
+                restart.crashes.policy.window
+            );
+            restart.failed.store(true, Ordering::Relaxed);
+            return None;
+        };
+        tracing::info!("Restarting process '{}' in {:?}", id.as_str(), backoff);
+        tokio::select! {
+            _ = tokio::time::sleep(backoff) => {}
+            // Nothing is running to stop
+            _ = stop.recv() => return None,
+        }
+
+        match (restart.command)().spawn() {
+            Ok(child) => {
+                last_exit.lock().unwrap().restarts += 1;
+                return Some(child);
+            }
+            Err(e) => {
+                tracing::error!("Failed to restart process '{}': {}", id.as_str(), e);
+                last_exit.lock().unwrap().record_error(&OrchestrationError::SpawnFailed(e.to_string()));
+            }
+        }
+    }
+}
+
+/// Ask `child` to exit with SIGTERM
+#[cfg(unix)]
+fn terminate(child: &mut Child) -> std::io::Result<()> {
+    let Some(pid) = child.id() else {
+        // Already reaped
+        return Ok(());
+    };
+    // SAFETY: `pid` is our own child, which hasn't been reaped yet since
+    // `child` still holds its id
+    if unsafe { libc::kill(pid as libc::pid_t, libc::SIGTERM) } == 0 {
+        Ok(())
+    } else {
+        Err(std::io::Error::last_os_error())
+    }
+}
+
+/// Without signals there is no gentler way to stop a child than killing it
+#[cfg(not(unix))]
+fn terminate(child: &mut Child) -> std::io::Result<()> {
+    child.start_kill()
+}
+
+/// Spawn one child per instance of `config`, on the addresses it declares,
+/// each with a supervisor recording its exit in `last_exit`
+///
+/// If any instance fails to spawn, those already started are killed.
+fn spawn_instances(config: &Process, last_exit: &Arc<Mutex<LastExit>>) -> Result<Vec<Instance>, OrchestrationError> {
+    // Resolved up front so a relative path means the same thing here as it
+    // did when the process was validated: relative to its working directory
+    let executable = resolve_executable(config)?;
+
+    let mut children = Vec::new();
+    let instances = config
+        .instance_addresses()
+        .into_iter()
+        .zip(config.instance_http_addresses());
+    for (address, http_address) in instances {
+        if config.listens_on_http() && address_in_use(&http_address) {
+            tracing::warn!(
+                "Address {} for process '{}' is already in use, so the process won't be able to listen there \
+                 and requests for it may reach whatever holds the port; give it a free http_port",
+                http_address,
+                config.id.as_str()
+            );
+        }
+
+        match instance_command(config, &executable, &address, &http_address).spawn() {
+            Ok(mut child) => {
+                let (stop, stop_signals) = mpsc::unbounded_channel();
+                let pid = Arc::new(AtomicU32::new(child.id().unwrap_or(0)));
+                let failed = Arc::new(AtomicBool::new(false));
+                let restart = config.restart.clone().map(|policy| {
+                    let (config, executable) = (config.clone(), executable.clone());
+                    Restart {
+                        crashes: CrashLoop::new(policy),
+                        command: Box::new(move || instance_command(&config, &executable, &address, &http_address)),
+                        failed: failed.clone(),
+                    }
+                });
+                children.push(Instance {
+                    pid: pid.clone(),
+                    failed,
+                    _stdin: child.stdin.take(),
+                    _stdout: child.stdout.take(),
+                    _stderr: child.stderr.take(),
+                    stop,
+                    exited: tokio::spawn(supervise(
+                        config.id.clone(),
+                        child,
+                        stop_signals,
+                        last_exit.clone(),
+                        pid,
+                        restart,
+                    )),
+                });
+            }
+            Err(e) => {
+                // Dropping the instances already started kills them, so
+                // no partially started process is left behind
+                drop(children);
+                return Err(spawn_failed(config, e));
+            }
+        }
+    }
+
+    Ok(children)
+}
+
+/// The command running one instance of `config`, told where to listen
+fn instance_command(config: &Process, executable: &Path, address: &str, http_address: &str) -> Command {
+    use crate::domain::entities::CommunicationMode;
+
+    // Set environment variable based on communication mode
+    let address_var = match config.communication_mode {
+        CommunicationMode::Pipe => "PIPE_ADDRESS",
+        CommunicationMode::Http | CommunicationMode::Grpc => "HTTP_ADDRESS",
+    };
+
+    let mut command = Command::new(executable);
+    command.args(config.instance_arguments(address, http_address));
+    command.stdin(Stdio::piped());
+    command.stdout(Stdio::piped());
+    command.stderr(Stdio::piped());
+    // In case the supervisor never gets to kill it, e.g. at shutdown
+    command.kill_on_drop(true);
+
+    if let Some(working_dir) = &config.working_directory {
+        command.current_dir(working_dir.as_str());
+    }
+
+    // Only the declared variables and the proxy's own reach an isolated process
+    if config.clean_env {
+        command.env_clear();
+    }
+    command.envs(config.environment.iter().map(|(k, v)| (k, v)));
+
+    // Switched to in the child just before it execs, so a failure shows up
+    // as a spawn error
+    #[cfg(unix)]
+    {
+        if let Some(uid) = config.uid {
+            command.uid(uid);
+        }
+        if let Some(gid) = config.gid {
+            command.gid(gid);
+        }
+    }
+
+    command.env(address_var, address);
+    command.env("PIPE_PROTOCOL", config.protocol.as_str());
+    tracing::debug!("Using {}: {}", address_var, address);
+
+    // A pipe process that may be reached over HTTP needs to know where to listen
+    if config.http_fallback && config.communication_mode == CommunicationMode::Pipe {
+        command.env("HTTP_ADDRESS", http_address);
+    }
+    command
+}
+
+/// Why an instance of `config` couldn't be spawned, naming the user and
+/// group it was to run as, if any
+fn spawn_failed(config: &Process, error: std::io::Error) -> OrchestrationError {
+    let identity: Vec<String> = [("uid", config.uid), ("gid", config.gid)]
+        .into_iter()
+        .filter_map(|(name, id)| Some(format!("{} {}", name, id?)))
+        .collect();
+    if identity.is_empty() {
+        return OrchestrationError::SpawnFailed(error.to_string());
+    }
+    let hint = if error.kind() == std::io::ErrorKind::PermissionDenied {
+        "; switching users needs the proxy to run with the privilege to do so"
+    } else {
+        ""
+    };
+    OrchestrationError::SpawnFailed(format!(
+        "'{}' as {}: {}{}",
+        config.id.as_str(),
+        identity.join(", "),
+        error,
+        hint
+    ))
+}
+
+/// Whether something already listens on the TCP `address`
+fn address_in_use(address: &str) -> bool {
+    matches!(std::net::TcpListener::bind(address), Err(e) if e.kind() == std::io::ErrorKind::AddrInUse)
+}
+
+/// Kill each child that is still running and wait for it to exit
+///
+/// A child that already exited keeps its own exit status.
+async fn stop_children(children: Vec<Instance>) -> Result<(), OrchestrationError> {
+    for instance in children {
+        // Fails only once the supervisor has seen the child exit by itself
+        let _ = instance.stop.send(StopSignal::Kill);
+        instance
+            .exited
+            .await
+            .map_err(|e| OrchestrationError::KillFailed(e.to_string()))?
+            .map_err(|e| OrchestrationError::KillFailed(e.to_string()))?;
+    }
+    Ok(())
+}
+
 #[async_trait]
 impl ProcessOrchestrationService for TokioProcessOrchestrator {
     async fn start_process(&mut self, id: &ProcessId) -> Result<(), OrchestrationError> {
-        use crate::domain::entities::CommunicationMode;
-        use crate::domain::utils::{get_pipe_address_from_name, get_http_address_from_name};
-        
         let process = self
             .processes
             .get_mut(id)
             .ok_or_else(|| OrchestrationError::ProcessNotFound(id.as_str().to_string()))?;
 
-        if process.child.is_some() {
+        if !process.children.is_empty() {
             return Err(OrchestrationError::AlreadyRunning(id.as_str().to_string()));
         }
 
-        tracing::info!("Starting process '{}': {} (mode: {:?})", 
-            id.as_str(), process.config.executable.as_str(), process.config.communication_mode);
+        tracing::info!("Starting process '{}': {} (mode: {:?}, instances: {})", 
+            id.as_str(), process.config.executable.as_str(), process.config.communication_mode,
+            process.config.instances);
+
+        // Kept so a process that never started can be told apart from one that crashed
+        process.children = spawn_instances(&process.config.for_generation(process.generation), &process.last_exit)
+            .inspect_err(|e| process.last_exit.lock().unwrap().record_error(e))?;
+        process.started_at = Some(Instant::now());
+        process.ready = false;
+        tracing::info!("Process '{}' started successfully", id.as_str());
 
-        let mut command = Command::new(process.config.executable.as_str());
-        command.args(&process.config.arguments);
-        command.stdin(Stdio::piped());
-        command.stdout(Stdio::piped());
-        command.stderr(Stdio::piped());
+        Ok(())
+    }
 
-        if let Some(working_dir) = &process.config.working_directory {
-            command.current_dir(working_dir.as_str());
+    async fn stop_process(&mut self, id: &ProcessId) -> Result<(), OrchestrationError> {
+        let process = self
+            .processes
+            .get_mut(id)
+            .ok_or_else(|| OrchestrationError::ProcessNotFound(id.as_str().to_string()))?;
+
+        if process.children.is_empty() {
+            tracing::warn!("Process '{}' is not running", id.as_str());
+            return Ok(());
         }
 
-        // Set environment variable based on communication mode
-        match process.config.communication_mode {
-            CommunicationMode::Pipe => {
-                let pipe_address = get_pipe_address_from_name(process.config.pipe_name.as_str());
-                command.env("PIPE_ADDRESS", &pipe_address);
-                tracing::debug!("Using pipe address: {}", pipe_address);
-            }
-            CommunicationMode::Http => {
-                let http_address = get_http_address_from_name(process.config.pipe_name.as_str());
-                command.env("HTTP_ADDRESS", &http_address);
-                tracing::debug!("Using HTTP address: {}", http_address);
-            }
+        tracing::info!("Stopping process '{}'", id.as_str());
+        process.started_at = None;
+        stop_children(std::mem::take(&mut process.replaced)).await?;
+        stop_children(std::mem::take(&mut process.children)).await?;
+        tracing::info!("Process '{}' stopped", id.as_str());
+
+        Ok(())
+    }
+
+    async fn restart_process(&mut self, id: &ProcessId) -> Result<(), OrchestrationError> {
+        if self.is_running(id) {
+            self.stop_process(id).await?;
         }
+        self.start_process(id).await
+    }
 
-        let child = command
-            .spawn()
-            .map_err(|e| OrchestrationError::SpawnFailed(e.to_string()))?;
+    async fn start_replacement(&mut self, id: &ProcessId) -> Result<u32, OrchestrationError> {
+        let process = self
+            .processes
+            .get_mut(id)
+            .ok_or_else(|| OrchestrationError::ProcessNotFound(id.as_str().to_string()))?;
 
-        process.child = Some(child);
-        tracing::info!("Process '{}' started successfully", id.as_str());
+        if process.children.is_empty() {
+            return Err(OrchestrationError::NotRunning(id.as_str().to_string()));
+        }
+        if !process.replaced.is_empty() {
+            return Err(OrchestrationError::AlreadyRunning(format!("{} (reload in progress)", id.as_str())));
+        }
 
+        let generation = process.generation + 1;
+        tracing::info!("Starting replacement for process '{}' (generation {})", id.as_str(), generation);
+        let children = spawn_instances(&process.config.for_generation(generation), &process.last_exit)?;
+
+        process.replaced = std::mem::replace(&mut process.children, children);
+        process.generation = generation;
+        process.started_at = Some(Instant::now());
+        Ok(generation)
+    }
+
+    async fn stop_replaced(&mut self, id: &ProcessId) -> Result<(), OrchestrationError> {
+        let process = self
+            .processes
+            .get_mut(id)
+            .ok_or_else(|| OrchestrationError::ProcessNotFound(id.as_str().to_string()))?;
+
+        tracing::info!("Stopping replaced instances of process '{}'", id.as_str());
+        stop_children(std::mem::take(&mut process.replaced)).await?;
         Ok(())
     }
 
-    async fn stop_process(&mut self, id: &ProcessId) -> Result<(), OrchestrationError> {
+    async fn abandon_replacement(&mut self, id: &ProcessId) -> Result<(), OrchestrationError> {
         let process = self
             .processes
             .get_mut(id)
             .ok_or_else(|| OrchestrationError::ProcessNotFound(id.as_str().to_string()))?;
 
-        if let Some(mut child) = process.child.take() {
-            tracing::info!("Stopping process '{}'", id.as_str());
-            child
-                .kill()
-                .await
-                .map_err(|e| OrchestrationError::KillFailed(e.to_string()))?;
-            tracing::info!("Process '{}' stopped", id.as_str());
-        } else {
-            tracing::warn!("Process '{}' is not running", id.as_str());
+        if process.replaced.is_empty() {
+            return Ok(());
         }
 
+        tracing::warn!("Abandoning replacement for process '{}'", id.as_str());
+        let replacement = std::mem::replace(&mut process.children, std::mem::take(&mut process.replaced));
+        process.generation -= 1;
+        stop_children(replacement).await?;
         Ok(())
     }
 
+    fn mark_ready(&mut self, id: &ProcessId) {
+        if let Some(process) = self.processes.get_mut(id) {
+            if !process.children.is_empty() && !process.ready {
+                tracing::debug!("Process '{}' is ready", id.as_str());
+                process.ready = true;
+            }
+        }
+    }
+
+    fn record_activity(&mut self, id: &ProcessId) {
+        if let Some(process) = self.processes.get_mut(id) {
+            process.last_activity = Some(Instant::now());
+        }
+    }
+
+    async fn stop_idle(&mut self) -> Vec<ProcessId> {
+        let now = Instant::now();
+        let idle: Vec<ProcessId> = self
+            .processes
+            .iter()
+            .filter(|(_, p)| p.is_idle(now))
+            .map(|(id, _)| id.clone())
+            .collect();
+
+        let mut stopped = Vec::new();
+        for id in idle {
+            tracing::info!("Process '{}' is idle, stopping it", id.as_str());
+            match self.stop_process(&id).await {
+                Ok(()) => stopped.push(id),
+                Err(e) => tracing::error!("Failed to stop idle process '{}': {}", id.as_str(), e),
+            }
+        }
+        stopped
+    }
+
+    fn validate_all(&self) -> Result<(), OrchestrationError> {
+        let failures: Vec<String> = self
+            .processes
+            .values()
+            .filter_map(|p| resolve_executable(&p.config).err())
+            .map(|e| {
+                tracing::error!("{}", e);
+                e.to_string()
+            })
+            .collect();
+
+        if failures.is_empty() {
+            Ok(())
+        } else {
+            Err(OrchestrationError::ExecutableNotFound(failures.join("; ")))
+        }
+    }
+
     fn is_running(&self, id: &ProcessId) -> bool {
         self.processes
             .get(id)
-            .and_then(|p| p.child.as_ref())
-            .is_some()
+            .is_some_and(|p| !p.children.is_empty())
+    }
+
+    fn status(&self, id: &ProcessId) -> Option<ProcessStatus> {
+        let process = self.processes.get(id)?;
+        let last_exit = process.last_exit.lock().unwrap();
+        // Children that exited, or are waiting to be restarted, don't count
+        let running = process.children.iter().any(|c| c.pid().is_some());
+        Some(ProcessStatus {
+            running,
+            uptime: process.started_at.filter(|_| running).map(|t| t.elapsed()),
+            pid: process.children.first().and_then(Instance::pid),
+            last_exit_code: last_exit.code,
+            last_exit_at: last_exit.at,
+            last_error: last_exit.error.clone(),
+            restart_count: last_exit.restarts,
+        })
+    }
+
+    fn state(&self, id: &ProcessId) -> Option<ProcessState> {
+        self.processes.get(id).map(ManagedProcess::state)
     }
 
     async fn start_all(&mut self) -> Result<(), OrchestrationError> {
@@ -145,14 +736,54 @@ impl ProcessOrchestrationService for TokioProcessOrchestrator {
 
         Ok(())
     }
+
+    async fn shutdown(&mut self, timeout: Duration) -> Vec<ProcessId> {
+        let deadline = tokio::time::Instant::now() + timeout;
+
+        // Ask every child to exit at once, so they all get the whole timeout
+        let mut stopping = Vec::new();
+        for (id, process) in self.processes.iter_mut() {
+            if process.children.is_empty() && process.replaced.is_empty() {
+                continue;
+            }
+            tracing::info!("Stopping process '{}'", id.as_str());
+            process.started_at = None;
+            let instances: Vec<Instance> = process.replaced.drain(..).chain(process.children.drain(..)).collect();
+            for instance in &instances {
+                let _ = instance.stop.send(StopSignal::Terminate);
+            }
+            stopping.push((id.clone(), instances));
+        }
+
+        let mut killed = Vec::new();
+        for (id, instances) in stopping {
+            let mut force_killed = false;
+            for mut instance in instances {
+                if tokio::time::timeout_at(deadline, &mut instance.exited).await.is_err() {
+                    force_killed = true;
+                    let _ = instance.stop.send(StopSignal::Kill);
+                    let _ = instance.exited.await;
+                }
+            }
+            if force_killed {
+                tracing::warn!("Process '{}' did not exit within {:?}; killed it", id.as_str(), timeout);
+                killed.push(id);
+            } else {
+                tracing::info!("Process '{}' stopped", id.as_str());
+            }
+        }
+        killed
+    }
 }
 
 impl Drop for TokioProcessOrchestrator {
     fn drop(&mut self) {
         for (id, process) in self.processes.iter_mut() {
-            if let Some(mut child) = process.child.take() {
+            if !process.children.is_empty() {
                 tracing::info!("Cleaning up process '{}'", id.as_str());
-                let _ = child.start_kill();
+            }
+            for instance in process.children.drain(..) {
+                let _ = instance.stop.send(StopSignal::Kill);
             }
         }
     }
@@ -161,18 +792,17 @@ impl Drop for TokioProcessOrchestrator {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::domain::entities::{Executable, Route, PipeName};
+    use crate::domain::entities::{CommunicationMode, Executable, Route, PipeName, WorkingDirectory};
 
     fn create_test_process(id: &str) -> Process {
-        Process {
-            id: ProcessId::new(id).unwrap(),
-            executable: Executable::new("sleep").unwrap(),
-            arguments: vec!["0.1".to_string()],
-            route: Route::new("/test").unwrap(),
-            pipe_name: PipeName::new("test_pipe").unwrap(),
-            working_directory: None,
-            communication_mode: crate::domain::entities::CommunicationMode::Pipe,
-        }
+        let mut process = Process::new(
+            ProcessId::new(id).unwrap(),
+            Executable::new("sleep").unwrap(),
+            Route::new("/test").unwrap(),
+            PipeName::new("test_pipe").unwrap(),
+        );
+        process.arguments = vec!["0.1".to_string()];
+        process
     }
 
     #[tokio::test]
@@ -190,4 +820,454 @@ mod tests {
 
         orchestrator.stop_process(&id).await.ok();
     }
+
+    #[tokio::test]
+    async fn test_start_multiple_instances() {
+        let mut orchestrator = TokioProcessOrchestrator::new();
+        let mut process = create_test_process("multi");
+        process.instances = 3;
+        let id = process.id.clone();
+
+        orchestrator.register(process);
+        orchestrator.start_process(&id).await.unwrap();
+        assert_eq!(orchestrator.processes[&id].children.len(), 3);
+
+        orchestrator.stop_process(&id).await.unwrap();
+        assert!(!orchestrator.is_running(&id));
+    }
+
+    #[tokio::test]
+    async fn test_state_tracks_readiness() {
+        let mut orchestrator = TokioProcessOrchestrator::new();
+        let mut process = create_test_process("ready");
+        process.arguments = vec!["5".to_string()];
+        process.health_check = Some(crate::domain::entities::HealthCheck {
+            path: "/healthz".to_string(),
+            interval: std::time::Duration::from_secs(1),
+        });
+        let id = process.id.clone();
+        orchestrator.register(process);
+        assert_eq!(orchestrator.state(&id), Some(ProcessState::Stopped));
+
+        orchestrator.start_process(&id).await.unwrap();
+        assert_eq!(orchestrator.state(&id), Some(ProcessState::Starting));
+        orchestrator.mark_ready(&id);
+        assert_eq!(orchestrator.state(&id), Some(ProcessState::Running));
+
+        // A restart has to become ready again
+        orchestrator.restart_process(&id).await.unwrap();
+        assert_eq!(orchestrator.state(&id), Some(ProcessState::Starting));
+
+        orchestrator.stop_process(&id).await.unwrap();
+        assert_eq!(orchestrator.state(&id), Some(ProcessState::Stopped));
+    }
+
+    #[tokio::test]
+    async fn test_restart_process() {
+        let mut orchestrator = TokioProcessOrchestrator::new();
+        let mut process = create_test_process("restart");
+        process.arguments = vec!["5".to_string()];
+        let id = process.id.clone();
+        orchestrator.register(process);
+
+        // Restarting a stopped process just starts it
+        orchestrator.restart_process(&id).await.unwrap();
+        let first_pid = orchestrator.status(&id).unwrap().pid;
+        assert!(first_pid.is_some());
+
+        orchestrator.restart_process(&id).await.unwrap();
+        let status = orchestrator.status(&id).unwrap();
+        assert!(status.running);
+        // Only restarts after crashes are counted
+        assert_eq!(status.restart_count, 0);
+        assert_ne!(status.pid, first_pid);
+
+        orchestrator.stop_process(&id).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_status() {
+        let mut orchestrator = TokioProcessOrchestrator::new();
+        let mut process = create_test_process("status");
+        process.executable = Executable::new("sh").unwrap();
+        process.arguments = vec!["-c".to_string(), "exit 3".to_string()];
+        let id = process.id.clone();
+        orchestrator.register(process);
+
+        assert!(orchestrator.status(&ProcessId::new("unknown").unwrap()).is_none());
+        let status = orchestrator.status(&id).unwrap();
+        assert!(!status.running);
+        assert_eq!(status.uptime, None);
+        assert_eq!(status.pid, None);
+
+        orchestrator.start_process(&id).await.unwrap();
+        let status = orchestrator.status(&id).unwrap();
+        assert!(status.running);
+        assert!(status.uptime.is_some());
+        assert!(status.pid.is_some());
+
+        assert_eq!(status.last_exit_at, None);
+
+        // The exit is recorded as it happens, without stopping the process
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+        let status = orchestrator.status(&id).unwrap();
+        assert!(!status.running);
+        assert_eq!(status.uptime, None);
+        assert_eq!(status.pid, None);
+        assert_eq!(orchestrator.state(&id), Some(ProcessState::Failed));
+        assert_eq!(status.last_exit_code, Some(3));
+        assert!(status.last_exit_at.is_some_and(|at| at <= SystemTime::now()));
+        assert_eq!(status.last_error, None);
+
+        orchestrator.stop_process(&id).await.unwrap();
+        let status = orchestrator.status(&id).unwrap();
+        assert!(!status.running);
+        assert_eq!(status.last_exit_code, Some(3));
+        assert_eq!(status.restart_count, 0);
+    }
+
+    #[tokio::test]
+    async fn test_crashing_process_is_restarted_with_backoff_until_failed() {
+        let mut orchestrator = TokioProcessOrchestrator::new();
+        let mut process = create_test_process("crashing");
+        process.executable = Executable::new("sh").unwrap();
+        // Living briefly, so each restart is seen before the next crash
+        process.arguments = vec!["-c".to_string(), "sleep 0.05; exit 1".to_string()];
+        process.restart = Some(RestartPolicy {
+            initial_backoff: Duration::from_millis(40),
+            max_backoff: Duration::from_secs(1),
+            max_crashes: 4,
+            window: Duration::from_secs(60),
+        });
+        let id = process.id.clone();
+        orchestrator.register(process);
+        orchestrator.start_process(&id).await.unwrap();
+
+        // Note when each restart is seen, until the supervisor gives up
+        let mut restarts = Vec::new();
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while orchestrator.state(&id) != Some(ProcessState::Failed) {
+            assert!(Instant::now() < deadline, "never marked failed");
+            let count = orchestrator.status(&id).unwrap().restart_count as usize;
+            if count > restarts.len() {
+                restarts.push(Instant::now());
+            }
+            tokio::time::sleep(Duration::from_millis(2)).await;
+        }
+
+        // Three crashes were restarted after 40, 80 and 160ms; the fourth
+        // was one too many
+        let status = orchestrator.status(&id).unwrap();
+        assert_eq!(status.restart_count, 3);
+        assert_eq!(status.last_exit_code, Some(1));
+        assert_eq!(restarts.len(), 3);
+        let tolerance = Duration::from_millis(5);
+        assert!(restarts[1] - restarts[0] + tolerance >= Duration::from_millis(80));
+        assert!(restarts[2] - restarts[1] + tolerance >= Duration::from_millis(160));
+
+        // It stays down until restarted from outside
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert_eq!(orchestrator.status(&id).unwrap().restart_count, 3);
+        orchestrator.restart_process(&id).await.unwrap();
+        assert_eq!(orchestrator.state(&id), Some(ProcessState::Starting));
+        orchestrator.stop_process(&id).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_status_records_spawn_failure() {
+        let mut orchestrator = TokioProcessOrchestrator::new();
+        let mut process = create_test_process("broken");
+        process.executable = Executable::new("./definitely/not/here").unwrap();
+        let id = process.id.clone();
+        orchestrator.register(process);
+
+        assert!(orchestrator.start_process(&id).await.is_err());
+        let status = orchestrator.status(&id).unwrap();
+        assert!(!status.running);
+        assert_eq!(status.last_exit_code, None);
+        assert!(status.last_exit_at.is_some());
+        let error = status.last_error.unwrap();
+        assert!(error.contains("'./definitely/not/here' for process 'broken' not found"), "{}", error);
+    }
+
+    #[tokio::test]
+    async fn test_stopped_process_records_no_error() {
+        let mut orchestrator = TokioProcessOrchestrator::new();
+        let mut process = create_test_process("stopped");
+        process.arguments = vec!["5".to_string()];
+        let id = process.id.clone();
+        orchestrator.register(process);
+
+        orchestrator.start_process(&id).await.unwrap();
+        orchestrator.stop_process(&id).await.unwrap();
+        let status = orchestrator.status(&id).unwrap();
+        assert_eq!(status.last_exit_code, None);
+        assert_eq!(status.last_error, None);
+    }
+
+    #[tokio::test]
+    async fn test_stop_idle() {
+        let mut orchestrator = TokioProcessOrchestrator::new();
+        let mut idle = create_test_process("idle");
+        idle.arguments = vec!["5".to_string()];
+        idle.idle_timeout = Some(std::time::Duration::from_millis(100));
+        let idle_id = idle.id.clone();
+        let mut always_on = create_test_process("always-on");
+        always_on.arguments = vec!["5".to_string()];
+        let always_on_id = always_on.id.clone();
+        orchestrator.register(idle);
+        orchestrator.register(always_on);
+        orchestrator.start_all().await.unwrap();
+
+        // Activity keeps the process alive past its timeout
+        tokio::time::sleep(std::time::Duration::from_millis(60)).await;
+        orchestrator.record_activity(&idle_id);
+        tokio::time::sleep(std::time::Duration::from_millis(60)).await;
+        assert!(orchestrator.stop_idle().await.is_empty());
+
+        tokio::time::sleep(std::time::Duration::from_millis(60)).await;
+        assert_eq!(orchestrator.stop_idle().await, vec![idle_id.clone()]);
+        assert!(!orchestrator.is_running(&idle_id));
+        assert!(orchestrator.is_running(&always_on_id));
+
+        // Activity from before a restart doesn't count against the new run
+        orchestrator.start_process(&idle_id).await.unwrap();
+        assert!(orchestrator.stop_idle().await.is_empty());
+
+        orchestrator.stop_all().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_replacement_runs_alongside_current_instances() {
+        let mut orchestrator = TokioProcessOrchestrator::new();
+        let mut process = create_test_process("reload");
+        process.arguments = vec!["5".to_string()];
+        let id = process.id.clone();
+        orchestrator.register(process);
+
+        assert!(matches!(orchestrator.start_replacement(&id).await, Err(OrchestrationError::NotRunning(_))));
+
+        orchestrator.start_process(&id).await.unwrap();
+        let original_pid = orchestrator.status(&id).unwrap().pid;
+
+        // Abandoning keeps the original instances
+        assert_eq!(orchestrator.start_replacement(&id).await.unwrap(), 1);
+        assert_eq!(orchestrator.processes[&id].replaced.len(), 1);
+        orchestrator.abandon_replacement(&id).await.unwrap();
+        assert_eq!(orchestrator.status(&id).unwrap().pid, original_pid);
+
+        // Completing switches to the new ones
+        assert_eq!(orchestrator.start_replacement(&id).await.unwrap(), 1);
+        orchestrator.stop_replaced(&id).await.unwrap();
+        let status = orchestrator.status(&id).unwrap();
+        assert!(status.running);
+        assert_ne!(status.pid, original_pid);
+        assert_eq!(status.restart_count, 0);
+        assert!(orchestrator.processes[&id].replaced.is_empty());
+
+        orchestrator.stop_process(&id).await.unwrap();
+    }
+
+    /// Start a process and collect everything its first instance writes to stdout
+    async fn run_to_completion(orchestrator: &mut TokioProcessOrchestrator, id: &ProcessId) -> String {
+        use tokio::io::AsyncReadExt;
+
+        orchestrator.start_process(id).await.unwrap();
+        let mut stdout = orchestrator.processes.get_mut(id).unwrap().children[0]._stdout.take().unwrap();
+        let mut output = String::new();
+        stdout.read_to_string(&mut output).await.unwrap();
+        orchestrator.stop_process(id).await.unwrap();
+        output
+    }
+
+    #[tokio::test]
+    async fn test_clean_env_isolates_process() {
+        std::env::set_var("LOCAL_LAMBDAS_TEST_SECRET", "leaked");
+
+        let mut orchestrator = TokioProcessOrchestrator::new();
+        for (id, clean_env) in [("isolated", true), ("inheriting", false)] {
+            let mut process = create_test_process(id);
+            process.executable = Executable::new("sh").unwrap();
+            process.arguments = vec![
+                "-c".to_string(),
+                "echo \"secret=$LOCAL_LAMBDAS_TEST_SECRET declared=$DECLARED pipe=${PIPE_ADDRESS:+set}\"".to_string(),
+            ];
+            process.environment = vec![("DECLARED".to_string(), "yes".to_string())];
+            process.clean_env = clean_env;
+            orchestrator.register(process);
+        }
+
+        let isolated = run_to_completion(&mut orchestrator, &ProcessId::new("isolated").unwrap()).await;
+        assert_eq!(isolated.trim(), "secret= declared=yes pipe=set");
+
+        let inheriting = run_to_completion(&mut orchestrator, &ProcessId::new("inheriting").unwrap()).await;
+        assert_eq!(inheriting.trim(), "secret=leaked declared=yes pipe=set");
+    }
+
+    #[tokio::test]
+    async fn test_arguments_are_given_the_instance_address() {
+        let mut process = create_test_process("templated");
+        process.executable = Executable::new("sh").unwrap();
+        process.arguments = vec![
+            "-c".to_string(),
+            "echo \"$0 $1\"".to_string(),
+            "--listen=${ADDRESS}".to_string(),
+            "--port=${PORT}".to_string(),
+        ];
+        process.communication_mode = CommunicationMode::Http;
+        process.http_port = Some(9123);
+        let id = process.id.clone();
+        let mut orchestrator = TokioProcessOrchestrator::new();
+        orchestrator.register(process);
+
+        let output = run_to_completion(&mut orchestrator, &id).await;
+        assert_eq!(output.trim(), "--listen=127.0.0.1:9123 --port=9123");
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_process_runs_as_configured_user() {
+        let mut process = create_test_process("nobody");
+        process.executable = Executable::new("sh").unwrap();
+        process.arguments = vec!["-c".to_string(), "echo \"$(id -u) $(id -g)\"".to_string()];
+        process.uid = Some(65534);
+        process.gid = Some(65534);
+        let id = process.id.clone();
+        let mut orchestrator = TokioProcessOrchestrator::new();
+        orchestrator.register(process);
+
+        // SAFETY: geteuid has no preconditions and can't fail
+        if unsafe { libc::geteuid() } == 0 {
+            let output = run_to_completion(&mut orchestrator, &id).await;
+            assert_eq!(output.trim(), "65534 65534");
+        } else {
+            // Without the privilege to switch users the spawn fails, saying why
+            let error = orchestrator.start_process(&id).await.unwrap_err().to_string();
+            assert!(error.contains("'nobody' as uid 65534, gid 65534"), "{}", error);
+            assert!(error.contains("privilege"), "{}", error);
+        }
+    }
+
+    #[test]
+    fn test_executable_resolved_on_path() {
+        let process = create_test_process("on-path");
+        let resolved = resolve_executable(&process).unwrap();
+        assert!(resolved.is_absolute(), "{}", resolved.display());
+
+        let mut missing = create_test_process("missing");
+        missing.executable = Executable::new("local-lambdas-no-such-binary").unwrap();
+        let OrchestrationError::ExecutableNotFound(msg) = resolve_executable(&missing).unwrap_err() else {
+            panic!("expected ExecutableNotFound");
+        };
+        assert!(
+            msg.starts_with("'local-lambdas-no-such-binary' for process 'missing' not found on PATH"),
+            "{}",
+            msg
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_bare_executable_uses_declared_path() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::TempDir::new().unwrap();
+        let script = dir.path().join("local-lambdas-tool");
+        std::fs::write(&script, "#!/bin/sh\n").unwrap();
+        std::fs::set_permissions(&script, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+        let mut process = create_test_process("tool");
+        process.executable = Executable::new("local-lambdas-tool").unwrap();
+        process.clean_env = true;
+        assert!(resolve_executable(&process).is_err());
+
+        process.environment = vec![("PATH".to_string(), dir.path().to_str().unwrap().to_string())];
+        assert_eq!(resolve_executable(&process).unwrap(), script);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_shutdown_kills_processes_ignoring_sigterm_at_deadline() {
+        let mut orchestrator = TokioProcessOrchestrator::new();
+        let mut process = create_test_process("polite");
+        process.arguments = vec!["30".to_string()];
+        let polite = process.id.clone();
+        orchestrator.register(process);
+        let mut process = create_test_process("stubborn");
+        process.executable = Executable::new("sh").unwrap();
+        process.arguments = vec!["-c".to_string(), "trap '' TERM; exec sleep 30".to_string()];
+        let stubborn = process.id.clone();
+        orchestrator.register(process);
+        orchestrator.register(create_test_process("never_started"));
+        orchestrator.start_process(&polite).await.unwrap();
+        orchestrator.start_process(&stubborn).await.unwrap();
+        // Give the shell time to install its trap
+        tokio::time::sleep(Duration::from_millis(200)).await;
+
+        let started = Instant::now();
+        let killed = orchestrator.shutdown(Duration::from_millis(500)).await;
+        let took = started.elapsed();
+
+        assert_eq!(killed, vec![stubborn.clone()]);
+        assert!(took >= Duration::from_millis(500), "{:?}", took);
+        assert!(took < Duration::from_secs(5), "{:?}", took);
+        assert!(!orchestrator.is_running(&polite));
+        assert!(!orchestrator.is_running(&stubborn));
+        // Stopping a process isn't a crash
+        assert_eq!(orchestrator.status(&polite).unwrap().last_error, None);
+    }
+
+    #[tokio::test]
+    async fn test_shutdown_waits_only_as_long_as_processes_take() {
+        let mut orchestrator = TokioProcessOrchestrator::new();
+        let mut process = create_test_process("polite");
+        process.arguments = vec!["30".to_string()];
+        let id = process.id.clone();
+        orchestrator.register(process);
+        orchestrator.start_process(&id).await.unwrap();
+
+        let started = Instant::now();
+        assert!(orchestrator.shutdown(Duration::from_secs(10)).await.is_empty());
+        assert!(started.elapsed() < Duration::from_secs(5));
+        assert!(!orchestrator.is_running(&id));
+    }
+
+    #[test]
+    fn test_address_in_use_detects_a_listener() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        assert!(address_in_use(&address));
+        drop(listener);
+        assert!(!address_in_use(&address));
+    }
+
+    #[test]
+    fn test_missing_executable_fails_validation() {
+        let mut orchestrator = TokioProcessOrchestrator::new();
+        orchestrator.register(create_test_process("ok"));
+        let mut bogus = create_test_process("bogus");
+        bogus.executable = Executable::new("./definitely/not/here").unwrap();
+        orchestrator.register(bogus);
+
+        let error = orchestrator.validate_all().unwrap_err();
+        assert!(matches!(&error, OrchestrationError::ExecutableNotFound(msg) if msg.contains("bogus")));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_relative_executable_uses_working_directory() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::TempDir::new().unwrap();
+        let script = dir.path().join("run.sh");
+        std::fs::write(&script, "#!/bin/sh\n").unwrap();
+        std::fs::set_permissions(&script, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+        let mut process = create_test_process("relative");
+        process.executable = Executable::new("./run.sh").unwrap();
+        assert!(resolve_executable(&process).is_err());
+
+        process.working_directory = Some(WorkingDirectory::new(dir.path().to_str().unwrap()));
+        assert!(resolve_executable(&process).is_ok());
+    }
 }
@@ -0,0 +1,321 @@
+//! Command line interface
+//! This file is part of the outermost layer (Frameworks & Drivers)
+
+use crate::adapters::http::tcp::{DEFAULT_CLIENT_TIMEOUT, DEFAULT_LISTEN_BACKLOG};
+use crate::adapters::http::{AccessLogFormat, DEFAULT_MAX_BODY_BYTES, DEFAULT_MAX_HEADERS, DEFAULT_MAX_HEADER_BYTES};
+use crate::infrastructure::http_client::{DEFAULT_POOL_IDLE_TIMEOUT, DEFAULT_POOL_MAX_IDLE_PER_HOST};
+use crate::infrastructure::pipes::DEFAULT_MAX_MESSAGE_BYTES;
+use crate::use_cases::{
+    DEFAULT_DEBUG_BODY_LIMIT, MAX_RESPONSE_BYTES, READY_POLL_INTERVAL, READY_TIMEOUT, SHUTDOWN_TIMEOUT,
+};
+use crate::domain::Process;
+use clap::builder::{BoolishValueParser, RangedU64ValueParser};
+use clap::Parser;
+use std::path::PathBuf;
+
+/// Local Lambdas HTTP proxy
+#[derive(Debug, Parser)]
+#[command(name = "local_lambdas", version, about)]
+pub struct Cli {
+    /// Path to the manifest file, a directory of them, or `-` to read it
+    /// from stdin [default: manifest.xml]
+    pub manifest: Option<PathBuf>,
+
+    /// Another manifest file or directory to merge in (repeatable); processes
+    /// are matched in the order their files are given
+    #[arg(long = "manifest", value_name = "PATH")]
+    pub extra_manifests: Vec<PathBuf>,
+
+    /// Validate the manifest and print the routing table, then exit without
+    /// starting any process or binding the server
+    #[arg(long)]
+    pub check: bool,
+
+    /// Address for the HTTP server to listen on (use port 0 to let the OS choose,
+    /// or `unix:/path/to.sock` for a Unix domain socket); separate several
+    /// with commas to listen on all of them
+    #[arg(long, env = "BIND_ADDRESS", default_value = "127.0.0.1:3000")]
+    pub bind: String,
+
+    /// PEM certificate chain to serve HTTPS with; requires --tls-key
+    #[arg(long, env = "TLS_CERT", value_name = "PATH", requires = "tls_key")]
+    pub tls_cert: Option<PathBuf>,
+
+    /// PEM private key for --tls-cert
+    #[arg(long, env = "TLS_KEY", value_name = "PATH", requires = "tls_cert")]
+    pub tls_key: Option<PathBuf>,
+
+    /// Include detailed error messages in error responses (development only)
+    #[arg(long, env = "DEV_MODE", value_parser = BoolishValueParser::new())]
+    pub dev: bool,
+
+    /// Token admin endpoints that change the proxy's state, reloads and
+    /// clearing the cache, require as `Authorization: Bearer <token>`;
+    /// without one they are disabled
+    #[arg(long, env = "ADMIN_TOKEN", hide_env_values = true)]
+    pub admin_token: Option<String>,
+
+    /// Accept minimal or malformed backend response envelopes instead of failing with 502
+    #[arg(long, env = "LENIENT_RESPONSES", value_parser = BoolishValueParser::new())]
+    pub lenient_responses: bool,
+
+    /// Origin allowed to make cross-origin requests (repeatable, `*` for any);
+    /// enables CORS handling in the proxy
+    #[arg(long = "cors-origin", env = "CORS_ORIGINS", value_delimiter = ',')]
+    pub cors_origins: Vec<String>,
+
+    /// Methods allowed in cross-origin requests
+    #[arg(long, env = "CORS_METHODS", value_delimiter = ',', default_value = "GET,POST,PUT,DELETE,PATCH,HEAD,OPTIONS")]
+    pub cors_methods: Vec<String>,
+
+    /// Request headers allowed in cross-origin requests (`*` for any)
+    #[arg(long, env = "CORS_HEADERS", value_delimiter = ',', default_value = "*")]
+    pub cors_headers: Vec<String>,
+
+    /// Allow credentials (cookies, authorization) on cross-origin requests
+    #[arg(long, env = "CORS_CREDENTIALS", value_parser = BoolishValueParser::new())]
+    pub cors_credentials: bool,
+
+    /// Working directory for processes that don't set `working_dir`
+    #[arg(long, env = "DEFAULT_WORKING_DIR")]
+    pub default_working_dir: Option<PathBuf>,
+
+    /// Don't check that each process's executable exists before starting
+    #[arg(long, env = "SKIP_EXEC_CHECK", value_parser = BoolishValueParser::new())]
+    pub skip_exec_check: bool,
+
+    /// Largest request body accepted, in bytes; processes can override it
+    /// with `max_body_bytes` in the manifest
+    #[arg(long, env = "MAX_BODY_BYTES", default_value_t = DEFAULT_MAX_BODY_BYTES)]
+    pub max_body_bytes: usize,
+
+    /// Largest total size of a request's header names and values, in bytes;
+    /// larger requests get 431
+    #[arg(long, env = "MAX_HEADER_BYTES", default_value_t = DEFAULT_MAX_HEADER_BYTES)]
+    pub max_header_bytes: usize,
+
+    /// Most headers a request may carry; more get 431
+    #[arg(long, env = "MAX_HEADERS", default_value_t = DEFAULT_MAX_HEADERS)]
+    pub max_headers: usize,
+
+    /// Largest message sent to or read from a pipe-mode backend, in bytes;
+    /// larger responses fail with 502 instead of being buffered
+    #[arg(long, env = "MAX_PIPE_MESSAGE_BYTES", default_value_t = DEFAULT_MAX_MESSAGE_BYTES)]
+    pub max_pipe_message_bytes: usize,
+
+    /// Idle connections kept open to each HTTP-mode backend for later
+    /// requests to reuse; 0 opens a new connection for every request
+    #[arg(long, env = "BACKEND_POOL_SIZE", default_value_t = DEFAULT_POOL_MAX_IDLE_PER_HOST)]
+    pub backend_pool_size: usize,
+
+    /// How long an idle connection to an HTTP-mode backend is kept open, in seconds
+    #[arg(long, env = "BACKEND_POOL_IDLE_TIMEOUT", value_name = "SECS",
+          default_value_t = DEFAULT_POOL_IDLE_TIMEOUT.as_secs(),
+          value_parser = RangedU64ValueParser::<u64>::new().range(1..))]
+    pub backend_pool_idle_timeout: u64,
+
+    /// Largest response read from a backend, in bytes; reading stops there
+    /// and the request fails with 502. Processes can override it with
+    /// `max_response_bytes` in the manifest
+    #[arg(long, env = "MAX_RESPONSE_BYTES", default_value_t = MAX_RESPONSE_BYTES, value_parser = RangedU64ValueParser::<usize>::new().range(1..))]
+    pub max_response_bytes: usize,
+
+    /// Save cached responses to this file on shutdown and reload the ones
+    /// still fresh on startup (requires ENABLE_CACHE)
+    #[arg(long, env = "CACHE_FILE")]
+    pub cache_file: Option<PathBuf>,
+
+    /// Record every request to a backend and its response to this file,
+    /// replacing what it held, for replaying later with --replay
+    #[arg(long, env = "RECORD_FILE", value_name = "PATH", conflicts_with = "replay")]
+    pub record: Option<PathBuf>,
+
+    /// Answer requests from a file written by --record instead of starting
+    /// any backend
+    #[arg(long, env = "REPLAY_FILE", value_name = "PATH")]
+    pub replay: Option<PathBuf>,
+
+    /// Match routes ignoring case and trailing slashes, so `/API/Users` matches
+    /// `/api/*` and `/api` matches `/api/`
+    #[arg(long, env = "NORMALIZE_ROUTES", value_parser = BoolishValueParser::new())]
+    pub normalize_routes: bool,
+
+    /// Add a `Server-Timing` header to proxied responses showing time spent
+    /// serializing, waiting on the backend and deserializing
+    #[arg(long, env = "SERVER_TIMING", value_parser = BoolishValueParser::new())]
+    pub server_timing: bool,
+
+    /// Don't gzip/deflate responses, even for clients that accept it
+    #[arg(long, env = "NO_COMPRESSION", value_parser = BoolishValueParser::new())]
+    pub no_compression: bool,
+
+    /// Log every request (method, path, process, status, bytes, duration)
+    /// as `plain` text or `json`
+    #[arg(long, env = "ACCESS_LOG", value_name = "FORMAT")]
+    pub access_log: Option<AccessLogFormat>,
+
+    /// Log the decoded request and response bodies of every process at trace
+    /// level (e.g. `RUST_LOG=local_lambdas=trace`), not only of processes with
+    /// `debug_body` set. Bodies can contain secrets; use only for debugging
+    #[arg(long, env = "DEBUG_BODIES", value_parser = BoolishValueParser::new())]
+    pub debug_bodies: bool,
+
+    /// Longest part of each body logged by body debugging, in bytes
+    #[arg(long, env = "DEBUG_BODY_LIMIT", default_value_t = DEFAULT_DEBUG_BODY_LIMIT)]
+    pub debug_body_limit: usize,
+
+    /// Answer requests for a process that is still starting with 503 and a
+    /// `Retry-After` of this many seconds, instead of waiting for it or
+    /// failing with 502
+    #[arg(long, env = "STARTING_RETRY_AFTER", value_parser = RangedU64ValueParser::<u64>::new().range(1..))]
+    pub starting_retry_after: Option<u64>,
+
+    /// Most requests proxied at once, across all processes; more are
+    /// answered with 503 until some finish (default: unbounded)
+    #[arg(long, env = "MAX_IN_FLIGHT", value_parser = RangedU64ValueParser::<usize>::new().range(1..))]
+    pub max_in_flight: Option<usize>,
+
+    /// Answer requests no route matches with an immediate 404, without
+    /// reading their body or passing them through the proxy
+    #[arg(long, env = "NO_CATCH_ALL", value_parser = BoolishValueParser::new())]
+    pub no_catch_all: bool,
+
+    /// Connections the OS queues on each TCP address before the proxy
+    /// accepts them
+    #[arg(long, env = "LISTEN_BACKLOG", default_value_t = DEFAULT_LISTEN_BACKLOG,
+          value_parser = RangedU64ValueParser::<u32>::new().range(1..))]
+    pub listen_backlog: u32,
+
+    /// Let the OS batch small writes to clients (Nagle's algorithm) instead
+    /// of sending each response right away
+    #[arg(long, env = "NO_TCP_NODELAY", value_parser = BoolishValueParser::new())]
+    pub no_tcp_nodelay: bool,
+
+    /// Close client connections that send no request for this many seconds,
+    /// whether just opened or kept alive after a response; HTTP/2
+    /// connections are only closed if they send nothing at all
+    #[arg(long, env = "CLIENT_TIMEOUT", value_name = "SECS", default_value_t = DEFAULT_CLIENT_TIMEOUT.as_secs(),
+          value_parser = RangedU64ValueParser::<u64>::new().range(1..))]
+    pub client_timeout: u64,
+
+    /// Log output format: `text` for humans or `json` for one object per line
+    #[arg(long, env = "LOG_FORMAT", value_name = "FORMAT", default_value = "text")]
+    pub log_format: LogFormat,
+
+    /// Write logs to this file instead of stdout, starting a new file each
+    /// day (the date is appended to the name)
+    #[arg(long, env = "LOG_FILE", value_name = "PATH")]
+    pub log_file: Option<PathBuf>,
+
+    /// Once the proxy is ready, print one JSON object to stdout describing
+    /// where it listens and each process; logs go to stderr instead
+    #[arg(long, env = "EMIT_STARTUP_JSON", value_parser = BoolishValueParser::new())]
+    pub emit_startup_json: bool,
+
+    /// How long a starting process has to become ready, in milliseconds:
+    /// for processes without a health check, until their socket or port
+    /// accepts connections
+    #[arg(long, env = "READY_TIMEOUT_MS", default_value_t = READY_TIMEOUT.as_millis() as u64,
+          value_parser = RangedU64ValueParser::<u64>::new().range(1..))]
+    pub ready_timeout_ms: u64,
+
+    /// Pause between checks of whether a starting process is ready, in milliseconds
+    #[arg(long, env = "READY_POLL_INTERVAL_MS", default_value_t = READY_POLL_INTERVAL.as_millis() as u64,
+          value_parser = RangedU64ValueParser::<u64>::new().range(1..))]
+    pub ready_poll_interval_ms: u64,
+
+    /// Longest the proxy takes to shut down, in seconds: time for in-flight
+    /// requests to finish and processes to exit after SIGTERM, after which
+    /// the processes still running are killed
+    #[arg(long, env = "SHUTDOWN_TIMEOUT", value_name = "SECS", default_value_t = SHUTDOWN_TIMEOUT.as_secs())]
+    pub shutdown_timeout: u64,
+
+    /// Number of async runtime worker threads (default: one per CPU); 1 keeps
+    /// all request handling on a single thread for predictable benchmarks
+    #[arg(long, env = "WORKER_THREADS", value_parser = RangedU64ValueParser::<usize>::new().range(1..))]
+    pub worker_threads: Option<usize>,
+}
+
+/// How log lines are written
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LogFormat {
+    Text,
+    Json,
+}
+
+impl std::str::FromStr for LogFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(LogFormat::Text),
+            "json" => Ok(LogFormat::Json),
+            other => Err(format!("invalid log format '{}', expected 'text' or 'json'", other)),
+        }
+    }
+}
+
+impl Cli {
+    /// Every manifest file or directory to load, in order
+    pub fn manifest_paths(&self) -> Vec<PathBuf> {
+        let paths: Vec<PathBuf> = self.manifest.iter().chain(&self.extra_manifests).cloned().collect();
+        if paths.is_empty() {
+            vec![PathBuf::from("manifest.xml")]
+        } else {
+            paths
+        }
+    }
+
+    /// Every address to listen on, from the comma-separated `--bind`
+    pub fn bind_addresses(&self) -> Vec<&str> {
+        self.bind.split(',').map(str::trim).filter(|addr| !addr.is_empty()).collect()
+    }
+}
+
+/// Render the processes as a plain-text table, one row per route in match
+/// order: a request goes to the first healthy process whose route matches
+pub fn format_routing_table(processes: &[Process]) -> String {
+    let header = ["ID", "ROUTE", "MODE", "ADDRESS", "EXECUTABLE"].map(String::from);
+    let rows: Vec<[String; 5]> = processes
+        .iter()
+        .map(|p| {
+            // Static routes are served by the proxy itself, from a directory
+            let address = match p.static_dir {
+                Some(_) => String::new(),
+                None => p.instance_addresses().join(", "),
+            };
+            [
+                p.id.as_str().to_string(),
+                if p.is_default {
+                    format!("{} (default)", p.route.as_str())
+                } else {
+                    p.route.as_str().to_string()
+                },
+                p.mode_name().to_string(),
+                address,
+                p.executable.as_str().to_string(),
+            ]
+        })
+        .collect();
+
+    let mut widths = header.clone().map(|h| h.len());
+    for row in &rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.len());
+        }
+    }
+
+    std::iter::once(&header)
+        .chain(&rows)
+        .map(|row| {
+            row.iter()
+                .zip(widths)
+                .map(|(cell, width)| format!("{:<width$}", cell, width = width))
+                .collect::<Vec<_>>()
+                .join("  ")
+                .trim_end()
+                .to_string()
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
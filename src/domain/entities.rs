@@ -1,15 +1,318 @@
 //! Domain entities - pure business logic with no external dependencies
 
+use crate::domain::utils::{get_http_address_from_name, get_pipe_address_from_name};
+use bytes::Bytes;
+use futures_util::Stream;
+use serde::{Deserialize, Serialize};
+use std::pin::Pin;
+use std::time::{Duration, SystemTime};
+
+/// Replaced in a process's arguments by the address each instance listens on
+pub const ADDRESS_PLACEHOLDER: &str = "${ADDRESS}";
+
+/// Replaced in a process's arguments by the port each instance listens on
+/// for HTTP
+pub const PORT_PLACEHOLDER: &str = "${PORT}";
+
 /// Represents a configured process to be orchestrated
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Process {
     pub id: ProcessId,
     pub executable: Executable,
+    /// Command line arguments, which may contain [`ADDRESS_PLACEHOLDER`]
+    /// and, for a process listening on HTTP, [`PORT_PLACEHOLDER`]
     pub arguments: Vec<String>,
     pub route: Route,
+    /// Methods the route serves, in declaration order; empty serves any
+    pub methods: Vec<HttpMethod>,
     pub pipe_name: PipeName,
     pub working_directory: Option<WorkingDirectory>,
     pub communication_mode: CommunicationMode,
+    /// Cap on simultaneous in-flight requests to this process
+    pub concurrency_limit: Option<ConcurrencyLimit>,
+    /// Number of copies of the executable to run behind the route
+    pub instances: usize,
+    /// Relative share of requests for each instance, one per instance;
+    /// empty shares them evenly
+    pub weights: Vec<u32>,
+    /// How long to wait for a response; `None` waits indefinitely
+    pub timeout: Option<Duration>,
+    /// Retry over HTTP when a pipe-mode process's pipe is unreachable
+    pub http_fallback: bool,
+    /// Fixed HTTP port for the first instance (later instances take the
+    /// following ports); `None` derives ports from the pipe names
+    pub http_port: Option<u16>,
+    /// Largest request body accepted for this process, overriding the server default
+    pub max_body_bytes: Option<usize>,
+    /// Media types a request body may have, lowercase, e.g.
+    /// `multipart/form-data` or `image/*`; empty accepts any
+    pub accepted_content_types: Vec<String>,
+    /// Largest response read from the process, overriding the proxy default
+    pub max_response_bytes: Option<usize>,
+    /// `Host` header sent to the process over HTTP instead of its address,
+    /// for backends that route on virtual hosts
+    pub host_header: Option<String>,
+    /// Leading path removed from requests before they're sent to the
+    /// process, for a backend that doesn't know where it's mounted
+    pub strip_prefix: Option<String>,
+    /// Path added in front of requests sent to the process, after
+    /// `strip_prefix` is removed
+    pub prepend_path: Option<String>,
+    /// Answer HEAD requests by sending GET to the process and dropping the body
+    pub head_from_get: bool,
+    /// Log decoded request and response bodies at trace level
+    pub debug_body: bool,
+    /// Periodic probe deciding whether the process receives traffic
+    pub health_check: Option<HealthCheck>,
+    /// Requests sent to each instance once it's ready and before it takes
+    /// traffic, in order
+    pub warmup: Vec<WarmupRequest>,
+    /// Keep the process from taking traffic when a warm-up request fails,
+    /// instead of only logging it
+    pub warmup_strict: bool,
+    /// Cache error responses briefly when response caching is enabled
+    pub negative_cache: Option<NegativeCachePolicy>,
+    /// Request headers, lowercased, whose values get separate cache entries
+    pub cache_vary: Vec<String>,
+    /// Query parameters left out of the cache key, such as cache busters
+    /// and tracking parameters that don't change the response
+    pub cache_ignore_query: Vec<String>,
+    /// Group whose processes share cached responses, keyed by the URL below
+    /// each one's route, so a resource reached through several routes is
+    /// cached once; `None` keeps the process's responses to its own routes
+    pub shared_cache: Option<String>,
+    /// Encoding of the request and response envelopes
+    pub protocol: SerializationFormat,
+    /// Environment variables set for the process, in declaration order
+    pub environment: Vec<(String, String)>,
+    /// Start from an empty environment instead of inheriting the proxy's
+    pub clean_env: bool,
+    /// User id the process runs as (Unix only); `None` keeps the proxy's
+    pub uid: Option<u32>,
+    /// Group id the process runs as (Unix only); `None` keeps the proxy's
+    pub gid: Option<u32>,
+    /// Stop the process after this long without requests; the next request
+    /// starts it again
+    pub idle_timeout: Option<Duration>,
+    /// Start the process again when it exits by itself; `None` leaves a
+    /// crashed process down
+    pub restart: Option<RestartPolicy>,
+    /// Send a request again when it fails in a way that may not recur;
+    /// `None` fails it on the first error
+    pub retry: Option<RetryPolicy>,
+    /// Handle requests that no route matches
+    pub is_default: bool,
+    /// Headers set on every response from this process, replacing any the
+    /// backend sent under the same name
+    pub response_headers: Vec<(String, String)>,
+    /// Directory whose files the proxy serves under the route itself, with
+    /// no process behind it
+    pub static_dir: Option<String>,
+}
+
+impl Process {
+    /// Create a process with default settings for all optional configuration
+    pub fn new(id: ProcessId, executable: Executable, route: Route, pipe_name: PipeName) -> Self {
+        Self {
+            id,
+            executable,
+            arguments: Vec::new(),
+            route,
+            methods: Vec::new(),
+            pipe_name,
+            working_directory: None,
+            communication_mode: CommunicationMode::default(),
+            concurrency_limit: None,
+            instances: 1,
+            weights: Vec::new(),
+            timeout: None,
+            http_fallback: false,
+            http_port: None,
+            max_body_bytes: None,
+            accepted_content_types: Vec::new(),
+            max_response_bytes: None,
+            host_header: None,
+            strip_prefix: None,
+            prepend_path: None,
+            head_from_get: false,
+            debug_body: false,
+            health_check: None,
+            warmup: Vec::new(),
+            warmup_strict: false,
+            negative_cache: None,
+            cache_vary: Vec::new(),
+            cache_ignore_query: Vec::new(),
+            shared_cache: None,
+            protocol: SerializationFormat::default(),
+            environment: Vec::new(),
+            clean_env: false,
+            uid: None,
+            gid: None,
+            idle_timeout: None,
+            restart: None,
+            retry: None,
+            is_default: false,
+            response_headers: Vec::new(),
+            static_dir: None,
+        }
+    }
+
+    /// A route served from the files in `dir` rather than by a process
+    ///
+    /// Nothing is spawned for it, so it has no pipe of its own: the pipe name
+    /// is only a placeholder, and the executable is the directory, as shown
+    /// in the routing table.
+    pub fn new_static(id: ProcessId, route: Route, dir: impl Into<String>) -> Self {
+        let dir = dir.into();
+        let pipe_name = PipeName(id.as_str().to_string());
+        let mut process = Self::new(id, Executable(dir.clone()), route, pipe_name);
+        process.static_dir = Some(dir);
+        process
+    }
+
+    /// Pipe names for each instance; a single instance keeps the configured
+    /// name, multiple instances get an index suffix
+    pub fn instance_pipe_names(&self) -> Vec<String> {
+        if self.instances <= 1 {
+            return vec![self.pipe_name.as_str().to_string()];
+        }
+        (0..self.instances)
+            .map(|i| format!("{}_{}", self.pipe_name.as_str(), i))
+            .collect()
+    }
+
+    /// Whether requests are sent to the process under another path than
+    /// the client asked for
+    pub fn rewrites_path(&self) -> bool {
+        self.strip_prefix.is_some() || self.prepend_path.is_some()
+    }
+
+    /// The request target sent to the process for the client's `target`:
+    /// `/api/v1/users?page=2` is `/users?page=2` with `strip_prefix` set to
+    /// `/api/v1`
+    ///
+    /// The prefix only comes off whole segments, compared ignoring ASCII
+    /// case as routes can be; a target outside it keeps its path.
+    pub fn rewrite_path(&self, target: &str) -> String {
+        let mut rest = target;
+        if let Some(prefix) = &self.strip_prefix {
+            let prefix = prefix.trim_end_matches('/');
+            if let Some(head) = target.get(..prefix.len()) {
+                let tail = &target[prefix.len()..];
+                if head.eq_ignore_ascii_case(prefix) && (tail.is_empty() || tail.starts_with(['/', '?'])) {
+                    rest = tail;
+                }
+            }
+        }
+        let base = self.prepend_path.as_deref().unwrap_or_default().trim_end_matches('/');
+        if rest.starts_with('/') {
+            format!("{}{}", base, rest)
+        } else {
+            format!("{}/{}", base, rest)
+        }
+    }
+
+    /// Whether a body with the `Content-Type` header `content_type` may be
+    /// sent to the process
+    ///
+    /// Parameters such as `; charset=utf-8` are ignored, and so is case.
+    pub fn accepts_content_type(&self, content_type: &str) -> bool {
+        if self.accepted_content_types.is_empty() {
+            return true;
+        }
+        let media_type = content_type.split(';').next().unwrap_or_default().trim().to_ascii_lowercase();
+        let Some((kind, _)) = media_type.split_once('/') else {
+            return false;
+        };
+        self.accepted_content_types.iter().any(|accepted| match accepted.strip_suffix("/*") {
+            Some(accepted_kind) => accepted_kind == kind,
+            None => *accepted == media_type,
+        })
+    }
+
+    /// Whether the route serves `method` requests
+    pub fn allows(&self, method: &HttpMethod) -> bool {
+        self.methods.is_empty() || self.methods.contains(method)
+    }
+
+    /// How the route is served, for display: its communication mode, or
+    /// `static` for a directory of files
+    pub fn mode_name(&self) -> &str {
+        match self.static_dir {
+            Some(_) => "static",
+            None => self.communication_mode.as_str(),
+        }
+    }
+
+    /// Whether each instance listens for HTTP, as its communication mode or
+    /// as a fallback
+    pub fn listens_on_http(&self) -> bool {
+        self.communication_mode != CommunicationMode::Pipe || self.http_fallback
+    }
+
+    /// The arguments for an instance listening on `address`, and for HTTP on
+    /// `http_address`, with the placeholders filled in
+    ///
+    /// `${PORT}` is left as it is unless the process listens on HTTP.
+    pub fn instance_arguments(&self, address: &str, http_address: &str) -> Vec<String> {
+        let port = http_address
+            .rsplit_once(':')
+            .map(|(_, port)| port)
+            .filter(|_| self.listens_on_http());
+        self.arguments
+            .iter()
+            .map(|arg| {
+                let arg = arg.replace(ADDRESS_PLACEHOLDER, address);
+                match port {
+                    Some(port) => arg.replace(PORT_PLACEHOLDER, port),
+                    None => arg,
+                }
+            })
+            .collect()
+    }
+
+    /// Communication addresses for each instance, in instance order
+    pub fn instance_addresses(&self) -> Vec<String> {
+        match self.communication_mode {
+            CommunicationMode::Pipe => self
+                .instance_pipe_names()
+                .iter()
+                .map(|name| get_pipe_address_from_name(name))
+                .collect(),
+            CommunicationMode::Http | CommunicationMode::Grpc => self.instance_http_addresses(),
+        }
+    }
+
+    /// Addresses each instance listens on for HTTP, whether as its
+    /// communication mode or as a fallback, in instance order
+    pub fn instance_http_addresses(&self) -> Vec<String> {
+        match self.http_port {
+            Some(port) => (0..self.instances.max(1))
+                .map(|i| format!("127.0.0.1:{}", port as usize + i))
+                .collect(),
+            None => self
+                .instance_pipe_names()
+                .iter()
+                .map(|name| get_http_address_from_name(name))
+                .collect(),
+        }
+    }
+
+    /// The process as started by its `generation`th reload: the same
+    /// configuration on fresh pipe names (and so fresh HTTP ports), suffixed
+    /// `_r1`, `_r2`, ...
+    ///
+    /// Generation 0 is the process as configured. Later generations run next
+    /// to the instances they replace, so they can't take a fixed `http_port`
+    /// and derive theirs like any other process.
+    pub fn for_generation(&self, generation: u32) -> Process {
+        let mut process = self.clone();
+        if generation > 0 {
+            process.pipe_name = PipeName(format!("{}_r{}", self.pipe_name.as_str(), generation));
+            process.http_port = None;
+        }
+        process
+    }
 }
 
 /// Value object for process identifier
@@ -53,11 +356,23 @@ impl Executable {
 pub struct Route(String);
 
 impl Route {
+    /// A route is an exact path (`/api`), a prefix ending in a slash
+    /// (`/api/`), a prefix followed by a wildcard (`/api/*`), or a pattern
+    /// of segments where `*` stands for any one segment and `**` for any
+    /// number of them (`/api/*/items`, `/static/**`)
     pub fn new(pattern: impl Into<String>) -> Result<Self, DomainError> {
         let pattern = pattern.into();
         if pattern.is_empty() || !pattern.starts_with('/') {
             return Err(DomainError::InvalidRoute("Route must start with /".to_string()));
         }
+        // Within a segment a `*` would silently be matched as a literal character
+        let partial = pattern.split('/').any(|segment| segment.contains('*') && segment != "*" && segment != "**");
+        if partial {
+            return Err(DomainError::InvalidRoute(format!(
+                "'{}': a wildcard has to be a whole segment, e.g. '/api/*/items' or '/static/**'",
+                pattern
+            )));
+        }
         Ok(Self(pattern))
     }
 
@@ -65,8 +380,18 @@ impl Route {
         &self.0
     }
 
+    /// A pattern matched segment by segment, rather than as an exact path
+    /// or a prefix; a single trailing `/*` keeps its prefix meaning
+    fn is_glob(&self) -> bool {
+        self.0.strip_suffix("/*").unwrap_or(&self.0).contains('*')
+    }
+
     /// Check if a request path matches this route pattern
     pub fn matches(&self, path: &str) -> bool {
+        if self.is_glob() {
+            return glob_matches(&segments(&self.0), &segments(path));
+        }
+
         // Exact match
         if self.0 == path {
             return true;
@@ -85,18 +410,157 @@ impl Route {
 
         false
     }
+
+    /// Like [`Route::matches`], but ignoring case and treating a path with
+    /// and without a trailing slash as the same
+    ///
+    /// The pattern and the path are normalized the same way, so `/API` and
+    /// `/api/` both match `/api`, and `/Api` matches the prefix route `/api/`.
+    pub fn matches_normalized(&self, path: &str) -> bool {
+        let pattern = self.0.to_lowercase();
+        let path = path.to_lowercase();
+
+        if self.is_glob() {
+            return glob_matches(&segments(trim_trailing_slash(&pattern)), &segments(trim_trailing_slash(&path)));
+        }
+
+        if let Some(prefix) = pattern.strip_suffix("/*") {
+            return path.starts_with(prefix);
+        }
+
+        if trim_trailing_slash(&pattern) == trim_trailing_slash(&path) {
+            return true;
+        }
+
+        pattern.ends_with('/') && path.starts_with(&pattern)
+    }
+
+    /// The part of a matching `path` below the route's prefix, as a path of
+    /// its own: `/static/css/site.css` is `/css/site.css` under `/static/*`
+    ///
+    /// The prefix of a pattern ends at its first wildcard, so under
+    /// `/static/**` the same path is also `/css/site.css`.
+    ///
+    /// The prefix is compared ignoring ASCII case, so this also works for
+    /// paths matched by [`Route::matches_normalized`]. A path outside the
+    /// prefix, as the default process gets, is returned whole.
+    pub fn mount_path(&self, path: &str) -> String {
+        let prefix = self.prefix();
+        let rest = match path.get(..prefix.len()) {
+            Some(head) if head.eq_ignore_ascii_case(prefix) => &path[prefix.len()..],
+            _ => path,
+        };
+        if rest.starts_with('/') {
+            rest.to_string()
+        } else {
+            format!("/{}", rest)
+        }
+    }
+
+    /// Like [`Route::mount_path`], but only for a path strictly below the
+    /// route's prefix: `None` for an exact route, for the prefix itself, or
+    /// for `/apix` under `/api/*`, which starts with the prefix without
+    /// being in its segment
+    pub fn path_below(&self, path: &str) -> Option<String> {
+        if !self.0.contains('*') && !self.0.ends_with('/') {
+            return None;
+        }
+        let prefix = self.prefix();
+        let head = path.get(..prefix.len())?;
+        let rest = &path[prefix.len()..];
+        (head.eq_ignore_ascii_case(prefix) && rest.len() > 1 && rest.starts_with('/')).then(|| rest.to_string())
+    }
+
+    /// The pattern up to its first wildcard, without a trailing slash
+    fn prefix(&self) -> &str {
+        self.0.split('*').next().unwrap_or_default().trim_end_matches('/')
+    }
+}
+
+/// The segments of a path after its leading slash
+fn segments(path: &str) -> Vec<&str> {
+    path.strip_prefix('/').unwrap_or(path).split('/').collect()
+}
+
+/// Whether `path` fits `pattern`, segment by segment: `*` matches any one
+/// segment and `**` any number of them, none included
+///
+/// On a mismatch only the last `**` seen takes another segment, as the ones
+/// before it can already cover anything a retry of theirs could, so a match
+/// takes at most pattern length times path length steps however many `**`
+/// the pattern has.
+fn glob_matches(pattern: &[&str], path: &[&str]) -> bool {
+    let (mut p, mut s) = (0, 0);
+    // The last `**` seen and the first path segment not yet given to it
+    let mut backtrack: Option<(usize, usize)> = None;
+    while s < path.len() {
+        match pattern.get(p) {
+            Some(&"**") => {
+                backtrack = Some((p, s));
+                p += 1;
+            }
+            Some(&segment) if segment == "*" || segment == path[s] => {
+                p += 1;
+                s += 1;
+            }
+            _ => match backtrack {
+                Some((star, taken)) => {
+                    backtrack = Some((star, taken + 1));
+                    p = star + 1;
+                    s = taken + 1;
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&segment| segment == "**")
+}
+
+/// `path` without its trailing slashes, leaving the root as `/`
+fn trim_trailing_slash(path: &str) -> &str {
+    match path.trim_end_matches('/') {
+        "" => "/",
+        trimmed => trimmed,
+    }
 }
 
 /// Value object for named pipe identifier
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct PipeName(String);
 
+/// Longest address a pipe can have: a Unix socket path has to fit in
+/// `sockaddr_un`, and Windows limits the whole pipe name
+#[cfg(unix)]
+const MAX_PIPE_ADDRESS_LEN: usize = 107;
+#[cfg(windows)]
+const MAX_PIPE_ADDRESS_LEN: usize = 256;
+
 impl PipeName {
+    /// The name becomes the last component of the pipe's address, so it can't
+    /// contain path separators or control characters, and the address has to
+    /// fit the platform's limit
     pub fn new(name: impl Into<String>) -> Result<Self, DomainError> {
         let name = name.into();
         if name.is_empty() {
             return Err(DomainError::InvalidPipeName("Pipe name cannot be empty".to_string()));
         }
+        // Both separators are rejected everywhere so manifests stay portable
+        if let Some(c) = name.chars().find(|c| matches!(c, '/' | '\\') || c.is_control()) {
+            return Err(DomainError::InvalidPipeName(format!(
+                "{:?} contains {:?}; use a plain name such as 'api_pipe', not a path",
+                name, c
+            )));
+        }
+        if name == "." || name == ".." {
+            return Err(DomainError::InvalidPipeName(format!("'{}' is not a usable name", name)));
+        }
+        let address = get_pipe_address_from_name(&name);
+        if address.len() > MAX_PIPE_ADDRESS_LEN {
+            return Err(DomainError::InvalidPipeName(format!(
+                "'{}' is too long; its address {} is over the {} byte limit",
+                name, address, MAX_PIPE_ADDRESS_LEN
+            )));
+        }
         Ok(Self(name))
     }
 
@@ -127,17 +591,274 @@ pub enum CommunicationMode {
     Pipe,
     /// Use HTTP protocol
     Http,
+    /// Forward gRPC calls as they are, over cleartext HTTP/2 (h2c), instead
+    /// of wrapping them in envelopes
+    Grpc,
+}
+
+impl CommunicationMode {
+    pub fn as_str(&self) -> &str {
+        match self {
+            CommunicationMode::Pipe => "pipe",
+            CommunicationMode::Http => "http",
+            CommunicationMode::Grpc => "grpc",
+        }
+    }
+}
+
+/// Wire format of the envelopes exchanged with a process
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SerializationFormat {
+    /// JSON with a base64-encoded body
+    #[default]
+    Json,
+    /// MessagePack with the body as raw bytes
+    MsgPack,
+    /// No envelope: the request is forwarded as plain HTTP with its body
+    /// streamed, and the backend's response is passed through; HTTP mode only
+    Raw,
+}
+
+/// Header naming the [`SerializationFormat`] of a request sent to a process
+/// over HTTP, so a backend can tell it was configured for another one
+pub const PROTOCOL_HEADER: &str = "x-proxy-protocol";
+
+impl SerializationFormat {
+    pub fn as_str(&self) -> &str {
+        match self {
+            SerializationFormat::Json => "json",
+            SerializationFormat::MsgPack => "msgpack",
+            SerializationFormat::Raw => "raw",
+        }
+    }
+
+    /// The envelope format `data` is in, judging by how it starts: JSON
+    /// envelopes are objects and MessagePack ones maps; `None` for anything
+    /// else
+    pub fn of_envelope(data: &[u8]) -> Option<Self> {
+        match data.iter().find(|b| !b.is_ascii_whitespace())? {
+            b'{' => Some(SerializationFormat::Json),
+            0x80..=0x8f | 0xde | 0xdf => Some(SerializationFormat::MsgPack),
+            _ => None,
+        }
+    }
+
+    /// Content type of an envelope in this format; raw requests keep the
+    /// client's
+    pub fn content_type(&self) -> Option<&'static str> {
+        match self {
+            SerializationFormat::Json => Some("application/json"),
+            SerializationFormat::MsgPack => Some("application/msgpack"),
+            SerializationFormat::Raw => None,
+        }
+    }
+}
+
+/// Maximum number of concurrent requests a process accepts
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConcurrencyLimit {
+    pub max_concurrent: usize,
+    pub overflow: OverflowPolicy,
+}
+
+/// What to do with a request when a process is at its concurrency limit
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OverflowPolicy {
+    /// Fail immediately
+    Reject,
+    /// Wait up to the given duration for a slot to free up
+    Queue(Duration),
+}
+
+impl Default for OverflowPolicy {
+    fn default() -> Self {
+        OverflowPolicy::Queue(Duration::from_secs(30))
+    }
+}
+
+/// Periodic health probe for a process
+///
+/// The probe is a `GET` for `path` sent through the process's normal
+/// communication channel; any 2xx answer counts as healthy.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HealthCheck {
+    pub path: String,
+    pub interval: Duration,
+}
+
+/// A request sent to a process before it takes traffic, so work it puts
+/// off until its first request (JIT compilation, lazy initialization)
+/// isn't paid for by a client
+///
+/// Like a health check it goes through the process's normal communication
+/// channel, with no headers or body.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WarmupRequest {
+    pub method: HttpMethod,
+    pub path: String,
+}
+
+/// Which error responses from a process may be cached, and for how long
+///
+/// `404 Not Found` is always included; 5xx statuses only when listed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NegativeCachePolicy {
+    pub ttl: Duration,
+    pub server_errors: Vec<u16>,
+}
+
+impl NegativeCachePolicy {
+    pub fn covers(&self, status_code: u16) -> bool {
+        status_code == 404 || self.server_errors.contains(&status_code)
+    }
+}
+
+/// How a process that exits by itself is restarted, and when to stop trying
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RestartPolicy {
+    /// Wait before the first restart, doubled for each further crash
+    /// within `window`
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+    /// Crashes within `window` after which the process is given up on
+    pub max_crashes: u32,
+    pub window: Duration,
+}
+
+impl RestartPolicy {
+    /// Wait before restarting after the `crashes`th crash within the window
+    pub fn backoff(&self, crashes: u32) -> Duration {
+        let doublings = crashes.saturating_sub(1).min(31);
+        self.initial_backoff.saturating_mul(1 << doublings).min(self.max_backoff)
+    }
+}
+
+/// How requests that fail to reach a process, or get no answer from it, are
+/// sent again
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// Attempts in all, the first included
+    pub max_attempts: u32,
+    /// Wait before the first retry, doubled for each further one
+    pub backoff: Duration,
+    /// Methods retried besides the idempotent ones, such as `POST` for a
+    /// backend that deduplicates requests
+    pub methods: Vec<HttpMethod>,
+}
+
+impl RetryPolicy {
+    /// Whether a failed `method` request may be sent again
+    pub fn covers(&self, method: &HttpMethod) -> bool {
+        method.is_idempotent() || self.methods.contains(method)
+    }
+
+    /// Wait before the `retry`th retry
+    pub fn backoff(&self, retry: u32) -> Duration {
+        let doublings = retry.saturating_sub(1).min(31);
+        self.backoff.saturating_mul(1 << doublings)
+    }
+}
+
+/// Whether a process may currently receive traffic
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HealthState {
+    /// Started but hasn't passed its first health check yet
+    Starting,
+    Healthy,
+    /// Failed its most recent health check
+    Unhealthy,
+}
+
+impl HealthState {
+    pub fn as_str(&self) -> &str {
+        match self {
+            HealthState::Starting => "starting",
+            HealthState::Healthy => "healthy",
+            HealthState::Unhealthy => "unhealthy",
+        }
+    }
+}
+
+/// Lifecycle stage of a managed process
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProcessState {
+    Stopped,
+    /// Spawned but not yet known to be accepting requests
+    Starting,
+    Running,
+    /// Crashed too often in a row to be restarted again, or exited with an
+    /// error and has no restart policy; only a reload brings it back
+    Failed,
+}
+
+impl ProcessState {
+    pub fn as_str(&self) -> &str {
+        match self {
+            ProcessState::Stopped => "stopped",
+            ProcessState::Starting => "starting",
+            ProcessState::Running => "running",
+            ProcessState::Failed => "failed",
+        }
+    }
+}
+
+/// Runtime status of a managed process
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProcessStatus {
+    /// Whether any instance is alive; `false` once they have all exited,
+    /// even if the process wasn't stopped
+    pub running: bool,
+    /// Time since the process was last started; `None` while not running
+    pub uptime: Option<Duration>,
+    /// OS process id of the first instance; `None` while stopped
+    pub pid: Option<u32>,
+    /// Exit code of the last instance to exit, if it exited normally
+    pub last_exit_code: Option<i32>,
+    /// When an instance last exited or the process last failed to start
+    pub last_exit_at: Option<SystemTime>,
+    /// Why the process last failed to start, or the signal that killed an
+    /// instance the proxy didn't stop
+    pub last_error: Option<String>,
+    /// Number of times an instance was restarted after crashing; restarts
+    /// and reloads asked for don't count
+    pub restart_count: u32,
 }
 
 /// HTTP request representation
 #[derive(Debug, Clone)]
 pub struct HttpRequest {
     pub method: HttpMethod,
+    /// Path of the request, followed by its query string if it has one
     pub path: String,
     pub headers: Vec<(String, String)>,
     pub body: Vec<u8>,
 }
 
+/// Chunks of a request body, read as the client sends them
+pub type BodyStream = Pin<Box<dyn Stream<Item = Result<Bytes, std::io::Error>> + Send>>;
+
+/// HTTP request whose body is streamed to the backend instead of being
+/// held in memory, for processes using [`SerializationFormat::Raw`]
+pub struct StreamingRequest {
+    pub method: HttpMethod,
+    /// Path of the request, followed by its query string if it has one
+    pub path: String,
+    pub headers: Vec<(String, String)>,
+    pub body: BodyStream,
+}
+
+impl From<HttpRequest> for StreamingRequest {
+    fn from(request: HttpRequest) -> Self {
+        let body = Bytes::from(request.body);
+        Self {
+            method: request.method,
+            path: request.path,
+            headers: request.headers,
+            body: Box::pin(futures_util::stream::once(async move { Ok(body) })),
+        }
+    }
+}
+
 /// HTTP method
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum HttpMethod {
@@ -148,9 +869,34 @@ pub enum HttpMethod {
     Patch,
     Head,
     Options,
+    /// Any other method (e.g. WebDAV's `PROPFIND`), kept as the client sent it
+    Other(String),
 }
 
 impl HttpMethod {
+    /// The method named `name`, which is case-sensitive like on the wire
+    pub fn from_name(name: &str) -> Self {
+        match name {
+            "GET" => HttpMethod::Get,
+            "POST" => HttpMethod::Post,
+            "PUT" => HttpMethod::Put,
+            "DELETE" => HttpMethod::Delete,
+            "PATCH" => HttpMethod::Patch,
+            "HEAD" => HttpMethod::Head,
+            "OPTIONS" => HttpMethod::Options,
+            other => HttpMethod::Other(other.to_string()),
+        }
+    }
+
+    /// Whether sending the request twice has the same effect as sending it
+    /// once, so a failed one can safely be sent again
+    pub fn is_idempotent(&self) -> bool {
+        matches!(
+            self,
+            HttpMethod::Get | HttpMethod::Head | HttpMethod::Put | HttpMethod::Delete | HttpMethod::Options
+        )
+    }
+
     pub fn as_str(&self) -> &str {
         match self {
             HttpMethod::Get => "GET",
@@ -160,16 +906,79 @@ impl HttpMethod {
             HttpMethod::Patch => "PATCH",
             HttpMethod::Head => "HEAD",
             HttpMethod::Options => "OPTIONS",
+            HttpMethod::Other(method) => method,
         }
     }
 }
 
+impl std::str::FromStr for HttpMethod {
+    type Err = DomainError;
+
+    /// Parse a method name, such as one written by [`HttpMethod::as_str`]
+    ///
+    /// The standard methods are recognized in any case, since manifests and
+    /// hand-written envelopes aren't always uppercase; any other token is
+    /// kept as it is.
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        // RFC 9110 token characters
+        let is_token = !name.is_empty()
+            && name.chars().all(|c| c.is_ascii_alphanumeric() || "!#$%&'*+-.^_`|~".contains(c));
+        if !is_token {
+            return Err(DomainError::InvalidHttpMethod(format!("'{}' is not a method name", name)));
+        }
+        Ok(match HttpMethod::from_name(&name.to_ascii_uppercase()) {
+            HttpMethod::Other(_) => HttpMethod::Other(name.to_string()),
+            standard => standard,
+        })
+    }
+}
+
+impl TryFrom<&str> for HttpMethod {
+    type Error = DomainError;
+
+    fn try_from(name: &str) -> Result<Self, Self::Error> {
+        name.parse()
+    }
+}
+
 /// HTTP response representation
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct HttpResponse {
     pub status_code: u16,
     pub headers: Vec<(String, String)>,
+    #[serde(with = "serde_bytes")]
     pub body: Vec<u8>,
+    /// Headers sent after the body; only raw-protocol backends can send
+    /// them, as an envelope has nowhere to put them
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub trailers: Vec<(String, String)>,
+}
+
+impl HttpResponse {
+    /// Turn a GET response into the equivalent HEAD response: same status and
+    /// headers, with `Content-Length` describing the dropped body
+    pub fn into_head_response(mut self) -> Self {
+        let has_length = self
+            .headers
+            .iter()
+            .any(|(k, _)| k.eq_ignore_ascii_case("content-length"));
+        if !has_length {
+            self.headers
+                .push(("content-length".to_string(), self.body.len().to_string()));
+        }
+        self.body.clear();
+        self.trailers.clear();
+        self
+    }
+
+    /// Set each of `headers`, replacing every value the response already has
+    /// under the same name, compared case-insensitively
+    pub fn set_headers(&mut self, headers: &[(String, String)]) {
+        for (name, value) in headers {
+            self.headers.retain(|(k, _)| !k.eq_ignore_ascii_case(name));
+            self.headers.push((name.clone(), value.clone()));
+        }
+    }
 }
 
 /// Domain errors
@@ -180,6 +989,7 @@ pub enum DomainError {
     InvalidExecutable(String),
     InvalidRoute(String),
     InvalidPipeName(String),
+    InvalidHttpMethod(String),
 }
 
 impl std::fmt::Display for DomainError {
@@ -189,6 +999,7 @@ impl std::fmt::Display for DomainError {
             DomainError::InvalidExecutable(msg) => write!(f, "Invalid executable: {}", msg),
             DomainError::InvalidRoute(msg) => write!(f, "Invalid route: {}", msg),
             DomainError::InvalidPipeName(msg) => write!(f, "Invalid pipe name: {}", msg),
+            DomainError::InvalidHttpMethod(msg) => write!(f, "Invalid HTTP method: {}", msg),
         }
     }
 }
@@ -205,6 +1016,38 @@ mod tests {
         assert!(ProcessId::new("").is_err());
     }
 
+    #[test]
+    fn test_route_validation() {
+        for valid in ["/", "/*", "/api", "/api/", "/api/*", "/api/v1/*", "/api/*/users", "/*/x", "/api/**", "/**"] {
+            assert!(Route::new(valid).is_ok(), "{}", valid);
+        }
+        for wildcard in ["/api*", "/a/*b", "/a/b**", "/a/***"] {
+            let error = Route::new(wildcard).unwrap_err();
+            assert!(matches!(error, DomainError::InvalidRoute(_)), "{}", wildcard);
+            assert!(error.to_string().contains("has to be a whole segment"), "{}", error);
+        }
+        assert!(Route::new("api/*").is_err());
+        assert!(Route::new("").is_err());
+    }
+
+    #[test]
+    fn test_pipe_name_validation() {
+        for valid in ["api_pipe", "api-pipe.v2", "api_pipe_r3", "UPPER", "a"] {
+            assert!(PipeName::new(valid).is_ok(), "{}", valid);
+        }
+        for invalid in ["", "tmp/api", "..\\api", "api\npipe", "api\0", ".", ".."] {
+            let error = PipeName::new(invalid).unwrap_err();
+            assert!(matches!(error, DomainError::InvalidPipeName(_)), "{:?}", invalid);
+        }
+        let error = PipeName::new("sub/pipe").unwrap_err();
+        assert!(error.to_string().contains("not a path"), "{}", error);
+
+        let longest = "p".repeat(MAX_PIPE_ADDRESS_LEN - get_pipe_address_from_name("").len());
+        assert!(PipeName::new(longest.clone()).is_ok());
+        let error = PipeName::new(format!("{}p", longest)).unwrap_err();
+        assert!(error.to_string().contains("too long"), "{}", error);
+    }
+
     #[test]
     fn test_route_matching() {
         let route = Route::new("/api/*").unwrap();
@@ -213,9 +1056,311 @@ mod tests {
         assert!(!route.matches("/other/path"));
     }
 
+    #[test]
+    fn test_single_segment_wildcard_matching() {
+        let route = Route::new("/api/*/items").unwrap();
+        assert!(route.matches("/api/42/items"));
+        assert!(route.matches("/api/users/items"));
+        assert!(!route.matches("/api/items"));
+        assert!(!route.matches("/api/a/b/items"));
+        assert!(!route.matches("/api/42/items/7"));
+        assert!(!route.matches("/api/42/other"));
+
+        // Past the first wildcard, a trailing `*` is one segment too
+        let route = Route::new("/*/items/*").unwrap();
+        assert!(route.matches("/shop/items/7"));
+        assert!(!route.matches("/shop/items/7/reviews"));
+        assert!(!route.matches("/shop/items"));
+        assert!(!Route::new("/api/*/*").unwrap().matches("/api/1/2/3"));
+    }
+
+    #[test]
+    fn test_recursive_wildcard_matching() {
+        let route = Route::new("/static/**").unwrap();
+        assert!(route.matches("/static"));
+        assert!(route.matches("/static/site.css"));
+        assert!(route.matches("/static/css/vendor/site.css"));
+        assert!(!route.matches("/statics/site.css"));
+        assert!(!route.matches("/other/static/site.css"));
+
+        let route = Route::new("/api/**/edit").unwrap();
+        assert!(route.matches("/api/edit"));
+        assert!(route.matches("/api/users/7/edit"));
+        assert!(!route.matches("/api/users/7"));
+        assert!(!route.matches("/api/users/7/edit/x"));
+
+        assert!(Route::new("/**").unwrap().matches("/anything/at/all"));
+
+        let route = Route::new("/a/**/b/**/c").unwrap();
+        assert!(route.matches("/a/b/c"));
+        assert!(route.matches("/a/x/b/y/b/z/c"));
+        assert!(!route.matches("/a/x/c/b"));
+    }
+
+    #[test]
+    fn test_many_recursive_wildcards_match_quickly() {
+        let pattern = format!("{}/never", "/**".repeat(20));
+        let route = Route::new(pattern).unwrap();
+        let path = "/x".repeat(40);
+        let started = std::time::Instant::now();
+        assert!(!route.matches(&path));
+        assert!(started.elapsed() < std::time::Duration::from_secs(1));
+    }
+
+    #[test]
+    fn test_wildcard_patterns_match_normalized_and_mount() {
+        let route = Route::new("/api/*/items").unwrap();
+        assert!(route.matches_normalized("/API/42/Items/"));
+        assert!(!route.matches_normalized("/api/42/items/7"));
+
+        let route = Route::new("/static/**").unwrap();
+        assert!(route.matches_normalized("/Static/CSS/"));
+        assert_eq!(route.mount_path("/static/css/site.css"), "/css/site.css");
+        assert_eq!(route.mount_path("/static"), "/");
+    }
+
+    #[test]
+    fn test_strict_route_matching_is_case_and_slash_sensitive() {
+        assert!(!Route::new("/api/*").unwrap().matches("/API/Users"));
+        assert!(!Route::new("/api/").unwrap().matches("/api"));
+        assert!(!Route::new("/api").unwrap().matches("/api/"));
+        assert!(!Route::new("/Api").unwrap().matches("/api"));
+    }
+
+    #[test]
+    fn test_normalized_route_matching() {
+        let wildcard = Route::new("/api/*").unwrap();
+        assert!(wildcard.matches_normalized("/API/Users"));
+        assert!(wildcard.matches_normalized("/Api"));
+        assert!(!wildcard.matches_normalized("/other"));
+
+        let prefix = Route::new("/api/").unwrap();
+        assert!(prefix.matches_normalized("/api"));
+        assert!(prefix.matches_normalized("/API/"));
+        assert!(prefix.matches_normalized("/api/Users"));
+        assert!(!prefix.matches_normalized("/apis"));
+
+        // The pattern is normalized too
+        let exact = Route::new("/Health").unwrap();
+        assert!(exact.matches_normalized("/health"));
+        assert!(exact.matches_normalized("/HEALTH/"));
+        assert!(exact.matches_normalized("/health//"));
+        assert!(!exact.matches_normalized("/health/x"));
+
+        assert!(Route::new("/").unwrap().matches_normalized("/"));
+    }
+
+    #[test]
+    fn test_rewrite_path() {
+        let mut process = Process::new(
+            ProcessId::new("api").unwrap(),
+            Executable::new("./api").unwrap(),
+            Route::new("/api/v1/*").unwrap(),
+            PipeName::new("api_pipe").unwrap(),
+        );
+        assert!(!process.rewrites_path());
+
+        process.strip_prefix = Some("/api/v1".to_string());
+        assert!(process.rewrites_path());
+        assert_eq!(process.rewrite_path("/api/v1/users"), "/users");
+        assert_eq!(process.rewrite_path("/API/V1/users?page=2"), "/users?page=2");
+        assert_eq!(process.rewrite_path("/api/v1"), "/");
+        assert_eq!(process.rewrite_path("/api/v1?page=2"), "/?page=2");
+        // Only whole segments are stripped
+        assert_eq!(process.rewrite_path("/api/v10/users"), "/api/v10/users");
+
+        process.prepend_path = Some("/internal/".to_string());
+        assert_eq!(process.rewrite_path("/api/v1/users"), "/internal/users");
+        process.strip_prefix = None;
+        assert_eq!(process.rewrite_path("/api/v1/users"), "/internal/api/v1/users");
+    }
+
+    #[test]
+    fn test_accepts_content_type() {
+        let mut process = Process::new(
+            ProcessId::new("upload").unwrap(),
+            Executable::new("./upload").unwrap(),
+            Route::new("/upload/*").unwrap(),
+            PipeName::new("upload_pipe").unwrap(),
+        );
+        assert!(process.accepts_content_type("text/plain"));
+        assert!(process.accepts_content_type(""));
+
+        process.accepted_content_types = vec!["multipart/form-data".to_string(), "image/*".to_string()];
+        assert!(process.accepts_content_type("multipart/form-data; boundary=x"));
+        assert!(process.accepts_content_type("Multipart/Form-Data"));
+        assert!(process.accepts_content_type("image/png"));
+        assert!(!process.accepts_content_type("application/json; charset=utf-8"));
+        assert!(!process.accepts_content_type("multipart/mixed"));
+        assert!(!process.accepts_content_type("image"));
+        assert!(!process.accepts_content_type(""));
+    }
+
+    #[test]
+    fn test_instance_arguments_fill_in_placeholders() {
+        let mut process = Process::new(
+            ProcessId::new("api").unwrap(),
+            Executable::new("./api").unwrap(),
+            Route::new("/api/*").unwrap(),
+            PipeName::new("api_pipe").unwrap(),
+        );
+        process.arguments = vec!["--listen=${ADDRESS}".to_string(), "--port=${PORT}".to_string(), "-v".to_string()];
+
+        // A pipe process has no port to give
+        let arguments = process.instance_arguments("/tmp/api_pipe", "127.0.0.1:9001");
+        assert_eq!(arguments, vec!["--listen=/tmp/api_pipe", "--port=${PORT}", "-v"]);
+
+        process.communication_mode = CommunicationMode::Http;
+        let arguments = process.instance_arguments("127.0.0.1:9001", "127.0.0.1:9001");
+        assert_eq!(arguments, vec!["--listen=127.0.0.1:9001", "--port=9001", "-v"]);
+    }
+
+    #[test]
+    fn test_mount_path_strips_route_prefix() {
+        let wildcard = Route::new("/static/*").unwrap();
+        assert_eq!(wildcard.mount_path("/static/css/site.css"), "/css/site.css");
+        assert_eq!(wildcard.mount_path("/Static/index.html"), "/index.html");
+        assert_eq!(wildcard.mount_path("/static"), "/");
+
+        assert_eq!(Route::new("/docs/").unwrap().mount_path("/docs/"), "/");
+        assert_eq!(Route::new("/").unwrap().mount_path("/index.html"), "/index.html");
+        // The default process gets paths outside its route
+        assert_eq!(wildcard.mount_path("/other/page"), "/other/page");
+
+        assert_eq!(wildcard.path_below("/static/css/site.css").as_deref(), Some("/css/site.css"));
+        assert_eq!(wildcard.path_below("/staticx"), None);
+        assert_eq!(wildcard.path_below("/static"), None);
+        assert_eq!(Route::new("/favicon.ico").unwrap().path_below("/favicon.ico"), None);
+    }
+
+    #[test]
+    fn test_generation_gets_fresh_addresses() {
+        let mut process = Process::new(
+            ProcessId::new("api").unwrap(),
+            Executable::new("./api").unwrap(),
+            Route::new("/api/*").unwrap(),
+            PipeName::new("api_pipe").unwrap(),
+        );
+        assert_eq!(process.for_generation(0), process);
+
+        process.instances = 2;
+        let reloaded = process.for_generation(3);
+        assert_eq!(reloaded.instance_pipe_names(), vec!["api_pipe_r3_0", "api_pipe_r3_1"]);
+        assert!(reloaded.instance_addresses().iter().all(|a| !process.instance_addresses().contains(a)));
+    }
+
+    #[test]
+    fn test_fixed_http_port_overrides_derived_port() {
+        let mut process = Process::new(
+            ProcessId::new("api").unwrap(),
+            Executable::new("./api").unwrap(),
+            Route::new("/api/*").unwrap(),
+            PipeName::new("api_pipe").unwrap(),
+        );
+        process.communication_mode = CommunicationMode::Http;
+        process.http_port = Some(8085);
+        process.instances = 2;
+        assert_eq!(process.instance_addresses(), vec!["127.0.0.1:8085", "127.0.0.1:8086"]);
+
+        // A reload can't share the port with the instances it replaces
+        let reloaded = process.for_generation(1);
+        assert_eq!(
+            reloaded.instance_addresses()[0],
+            get_http_address_from_name("api_pipe_r1_0")
+        );
+    }
+
+    #[test]
+    fn test_envelope_format_is_recognized() {
+        assert_eq!(SerializationFormat::of_envelope(b" {\"status\": 200}"), Some(SerializationFormat::Json));
+        // A fixmap of one entry, and a map16
+        assert_eq!(SerializationFormat::of_envelope(&[0x81, 0xa6]), Some(SerializationFormat::MsgPack));
+        assert_eq!(SerializationFormat::of_envelope(&[0xde, 0x00, 0x10]), Some(SerializationFormat::MsgPack));
+        assert_eq!(SerializationFormat::of_envelope(b"HTTP/1.1 200 OK"), None);
+        assert_eq!(SerializationFormat::of_envelope(b""), None);
+    }
+
+    #[test]
+    fn test_other_method_keeps_its_token() {
+        assert_eq!(HttpMethod::Other("PROPFIND".to_string()).as_str(), "PROPFIND");
+        assert_eq!(HttpMethod::Get.as_str(), "GET");
+        assert_eq!(HttpMethod::from_name("PROPFIND").as_str(), "PROPFIND");
+        assert_eq!(HttpMethod::from_name("GET"), HttpMethod::Get);
+    }
+
+    #[test]
+    fn test_method_round_trips_through_its_name() {
+        let methods = [
+            HttpMethod::Get,
+            HttpMethod::Post,
+            HttpMethod::Put,
+            HttpMethod::Delete,
+            HttpMethod::Patch,
+            HttpMethod::Head,
+            HttpMethod::Options,
+            HttpMethod::Other("PROPFIND".to_string()),
+        ];
+        for method in methods {
+            assert_eq!(method.as_str().parse::<HttpMethod>(), Ok(method.clone()));
+            assert_eq!(HttpMethod::try_from(method.as_str()), Ok(method));
+        }
+
+        // Standard methods in any case; others as they were written
+        assert_eq!("get".parse(), Ok(HttpMethod::Get));
+        assert_eq!("Options".parse(), Ok(HttpMethod::Options));
+        assert_eq!("purge".parse(), Ok(HttpMethod::Other("purge".to_string())));
+
+        for invalid in ["", "GET /", "GE\tT", "GÉT"] {
+            assert!(matches!(invalid.parse::<HttpMethod>(), Err(DomainError::InvalidHttpMethod(_))), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn test_restart_backoff_doubles_up_to_its_cap() {
+        let policy = RestartPolicy {
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_millis(500),
+            max_crashes: 10,
+            window: Duration::from_secs(60),
+        };
+        let backoffs: Vec<u64> = (1..=5).map(|n| policy.backoff(n).as_millis() as u64).collect();
+        assert_eq!(backoffs, vec![100, 200, 400, 500, 500]);
+        assert_eq!(policy.backoff(u32::MAX), policy.max_backoff);
+    }
+
+    #[test]
+    fn test_retry_policy_covers_idempotent_methods() {
+        let policy = RetryPolicy {
+            max_attempts: 3,
+            backoff: Duration::from_millis(100),
+            methods: vec![HttpMethod::Patch],
+        };
+        assert!(policy.covers(&HttpMethod::Get));
+        assert!(policy.covers(&HttpMethod::Delete));
+        assert!(policy.covers(&HttpMethod::Patch));
+        assert!(!policy.covers(&HttpMethod::Post));
+        assert!(!policy.covers(&HttpMethod::Other("PROPFIND".to_string())));
+        let backoffs: Vec<u64> = (1..=3).map(|n| policy.backoff(n).as_millis() as u64).collect();
+        assert_eq!(backoffs, vec![100, 200, 400]);
+    }
+
     #[test]
     fn test_executable_validation() {
         assert!(Executable::new("/bin/test").is_ok());
         assert!(Executable::new("").is_err());
     }
+
+    #[test]
+    fn test_instance_pipe_names() {
+        let mut process = Process::new(
+            ProcessId::new("api").unwrap(),
+            Executable::new("./api").unwrap(),
+            Route::new("/api/*").unwrap(),
+            PipeName::new("api_pipe").unwrap(),
+        );
+        assert_eq!(process.instance_pipe_names(), vec!["api_pipe"]);
+
+        process.instances = 3;
+        assert_eq!(process.instance_pipe_names(), vec!["api_pipe_0", "api_pipe_1", "api_pipe_2"]);
+    }
 }
@@ -1,8 +1,12 @@
 //! Repository interfaces (Ports) - define contracts without implementation
 //! These follow the Dependency Inversion Principle
 
-use crate::domain::entities::{Process, ProcessId};
+use crate::domain::entities::{
+    HttpResponse, Process, ProcessId, ProcessState, ProcessStatus, SerializationFormat, StreamingRequest,
+};
 use async_trait::async_trait;
+use std::sync::Arc;
+use std::time::Duration;
 
 /// Repository for managing process configurations
 #[async_trait]
@@ -19,27 +23,191 @@ pub trait ProcessOrchestrationService: Send + Sync {
     
     /// Stop a process
     async fn stop_process(&mut self, id: &ProcessId) -> Result<(), OrchestrationError>;
+
+    /// Stop a process if it is running, then start it again
+    #[allow(dead_code)]
+    async fn restart_process(&mut self, id: &ProcessId) -> Result<(), OrchestrationError>;
+    
+    /// Start a new set of children for a running process on fresh addresses
+    /// (see [`Process::for_generation`]), keeping the current ones running,
+    /// and return the new generation
+    async fn start_replacement(&mut self, id: &ProcessId) -> Result<u32, OrchestrationError>;
+
+    /// Stop the children replaced by [`start_replacement`](Self::start_replacement)
+    async fn stop_replaced(&mut self, id: &ProcessId) -> Result<(), OrchestrationError>;
+
+    /// Stop the children started by [`start_replacement`](Self::start_replacement)
+    /// and go back to the ones they were to replace
+    async fn abandon_replacement(&mut self, id: &ProcessId) -> Result<(), OrchestrationError>;
     
     /// Check if a process is running
     #[allow(dead_code)]
     fn is_running(&self, id: &ProcessId) -> bool;
+
+    /// Runtime status of a process, or `None` if it isn't registered
+    #[allow(dead_code)]
+    fn status(&self, id: &ProcessId) -> Option<ProcessStatus>;
+
+    /// Lifecycle stage of a process, or `None` if it isn't registered
+    fn state(&self, id: &ProcessId) -> Option<ProcessState>;
+
+    /// Note that a started process is accepting requests, ending its
+    /// [`ProcessState::Starting`] stage
+    fn mark_ready(&mut self, id: &ProcessId);
+
+    /// Note that a request was routed to a process, postponing its idle timeout
+    fn record_activity(&mut self, id: &ProcessId);
+
+    /// Stop every process that has gone without requests for longer than its
+    /// idle timeout, returning the ones stopped
+    async fn stop_idle(&mut self) -> Vec<ProcessId>;
+
+    /// Check every registered process can be started, without starting it
+    fn validate_all(&self) -> Result<(), OrchestrationError>;
     
     /// Start all registered processes
     async fn start_all(&mut self) -> Result<(), OrchestrationError>;
     
     /// Stop all running processes
+    #[allow(dead_code)]
     async fn stop_all(&mut self) -> Result<(), OrchestrationError>;
+
+    /// Ask every running process to exit, killing those still running after
+    /// `timeout`, and return the ones that had to be killed
+    async fn shutdown(&mut self, timeout: Duration) -> Vec<ProcessId>;
+}
+
+/// How a single exchange with a backend is carried out, from the settings
+/// of the process it's for
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SendOptions {
+    /// Largest response read before the exchange fails
+    pub max_response_bytes: usize,
+    /// `Host` header sent to HTTP backends instead of their address;
+    /// transports that don't speak HTTP ignore it
+    pub host: Option<String>,
+    /// Envelope format the process speaks, announced to HTTP backends so
+    /// one expecting another can say so instead of misreading it
+    pub protocol: SerializationFormat,
+}
+
+impl Default for SendOptions {
+    fn default() -> Self {
+        Self {
+            max_response_bytes: usize::MAX,
+            host: None,
+            protocol: SerializationFormat::default(),
+        }
+    }
 }
 
 /// Service for communicating with processes via named pipes
 #[async_trait]
 pub trait PipeCommunicationService: Send + Sync {
     /// Send a request through a named pipe and get response
+    ///
+    /// The proxy drops this future when the client disconnects, so dropping
+    /// it must abandon the exchange and close the connection rather than
+    /// leave a task behind that keeps the backend busy.
     async fn send_request(
         &self,
         pipe_name: &str,
         request: Vec<u8>,
     ) -> Result<Vec<u8>, CommunicationError>;
+
+    /// Send a request as plain HTTP, streaming its body, for processes using
+    /// the raw protocol
+    ///
+    /// The backend's status is passed through rather than treated as a
+    /// failure. Like [`send_request`](Self::send_request), dropping the future
+    /// must abandon the exchange. Transports that don't speak HTTP refuse it.
+    async fn send_streaming(
+        &self,
+        address: &str,
+        _request: StreamingRequest,
+    ) -> Result<HttpResponse, CommunicationError> {
+        Err(CommunicationError::SendFailed(format!(
+            "{} can't be sent a raw request over this transport",
+            address
+        )))
+    }
+
+    /// [`send_request`](Self::send_request), applying `options`
+    ///
+    /// A response growing past the limit fails the exchange. Transports that
+    /// can stop reading early should, so a runaway backend can't exhaust the
+    /// proxy's memory; the rest check the whole response.
+    async fn send_request_with(
+        &self,
+        pipe_name: &str,
+        request: Vec<u8>,
+        options: &SendOptions,
+    ) -> Result<Vec<u8>, CommunicationError> {
+        let response = self.send_request(pipe_name, request).await?;
+        if response.len() > options.max_response_bytes {
+            return Err(CommunicationError::response_too_large(options.max_response_bytes));
+        }
+        Ok(response)
+    }
+
+    /// [`send_streaming`](Self::send_streaming), applying `options`
+    async fn send_streaming_with(
+        &self,
+        address: &str,
+        request: StreamingRequest,
+        options: &SendOptions,
+    ) -> Result<HttpResponse, CommunicationError> {
+        let response = self.send_streaming(address, request).await?;
+        if response.body.len() > options.max_response_bytes {
+            return Err(CommunicationError::response_too_large(options.max_response_bytes));
+        }
+        Ok(response)
+    }
+
+    /// Check that a backend is accepting connections at `address`, without
+    /// sending it a request; transports that can't tell report it ready
+    async fn probe(&self, _address: &str) -> Result<(), CommunicationError> {
+        Ok(())
+    }
+}
+
+/// A shared transport is one too, so the proxy can pick its transport at
+/// runtime as an `Arc<dyn PipeCommunicationService>`
+#[async_trait]
+impl<T: PipeCommunicationService + ?Sized> PipeCommunicationService for Arc<T> {
+    async fn send_request(&self, pipe_name: &str, request: Vec<u8>) -> Result<Vec<u8>, CommunicationError> {
+        (**self).send_request(pipe_name, request).await
+    }
+
+    async fn send_streaming(
+        &self,
+        address: &str,
+        request: StreamingRequest,
+    ) -> Result<HttpResponse, CommunicationError> {
+        (**self).send_streaming(address, request).await
+    }
+
+    async fn send_request_with(
+        &self,
+        pipe_name: &str,
+        request: Vec<u8>,
+        options: &SendOptions,
+    ) -> Result<Vec<u8>, CommunicationError> {
+        (**self).send_request_with(pipe_name, request, options).await
+    }
+
+    async fn send_streaming_with(
+        &self,
+        address: &str,
+        request: StreamingRequest,
+        options: &SendOptions,
+    ) -> Result<HttpResponse, CommunicationError> {
+        (**self).send_streaming_with(address, request, options).await
+    }
+
+    async fn probe(&self, address: &str) -> Result<(), CommunicationError> {
+        (**self).probe(address).await
+    }
 }
 
 /// Repository errors
@@ -72,6 +240,7 @@ pub enum OrchestrationError {
     NotRunning(String),
     SpawnFailed(String),
     KillFailed(String),
+    ExecutableNotFound(String),
 }
 
 impl std::fmt::Display for OrchestrationError {
@@ -82,6 +251,7 @@ impl std::fmt::Display for OrchestrationError {
             OrchestrationError::NotRunning(msg) => write!(f, "Not running: {}", msg),
             OrchestrationError::SpawnFailed(msg) => write!(f, "Spawn failed: {}", msg),
             OrchestrationError::KillFailed(msg) => write!(f, "Kill failed: {}", msg),
+            OrchestrationError::ExecutableNotFound(msg) => write!(f, "Executable not found: {}", msg),
         }
     }
 }
@@ -89,13 +259,25 @@ impl std::fmt::Display for OrchestrationError {
 impl std::error::Error for OrchestrationError {}
 
 /// Communication errors
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 #[allow(dead_code)]
 pub enum CommunicationError {
     ConnectionFailed(String),
     SendFailed(String),
     ReceiveFailed(String),
     Timeout(String),
+    /// The request body streamed to the backend grew past the proxy's limit
+    PayloadTooLarge(String),
+    /// The request body couldn't be read from the client, e.g. because it
+    /// hung up mid-upload; the backend isn't at fault
+    RequestBodyFailed(String),
+}
+
+impl CommunicationError {
+    /// The backend's response grew past the `max_bytes` it's allowed
+    pub fn response_too_large(max_bytes: usize) -> Self {
+        CommunicationError::ReceiveFailed(format!("response exceeds the {}-byte limit", max_bytes))
+    }
 }
 
 impl std::fmt::Display for CommunicationError {
@@ -105,6 +287,8 @@ impl std::fmt::Display for CommunicationError {
             CommunicationError::SendFailed(msg) => write!(f, "Send failed: {}", msg),
             CommunicationError::ReceiveFailed(msg) => write!(f, "Receive failed: {}", msg),
             CommunicationError::Timeout(msg) => write!(f, "Timeout: {}", msg),
+            CommunicationError::PayloadTooLarge(msg) => write!(f, "Request body too large: {}", msg),
+            CommunicationError::RequestBodyFailed(msg) => write!(f, "Request body failed: {}", msg),
         }
     }
 }
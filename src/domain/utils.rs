@@ -43,7 +43,7 @@ mod tests {
     #[test]
     fn test_http_port_in_range() {
         let port = get_http_port_from_name("test_pipe");
-        assert!(port >= 9000 && port < 10000, "Port should be in range 9000-9999");
+        assert!((9000..10000).contains(&port), "Port should be in range 9000-9999");
     }
 
     #[test]
@@ -61,6 +61,6 @@ mod tests {
         assert!(addr.starts_with("127.0.0.1:"));
         let port_str = addr.split(':').nth(1).unwrap();
         let port: u16 = port_str.parse().unwrap();
-        assert!(port >= 9000 && port < 10000, "Port should be in 9000-9999 range");
+        assert!((9000..10000).contains(&port), "Port should be in 9000-9999 range");
     }
 }
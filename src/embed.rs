@@ -0,0 +1,220 @@
+//! Embedding - runs the proxy inside another binary, configured in code
+//! instead of by a manifest and command line flags
+//!
+//! See `examples/embedded.rs` for a proxy in front of the echo service.
+
+use crate::adapters::http::{tcp, TcpOptions};
+use crate::adapters::{HttpServerState, ServerOptions, TokioProcessOrchestrator};
+use crate::domain::{Process, ProcessId};
+use crate::infrastructure::{HttpClient, NamedPipeClient};
+use crate::startup;
+use crate::use_cases::{ProxyHttpRequestUseCase, ProxyOptions, StopAllProcessesUseCase, SHUTDOWN_TIMEOUT};
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{oneshot, RwLock};
+use tokio::task::JoinHandle;
+use tokio::time::Instant;
+
+/// Why the proxy failed to start or to shut down cleanly
+pub type Error = Box<dyn std::error::Error + Send + Sync>;
+
+/// Address listened on unless [`LocalLambdasBuilder::bind`] says otherwise,
+/// the binary's default
+pub const DEFAULT_BIND: &str = "127.0.0.1:3000";
+
+/// A proxy configured in code, ready to [`start`](Self::start)
+pub struct LocalLambdas {
+    processes: Vec<Process>,
+    bind: String,
+    proxy_options: ProxyOptions,
+    server_options: ServerOptions,
+    tcp_options: TcpOptions,
+    shutdown_timeout: Duration,
+}
+
+impl LocalLambdas {
+    pub fn builder() -> LocalLambdasBuilder {
+        LocalLambdasBuilder::default()
+    }
+
+    /// Start every process, wait for those without a health check to be
+    /// ready, and serve in the background, as the binary does
+    ///
+    /// The address is bound first, so one that's taken fails before any
+    /// process is started.
+    pub async fn start(self) -> Result<RunningProxy, Error> {
+        let listener = tcp::bind(&self.bind, &self.tcp_options)
+            .await
+            .map_err(|e| format!("Failed to bind {}: {}", self.bind, e))?;
+        let local_addr = listener.local_addr()?;
+
+        let orchestrator = startup::orchestrator(&self.processes);
+        startup::check(&orchestrator, &self.processes, true).await?;
+        let use_case = startup::proxy(
+            NamedPipeClient::new(),
+            Arc::new(HttpClient::new()),
+            self.processes,
+            self.proxy_options,
+            Some(&orchestrator),
+        )
+        .await;
+        startup::start(&use_case, Some(&orchestrator)).await?;
+        let tasks = startup::spawn_background_tasks(&use_case);
+
+        let app = HttpServerState::with_options(use_case.clone(), self.server_options).create_router();
+        let (stop, stopped) = oneshot::channel::<()>();
+        let server = tokio::spawn(async move {
+            let shutdown = async {
+                let _ = stopped.await;
+            };
+            tcp::serve(listener, app, &self.tcp_options, shutdown).await
+        });
+        tracing::info!("Listening on http://{}", local_addr);
+
+        Ok(RunningProxy {
+            local_addr,
+            use_case,
+            orchestrator,
+            server,
+            stop,
+            tasks,
+            shutdown_timeout: self.shutdown_timeout,
+        })
+    }
+}
+
+/// Configuration for a [`LocalLambdas`]; everything not set behaves as the
+/// binary does without the corresponding flag
+pub struct LocalLambdasBuilder {
+    processes: Vec<Process>,
+    bind: String,
+    proxy_options: ProxyOptions,
+    server_options: ServerOptions,
+    tcp_options: TcpOptions,
+    shutdown_timeout: Duration,
+}
+
+impl Default for LocalLambdasBuilder {
+    fn default() -> Self {
+        Self {
+            processes: Vec::new(),
+            bind: DEFAULT_BIND.to_string(),
+            proxy_options: ProxyOptions::default(),
+            server_options: ServerOptions::default(),
+            tcp_options: TcpOptions::default(),
+            shutdown_timeout: SHUTDOWN_TIMEOUT,
+        }
+    }
+}
+
+impl LocalLambdasBuilder {
+    /// Route to `processes`, in match order, replacing any added before
+    pub fn processes(mut self, processes: Vec<Process>) -> Self {
+        self.processes = processes;
+        self
+    }
+
+    /// Route to `process` too, matched after those already added
+    pub fn process(mut self, process: Process) -> Self {
+        self.processes.push(process);
+        self
+    }
+
+    /// Cache up to `entries` responses; nothing is cached unless this is set
+    pub fn cache_size(mut self, entries: u64) -> Self {
+        self.proxy_options.cache_size = Some(entries);
+        self
+    }
+
+    /// Listen on the TCP `addr`; port 0 lets the OS choose, see
+    /// [`RunningProxy::local_addr`]
+    pub fn bind(mut self, addr: impl Into<String>) -> Self {
+        self.bind = addr.into();
+        self
+    }
+
+    /// How requests are proxied, replacing any options set before, the
+    /// cache size included
+    pub fn proxy_options(mut self, options: ProxyOptions) -> Self {
+        self.proxy_options = options;
+        self
+    }
+
+    /// How the HTTP server behaves: body limits, CORS, compression, ...
+    pub fn server_options(mut self, options: ServerOptions) -> Self {
+        self.server_options = options;
+        self
+    }
+
+    /// Listen backlog, `TCP_NODELAY`, which is on by default, and how long
+    /// a silent client may hold a connection
+    pub fn tcp_options(mut self, options: TcpOptions) -> Self {
+        self.tcp_options = options;
+        self
+    }
+
+    /// Longest [`RunningProxy::shutdown`] takes, draining requests and
+    /// stopping processes together
+    pub fn shutdown_timeout(mut self, timeout: Duration) -> Self {
+        self.shutdown_timeout = timeout;
+        self
+    }
+
+    pub fn build(self) -> LocalLambdas {
+        LocalLambdas {
+            processes: self.processes,
+            bind: self.bind,
+            proxy_options: self.proxy_options,
+            server_options: self.server_options,
+            tcp_options: self.tcp_options,
+            shutdown_timeout: self.shutdown_timeout,
+        }
+    }
+}
+
+/// A proxy serving in the background until [`shutdown`](Self::shutdown)
+pub struct RunningProxy {
+    local_addr: SocketAddr,
+    use_case: Arc<ProxyHttpRequestUseCase<NamedPipeClient>>,
+    orchestrator: Arc<RwLock<TokioProcessOrchestrator>>,
+    server: JoinHandle<std::io::Result<()>>,
+    stop: oneshot::Sender<()>,
+    /// Health checks and the idle reaper
+    tasks: Vec<JoinHandle<()>>,
+    shutdown_timeout: Duration,
+}
+
+impl RunningProxy {
+    /// The address actually bound, which differs from the requested one
+    /// when binding to port 0
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    /// Stop accepting requests, let those in flight finish, and stop every
+    /// process, all within the shutdown timeout
+    ///
+    /// Returns the processes still running at the deadline, which were
+    /// killed.
+    pub async fn shutdown(mut self) -> Result<Vec<ProcessId>, Error> {
+        let deadline = Instant::now() + self.shutdown_timeout;
+        let _ = self.stop.send(());
+        match tokio::time::timeout_at(deadline, &mut self.server).await {
+            Ok(served) => served??,
+            Err(_) => {
+                tracing::warn!("In-flight requests still running after {:?}; abandoning them", self.shutdown_timeout);
+                self.server.abort();
+            }
+        }
+        for task in &self.tasks {
+            task.abort();
+        }
+
+        if let Err(e) = self.use_case.save_cache() {
+            tracing::error!("{}", e);
+        }
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        Ok(StopAllProcessesUseCase::new(self.orchestrator).execute_within(remaining).await)
+    }
+}
@@ -1,13 +1,149 @@
 //! HTTP communication adapter
 //! Implements PipeCommunicationService using HTTP protocol
 
-use crate::domain::repositories::{PipeCommunicationService, CommunicationError};
+use crate::domain::entities::{HttpResponse, SerializationFormat, StreamingRequest, PROTOCOL_HEADER};
+use crate::domain::repositories::{PipeCommunicationService, CommunicationError, SendOptions};
 use async_trait::async_trait;
+use http_body_util::{BodyExt, Limited};
+use reqwest::header::HeaderMap;
+use std::time::Duration;
+
+/// Headers describing a single connection, which aren't passed on between
+/// the client and a raw-protocol backend; `Expect` was already answered by
+/// the proxy
+const HOP_BY_HOP: &[&str] = &[
+    "connection",
+    "expect",
+    "host",
+    "keep-alive",
+    "proxy-connection",
+    "te",
+    "trailer",
+    "transfer-encoding",
+    "upgrade",
+];
+
+fn is_hop_by_hop(name: &str) -> bool {
+    HOP_BY_HOP.iter().any(|h| name.eq_ignore_ascii_case(h))
+}
+
+/// The headers whose names pass `keep`, skipping values that aren't text
+fn to_pairs(headers: &HeaderMap, keep: impl Fn(&str) -> bool) -> Vec<(String, String)> {
+    headers
+        .iter()
+        .filter(|(name, _)| keep(name.as_str()))
+        .filter_map(|(name, value)| Some((name.to_string(), value.to_str().ok()?.to_string())))
+        .collect()
+}
+
+/// Base URL of a backend, whether its address has a scheme or is `host:port`
+fn base_url(address: &str) -> String {
+    if address.starts_with("http://") || address.starts_with("https://") {
+        address.trim_end_matches('/').to_string()
+    } else {
+        format!("http://{}", address)
+    }
+}
+
+/// Why sending a request failed
+///
+/// The client's body failing while it's streamed, by growing past the limit
+/// or the client hanging up, says nothing about the backend, so it's told
+/// apart from the backend being unreachable.
+fn request_error(e: reqwest::Error) -> CommunicationError {
+    if e.is_timeout() {
+        return CommunicationError::Timeout(e.to_string());
+    }
+    let mut from_body = e.is_body();
+    let mut source = std::error::Error::source(&e);
+    while let Some(error) = source {
+        if is_length_limit(error) {
+            return CommunicationError::PayloadTooLarge(e.to_string());
+        }
+        // hyper reports the body it was given failing as a user error
+        from_body |= error.downcast_ref::<hyper::Error>().is_some_and(hyper::Error::is_user);
+        source = error.source();
+    }
+    if from_body {
+        CommunicationError::RequestBodyFailed(e.to_string())
+    } else {
+        CommunicationError::ConnectionFailed(e.to_string())
+    }
+}
+
+/// Whether `error` is `http_body_util::Limited` refusing more of a body,
+/// possibly wrapped in an I/O error by the stream it went through
+pub(crate) fn is_length_limit(error: &(dyn std::error::Error + 'static)) -> bool {
+    if error.is::<http_body_util::LengthLimitError>() {
+        return true;
+    }
+    let inner = error.downcast_ref::<std::io::Error>().and_then(std::io::Error::get_ref);
+    let mut source = inner.map(|inner| inner as &(dyn std::error::Error + 'static));
+    while let Some(error) = source {
+        if error.is::<http_body_util::LengthLimitError>() {
+            return true;
+        }
+        source = error.source();
+    }
+    false
+}
+
+fn receive_error(e: reqwest::Error) -> CommunicationError {
+    if e.is_timeout() {
+        CommunicationError::Timeout(e.to_string())
+    } else {
+        CommunicationError::ReceiveFailed(e.to_string())
+    }
+}
+
+/// Why reading a body capped by `http_body_util::Limited` failed
+fn limited_body_error(e: Box<dyn std::error::Error + Send + Sync>, max_bytes: usize) -> CommunicationError {
+    if e.is::<http_body_util::LengthLimitError>() {
+        return CommunicationError::response_too_large(max_bytes);
+    }
+    match e.downcast::<reqwest::Error>() {
+        Ok(e) => receive_error(*e),
+        Err(e) => CommunicationError::ReceiveFailed(e.to_string()),
+    }
+}
+
+/// Idle connections kept per backend unless configured otherwise
+pub const DEFAULT_POOL_MAX_IDLE_PER_HOST: usize = 32;
+
+/// How long an idle connection to a backend is kept unless configured otherwise
+pub const DEFAULT_POOL_IDLE_TIMEOUT: Duration = Duration::from_secs(90);
+
+/// Connection pool settings for the HTTP client
+#[derive(Debug, Clone)]
+pub struct HttpClientOptions {
+    /// Maximum idle keep-alive connections kept per backend; 0 opens a new
+    /// connection for every request
+    pub pool_max_idle_per_host: usize,
+    /// How long an idle connection is kept before being closed; `None` keeps it indefinitely
+    pub pool_idle_timeout: Option<Duration>,
+    /// Overall request timeout; `None` waits indefinitely
+    pub timeout: Option<Duration>,
+}
+
+impl Default for HttpClientOptions {
+    fn default() -> Self {
+        Self {
+            pool_max_idle_per_host: DEFAULT_POOL_MAX_IDLE_PER_HOST,
+            pool_idle_timeout: Some(DEFAULT_POOL_IDLE_TIMEOUT),
+            timeout: None,
+        }
+    }
+}
 
 /// Implementation using HTTP protocol
+///
+/// The underlying `reqwest::Client` is built once and shared by all clones,
+/// so connections to backends are pooled and kept alive between requests.
 #[derive(Clone)]
 #[allow(dead_code)]
-pub struct HttpClient;
+pub struct HttpClient {
+    client: reqwest::Client,
+}
 
 impl Default for HttpClient {
     fn default() -> Self {
@@ -18,40 +154,79 @@ impl Default for HttpClient {
 impl HttpClient {
     #[allow(dead_code)]
     pub fn new() -> Self {
-        Self
+        Self::with_options(HttpClientOptions::default())
+    }
+
+    pub fn with_options(options: HttpClientOptions) -> Self {
+        let mut builder = reqwest::Client::builder()
+            .pool_max_idle_per_host(options.pool_max_idle_per_host)
+            .pool_idle_timeout(options.pool_idle_timeout);
+        if let Some(timeout) = options.timeout {
+            builder = builder.timeout(timeout);
+        }
+        let client = builder
+            .build()
+            // Only fails if the TLS backend can't be initialised, which is unrecoverable
+            .expect("Failed to build HTTP client");
+
+        Self { client }
     }
 }
 
 #[async_trait]
 impl PipeCommunicationService for HttpClient {
+    /// The backend is ready once it accepts TCP connections
+    async fn probe(&self, address: &str) -> Result<(), CommunicationError> {
+        let host = address
+            .trim_start_matches("http://")
+            .trim_start_matches("https://")
+            .split('/')
+            .next()
+            .unwrap_or(address);
+        tokio::net::TcpStream::connect(host)
+            .await
+            .map(drop)
+            .map_err(|e| CommunicationError::ConnectionFailed(e.to_string()))
+    }
+
     async fn send_request(
         &self,
         address: &str,
         data: Vec<u8>,
+    ) -> Result<Vec<u8>, CommunicationError> {
+        self.send_request_with(address, data, &SendOptions::default()).await
+    }
+
+    /// The body is read as it arrives, and reading stops as soon as it's
+    /// over the limit
+    async fn send_request_with(
+        &self,
+        address: &str,
+        data: Vec<u8>,
+        options: &SendOptions,
     ) -> Result<Vec<u8>, CommunicationError> {
         // Parse the address - should be in format "host:port" or "127.0.0.1:port"
-        let url = if address.starts_with("http://") || address.starts_with("https://") {
-            address.to_string()
-        } else {
-            format!("http://{}", address)
-        };
+        let url = base_url(address);
 
         tracing::debug!("Sending HTTP request to: {}", url);
 
-        // Create HTTP client
-        let client = reqwest::Client::builder()
-            .timeout(std::time::Duration::from_secs(30))
-            .build()
-            .map_err(|e| CommunicationError::ConnectionFailed(e.to_string()))?;
+        // Tell the backend which envelope it's getting
+        let format = options.protocol;
 
         // Send POST request with the data
-        let response = client
+        let mut builder = self
+            .client
             .post(&url)
-            .header("Content-Type", "application/json")
+            .header("Content-Type", format.content_type().unwrap_or("application/json"))
+            .header(PROTOCOL_HEADER, format.as_str());
+        if let Some(host) = &options.host {
+            builder = builder.header(reqwest::header::HOST, host);
+        }
+        let response = builder
             .body(data)
             .send()
             .await
-            .map_err(|e| CommunicationError::ConnectionFailed(e.to_string()))?;
+            .map_err(request_error)?;
 
         // Check response status
         if !response.status().is_success() {
@@ -62,12 +237,314 @@ impl PipeCommunicationService for HttpClient {
         }
 
         // Read response body
-        let response_bytes = response
-            .bytes()
+        let max_bytes = options.max_response_bytes;
+        let response_bytes = Limited::new(reqwest::Body::from(response), max_bytes)
+            .collect()
             .await
-            .map_err(|e| CommunicationError::ReceiveFailed(e.to_string()))?
+            .map_err(|e| limited_body_error(e, max_bytes))?
+            .to_bytes()
             .to_vec();
 
         Ok(response_bytes)
     }
+
+    /// The body goes to the backend chunk by chunk as the client sends it,
+    /// so large uploads are never held in memory
+    async fn send_streaming(
+        &self,
+        address: &str,
+        request: StreamingRequest,
+    ) -> Result<HttpResponse, CommunicationError> {
+        self.send_streaming_with(address, request, &SendOptions::default()).await
+    }
+
+    /// Like [`send_request_with`](Self::send_request_with), the response
+    /// body stops being read once it's over the limit
+    async fn send_streaming_with(
+        &self,
+        address: &str,
+        request: StreamingRequest,
+        options: &SendOptions,
+    ) -> Result<HttpResponse, CommunicationError> {
+        let url = format!("{}{}", base_url(address), request.path);
+        let method = reqwest::Method::from_bytes(request.method.as_str().as_bytes())
+            .map_err(|e| CommunicationError::SendFailed(e.to_string()))?;
+        tracing::debug!("Streaming {} request to: {}", method, url);
+
+        // The proxy passes trailers on, so the backend may send them
+        let mut builder = self
+            .client
+            .request(method, &url)
+            .header("te", "trailers")
+            .header(PROTOCOL_HEADER, SerializationFormat::Raw.as_str());
+        let forwarded = request
+            .headers
+            .iter()
+            .filter(|(name, _)| !is_hop_by_hop(name) && !name.eq_ignore_ascii_case(PROTOCOL_HEADER));
+        for (name, value) in forwarded {
+            builder = builder.header(name, value);
+        }
+        // The client's own `Host` names the proxy, so the backend is sent
+        // its address unless the process asks for another
+        if let Some(host) = &options.host {
+            builder = builder.header(reqwest::header::HOST, host);
+        }
+        let response = builder
+            .body(reqwest::Body::wrap_stream(request.body))
+            .send()
+            .await
+            .map_err(request_error)?;
+
+        let status_code = response.status().as_u16();
+        // `Trailer` announces the trailers, which an HTTP/1.1 client needs
+        // to be told about to receive them
+        let headers = to_pairs(response.headers(), |name| name == "trailer" || !is_hop_by_hop(name));
+        let max_bytes = options.max_response_bytes;
+        let collected = Limited::new(reqwest::Body::from(response), max_bytes)
+            .collect()
+            .await
+            .map_err(|e| limited_body_error(e, max_bytes))?;
+        let trailers = collected
+            .trailers()
+            .map(|trailers| to_pairs(trailers, |name| !is_hop_by_hop(name)))
+            .unwrap_or_default();
+
+        Ok(HttpResponse {
+            status_code,
+            headers,
+            body: collected.to_bytes().to_vec(),
+            trailers,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_send_request_round_trip() {
+        let mut server = mockito::Server::new_async().await;
+        let mock = server
+            .mock("POST", "/")
+            .match_body("ping")
+            .with_body("pong")
+            .expect(3)
+            .create_async()
+            .await;
+
+        let client = HttpClient::new();
+        for _ in 0..3 {
+            let response = client.send_request(&server.host_with_port(), b"ping".to_vec()).await.unwrap();
+            assert_eq!(response, b"pong");
+        }
+
+        mock.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn test_error_status_is_send_failure() {
+        let mut server = mockito::Server::new_async().await;
+        server.mock("POST", "/").with_status(500).create_async().await;
+
+        let result = HttpClient::new().send_request(&server.url(), Vec::new()).await;
+        assert!(matches!(result, Err(CommunicationError::SendFailed(_))));
+    }
+
+    #[tokio::test]
+    async fn test_response_over_the_bound_is_refused() {
+        let mut server = mockito::Server::new_async().await;
+        server.mock("POST", "/").with_body(vec![b'x'; 64 * 1024]).create_async().await;
+
+        let client = HttpClient::new();
+        let bounded = |max_response_bytes| SendOptions {
+            max_response_bytes,
+            ..SendOptions::default()
+        };
+        let response = client.send_request_with(&server.url(), Vec::new(), &bounded(64 * 1024)).await.unwrap();
+        assert_eq!(response.len(), 64 * 1024);
+        match client.send_request_with(&server.url(), Vec::new(), &bounded(1024)).await {
+            Err(CommunicationError::ReceiveFailed(msg)) => assert_eq!(msg, "response exceeds the 1024-byte limit"),
+            other => panic!("expected a receive failure, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_host_override_is_sent_to_the_backend() {
+        let mut server = mockito::Server::new_async().await;
+        let envelope = server
+            .mock("POST", "/")
+            .match_header("host", "api.internal")
+            .with_body("pong")
+            .create_async()
+            .await;
+        let raw = server
+            .mock("GET", "/users")
+            .match_header("host", "api.internal")
+            .with_body("users")
+            .create_async()
+            .await;
+
+        let client = HttpClient::new();
+        let options = SendOptions {
+            host: Some("api.internal".to_string()),
+            ..SendOptions::default()
+        };
+        let response = client.send_request_with(&server.host_with_port(), b"ping".to_vec(), &options).await.unwrap();
+        assert_eq!(response, b"pong");
+
+        // The client's own Host is replaced, not sent alongside
+        let request = StreamingRequest {
+            method: crate::domain::HttpMethod::Get,
+            path: "/users".to_string(),
+            headers: vec![("host".to_string(), "localhost:3000".to_string())],
+            body: Box::pin(futures_util::stream::empty()),
+        };
+        let response = client.send_streaming_with(&server.host_with_port(), request, &options).await.unwrap();
+        assert_eq!(response.body, b"users");
+
+        envelope.assert_async().await;
+        raw.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn test_protocol_is_announced_to_the_backend() {
+        let mut server = mockito::Server::new_async().await;
+        let mock = server
+            .mock("POST", "/")
+            .match_header("content-type", "application/msgpack")
+            .match_header(PROTOCOL_HEADER, "msgpack")
+            .with_body("pong")
+            .create_async()
+            .await;
+
+        let options = SendOptions {
+            protocol: SerializationFormat::MsgPack,
+            ..SendOptions::default()
+        };
+        let response = HttpClient::new()
+            .send_request_with(&server.host_with_port(), b"ping".to_vec(), &options)
+            .await
+            .unwrap();
+        assert_eq!(response, b"pong");
+
+        mock.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn test_send_streaming_passes_request_and_response_through() {
+        let mut server = mockito::Server::new_async().await;
+        let mock = server
+            .mock("PUT", "/upload/file.bin")
+            .match_header("x-upload", "1")
+            .match_body("first chunk, second chunk")
+            .with_status(201)
+            .with_header("location", "/files/1")
+            .with_body("stored")
+            .create_async()
+            .await;
+
+        let chunks = ["first chunk, ", "second chunk"].map(|c| Ok(bytes::Bytes::from(c)));
+        let request = StreamingRequest {
+            method: crate::domain::HttpMethod::Put,
+            path: "/upload/file.bin".to_string(),
+            headers: vec![
+                ("x-upload".to_string(), "1".to_string()),
+                ("connection".to_string(), "close".to_string()),
+            ],
+            body: Box::pin(futures_util::stream::iter(chunks)),
+        };
+        let response = HttpClient::new().send_streaming(&server.host_with_port(), request).await.unwrap();
+
+        // The backend's status and headers are its own
+        assert_eq!(response.status_code, 201);
+        assert!(response.headers.contains(&("location".to_string(), "/files/1".to_string())));
+        assert_eq!(response.body, b"stored");
+        mock.assert_async().await;
+    }
+
+    /// A streamed request whose body fails after `sent` bytes
+    fn failing_upload(error: std::io::Error) -> StreamingRequest {
+        let chunks = vec![Ok(bytes::Bytes::from_static(b"sent")), Err(error)];
+        StreamingRequest {
+            method: crate::domain::HttpMethod::Post,
+            path: "/upload".to_string(),
+            headers: vec![],
+            body: Box::pin(futures_util::stream::iter(chunks)),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_failed_request_body_is_not_a_connection_failure() {
+        let mut server = mockito::Server::new_async().await;
+        server.mock("POST", "/upload").create_async().await;
+        let client = HttpClient::new();
+
+        // The error the proxy's capped body gives once the client sends too much
+        let mut capped = Limited::new(http_body_util::Full::new(bytes::Bytes::from(vec![0; 64])), 16);
+        let limit = capped.frame().await.unwrap().unwrap_err();
+        let oversized = failing_upload(std::io::Error::other(limit));
+        let result = client.send_streaming(&server.host_with_port(), oversized).await;
+        assert!(matches!(result, Err(CommunicationError::PayloadTooLarge(_))), "{:?}", result);
+
+        let aborted = failing_upload(std::io::Error::from(std::io::ErrorKind::ConnectionReset));
+        let result = client.send_streaming(&server.host_with_port(), aborted).await;
+        assert!(matches!(result, Err(CommunicationError::RequestBodyFailed(_))), "{:?}", result);
+    }
+
+    #[tokio::test]
+    async fn test_connections_are_reused_only_when_pooled() {
+        use axum::extract::ConnectInfo;
+        use std::collections::HashSet;
+        use std::net::SocketAddr;
+        use std::sync::{Arc, Mutex};
+
+        // Every client address the backend sees is a connection the client opened
+        let peers = Arc::new(Mutex::new(HashSet::new()));
+        let seen = peers.clone();
+        let app = axum::Router::new().route(
+            "/",
+            axum::routing::post(move |ConnectInfo(peer): ConnectInfo<SocketAddr>| async move {
+                seen.lock().unwrap().insert(peer);
+                "pong"
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        tokio::spawn(async move {
+            axum::serve(listener, app.into_make_service_with_connect_info::<SocketAddr>()).await.unwrap()
+        });
+
+        for (pool_max_idle_per_host, connections) in [(DEFAULT_POOL_MAX_IDLE_PER_HOST, 1), (0, 5)] {
+            peers.lock().unwrap().clear();
+            let client = HttpClient::with_options(HttpClientOptions {
+                pool_max_idle_per_host,
+                ..HttpClientOptions::default()
+            });
+            for _ in 0..5 {
+                assert_eq!(client.send_request(&address, b"ping".to_vec()).await.unwrap(), b"pong");
+            }
+            assert_eq!(peers.lock().unwrap().len(), connections, "pool of {}", pool_max_idle_per_host);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_configured_timeout() {
+        // Accepts connections but never answers
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        tokio::spawn(async move {
+            let mut held = Vec::new();
+            while let Ok((stream, _)) = listener.accept().await {
+                held.push(stream);
+            }
+        });
+
+        let client = HttpClient::with_options(HttpClientOptions {
+            timeout: Some(Duration::from_millis(100)),
+            ..HttpClientOptions::default()
+        });
+        let result = client.send_request(&address, b"{}".to_vec()).await;
+        assert!(matches!(result, Err(CommunicationError::Timeout(_))));
+    }
 }
@@ -1,7 +1,10 @@
 /// Infrastructure layer - external frameworks and tools
 pub mod pipes;
 pub mod http_client;
+pub mod replay;
 
 pub use pipes::NamedPipeClient;
 #[allow(unused_imports)]
-pub use http_client::HttpClient;
+pub use http_client::{HttpClient, HttpClientOptions};
+#[allow(unused_imports)]
+pub use replay::{Recorder, RecordingCommunicationService, ReplayCommunicationService};
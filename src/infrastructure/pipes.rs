@@ -1,16 +1,38 @@
 //! Named pipe communication adapter
 //! Implements PipeCommunicationService using platform-specific named pipes
 
-use crate::domain::repositories::{PipeCommunicationService, CommunicationError};
+use crate::domain::repositories::{PipeCommunicationService, CommunicationError, SendOptions};
 use async_trait::async_trait;
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
 
 #[cfg(unix)]
 use tokio::net::UnixStream;
 
+/// Largest message sent or received over a pipe unless configured otherwise,
+/// well above the default request body limit once base64-encoded
+pub const DEFAULT_MAX_MESSAGE_BYTES: usize = 256 * 1024 * 1024;
+
 /// Implementation using platform-specific named pipes
+///
+/// Every request opens its own connection. The pipe protocol has no framing:
+/// a backend ends its response by closing the connection, so a connection
+/// can't be reused for the next request. Backends that want connections kept
+/// alive between requests can use the `http` communication mode, whose
+/// client pools them.
+///
+/// Each exchange is half-duplex as far as the backend needs to know: the
+/// proxy writes the whole request and then, on Unix, shuts down its side for
+/// writing, so the backend can read until end of file; the backend then
+/// writes its response and closes the connection. Windows named pipes have
+/// no such half-close, so backends there have to stop reading once they've
+/// parsed a complete envelope. The proxy reads while it writes,
+/// though, so a backend that starts answering before it has read the whole
+/// request doesn't deadlock with the proxy, each blocked writing to a full
+/// pipe the other isn't reading.
 #[derive(Clone)]
-pub struct NamedPipeClient;
+pub struct NamedPipeClient {
+    max_message_bytes: usize,
+}
 
 impl Default for NamedPipeClient {
     fn default() -> Self {
@@ -20,10 +42,64 @@ impl Default for NamedPipeClient {
 
 impl NamedPipeClient {
     pub fn new() -> Self {
-        Self
+        Self {
+            max_message_bytes: DEFAULT_MAX_MESSAGE_BYTES,
+        }
+    }
+
+    /// Refuse to send or receive messages larger than `max_message_bytes`,
+    /// so a backend that never stops writing can't exhaust memory
+    pub fn with_max_message_bytes(mut self, max_message_bytes: usize) -> Self {
+        self.max_message_bytes = max_message_bytes;
+        self
+    }
+
+    fn check_request_size(&self, data: &[u8]) -> Result<(), CommunicationError> {
+        if data.len() > self.max_message_bytes {
+            return Err(CommunicationError::SendFailed(format!(
+                "request of {} bytes exceeds the {}-byte pipe message limit",
+                data.len(),
+                self.max_message_bytes
+            )));
+        }
+        Ok(())
+    }
+
+    /// The smaller of `max_bytes` and the pipe message limit, with the error
+    /// to report when a response goes past it
+    fn response_limit(&self, max_bytes: usize) -> (usize, fn(usize) -> CommunicationError) {
+        if max_bytes < self.max_message_bytes {
+            (max_bytes, CommunicationError::response_too_large)
+        } else {
+            (self.max_message_bytes, message_too_large)
+        }
+    }
+
+    async fn send(
+        &self,
+        pipe_address: &str,
+        data: Vec<u8>,
+        max_response_bytes: usize,
+    ) -> Result<Vec<u8>, CommunicationError> {
+        self.check_request_size(&data)?;
+        let limit = self.response_limit(max_response_bytes);
+
+        #[cfg(windows)]
+        {
+            self.send_request_windows(pipe_address, data, limit).await
+        }
+
+        #[cfg(unix)]
+        {
+            self.send_request_unix(pipe_address, data, limit).await
+        }
     }
 }
 
+fn message_too_large(max_bytes: usize) -> CommunicationError {
+    CommunicationError::ReceiveFailed(format!("response exceeds the {}-byte pipe message limit", max_bytes))
+}
+
 #[async_trait]
 impl PipeCommunicationService for NamedPipeClient {
     async fn send_request(
@@ -31,14 +107,39 @@ impl PipeCommunicationService for NamedPipeClient {
         pipe_address: &str,
         data: Vec<u8>,
     ) -> Result<Vec<u8>, CommunicationError> {
+        self.send(pipe_address, data, self.max_message_bytes).await
+    }
+
+    /// Reading stops as soon as the response is over the limit
+    async fn send_request_with(
+        &self,
+        pipe_address: &str,
+        data: Vec<u8>,
+        options: &SendOptions,
+    ) -> Result<Vec<u8>, CommunicationError> {
+        self.send(pipe_address, data, options.max_response_bytes).await
+    }
+
+    /// The backend is ready once it has created its socket and accepts a
+    /// connection on it
+    async fn probe(&self, pipe_address: &str) -> Result<(), CommunicationError> {
         #[cfg(windows)]
         {
-            self.send_request_windows(pipe_address, data).await
+            tokio::net::windows::named_pipe::ClientOptions::new()
+                .open(pipe_address)
+                .map(drop)
+                .map_err(|e| CommunicationError::ConnectionFailed(e.to_string()))
         }
 
         #[cfg(unix)]
         {
-            self.send_request_unix(pipe_address, data).await
+            if !std::path::Path::new(pipe_address).exists() {
+                return Err(CommunicationError::ConnectionFailed(format!("socket {} does not exist", pipe_address)));
+            }
+            UnixStream::connect(pipe_address)
+                .await
+                .map(drop)
+                .map_err(|e| CommunicationError::ConnectionFailed(e.to_string()))
         }
     }
 }
@@ -49,30 +150,15 @@ impl NamedPipeClient {
         &self,
         pipe_address: &str,
         data: Vec<u8>,
+        (max_bytes, too_large): (usize, fn(usize) -> CommunicationError),
     ) -> Result<Vec<u8>, CommunicationError> {
         use tokio::net::windows::named_pipe::ClientOptions;
 
-        let mut client = ClientOptions::new()
+        let client = ClientOptions::new()
             .open(pipe_address)
             .map_err(|e| CommunicationError::ConnectionFailed(e.to_string()))?;
 
-        client
-            .write_all(&data)
-            .await
-            .map_err(|e| CommunicationError::SendFailed(e.to_string()))?;
-
-        client
-            .flush()
-            .await
-            .map_err(|e| CommunicationError::SendFailed(e.to_string()))?;
-
-        let mut response = Vec::new();
-        client
-            .read_to_end(&mut response)
-            .await
-            .map_err(|e| CommunicationError::ReceiveFailed(e.to_string()))?;
-
-        Ok(response)
+        exchange(client, &data, max_bytes, too_large).await
     }
 
     #[cfg(unix)]
@@ -80,27 +166,241 @@ impl NamedPipeClient {
         &self,
         pipe_address: &str,
         data: Vec<u8>,
+        (max_bytes, too_large): (usize, fn(usize) -> CommunicationError),
     ) -> Result<Vec<u8>, CommunicationError> {
-        let mut stream = UnixStream::connect(pipe_address)
+        let stream = UnixStream::connect(pipe_address)
             .await
             .map_err(|e| CommunicationError::ConnectionFailed(e.to_string()))?;
 
-        stream
-            .write_all(&data)
-            .await
-            .map_err(|e| CommunicationError::SendFailed(e.to_string()))?;
+        exchange(stream, &data, max_bytes, too_large).await
+    }
+}
 
-        stream
-            .flush()
-            .await
-            .map_err(|e| CommunicationError::SendFailed(e.to_string()))?;
+/// Send `request` over a connected pipe and read the response, reading
+/// while writing; see [`NamedPipeClient`] for the protocol
+///
+/// Writing is abandoned once the response is complete, so a backend that
+/// answers without reading the whole request doesn't hold the exchange up.
+async fn exchange<S: AsyncRead + AsyncWrite>(
+    stream: S,
+    request: &[u8],
+    max_bytes: usize,
+    too_large: fn(usize) -> CommunicationError,
+) -> Result<Vec<u8>, CommunicationError> {
+    let (mut reader, mut writer) = tokio::io::split(stream);
+    let writing = async {
+        writer.write_all(request).await?;
+        writer.flush().await?;
+        // End of file tells the backend the request is complete; on Windows
+        // this does nothing, as a named pipe can't be closed for writing
+        // alone, and the backend relies on the envelope ending instead
+        writer.shutdown().await
+    };
+    let reading = read_response(&mut reader, max_bytes, too_large);
+    tokio::pin!(writing, reading);
 
-        let mut response = Vec::new();
-        stream
-            .read_to_end(&mut response)
-            .await
-            .map_err(|e| CommunicationError::ReceiveFailed(e.to_string()))?;
+    let mut written = None;
+    let response = loop {
+        tokio::select! {
+            result = &mut writing, if written.is_none() => written = Some(result),
+            response = &mut reading => break response,
+        }
+    };
+    match (response, written) {
+        (Ok(response), _) => Ok(response),
+        // The response failing is most likely a consequence of the request
+        // not getting through, which is the more useful error
+        (Err(_), Some(Err(e))) => Err(CommunicationError::SendFailed(e.to_string())),
+        (Err(e), _) => Err(e),
+    }
+}
+
+/// Read a whole response, which the backend ends by closing its side
+///
+/// Backends that crash mid-response close the pipe too, so a response that
+/// stops partway through a message is reported as truncated rather than
+/// handed on to fail parsing with a confusing error. Reading stops once the
+/// response is over `max_bytes`, without buffering the rest, and fails with
+/// `too_large`.
+async fn read_response<R: AsyncRead + Unpin>(
+    reader: &mut R,
+    max_bytes: usize,
+    too_large: fn(usize) -> CommunicationError,
+) -> Result<Vec<u8>, CommunicationError> {
+    let mut response = Vec::new();
+    if let Err(e) = reader.take(max_bytes as u64 + 1).read_to_end(&mut response).await {
+        return Err(CommunicationError::ReceiveFailed(if response.is_empty() {
+            e.to_string()
+        } else {
+            format!("truncated response: connection lost after {} bytes ({})", response.len(), e)
+        }));
+    }
+
+    if response.len() > max_bytes {
+        return Err(too_large(max_bytes));
+    }
+    if response.is_empty() {
+        return Err(CommunicationError::ReceiveFailed(
+            "backend closed the pipe without sending a response".to_string(),
+        ));
+    }
+    if ends_mid_message(&response) {
+        return Err(CommunicationError::ReceiveFailed(format!(
+            "truncated response: backend closed the pipe after {} bytes, partway through a message",
+            response.len()
+        )));
+    }
+    Ok(response)
+}
+
+/// Whether `data` is the start of a JSON or MessagePack envelope that was cut
+/// off; complete but malformed data is left for the caller to reject
+fn ends_mid_message(data: &[u8]) -> bool {
+    use serde::de::IgnoredAny;
+
+    let json = data.iter().find(|b| !b.is_ascii_whitespace()) == Some(&b'{');
+    if json {
+        return matches!(serde_json::from_slice::<IgnoredAny>(data), Err(e) if e.is_eof());
+    }
+    match rmp_serde::from_slice::<IgnoredAny>(data) {
+        Err(rmp_serde::decode::Error::InvalidMarkerRead(e) | rmp_serde::decode::Error::InvalidDataRead(e)) => {
+            e.kind() == std::io::ErrorKind::UnexpectedEof
+        }
+        _ => false,
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use tokio::net::UnixListener;
+
+    /// Serve one connection that reads the request, writes `response` and
+    /// closes, returning the address to send to
+    async fn scripted_backend(dir: &tempfile::TempDir, response: Vec<u8>) -> String {
+        let path = dir.path().join("backend.sock");
+        let listener = UnixListener::bind(&path).unwrap();
+        tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut request = [0u8; 1024];
+            let _ = stream.read(&mut request).await.unwrap();
+            stream.write_all(&response).await.unwrap();
+        });
+        path.to_string_lossy().into_owned()
+    }
+
+    async fn send(response: &[u8]) -> Result<Vec<u8>, CommunicationError> {
+        let dir = tempfile::TempDir::new().unwrap();
+        let address = scripted_backend(&dir, response.to_vec()).await;
+        NamedPipeClient::new().send_request(&address, b"{}".to_vec()).await
+    }
+
+    fn receive_error(result: Result<Vec<u8>, CommunicationError>) -> String {
+        match result {
+            Err(CommunicationError::ReceiveFailed(msg)) => msg,
+            other => panic!("expected ReceiveFailed, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_complete_response_is_returned() {
+        let body = br#"{"status": 200, "body": ""}"#;
+        assert_eq!(send(body).await.unwrap(), body);
+    }
+
+    #[tokio::test]
+    async fn test_empty_response_is_reported() {
+        let msg = receive_error(send(b"").await);
+        assert!(msg.contains("without sending a response"), "{}", msg);
+    }
+
+    #[tokio::test]
+    async fn test_partial_json_is_reported_as_truncated() {
+        let msg = receive_error(send(br#"{"status": 200, "bo"#).await);
+        assert!(msg.starts_with("truncated response"), "{}", msg);
+    }
+
+    #[tokio::test]
+    async fn test_partial_msgpack_is_reported_as_truncated() {
+        let mut encoded = rmp_serde::to_vec_named(&serde_json::json!({"status": 200, "body": "hello"})).unwrap();
+        encoded.truncate(encoded.len() - 3);
+        let msg = receive_error(send(&encoded).await);
+        assert!(msg.starts_with("truncated response"), "{}", msg);
+    }
+
+    #[tokio::test]
+    async fn test_oversized_response_is_rejected() {
+        // A backend that never stops writing
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("backend.sock");
+        let listener = UnixListener::bind(&path).unwrap();
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                tokio::spawn(async move {
+                    let chunk = [b'x'; 64 * 1024];
+                    while stream.write_all(&chunk).await.is_ok() {}
+                });
+            }
+        });
+
+        let address = path.to_str().unwrap();
+        let client = NamedPipeClient::new().with_max_message_bytes(1024);
+        let msg = receive_error(client.send_request(address, b"{}".to_vec()).await);
+        assert_eq!(msg, "response exceeds the 1024-byte pipe message limit");
+
+        // A tighter per-request bound applies before the pipe limit
+        let bounded = |max_response_bytes| SendOptions {
+            max_response_bytes,
+            ..SendOptions::default()
+        };
+        let msg = receive_error(client.send_request_with(address, b"{}".to_vec(), &bounded(512)).await);
+        assert_eq!(msg, "response exceeds the 512-byte limit");
+        let msg = receive_error(client.send_request_with(address, b"{}".to_vec(), &bounded(4096)).await);
+        assert_eq!(msg, "response exceeds the 1024-byte pipe message limit");
+    }
+
+    #[tokio::test]
+    async fn test_backend_answering_while_reading_does_not_deadlock() {
+        // Echoes each chunk as soon as it arrives, so it's writing long
+        // before the request is over, and stops at end of file
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("backend.sock");
+        let listener = UnixListener::bind(&path).unwrap();
+        tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let (mut reader, mut writer) = stream.into_split();
+            tokio::io::copy(&mut reader, &mut writer).await.unwrap();
+        });
+
+        // Far more than the socket buffers hold in either direction
+        let request = format!(r#"{{"method":"POST","uri":"/","headers":[],"body":"{}"}}"#, "A".repeat(8 * 1024 * 1024));
+        let client = NamedPipeClient::new();
+        let response = tokio::time::timeout(
+            std::time::Duration::from_secs(10),
+            client.send_request(path.to_str().unwrap(), request.clone().into_bytes()),
+        )
+        .await
+        .expect("the exchange deadlocked")
+        .unwrap();
+        assert_eq!(response, request.as_bytes());
+    }
+
+    #[tokio::test]
+    async fn test_oversized_request_is_not_sent() {
+        // Nothing listens here, so only the size check can fail first
+        let client = NamedPipeClient::new().with_max_message_bytes(4);
+        match client.send_request("/nonexistent/backend.sock", b"{\"x\": 1}".to_vec()).await {
+            Err(CommunicationError::SendFailed(msg)) => {
+                assert_eq!(msg, "request of 8 bytes exceeds the 4-byte pipe message limit")
+            }
+            other => panic!("expected SendFailed, got {:?}", other),
+        }
+    }
 
-        Ok(response)
+    #[tokio::test]
+    async fn test_complete_but_malformed_response_is_passed_on() {
+        // Rejecting this is the caller's job, with a proper parse error
+        assert_eq!(send(b"{\"status\": }").await.unwrap(), b"{\"status\": }");
+        assert_eq!(send(b"not json").await.unwrap(), b"not json");
     }
 }
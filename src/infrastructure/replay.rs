@@ -0,0 +1,454 @@
+//! Recording exchanges with backends, and replaying them later with no
+//! backend running, for reproducing bugs offline
+//!
+//! A recording holds one JSON object per line: the request (method, path,
+//! headers and body) and the response the backend gave it (status, headers
+//! and body), with bodies in base64.
+
+use crate::domain::entities::{HttpResponse, StreamingRequest};
+use crate::domain::repositories::{CommunicationError, PipeCommunicationService, SendOptions};
+use async_trait::async_trait;
+use futures_util::TryStreamExt;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Write};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+/// One request to a backend and its response, a line of a recording
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Interaction {
+    pub method: String,
+    pub path: String,
+    pub headers: Vec<(String, String)>,
+    #[serde(with = "base64_bytes")]
+    pub body: Vec<u8>,
+    pub response: RecordedResponse,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RecordedResponse {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    #[serde(with = "base64_bytes")]
+    pub body: Vec<u8>,
+}
+
+mod base64_bytes {
+    use base64::{engine::general_purpose::STANDARD, Engine as _};
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(bytes: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&STANDARD.encode(bytes))
+    }
+
+    /// A missing or null body is empty, as in the JSON envelopes
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+        let encoded = Option::<String>::deserialize(deserializer)?.unwrap_or_default();
+        STANDARD.decode(encoded).map_err(serde::de::Error::custom)
+    }
+}
+
+/// Encoding of the envelopes exchanged with a process; a replayed response
+/// is encoded the same way as the request it answers
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Format {
+    Json,
+    MsgPack,
+}
+
+impl Format {
+    /// JSON envelopes are objects, so anything else is MessagePack
+    pub(crate) fn of(envelope: &[u8]) -> Self {
+        if envelope.first() == Some(&b'{') {
+            Format::Json
+        } else {
+            Format::MsgPack
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct JsonRequest {
+    method: String,
+    uri: String,
+    #[serde(default)]
+    headers: Vec<(String, String)>,
+    #[serde(default, with = "base64_bytes")]
+    body: Vec<u8>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct JsonResponse {
+    status: u16,
+    #[serde(default)]
+    headers: HashMap<String, String>,
+    #[serde(default, with = "base64_bytes")]
+    body: Vec<u8>,
+}
+
+#[derive(Deserialize)]
+struct MsgPackRequest {
+    method: String,
+    uri: String,
+    #[serde(default)]
+    headers: Vec<(String, String)>,
+    #[serde(default, with = "serde_bytes")]
+    body: Vec<u8>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct MsgPackResponse {
+    status: u16,
+    #[serde(default)]
+    headers: HashMap<String, String>,
+    #[serde(default, with = "serde_bytes")]
+    body: Vec<u8>,
+}
+
+/// Decode a request envelope into a recording without its response yet
+pub(crate) fn decode_request(envelope: &[u8]) -> Result<Interaction, String> {
+    let (method, path, headers, body) = match Format::of(envelope) {
+        Format::Json => {
+            let request: JsonRequest = serde_json::from_slice(envelope).map_err(|e| e.to_string())?;
+            (request.method, request.uri, request.headers, request.body)
+        }
+        Format::MsgPack => {
+            let request: MsgPackRequest = rmp_serde::from_slice(envelope).map_err(|e| e.to_string())?;
+            (request.method, request.uri, request.headers, request.body)
+        }
+    };
+    Ok(Interaction {
+        method,
+        path,
+        headers,
+        body,
+        response: RecordedResponse {
+            status: 0,
+            headers: Vec::new(),
+            body: Vec::new(),
+        },
+    })
+}
+
+fn decode_response(envelope: &[u8], format: Format) -> Result<RecordedResponse, String> {
+    let (status, headers, body) = match format {
+        Format::Json => {
+            let response: JsonResponse = serde_json::from_slice(envelope).map_err(|e| e.to_string())?;
+            (response.status, response.headers, response.body)
+        }
+        Format::MsgPack => {
+            let response: MsgPackResponse = rmp_serde::from_slice(envelope).map_err(|e| e.to_string())?;
+            (response.status, response.headers, response.body)
+        }
+    };
+    Ok(RecordedResponse {
+        status,
+        headers: headers.into_iter().collect(),
+        body,
+    })
+}
+
+pub(crate) fn encode_response(response: RecordedResponse, format: Format) -> Result<Vec<u8>, String> {
+    let headers = response.headers.into_iter().collect();
+    match format {
+        Format::Json => serde_json::to_vec(&JsonResponse {
+            status: response.status,
+            headers,
+            body: response.body,
+        })
+        .map_err(|e| e.to_string()),
+        Format::MsgPack => rmp_serde::to_vec_named(&MsgPackResponse {
+            status: response.status,
+            headers,
+            body: response.body,
+        })
+        .map_err(|e| e.to_string()),
+    }
+}
+
+/// Writes interactions to a recording file, shared by every transport that
+/// records into it
+pub struct Recorder {
+    file: Mutex<File>,
+}
+
+impl Recorder {
+    /// Start a new recording at `path`, replacing any file already there
+    pub fn create(path: &Path) -> std::io::Result<Arc<Self>> {
+        Ok(Arc::new(Self {
+            file: Mutex::new(File::create(path)?),
+        }))
+    }
+
+    /// Record the exchanges `inner` carries
+    pub fn wrap(self: &Arc<Self>, inner: Arc<dyn PipeCommunicationService>) -> RecordingCommunicationService {
+        RecordingCommunicationService {
+            inner,
+            recorder: self.clone(),
+        }
+    }
+
+    /// Each line is written whole and unbuffered, so a recording cut short
+    /// by the proxy being killed is still readable
+    fn record(&self, interaction: &Interaction) {
+        let mut line = serde_json::to_string(interaction).expect("interactions always serialize");
+        line.push('\n');
+        if let Err(e) = self.file.lock().unwrap().write_all(line.as_bytes()) {
+            tracing::warn!("Failed to record {} {}: {}", interaction.method, interaction.path, e);
+        }
+    }
+}
+
+/// Transport passing requests on to another one and recording each exchange
+///
+/// Requests that fail to reach the backend aren't recorded, and neither are
+/// raw-protocol ones, since recording them would mean buffering their bodies.
+pub struct RecordingCommunicationService {
+    inner: Arc<dyn PipeCommunicationService>,
+    recorder: Arc<Recorder>,
+}
+
+#[async_trait]
+impl PipeCommunicationService for RecordingCommunicationService {
+    async fn send_request(&self, address: &str, request: Vec<u8>) -> Result<Vec<u8>, CommunicationError> {
+        self.send_request_with(address, request, &SendOptions::default()).await
+    }
+
+    async fn send_request_with(
+        &self,
+        address: &str,
+        request: Vec<u8>,
+        options: &SendOptions,
+    ) -> Result<Vec<u8>, CommunicationError> {
+        let format = Format::of(&request);
+        let decoded = decode_request(&request);
+        let response = self.inner.send_request_with(address, request, options).await?;
+
+        match decoded.and_then(|mut interaction| {
+            interaction.response = decode_response(&response, format)?;
+            Ok(interaction)
+        }) {
+            Ok(interaction) => self.recorder.record(&interaction),
+            Err(e) => tracing::warn!("Not recording exchange with {}: {}", address, e),
+        }
+        Ok(response)
+    }
+
+    async fn send_streaming(
+        &self,
+        address: &str,
+        request: StreamingRequest,
+    ) -> Result<HttpResponse, CommunicationError> {
+        self.inner.send_streaming(address, request).await
+    }
+
+    async fn send_streaming_with(
+        &self,
+        address: &str,
+        request: StreamingRequest,
+        options: &SendOptions,
+    ) -> Result<HttpResponse, CommunicationError> {
+        self.inner.send_streaming_with(address, request, options).await
+    }
+
+    async fn probe(&self, address: &str) -> Result<(), CommunicationError> {
+        self.inner.probe(address).await
+    }
+}
+
+/// Method, path and body: what a replayed request is matched on
+type ReplayKey = (String, String, Vec<u8>);
+
+/// Transport answering requests from a recording instead of a backend
+///
+/// Requests are matched on their method, path and body; headers are ignored
+/// since they carry per-request values such as `X-Request-Id`. When the same
+/// request was recorded more than once its responses are replayed in order,
+/// the last one repeating once they run out. Every backend is ready at once.
+pub struct ReplayCommunicationService {
+    /// Responses for each request, and how many of them were replayed
+    responses: Mutex<HashMap<ReplayKey, (Vec<RecordedResponse>, usize)>>,
+}
+
+impl ReplayCommunicationService {
+    /// Load a recording made with [`Recorder`]
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let file = File::open(path).map_err(|e| format!("Failed to read recording {}: {}", path.display(), e))?;
+
+        let mut responses: HashMap<ReplayKey, (Vec<RecordedResponse>, usize)> = HashMap::new();
+        for (number, line) in BufReader::new(file).lines().enumerate() {
+            let line = line.map_err(|e| format!("Failed to read recording {}: {}", path.display(), e))?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let interaction: Interaction = serde_json::from_str(&line)
+                .map_err(|e| format!("Invalid recording {} at line {}: {}", path.display(), number + 1, e))?;
+            let key = (interaction.method, interaction.path, interaction.body);
+            responses.entry(key).or_default().0.push(interaction.response);
+        }
+        Ok(Self {
+            responses: Mutex::new(responses),
+        })
+    }
+
+    fn next_response(&self, method: String, path: String, body: Vec<u8>) -> Result<RecordedResponse, CommunicationError> {
+        let mut responses = self.responses.lock().unwrap();
+        let key = (method, path, body);
+        let Some((recorded, replayed)) = responses.get_mut(&key) else {
+            return Err(CommunicationError::SendFailed(format!(
+                "no recorded response for {} {}",
+                key.0, key.1
+            )));
+        };
+        let response = recorded[(*replayed).min(recorded.len() - 1)].clone();
+        *replayed += 1;
+        Ok(response)
+    }
+}
+
+#[async_trait]
+impl PipeCommunicationService for ReplayCommunicationService {
+    async fn send_request(&self, _address: &str, request: Vec<u8>) -> Result<Vec<u8>, CommunicationError> {
+        let format = Format::of(&request);
+        let request = decode_request(&request).map_err(CommunicationError::SendFailed)?;
+        let response = self.next_response(request.method, request.path, request.body)?;
+        encode_response(response, format).map_err(CommunicationError::ReceiveFailed)
+    }
+
+    async fn send_streaming(
+        &self,
+        _address: &str,
+        request: StreamingRequest,
+    ) -> Result<HttpResponse, CommunicationError> {
+        let body: Vec<bytes::Bytes> = request
+            .body
+            .try_collect()
+            .await
+            .map_err(|e| CommunicationError::SendFailed(e.to_string()))?;
+        let response = self.next_response(request.method.as_str().to_string(), request.path, body.concat())?;
+        Ok(HttpResponse {
+            status_code: response.status,
+            headers: response.headers,
+            body: response.body,
+            trailers: Vec::new(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::{
+        Executable, HttpMethod, HttpRequest, PipeName, Process, ProcessId, Route, SerializationFormat,
+    };
+    use crate::use_cases::ProxyHttpRequestUseCase;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// Backend answering with its method, path and body, and how many
+    /// requests it has had, in whatever format it was sent
+    #[derive(Default)]
+    struct CountingBackend {
+        requests: AtomicUsize,
+    }
+
+    #[async_trait]
+    impl PipeCommunicationService for CountingBackend {
+        async fn send_request(&self, _address: &str, request: Vec<u8>) -> Result<Vec<u8>, CommunicationError> {
+            let format = Format::of(&request);
+            let request = decode_request(&request).unwrap();
+            let count = self.requests.fetch_add(1, Ordering::SeqCst) + 1;
+            let response = RecordedResponse {
+                status: 200,
+                headers: vec![("x-count".to_string(), count.to_string())],
+                body: format!("{} {} {} #{}", request.method, request.path, String::from_utf8_lossy(&request.body), count)
+                    .into_bytes(),
+            };
+            Ok(encode_response(response, format).unwrap())
+        }
+    }
+
+    fn processes() -> Arc<Vec<Process>> {
+        let json = Process::new(
+            ProcessId::new("api").unwrap(),
+            Executable::new("./api").unwrap(),
+            Route::new("/api/*").unwrap(),
+            PipeName::new("api_pipe").unwrap(),
+        );
+        let mut msgpack = Process::new(
+            ProcessId::new("bin").unwrap(),
+            Executable::new("./bin").unwrap(),
+            Route::new("/bin/*").unwrap(),
+            PipeName::new("bin_pipe").unwrap(),
+        );
+        msgpack.protocol = SerializationFormat::MsgPack;
+        Arc::new(vec![json, msgpack])
+    }
+
+    fn request(method: HttpMethod, path: &str, body: &str) -> HttpRequest {
+        HttpRequest {
+            method,
+            path: path.to_string(),
+            // Differs between the recording and the replay, like a request id would
+            headers: vec![("x-request-id".to_string(), uuid::Uuid::new_v4().to_string())],
+            body: body.as_bytes().to_vec(),
+        }
+    }
+
+    fn session() -> Vec<HttpRequest> {
+        vec![
+            request(HttpMethod::Get, "/api/users", ""),
+            request(HttpMethod::Post, "/api/users", r#"{"name":"a"}"#),
+            request(HttpMethod::Get, "/api/users", ""),
+            request(HttpMethod::Put, "/bin/blob", "bytes"),
+        ]
+    }
+
+    #[tokio::test]
+    async fn test_recorded_session_replays_without_backend() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("session.jsonl");
+
+        let recorder = Recorder::create(&path).unwrap();
+        let recording = recorder.wrap(Arc::new(CountingBackend::default()));
+        let use_case = ProxyHttpRequestUseCase::new(Arc::new(recording), processes());
+        let mut recorded = Vec::new();
+        for request in session() {
+            recorded.push(use_case.execute(request).await.unwrap());
+        }
+        assert_eq!(std::fs::read_to_string(&path).unwrap().lines().count(), 4);
+
+        let replay = ReplayCommunicationService::load(&path).unwrap();
+        let use_case = ProxyHttpRequestUseCase::new(Arc::new(replay), processes());
+        for (request, expected) in session().into_iter().zip(&recorded) {
+            let response = use_case.execute(request).await.unwrap();
+            assert_eq!(response.status_code, expected.status_code);
+            assert_eq!(response.headers, expected.headers);
+            assert_eq!(response.body, expected.body);
+        }
+
+        // Repeated requests were answered in order, then the last answer repeats
+        assert_eq!(recorded[0].body, b"GET /api/users  #1");
+        assert_eq!(recorded[2].body, b"GET /api/users  #3");
+        let again = use_case.execute(request(HttpMethod::Get, "/api/users", "")).await.unwrap();
+        assert_eq!(again.body, recorded[2].body);
+
+        let error = use_case.execute(request(HttpMethod::Delete, "/api/users", "")).await.unwrap_err();
+        assert!(error.to_string().contains("no recorded response for DELETE /api/users"), "{}", error);
+    }
+
+    #[test]
+    fn test_load_errors_name_the_line() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("broken.jsonl");
+        std::fs::write(
+            &path,
+            "{\"method\":\"GET\",\"path\":\"/\",\"headers\":[],\"body\":\"\",\"response\":{\"status\":200,\"headers\":[],\"body\":\"\"}}\nnot json\n",
+        )
+        .unwrap();
+
+        let error = ReplayCommunicationService::load(&path).err().unwrap();
+        assert!(error.starts_with(&format!("Invalid recording {} at line 2", path.display())), "{}", error);
+    }
+}
@@ -12,6 +12,17 @@ pub mod adapters;
 // Infrastructure layer (frameworks & drivers)
 pub mod infrastructure;
 
+// Programmatic entry point, for running the proxy inside another binary
+pub mod embed;
+pub use embed::{LocalLambdas, LocalLambdasBuilder, RunningProxy};
+
+// Startup shared by the binary and the embedding API
+mod startup;
+
+// Test doubles, for this crate's tests or others' with the `test-util` feature
+#[cfg(any(test, feature = "test-util"))]
+pub mod test_support;
+
 // Legacy modules for backward compatibility
 #[allow(dead_code)]
 pub mod config;
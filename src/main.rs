@@ -5,6 +5,10 @@ mod domain;
 mod use_cases;
 mod adapters;
 mod infrastructure;
+mod cli;
+mod startup;
+#[cfg(test)]
+mod test_support;
 
 // Legacy modules for backward compatibility
 #[allow(dead_code)]
@@ -16,48 +20,104 @@ mod pipes;
 #[allow(dead_code)]
 mod proxy;
 
-use adapters::{XmlProcessRepository, TokioProcessOrchestrator, HttpServerState};
-use infrastructure::NamedPipeClient;
-use use_cases::{InitializeSystemUseCase, StartAllProcessesUseCase, StopAllProcessesUseCase, ProxyHttpRequestUseCase};
-use std::path::PathBuf;
+use adapters::{XmlProcessRepository, TokioProcessOrchestrator, HttpServerState, ServerOptions, CorsOptions};
+use clap::Parser;
+use futures_util::FutureExt;
+use cli::{Cli, LogFormat};
+use domain::PipeCommunicationService;
+use infrastructure::{HttpClient, HttpClientOptions, NamedPipeClient, Recorder, ReplayCommunicationService};
+use use_cases::{InitializeSystemUseCase, CheckManifestUseCase, StopAllProcessesUseCase, ProxyHttpRequestUseCase, ProxyOptions};
 use std::sync::Arc;
+use std::time::Duration;
 use tokio::sync::RwLock;
-use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
+use tracing_appender::non_blocking::WorkerGuard;
+use tracing_subscriber::fmt::writer::BoxMakeWriter;
+use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt, Layer};
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let cli = Cli::parse();
+
+    // Without --worker-threads this matches `#[tokio::main]`: one worker per CPU
+    let mut runtime = tokio::runtime::Builder::new_multi_thread();
+    if let Some(worker_threads) = cli.worker_threads {
+        runtime.worker_threads(worker_threads);
+    }
+    runtime.enable_all().build()?.block_on(run(cli))
+}
+
+/// Install the global subscriber, filtered by `RUST_LOG`
+///
+/// When logging to a file the returned guard flushes buffered lines on drop,
+/// so it has to live as long as the proxy runs.
+fn init_logging(cli: &Cli) -> Option<WorkerGuard> {
+    let (writer, guard) = match &cli.log_file {
+        Some(path) => {
+            let directory = path.parent().filter(|dir| !dir.as_os_str().is_empty()).unwrap_or(".".as_ref());
+            let file_name = path.file_name().unwrap_or("local_lambdas.log".as_ref());
+            let (writer, guard) = tracing_appender::non_blocking(tracing_appender::rolling::daily(directory, file_name));
+            (BoxMakeWriter::new(writer), Some(guard))
+        }
+        // Stdout is left to the startup summary
+        None if cli.emit_startup_json => (BoxMakeWriter::new(std::io::stderr), None),
+        None => (BoxMakeWriter::new(std::io::stdout), None),
+    };
+
+    let layer = tracing_subscriber::fmt::layer()
+        .with_writer(writer)
+        .with_ansi(cli.log_file.is_none());
+    let layer = match cli.log_format {
+        LogFormat::Text => layer.boxed(),
+        LogFormat::Json => layer.json().boxed(),
+    };
 
-#[tokio::main]
-async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    // Initialize logging
     tracing_subscriber::registry()
         .with(
             tracing_subscriber::EnvFilter::try_from_default_env()
-                .unwrap_or_else(|_| "local_lambdas=debug,tower_http=debug".into()),
+                .unwrap_or_else(|_| "local_lambdas=debug,tower_http=debug,access_log=info".into()),
         )
-        .with(tracing_subscriber::fmt::layer())
+        .with(layer)
         .init();
+    guard
+}
+
+async fn run(cli: Cli) -> Result<(), Box<dyn std::error::Error>> {
+    let log_guard = init_logging(&cli);
 
     tracing::info!("Starting Local Lambdas HTTP Proxy (Clean Architecture)");
+    if let Some(worker_threads) = cli.worker_threads {
+        tracing::info!("Using {} runtime worker thread(s)", worker_threads);
+    }
 
-    // Parse command line arguments
-    let manifest_path = std::env::args()
-        .nth(1)
-        .unwrap_or_else(|| "manifest.xml".to_string());
-    
-    let manifest_path = PathBuf::from(&manifest_path);
-    
-    if !manifest_path.exists() {
-        tracing::error!("Manifest file not found: {}", manifest_path.display());
-        tracing::info!("Usage: local_lambdas [manifest.xml]");
+    let manifest_paths = cli.manifest_paths();
+
+    let is_stdin = |path: &std::path::PathBuf| path.as_os_str() == adapters::config::STDIN_MANIFEST;
+    if let Some(missing) = manifest_paths.iter().find(|path| !is_stdin(path) && !path.exists()) {
+        tracing::error!("Manifest file not found: {}", missing.display());
+        tracing::info!("Usage: local_lambdas [manifest.xml] [--manifest PATH]...");
+        if cli.check {
+            drop(log_guard);
+            std::process::exit(1);
+        }
         return Ok(());
     }
 
-    tracing::info!("Loading manifest from: {}", manifest_path.display());
+    for path in &manifest_paths {
+        if is_stdin(path) {
+            tracing::info!("Loading manifest from standard input");
+        } else {
+            tracing::info!("Loading manifest from: {}", path.display());
+        }
+    }
+
+    let manifests = manifest_paths.iter().map(|path| path.display().to_string()).collect();
 
     // ========== Dependency Injection Setup ==========
     
     // Infrastructure Layer
-    let process_repository = Arc::new(XmlProcessRepository::new(&manifest_path));
-    let pipe_service = Arc::new(NamedPipeClient::new());
-    
+    let process_repository = Arc::new(
+        XmlProcessRepository::from_paths(manifest_paths).with_default_working_dir(cli.default_working_dir.clone()),
+    );
+
     // Use Cases Layer
     let init_use_case = InitializeSystemUseCase::new(process_repository.clone());
     
@@ -66,27 +126,89 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     tracing::info!("Loaded {} process configuration(s)", processes.len());
 
     // Create orchestrator and register processes
-    let mut orchestrator = TokioProcessOrchestrator::new();
-    for process in &processes {
-        tracing::info!("Registering process '{}': {} -> {}", 
-            process.id.as_str(), process.route.as_str(), process.executable.as_str());
-        orchestrator.register(process.clone());
+    let orchestrator = startup::orchestrator(&processes);
+
+    if cli.check {
+        let problems = CheckManifestUseCase::new(orchestrator.clone()).execute(&processes).await;
+        println!("{}", cli::format_routing_table(&processes));
+        if problems.is_empty() {
+            println!("\nManifest OK: {} process(es)", processes.len());
+            return Ok(());
+        }
+        println!("\nManifest has {} problem(s):", problems.len());
+        for problem in &problems {
+            println!("  - {}", problem);
+        }
+        drop(log_guard);
+        std::process::exit(1);
     }
-    
-    let orchestrator = Arc::new(RwLock::new(orchestrator));
-    
-    // Use case for starting processes
-    let start_use_case = StartAllProcessesUseCase::new(orchestrator.clone());
-    
-    tracing::info!("Starting all processes...");
-    start_use_case.execute().await?;
 
-    // Give processes time to start up
-    tokio::time::sleep(tokio::time::Duration::from_secs(2)).await;
+    // Fail fast on clashes and on executables that can't be found rather
+    // than serving dead routes; a replayed recording runs nothing
+    let replaying = cli.replay.is_some();
+    if cli.skip_exec_check {
+        tracing::warn!("Skipping executable checks");
+    }
+    startup::check(&orchestrator, &processes, !cli.skip_exec_check && !replaying).await?;
+
+    tracing::info!("Routing table (in match order):");
+    for line in cli::format_routing_table(&processes).lines() {
+        tracing::info!("  {}", line);
+    }
+
+    // Load the certificate before starting anything, so a bad one fails fast
+    let tls_config = match (&cli.tls_cert, &cli.tls_key) {
+        (Some(cert), Some(key)) => {
+            if cli.bind_addresses().iter().any(|addr| addr.starts_with("unix:")) {
+                return Err("TLS is only supported when binding to a TCP address".into());
+            }
+            Some(adapters::http::tls::load_config(cert, key)?)
+        }
+        _ => None,
+    };
+
+    // Bind every address before starting anything, so one that's taken
+    // fails fast instead of leaving the proxy reachable on only some
+    let tcp_options = adapters::http::TcpOptions {
+        backlog: cli.listen_backlog,
+        nodelay: !cli.no_tcp_nodelay,
+        client_timeout: Some(Duration::from_secs(cli.client_timeout)),
+    };
+    let mut listeners = Vec::new();
+    for addr in cli.bind_addresses() {
+        let listener = BoundListener::bind(addr, &tcp_options)
+            .await
+            .map_err(|e| bind_error(addr, e))?;
+        listeners.push(listener);
+    }
+
+    // Transports to the backends: a recording replayed in their place, or
+    // the real ones, recorded if asked to
+    let named_pipe_client = NamedPipeClient::new().with_max_message_bytes(cli.max_pipe_message_bytes);
+    let http_client = HttpClient::with_options(HttpClientOptions {
+        pool_max_idle_per_host: cli.backend_pool_size,
+        pool_idle_timeout: Some(Duration::from_secs(cli.backend_pool_idle_timeout)),
+        timeout: None,
+    });
+    let (pipe_service, http_service): (Arc<dyn PipeCommunicationService>, Arc<dyn PipeCommunicationService>) =
+        match (&cli.replay, &cli.record) {
+            (Some(path), _) => {
+                let replay: Arc<dyn PipeCommunicationService> = Arc::new(ReplayCommunicationService::load(path)?);
+                tracing::info!("Replaying responses from {} instead of starting processes", path.display());
+                (replay.clone(), replay)
+            }
+            (None, Some(path)) => {
+                let recorder = Recorder::create(path)
+                    .map_err(|e| format!("Failed to create recording {}: {}", path.display(), e))?;
+                tracing::info!("Recording backend exchanges to {}", path.display());
+                (
+                    Arc::new(recorder.wrap(Arc::new(named_pipe_client))),
+                    Arc::new(recorder.wrap(Arc::new(http_client))),
+                )
+            }
+            (None, None) => (Arc::new(named_pipe_client), Arc::new(http_client)),
+        };
 
-    // Create proxy use case
-    let processes_arc = Arc::new(processes);
-    
     // Check if caching is enabled via environment variable
     let enable_cache_env = std::env::var("ENABLE_CACHE").ok();
     let cache_size = enable_cache_env
@@ -101,73 +223,273 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
             }
         });
     
-    let proxy_use_case = if let Some(size) = cache_size {
+    if let Some(size) = cache_size {
         tracing::info!("Response caching enabled with {} entries", size);
-        Arc::new(ProxyHttpRequestUseCase::new_with_cache(
-            pipe_service.clone(),
-            processes_arc,
-            Some(size),
-        ))
-    } else {
-        Arc::new(ProxyHttpRequestUseCase::new(
-            pipe_service.clone(),
-            processes_arc,
-        ))
+    }
+    if cli.cache_file.is_some() && cache_size.is_none() {
+        tracing::warn!("--cache-file has no effect without ENABLE_CACHE");
+    }
+    if cli.lenient_responses {
+        tracing::warn!("Lenient response parsing enabled: malformed backend envelopes will not be rejected");
+    }
+    let proxy_options = ProxyOptions {
+        cache_size,
+        lenient_responses: cli.lenient_responses,
+        normalize_routes: cli.normalize_routes,
+        cache_file: cli.cache_file,
+        starting_retry_after: cli.starting_retry_after.map(Duration::from_secs),
+        debug_bodies: cli.debug_bodies,
+        debug_body_limit: Some(cli.debug_body_limit),
+        ready_timeout: Some(Duration::from_millis(cli.ready_timeout_ms)),
+        ready_poll_interval: Some(Duration::from_millis(cli.ready_poll_interval_ms)),
+        max_response_bytes: Some(cli.max_response_bytes),
     };
+    // With nothing started there's nothing to wake, restart or reap
+    let managed = (!replaying).then_some(&orchestrator);
+    let proxy_use_case = startup::proxy(pipe_service, http_service, processes, proxy_options, managed).await;
+
+    // One signal stops every listener, and starts the clock on shutting down.
+    // It's listened for before any process starts, so an interrupt while
+    // they start up stops them too rather than leaving them orphaned
+    let shutdown = shutdown_signal().map(|()| tokio::time::Instant::now()).shared();
+    let shutdown_timeout = Duration::from_secs(cli.shutdown_timeout);
+
+    tokio::select! {
+        started = startup::start(&proxy_use_case, managed) => started?,
+        _ = shutdown.clone() => {
+            tracing::info!("Interrupted while starting up; stopping processes");
+            stop_processes(orchestrator, shutdown_timeout).await;
+            return Ok(());
+        }
+    }
+    startup::spawn_background_tasks(&proxy_use_case);
 
     // Adapters Layer - HTTP Server
-    let server_state = HttpServerState::new(proxy_use_case);
+    if cli.dev {
+        tracing::warn!("Dev mode enabled: error responses include internal error details");
+    }
+    let cors = if cli.cors_origins.is_empty() {
+        None
+    } else {
+        tracing::info!("CORS enabled for origins: {}", cli.cors_origins.join(", "));
+        Some(CorsOptions {
+            allowed_origins: cli.cors_origins,
+            allowed_methods: cli.cors_methods,
+            allowed_headers: cli.cors_headers,
+            allow_credentials: cli.cors_credentials,
+        })
+    };
+    let server_options = ServerOptions {
+        dev_mode: cli.dev,
+        cors,
+        max_body_bytes: cli.max_body_bytes,
+        max_header_bytes: cli.max_header_bytes,
+        max_headers: cli.max_headers,
+        server_timing: cli.server_timing,
+        compression: !cli.no_compression,
+        access_log: cli.access_log,
+        max_in_flight: cli.max_in_flight,
+        catch_all: !cli.no_catch_all,
+        manifests,
+        admin_token: cli.admin_token,
+    };
+    let server_state = HttpServerState::with_options(proxy_use_case.clone(), server_options);
     let app = server_state.create_router();
 
-    // Bind to address
-    let addr = std::env::var("BIND_ADDRESS")
-        .unwrap_or_else(|_| "127.0.0.1:3000".to_string());
-    
-    tracing::info!("Starting HTTP proxy server on {}", addr);
-    
-    let listener = tokio::net::TcpListener::bind(&addr).await?;
+    tracing::info!("Starting HTTP proxy server on {}", cli.bind);
 
+    let listening = listeners
+        .iter()
+        .map(|listener| listener.url(tls_config.is_some()))
+        .collect::<std::io::Result<Vec<_>>>()?;
+    let servers = listeners
+        .into_iter()
+        .map(|listener| listener.serve(app.clone(), tls_config.clone(), tcp_options, shutdown.clone().map(drop)));
+    let serving = futures_util::future::try_join_all(servers);
     tracing::info!("Local Lambdas HTTP Proxy is ready!");
-    tracing::info!("Listening on http://{}", addr);
+    if cli.emit_startup_json {
+        println!("{}", startup_summary(&proxy_use_case, &listening).await);
+    }
 
-    // Run the server
-    axum::serve(listener, app)
-        .with_graceful_shutdown(shutdown_signal())
-        .await?;
+    // Draining in-flight requests and stopping processes share one deadline
+    let drain_deadline = shutdown.clone().then(|at| tokio::time::sleep_until(at + shutdown_timeout));
+    tokio::select! {
+        served = serving => { served?; }
+        () = drain_deadline => {
+            tracing::warn!("In-flight requests still running after {:?}; abandoning them", shutdown_timeout);
+        }
+    }
 
     // Cleanup
     tracing::info!("Shutting down...");
-    let stop_use_case = StopAllProcessesUseCase::new(orchestrator);
-    stop_use_case.execute().await?;
+    if let Err(e) = proxy_use_case.save_cache() {
+        tracing::error!("{}", e);
+    }
+    let signalled_at = shutdown.peek().copied().unwrap_or_else(tokio::time::Instant::now);
+    let remaining = shutdown_timeout.saturating_sub(signalled_at.elapsed());
+    stop_processes(orchestrator, remaining).await;
 
     Ok(())
 }
 
-/// Wait for shutdown signal (Ctrl+C)
-async fn shutdown_signal() {
-    let ctrl_c = async {
-        tokio::signal::ctrl_c()
-            .await
-            .expect("Failed to install Ctrl+C handler");
-    };
+/// What `--emit-startup-json` prints: the URLs the proxy listens on, and
+/// each process's route, transport, addresses and readiness
+async fn startup_summary<P: PipeCommunicationService>(
+    use_case: &ProxyHttpRequestUseCase<P>,
+    listening: &[String],
+) -> serde_json::Value {
+    let unready = use_case.unready_processes().await;
+    let processes: Vec<serde_json::Value> = use_case
+        .processes()
+        .iter()
+        .map(|p| {
+            let status = unready
+                .iter()
+                .find(|(unready, _)| unready.id == p.id)
+                .map_or("ready", |(_, reason)| reason);
+            serde_json::json!({
+                "id": p.id.as_str(),
+                "route": p.route.as_str(),
+                "mode": p.mode_name(),
+                "addresses": use_case.addresses(p),
+                "status": status,
+            })
+        })
+        .collect();
+    serde_json::json!({ "listening": listening, "processes": processes })
+}
+
+/// Stop every process, killing those still running after `timeout`
+async fn stop_processes(orchestrator: Arc<RwLock<TokioProcessOrchestrator>>, timeout: Duration) {
+    let killed = StopAllProcessesUseCase::new(orchestrator).execute_within(timeout).await;
+    if !killed.is_empty() {
+        let ids: Vec<&str> = killed.iter().map(|id| id.as_str()).collect();
+        tracing::warn!("Force-killed process(es) still running at the shutdown deadline: {}", ids.join(", "));
+    }
+}
 
+/// Describe a failure to bind `addr`, with a way out when it's taken
+fn bind_error(addr: &str, e: std::io::Error) -> String {
+    if e.kind() == std::io::ErrorKind::AddrInUse {
+        format!(
+            "Failed to bind {}: the address is already in use, perhaps by another local_lambdas \
+             still running; pass --bind (or set BIND_ADDRESS) to listen somewhere else",
+            addr
+        )
+    } else {
+        format!("Failed to bind {}: {}", addr, e)
+    }
+}
+
+/// An address the proxy has bound, ready to serve on
+enum BoundListener {
+    Tcp(tokio::net::TcpListener),
+    #[cfg(unix)]
+    Unix(std::path::PathBuf, tokio::net::UnixListener),
+}
+
+impl BoundListener {
+    /// Bind a TCP `host:port`, or a Unix domain socket given as `unix:/path`
+    async fn bind(addr: &str, tcp_options: &adapters::http::TcpOptions) -> std::io::Result<Self> {
+        match addr.strip_prefix("unix:") {
+            #[cfg(unix)]
+            Some(path) => {
+                let listener = adapters::http::unix_socket::bind(path.as_ref()).await?;
+                Ok(Self::Unix(path.into(), listener))
+            }
+            #[cfg(not(unix))]
+            Some(_) => Err(std::io::Error::new(
+                std::io::ErrorKind::Unsupported,
+                "Unix socket binding is only supported on Unix",
+            )),
+            None => Ok(Self::Tcp(adapters::http::tcp::bind(addr, tcp_options).await?)),
+        }
+    }
+
+    /// Where clients reach the listener, e.g. `http://127.0.0.1:3000` or
+    /// `unix:/run/proxy.sock`, with the port actually bound
+    fn url(&self, tls: bool) -> std::io::Result<String> {
+        match self {
+            Self::Tcp(listener) => {
+                let scheme = if tls { "https" } else { "http" };
+                Ok(format!("{}://{}", scheme, listener.local_addr()?))
+            }
+            #[cfg(unix)]
+            Self::Unix(path, _) => Ok(format!("unix:{}", path.display())),
+        }
+    }
+
+    /// Serve `app` until `shutdown` completes, over TLS for TCP addresses
+    /// when `tls_config` is given
+    async fn serve(
+        self,
+        app: axum::Router,
+        tls_config: Option<tokio_rustls::rustls::ServerConfig>,
+        tcp_options: adapters::http::TcpOptions,
+        shutdown: impl std::future::Future<Output = ()> + Send + 'static,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        // Reports the address actually bound, which differs from the
+        // requested one when binding to port 0
+        tracing::info!("Listening on {}", self.url(tls_config.is_some())?);
+        match self {
+            Self::Tcp(listener) => match tls_config {
+                Some(tls_config) => {
+                    adapters::http::tls::serve(listener, tls_config, app, &tcp_options, shutdown).await?;
+                }
+                None => adapters::http::tcp::serve(listener, app, &tcp_options, shutdown).await?,
+            },
+            #[cfg(unix)]
+            Self::Unix(_, listener) => {
+                adapters::http::unix_socket::serve(listener, app, tcp_options.client_timeout, shutdown).await?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Wait for shutdown signal (Ctrl+C or SIGTERM)
+///
+/// On Unix both handlers are installed right away rather than when the
+/// future is first polled, so a signal arriving before then isn't left to
+/// kill the proxy without cleaning up.
+fn shutdown_signal() -> impl std::future::Future<Output = ()> {
     #[cfg(unix)]
-    let terminate = async {
-        tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
-            .expect("Failed to install signal handler")
-            .recv()
-            .await;
+    let (mut interrupt, mut terminate) = {
+        use tokio::signal::unix::{signal, SignalKind};
+        (
+            signal(SignalKind::interrupt()).expect("Failed to install Ctrl+C handler"),
+            signal(SignalKind::terminate()).expect("Failed to install signal handler"),
+        )
     };
 
-    #[cfg(not(unix))]
-    let terminate = std::future::pending::<()>();
+    async move {
+        #[cfg(unix)]
+        let ctrl_c = async {
+            interrupt.recv().await;
+        };
 
-    tokio::select! {
-        _ = ctrl_c => {
-            tracing::info!("Received Ctrl+C signal");
-        },
-        _ = terminate => {
-            tracing::info!("Received terminate signal");
-        },
+        #[cfg(not(unix))]
+        let ctrl_c = async {
+            tokio::signal::ctrl_c()
+                .await
+                .expect("Failed to install Ctrl+C handler");
+        };
+
+        #[cfg(unix)]
+        let terminate = async {
+            terminate.recv().await;
+        };
+
+        #[cfg(not(unix))]
+        let terminate = std::future::pending::<()>();
+
+        tokio::select! {
+            _ = ctrl_c => {
+                tracing::info!("Received Ctrl+C signal");
+            },
+            _ = terminate => {
+                tracing::info!("Received terminate signal");
+            },
+        }
     }
 }
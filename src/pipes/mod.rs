@@ -1,6 +1,13 @@
 use anyhow::{Context, Result};
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use std::future::Future;
 use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Semaphore;
+use tokio_util::sync::CancellationToken;
+
+use crate::infrastructure::pipes::DEFAULT_MAX_MESSAGE_BYTES;
 
 #[cfg(unix)]
 use tokio::net::UnixListener;
@@ -8,12 +15,27 @@ use tokio::net::UnixListener;
 #[cfg(windows)]
 use tokio::net::windows::named_pipe::{ServerOptions, NamedPipeServer};
 
+/// Connections handled at once unless configured otherwise
+pub const DEFAULT_MAX_CONNECTIONS: usize = 64;
+
+/// How long connections still being handled are waited for on shutdown
+/// unless configured otherwise
+pub const DEFAULT_DRAIN_TIMEOUT: Duration = Duration::from_secs(30);
+
 /// Cross-platform named pipe server
+///
+/// Handles up to `max_connections` connections at once; further clients wait
+/// to be accepted. [`listen`](Self::listen) runs until the shutdown token is
+/// cancelled, [`listen_until`](Self::listen_until) also until a future
+/// completes.
 pub struct PipeServer {
-    #[allow(dead_code)]
     pipe_name: String,
     #[cfg(unix)]
     path: PathBuf,
+    max_message_bytes: usize,
+    max_connections: usize,
+    drain_timeout: Duration,
+    shutdown: CancellationToken,
 }
 
 impl PipeServer {
@@ -28,6 +50,87 @@ impl PipeServer {
             pipe_name,
             #[cfg(unix)]
             path,
+            max_message_bytes: DEFAULT_MAX_MESSAGE_BYTES,
+            max_connections: DEFAULT_MAX_CONNECTIONS,
+            drain_timeout: DEFAULT_DRAIN_TIMEOUT,
+            shutdown: CancellationToken::new(),
+        }
+    }
+
+    /// Drop connections whose request is larger than `max_message_bytes`
+    /// instead of buffering it
+    pub fn with_max_message_bytes(mut self, max_message_bytes: usize) -> Self {
+        self.max_message_bytes = max_message_bytes;
+        self
+    }
+
+    /// Handle at most `max_connections` connections at once, leaving further
+    /// clients waiting to be accepted rather than spawning a task for each
+    pub fn with_max_connections(mut self, max_connections: usize) -> Self {
+        self.max_connections = max_connections.max(1);
+        self
+    }
+
+    /// On shutdown, wait at most `drain_timeout` for the connections being
+    /// handled to finish before returning without them
+    pub fn with_drain_timeout(mut self, drain_timeout: Duration) -> Self {
+        self.drain_timeout = drain_timeout;
+        self
+    }
+
+    /// Stop listening when `shutdown` is cancelled, e.g. a token shared with
+    /// the rest of the application
+    pub fn with_shutdown(mut self, shutdown: CancellationToken) -> Self {
+        self.shutdown = shutdown;
+        self
+    }
+
+    /// A token that stops [`listen`](Self::listen) when cancelled
+    pub fn shutdown_token(&self) -> CancellationToken {
+        self.shutdown.clone()
+    }
+
+    /// Serve connections until the shutdown token is cancelled, then wait up
+    /// to the drain timeout for the connections being handled to finish and,
+    /// on Unix, remove the socket file
+    pub async fn listen(
+        &self,
+        handler: impl Fn(Vec<u8>) -> Result<Vec<u8>> + Send + 'static + Clone,
+    ) -> Result<()> {
+        self.listen_until(handler, std::future::pending()).await
+    }
+
+    /// Like [`listen`](Self::listen), also stopping once `signal` completes,
+    /// e.g. a Ctrl+C handler or the end of a test
+    pub async fn listen_until(
+        &self,
+        handler: impl Fn(Vec<u8>) -> Result<Vec<u8>> + Send + 'static + Clone,
+        signal: impl Future<Output = ()>,
+    ) -> Result<()> {
+        // A child token, so the signal stops this server without cancelling
+        // a token it shares with the rest of the application
+        let stop = self.shutdown.child_token();
+        let serving = self.serve(handler, &stop);
+        tokio::pin!(serving);
+        tokio::select! {
+            served = &mut serving => return served,
+            () = signal => stop.cancel(),
+        }
+        serving.await
+    }
+
+    /// Wait for every connection slot to be free again, i.e. for the
+    /// connections being handled to finish, for up to the drain timeout;
+    /// any still open after it are left to finish on their own
+    async fn drain(&self, connections: &Semaphore) {
+        let all = u32::try_from(self.max_connections).unwrap_or(u32::MAX);
+        if tokio::time::timeout(self.drain_timeout, connections.acquire_many(all)).await.is_err() {
+            tracing::warn!(
+                "Stopped pipe server '{}' with {} connection(s) still open after {:?}",
+                self.pipe_name,
+                self.max_connections - connections.available_permits(),
+                self.drain_timeout
+            );
         }
     }
 
@@ -44,39 +147,55 @@ impl PipeServer {
         }
     }
 
-    /// Start listening for connections on the named pipe
+    /// Serve connections on the named pipe until `stop` is cancelled, then
+    /// wait for the connections being handled to finish
     #[cfg(windows)]
-    pub async fn listen(
+    async fn serve(
         &self,
         handler: impl Fn(Vec<u8>) -> Result<Vec<u8>> + Send + 'static + Clone,
+        stop: &CancellationToken,
     ) -> Result<()> {
         let pipe_path = format!(r"\\.\pipe\{}", self.pipe_name);
-        
+        let connections = Arc::new(Semaphore::new(self.max_connections));
+
         loop {
+            // A slot is taken before a pipe instance is offered, so no more
+            // clients connect than can be handled
+            let permit = tokio::select! {
+                _ = stop.cancelled() => break,
+                permit = connections.clone().acquire_owned() => permit.expect("the semaphore is never closed"),
+            };
             let server = ServerOptions::new()
                 .first_pipe_instance(false)
                 .create(&pipe_path)
                 .context("Failed to create named pipe")?;
+            tokio::select! {
+                _ = stop.cancelled() => break,
+                connected = server.connect() => connected.context("Failed to connect pipe")?,
+            }
 
             let handler = handler.clone();
+            let max_message_bytes = self.max_message_bytes;
             
             tokio::spawn(async move {
-                if let Err(e) = Self::handle_windows_connection(server, handler).await {
+                if let Err(e) = Self::handle_windows_connection(server, handler, max_message_bytes).await {
                     tracing::error!("Error handling pipe connection: {}", e);
                 }
+                drop(permit);
             });
         }
+
+        self.drain(&connections).await;
+        Ok(())
     }
 
     #[cfg(windows)]
     async fn handle_windows_connection(
         mut server: NamedPipeServer,
         handler: impl Fn(Vec<u8>) -> Result<Vec<u8>>,
+        max_message_bytes: usize,
     ) -> Result<()> {
-        server.connect().await.context("Failed to connect pipe")?;
-        
-        let mut buffer = Vec::new();
-        server.read_to_end(&mut buffer).await.context("Failed to read from pipe")?;
+        let buffer = read_request(&mut server, max_message_bytes).await.context("Failed to read from pipe")?;
         
         let response = handler(buffer)?;
         server.write_all(&response).await.context("Failed to write to pipe")?;
@@ -85,27 +204,56 @@ impl PipeServer {
         Ok(())
     }
 
+    /// Serve connections on the socket until `stop` is cancelled, then wait
+    /// for the connections being handled to finish and remove the socket file
     #[cfg(unix)]
-    pub async fn listen(
+    async fn serve(
         &self,
         handler: impl Fn(Vec<u8>) -> Result<Vec<u8>> + Send + 'static + Clone,
+        stop: &CancellationToken,
     ) -> Result<()> {
         // Remove existing socket file if it exists
         let _ = std::fs::remove_file(&self.path);
         
         let listener = UnixListener::bind(&self.path)
             .context("Failed to bind Unix socket")?;
-        
+        let connections = Arc::new(Semaphore::new(self.max_connections));
+        let accepting = self.accept_unix(&listener, &connections, handler, stop).await;
+
+        drop(listener);
+        self.drain(&connections).await;
+        let _ = std::fs::remove_file(&self.path);
+        accepting
+    }
+
+    #[cfg(unix)]
+    async fn accept_unix(
+        &self,
+        listener: &UnixListener,
+        connections: &Arc<Semaphore>,
+        handler: impl Fn(Vec<u8>) -> Result<Vec<u8>> + Send + 'static + Clone,
+        stop: &CancellationToken,
+    ) -> Result<()> {
         loop {
-            let (mut stream, _) = listener.accept().await
-                .context("Failed to accept connection")?;
+            // A slot is taken before accepting, so clients beyond the limit
+            // wait in the listen backlog rather than as tasks
+            let permit = tokio::select! {
+                _ = stop.cancelled() => return Ok(()),
+                permit = connections.clone().acquire_owned() => permit.expect("the semaphore is never closed"),
+            };
+            let (mut stream, _) = tokio::select! {
+                _ = stop.cancelled() => return Ok(()),
+                accepted = listener.accept() => accepted.context("Failed to accept connection")?,
+            };
             
             let handler = handler.clone();
+            let max_message_bytes = self.max_message_bytes;
             
             tokio::spawn(async move {
-                if let Err(e) = Self::handle_unix_connection(&mut stream, handler).await {
+                if let Err(e) = Self::handle_unix_connection(&mut stream, handler, max_message_bytes).await {
                     tracing::error!("Error handling pipe connection: {}", e);
                 }
+                drop(permit);
             });
         }
     }
@@ -114,9 +262,9 @@ impl PipeServer {
     async fn handle_unix_connection(
         stream: &mut tokio::net::UnixStream,
         handler: impl Fn(Vec<u8>) -> Result<Vec<u8>>,
+        max_message_bytes: usize,
     ) -> Result<()> {
-        let mut buffer = Vec::new();
-        stream.read_to_end(&mut buffer).await
+        let buffer = read_request(stream, max_message_bytes).await
             .context("Failed to read from Unix socket")?;
         
         let response = handler(buffer)?;
@@ -129,6 +277,17 @@ impl PipeServer {
     }
 }
 
+/// Read a whole request, refusing one larger than `max_bytes` without
+/// buffering the rest
+async fn read_request<R: tokio::io::AsyncRead + Unpin>(reader: &mut R, max_bytes: usize) -> Result<Vec<u8>> {
+    let mut buffer = Vec::new();
+    reader.take(max_bytes as u64 + 1).read_to_end(&mut buffer).await?;
+    if buffer.len() > max_bytes {
+        anyhow::bail!("request exceeds the {}-byte pipe message limit", max_bytes);
+    }
+    Ok(buffer)
+}
+
 /// Client for connecting to a named pipe
 pub struct PipeClient {
     pipe_address: String,
@@ -171,6 +330,9 @@ impl PipeClient {
             .context("Failed to write to Unix socket")?;
         stream.flush().await
             .context("Failed to flush Unix socket")?;
+        // The server reads the request until end of file
+        stream.shutdown().await
+            .context("Failed to shut down Unix socket for writing")?;
         
         let mut response = Vec::new();
         stream.read_to_end(&mut response).await
@@ -179,3 +341,106 @@ impl PipeClient {
         Ok(response)
     }
 }
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    /// A socket name no other test run is using
+    fn unique_pipe_name(test: &str) -> String {
+        format!("local_lambdas_{}_{}", test, std::process::id())
+    }
+
+    async fn wait_for_socket(address: &str) {
+        for _ in 0..100 {
+            if tokio::net::UnixStream::connect(address).await.is_ok() {
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        panic!("{} never started listening", address);
+    }
+
+    #[tokio::test]
+    async fn test_serves_until_shut_down() {
+        let server = PipeServer::new(unique_pipe_name("serve")).with_max_connections(1);
+        let address = server.get_pipe_address();
+        let shutdown = server.shutdown_token();
+        let listening = tokio::spawn(async move {
+            server
+                .listen(|request| Ok(request.to_ascii_uppercase()))
+                .await
+        });
+        wait_for_socket(&address).await;
+
+        let client = PipeClient::new(address.clone());
+        assert_eq!(client.send_request(b"ping".to_vec()).await.unwrap(), b"PING");
+
+        // With the only slot taken, the next client waits to be accepted
+        let held = tokio::net::UnixStream::connect(&address).await.unwrap();
+        let waiting = tokio::spawn(async move { client.send_request(b"next".to_vec()).await });
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert!(!waiting.is_finished());
+        drop(held);
+        assert_eq!(waiting.await.unwrap().unwrap(), b"NEXT");
+
+        shutdown.cancel();
+        tokio::time::timeout(Duration::from_secs(5), listening)
+            .await
+            .expect("the server didn't stop")
+            .unwrap()
+            .unwrap();
+        assert!(!std::path::Path::new(&address).exists());
+    }
+
+    #[tokio::test]
+    async fn test_listen_until_stops_on_the_signal() {
+        let server = PipeServer::new(unique_pipe_name("until"));
+        let address = server.get_pipe_address();
+        let shared = server.shutdown_token();
+        let (signal, signalled) = tokio::sync::oneshot::channel::<()>();
+        let listening = tokio::spawn(async move {
+            server
+                .listen_until(Ok, async {
+                    let _ = signalled.await;
+                })
+                .await
+        });
+        wait_for_socket(&address).await;
+
+        let client = PipeClient::new(address.clone());
+        assert_eq!(client.send_request(b"echo".to_vec()).await.unwrap(), b"echo");
+
+        signal.send(()).unwrap();
+        tokio::time::timeout(Duration::from_secs(5), listening)
+            .await
+            .expect("the server didn't stop")
+            .unwrap()
+            .unwrap();
+        assert!(!std::path::Path::new(&address).exists());
+        // The token passed in is left for the rest of the application
+        assert!(!shared.is_cancelled());
+    }
+
+    #[tokio::test]
+    async fn test_drain_gives_up_on_stalled_connections() {
+        let server = PipeServer::new(unique_pipe_name("drain")).with_drain_timeout(Duration::from_millis(100));
+        let address = server.get_pipe_address();
+        let shutdown = server.shutdown_token();
+        let listening = tokio::spawn(async move { server.listen(Ok).await });
+        wait_for_socket(&address).await;
+
+        // A client that never finishes its request keeps its connection open
+        let _stalled = tokio::net::UnixStream::connect(&address).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(50)).await;
+
+        shutdown.cancel();
+        tokio::time::timeout(Duration::from_secs(5), listening)
+            .await
+            .expect("the server waited for the stalled connection")
+            .unwrap()
+            .unwrap();
+        assert!(!std::path::Path::new(&address).exists());
+    }
+}
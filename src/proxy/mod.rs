@@ -1,5 +1,7 @@
 use crate::config::ProcessConfig;
+use crate::domain::{HttpMethod, HttpRequest};
 use crate::pipes::PipeClient;
+use crate::use_cases::envelope;
 use axum::{
     body::Body,
     extract::State,
@@ -10,12 +12,20 @@ use axum::{
 };
 use std::sync::Arc;
 use tower_http::trace::TraceLayer;
-use base64::{Engine as _, engine::general_purpose};
+use http_body_util::LengthLimitError;
+
+/// Largest request body buffered before answering 413 Payload Too Large,
+/// unless set with [`ProxyState::with_max_body_bytes`]
+///
+/// `--max-body-bytes` only configures the main proxy; this legacy module
+/// isn't started by the binary and never reads it.
+const MAX_BODY_BYTES: usize = 16 * 1024 * 1024;
 
 /// HTTP proxy server state
 #[derive(Clone)]
 pub struct ProxyState {
     routes: Arc<Vec<RouteMapping>>,
+    max_body_bytes: usize,
 }
 
 /// Mapping from HTTP route pattern to process pipe
@@ -43,9 +53,17 @@ impl ProxyState {
 
         Self {
             routes: Arc::new(routes),
+            max_body_bytes: MAX_BODY_BYTES,
         }
     }
 
+    /// Answer 413 for request bodies larger than `max_body_bytes` instead
+    /// of buffering them
+    pub fn with_max_body_bytes(mut self, max_body_bytes: usize) -> Self {
+        self.max_body_bytes = max_body_bytes;
+        self
+    }
+
     /// Get the pipe address for a given pipe name
     fn get_pipe_address(pipe_name: &str) -> String {
         #[cfg(windows)]
@@ -96,6 +114,18 @@ pub fn create_router(state: ProxyState) -> Router {
         .with_state(state)
 }
 
+/// Serve the proxy on `listener` until `shutdown` completes, then let the
+/// requests in flight finish
+pub async fn serve(
+    listener: tokio::net::TcpListener,
+    state: ProxyState,
+    shutdown: impl std::future::Future<Output = ()> + Send + 'static,
+) -> std::io::Result<()> {
+    axum::serve(listener, create_router(state))
+        .with_graceful_shutdown(shutdown)
+        .await
+}
+
 /// Handle incoming HTTP requests and proxy them to the appropriate process
 async fn proxy_handler(
     State(state): State<ProxyState>,
@@ -124,8 +154,16 @@ async fn proxy_handler(
     tracing::info!("Routing {} {} to process '{}'", method, path, route.process_id);
 
     // Convert request to bytes for pipe communication
-    let request_data = match serialize_request(method, uri, headers, body).await {
+    let request_data = match serialize_request(method, uri, headers, body, state.max_body_bytes).await {
         Ok(data) => data,
+        Err(e) if e.chain().any(|cause| cause.is::<LengthLimitError>()) => {
+            tracing::warn!("Request body exceeds {} bytes", state.max_body_bytes);
+            return (
+                StatusCode::PAYLOAD_TOO_LARGE,
+                format!("Request body exceeds {} bytes", state.max_body_bytes),
+            )
+                .into_response();
+        }
         Err(e) => {
             tracing::error!("Failed to serialize request: {}", e);
             return (
@@ -164,63 +202,61 @@ async fn proxy_handler(
     }
 }
 
-/// Serialize an HTTP request to bytes for pipe communication
+/// Serialize an HTTP request to bytes for pipe communication, in the
+/// same JSON envelope the main proxy sends
 async fn serialize_request(
     method: Method,
     uri: Uri,
     headers: HeaderMap,
     body: Body,
+    max_body_bytes: usize,
 ) -> anyhow::Result<Vec<u8>> {
     use axum::body::to_bytes;
     
-    let body_bytes = to_bytes(body, usize::MAX).await?;
+    let body_bytes = to_bytes(body, max_body_bytes).await?;
     
-    // Simple serialization format: JSON-like structure
-    let request = serde_json::json!({
-        "method": method.as_str(),
-        "uri": uri.to_string(),
-        "headers": headers.iter()
-            .map(|(k, v)| (k.as_str(), v.to_str().unwrap_or("")))
-            .collect::<Vec<_>>(),
-        "body": general_purpose::STANDARD.encode(&body_bytes),
-    });
-
-    Ok(serde_json::to_vec(&request)?)
+    // Only the path and query reach the backend, as headers that aren't
+    // valid UTF-8 don't
+    let request = HttpRequest {
+        method: HttpMethod::from_name(method.as_str()),
+        path: uri.path_and_query().map_or(uri.path(), |target| target.as_str()).to_string(),
+        headers: headers
+            .iter()
+            .filter_map(|(k, v)| v.to_str().ok().map(|v| (k.as_str().to_string(), v.to_string())))
+            .collect(),
+        body: body_bytes.to_vec(),
+    };
+
+    envelope::encode_request(&request).map_err(anyhow::Error::msg)
 }
 
 /// Deserialize a response from bytes received through the pipe
+///
+/// Envelopes are parsed leniently, as they always were here: a missing
+/// status is 200 and an undecodable body is dropped.
 fn deserialize_response(data: Vec<u8>) -> anyhow::Result<Response> {
-    // Parse JSON response
-    let response: serde_json::Value = serde_json::from_slice(&data)?;
-    
-    let status = response["status"]
-        .as_u64()
-        .unwrap_or(200) as u16;
-    
-    let body = response["body"]
-        .as_str()
-        .map(|s| general_purpose::STANDARD.decode(s).unwrap_or_default())
-        .unwrap_or_default();
+    let response = envelope::decode_response(&data, true).map_err(anyhow::Error::msg)?;
     
     let mut response_builder = Response::builder()
-        .status(StatusCode::from_u16(status).unwrap_or(StatusCode::OK));
+        .status(StatusCode::from_u16(response.status_code).unwrap_or(StatusCode::OK));
     
-    // Add headers if present
-    if let Some(headers) = response["headers"].as_object() {
-        for (key, value) in headers {
-            if let Some(value_str) = value.as_str() {
-                response_builder = response_builder.header(key, value_str);
-            }
+    // Content-Length is left for the body to set, as the backend's may not
+    // match the decoded bytes
+    for (key, value) in &response.headers {
+        if key.eq_ignore_ascii_case("content-length") {
+            continue;
         }
+        response_builder = response_builder.header(key, value);
     }
     
-    Ok(response_builder.body(Body::from(body))?)
+    Ok(response_builder.body(Body::from(response.body))?)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::config::ProcessConfig;
+    use base64::{Engine as _, engine::general_purpose};
 
     fn create_test_config(id: &str, route: &str, pipe_name: &str) -> ProcessConfig {
         ProcessConfig {
@@ -344,17 +380,109 @@ mod tests {
         let headers = HeaderMap::new();
         let body = Body::from("test body");
 
-        let result = serialize_request(method, uri, headers, body).await;
+        let result = serialize_request(method, uri, headers, body, MAX_BODY_BYTES).await;
         assert!(result.is_ok());
 
         let data = result.unwrap();
         let json: serde_json::Value = serde_json::from_slice(&data).unwrap();
 
         assert_eq!(json["method"], "GET");
-        assert_eq!(json["uri"], "http://example.com/test");
+        assert_eq!(json["uri"], "/test");
         assert!(json["body"].is_string());
     }
 
+    /// A backend written against the main proxy's envelopes: requests are
+    /// decoded strictly, so any drift in field names or shapes fails
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_backend_for_main_proxy_works_through_legacy_proxy() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+        use tower::Service;
+
+        #[derive(serde::Deserialize)]
+        #[serde(deny_unknown_fields)]
+        struct Request {
+            method: String,
+            uri: String,
+            headers: Vec<(String, String)>,
+            body: String,
+        }
+
+        let pipe_name = format!("legacy_envelope_{}", std::process::id());
+        let listener = tokio::net::UnixListener::bind(ProxyState::get_pipe_address(&pipe_name)).unwrap();
+        let backend = tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut data = Vec::new();
+            let request = loop {
+                let mut chunk = [0u8; 1024];
+                let read = stream.read(&mut chunk).await.unwrap();
+                data.extend_from_slice(&chunk[..read]);
+                if let Ok(request) = serde_json::from_slice::<Request>(&data) {
+                    break request;
+                }
+            };
+            let body = general_purpose::STANDARD.decode(&request.body).unwrap();
+            let response = serde_json::json!({
+                "status": 201,
+                "headers": {"x-echo-method": request.method, "x-echo-uri": request.uri},
+                "body": general_purpose::STANDARD.encode(&body),
+            });
+            stream.write_all(&serde_json::to_vec(&response).unwrap()).await.unwrap();
+            request.headers
+        });
+
+        let config = create_test_config("api", "/api/*", &pipe_name);
+        let mut app = create_router(ProxyState::new(vec![config]));
+        let request = axum::http::Request::builder()
+            .method(Method::PATCH)
+            .uri("/api/items/7?fields=name")
+            .header("content-type", "application/json")
+            .body(Body::from(r#"{"name":"new"}"#))
+            .unwrap();
+        let response = app.call(request).await.unwrap();
+        let headers = backend.await.unwrap();
+        let _ = std::fs::remove_file(ProxyState::get_pipe_address(&pipe_name));
+
+        assert_eq!(response.status(), StatusCode::CREATED);
+        assert_eq!(response.headers()["x-echo-method"], "PATCH");
+        assert_eq!(response.headers()["x-echo-uri"], "/api/items/7?fields=name");
+        assert!(headers.contains(&("content-type".to_string(), "application/json".to_string())));
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        assert_eq!(&body[..], br#"{"name":"new"}"#);
+    }
+
+    #[tokio::test]
+    async fn test_body_over_the_configured_limit_is_rejected() {
+        use tower::Service;
+
+        let config = create_test_config("api", "/api/*", "legacy_limit_pipe");
+        let mut app = create_router(ProxyState::new(vec![config]).with_max_body_bytes(4));
+        let request = axum::http::Request::post("/api/upload").body(Body::from("too long")).unwrap();
+        let response = app.call(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+    }
+
+    #[tokio::test]
+    async fn test_serve_stops_on_shutdown() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (signal, signalled) = tokio::sync::oneshot::channel::<()>();
+        let serving = tokio::spawn(serve(listener, ProxyState::new(vec![]), async {
+            let _ = signalled.await;
+        }));
+
+        let response = reqwest::get(format!("http://{}/unrouted", addr)).await.unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::NOT_FOUND);
+
+        signal.send(()).unwrap();
+        tokio::time::timeout(std::time::Duration::from_secs(5), serving)
+            .await
+            .expect("the server didn't stop")
+            .unwrap()
+            .unwrap();
+        assert!(tokio::net::TcpStream::connect(addr).await.is_err());
+    }
+
     #[test]
     fn test_deserialize_response_success() {
         let response_json = serde_json::json!({
@@ -389,6 +517,18 @@ mod tests {
         assert_eq!(response.status(), StatusCode::NOT_FOUND);
     }
 
+    #[test]
+    fn test_deserialize_response_ignores_content_length() {
+        let response_json = serde_json::json!({
+            "status": 200,
+            "headers": { "Content-Length": "3" },
+            "body": general_purpose::STANDARD.encode(b"test response")
+        });
+
+        let response = deserialize_response(serde_json::to_vec(&response_json).unwrap()).unwrap();
+        assert!(!response.headers().contains_key("content-length"));
+    }
+
     #[test]
     fn test_deserialize_response_invalid_json() {
         let data = b"not json".to_vec();
@@ -0,0 +1,102 @@
+//! Startup shared by the binary and the embedding API, so a proxy is
+//! checked and started the same way however it's configured
+//! This file is part of the outermost layer (Frameworks & Drivers)
+
+use crate::adapters::TokioProcessOrchestrator;
+use crate::domain::{PipeCommunicationService, Process};
+use crate::use_cases::{
+    CheckManifestUseCase, ProxyHttpRequestUseCase, ProxyOptions, StartAllProcessesUseCase, UseCaseError,
+    ValidateProcessesUseCase,
+};
+use std::sync::Arc;
+use tokio::sync::RwLock;
+use tokio::task::JoinHandle;
+
+/// Starts, restarts and stops a proxy's processes
+pub type Orchestrator = Arc<RwLock<TokioProcessOrchestrator>>;
+
+/// An orchestrator with every one of `processes` that runs registered
+pub fn orchestrator(processes: &[Process]) -> Orchestrator {
+    let mut orchestrator = TokioProcessOrchestrator::new();
+    // Static routes are served by the proxy itself, with nothing to start
+    for process in processes.iter().filter(|p| p.static_dir.is_none()) {
+        tracing::info!(
+            "Registering process '{}': {} -> {}",
+            process.id.as_str(),
+            process.route.as_str(),
+            process.executable.as_str()
+        );
+        orchestrator.register(process.clone());
+    }
+    Arc::new(RwLock::new(orchestrator))
+}
+
+/// Refuse to start on what `--check` reports: processes that clash over
+/// ids, routes, pipe names or ports, and, with `check_executables`,
+/// executables that can't be found
+pub async fn check(orchestrator: &Orchestrator, processes: &[Process], check_executables: bool) -> Result<(), String> {
+    let conflicts = CheckManifestUseCase::new(orchestrator.clone()).conflicts(processes);
+    if !conflicts.is_empty() {
+        return Err(format!("Manifest has {} problem(s): {}", conflicts.len(), conflicts.join("; ")));
+    }
+    if check_executables {
+        ValidateProcessesUseCase::new(orchestrator.clone())
+            .execute()
+            .await
+            .map_err(|e| e.to_string())?;
+    }
+    Ok(())
+}
+
+/// The proxy in front of `processes`, with its cache restored
+///
+/// It manages the processes through `orchestrator`; without one, as when a
+/// recording is replayed in their place, there's nothing to wake, restart
+/// or reap.
+pub async fn proxy<P: PipeCommunicationService>(
+    pipe_service: P,
+    http_service: Arc<dyn PipeCommunicationService>,
+    processes: Vec<Process>,
+    options: ProxyOptions,
+    orchestrator: Option<&Orchestrator>,
+) -> Arc<ProxyHttpRequestUseCase<P>> {
+    let use_case = ProxyHttpRequestUseCase::with_options(Arc::new(pipe_service), Arc::new(processes), options)
+        .with_http_service(http_service);
+    let use_case = match orchestrator {
+        Some(orchestrator) => use_case.with_orchestrator(orchestrator.clone()),
+        None => use_case,
+    };
+    use_case.restore_cache().await;
+    Arc::new(use_case)
+}
+
+/// Start the processes, when there's an orchestrator to start them with,
+/// and wait for those without a health check to open their socket or port
+/// and warm up; the rest only receive traffic once their check passes
+pub async fn start<P: PipeCommunicationService>(
+    use_case: &ProxyHttpRequestUseCase<P>,
+    orchestrator: Option<&Orchestrator>,
+) -> Result<(), UseCaseError> {
+    if let Some(orchestrator) = orchestrator {
+        tracing::info!("Starting all processes...");
+        StartAllProcessesUseCase::new(orchestrator.clone()).execute().await?;
+    }
+    match use_case.wait_until_ready().await {
+        Ok(()) => Ok(()),
+        Err(e @ UseCaseError::WarmupFailed(_)) => Err(e),
+        Err(e) => {
+            tracing::warn!("{}", e);
+            Ok(())
+        }
+    }
+}
+
+/// Run health checks and stop idle processes in the background, for as
+/// long as the returned tasks do
+pub fn spawn_background_tasks<P: PipeCommunicationService + 'static>(
+    use_case: &Arc<ProxyHttpRequestUseCase<P>>,
+) -> Vec<JoinHandle<()>> {
+    let mut tasks = use_case.spawn_health_checks();
+    tasks.extend(use_case.spawn_idle_reaper());
+    tasks
+}
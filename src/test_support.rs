@@ -0,0 +1,425 @@
+//! Test doubles for exercising the proxy without spawning processes
+//!
+//! Available to this crate's tests, and to other crates' with the
+//! `test-util` feature.
+
+use crate::adapters::http::HttpServerState;
+use crate::domain::{CommunicationError, HttpResponse, PipeCommunicationService, Process, StreamingRequest};
+use crate::infrastructure::http_client;
+use crate::infrastructure::replay::{self, Format, RecordedResponse};
+use crate::use_cases::ProxyHttpRequestUseCase;
+use async_trait::async_trait;
+use futures_util::TryStreamExt;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use tokio::sync::Semaphore;
+
+/// Log output collected in memory, to check what the proxy logs
+#[derive(Clone, Default)]
+pub struct CapturedLogs(Arc<Mutex<Vec<u8>>>);
+
+impl CapturedLogs {
+    /// Collect everything logged on this thread, at every level, until the
+    /// guard is dropped; the subscriber is thread-local, so whatever logs
+    /// has to run on this thread too
+    pub fn capture() -> (Self, tracing::subscriber::DefaultGuard) {
+        let captured = Self::default();
+        let writer = captured.clone();
+        let subscriber = tracing_subscriber::fmt()
+            .with_max_level(tracing::Level::TRACE)
+            .with_writer(move || writer.clone())
+            .with_ansi(false)
+            .finish();
+        (captured, tracing::subscriber::set_default(subscriber))
+    }
+
+    /// The lines logged so far
+    pub fn lines(&self) -> Vec<String> {
+        String::from_utf8_lossy(&self.0.lock().unwrap())
+            .lines()
+            .map(String::from)
+            .collect()
+    }
+}
+
+impl std::io::Write for CapturedLogs {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+/// A request the mock received, decoded from its envelope
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReceivedRequest {
+    /// Address of the instance the request was sent to
+    pub address: String,
+    pub method: String,
+    pub path: String,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl ReceivedRequest {
+    /// Value of the first header named `name`, ignoring case
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+}
+
+enum Outcome {
+    Respond(HttpResponse),
+    Envelope(Vec<u8>),
+    Fail(CommunicationError),
+}
+
+#[derive(Default)]
+struct MockState {
+    outcomes: HashMap<(String, String), Outcome>,
+    /// Failures still to come before the programmed outcome, and the error
+    failures: HashMap<(String, String), (usize, CommunicationError)>,
+    received: Vec<ReceivedRequest>,
+    gate: Option<Gate>,
+}
+
+/// Holds the mock's requests until a test lets them through, to look at
+/// the proxy while they're in flight
+#[derive(Clone)]
+pub struct Gate {
+    arrived: Arc<Semaphore>,
+    release: Arc<Semaphore>,
+}
+
+impl Gate {
+    fn new() -> Self {
+        Self {
+            arrived: Arc::new(Semaphore::new(0)),
+            release: Arc::new(Semaphore::new(0)),
+        }
+    }
+
+    /// Wait until `count` more requests are being held
+    pub async fn arrived(&self, count: u32) {
+        self.arrived.acquire_many(count).await.unwrap().forget();
+    }
+
+    /// Let `count` held requests, or ones still to come, through
+    pub fn release(&self, count: usize) {
+        self.release.add_permits(count);
+    }
+
+    async fn hold(&self) {
+        self.arrived.add_permits(1);
+        self.release.acquire().await.unwrap().forget();
+    }
+}
+
+/// Transport answering from programmed responses instead of a backend
+///
+/// Responses are programmed per method and exact path, and encoded in
+/// whichever envelope format the request came in. A request nothing was
+/// programmed for fails as if the backend couldn't be reached. Clones share
+/// their responses and received requests, so a test can keep one while the
+/// proxy uses another.
+#[derive(Clone, Default)]
+pub struct MockPipeCommunicationService {
+    state: Arc<Mutex<MockState>>,
+}
+
+impl MockPipeCommunicationService {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn program(&self, method: &str, path: &str, outcome: Outcome) -> &Self {
+        self.state
+            .lock()
+            .unwrap()
+            .outcomes
+            .insert((method.to_string(), path.to_string()), outcome);
+        self
+    }
+
+    /// Answer `method` requests for `path` with `status` and `body`
+    pub fn respond(&self, method: &str, path: &str, status: u16, body: impl Into<Vec<u8>>) -> &Self {
+        self.respond_with(
+            method,
+            path,
+            HttpResponse {
+                status_code: status,
+                headers: Vec::new(),
+                body: body.into(),
+                trailers: Vec::new(),
+            },
+        )
+    }
+
+    /// Answer `method` requests for `path` with `response`, headers and all
+    pub fn respond_with(&self, method: &str, path: &str, response: HttpResponse) -> &Self {
+        self.program(method, path, Outcome::Respond(response))
+    }
+
+    /// Answer `method` requests for `path` with `envelope` as is, e.g. to
+    /// send a malformed one
+    pub fn respond_with_envelope(&self, method: &str, path: &str, envelope: impl Into<Vec<u8>>) -> &Self {
+        self.program(method, path, Outcome::Envelope(envelope.into()))
+    }
+
+    /// Fail `method` requests for `path` with `error`, the way the real
+    /// transport does when a backend misbehaves
+    pub fn fail(&self, method: &str, path: &str, error: CommunicationError) -> &Self {
+        self.program(method, path, Outcome::Fail(error))
+    }
+
+    /// Fail the next `times` `method` requests for `path` with `error`, then
+    /// go back to answering them as programmed, the way a backend that is
+    /// briefly unreachable does
+    pub fn fail_first(&self, method: &str, path: &str, times: usize, error: CommunicationError) -> &Self {
+        self.state
+            .lock()
+            .unwrap()
+            .failures
+            .insert((method.to_string(), path.to_string()), (times, error));
+        self
+    }
+
+    /// Hold every request from now on until the returned gate releases it
+    pub fn gate(&self) -> Gate {
+        let gate = Gate::new();
+        self.state.lock().unwrap().gate = Some(gate.clone());
+        gate
+    }
+
+    /// Requests received so far, oldest first
+    pub fn received(&self) -> Vec<ReceivedRequest> {
+        self.state.lock().unwrap().received.clone()
+    }
+
+    /// The proxy's router, serving `processes` through this mock
+    pub fn router(&self, processes: Vec<Process>) -> axum::Router {
+        let use_case = ProxyHttpRequestUseCase::new(Arc::new(self.clone()), Arc::new(processes));
+        HttpServerState::new(Arc::new(use_case)).create_router()
+    }
+
+    /// Wait for the gate, if there is one, to let a request through
+    async fn pass_gate(&self) {
+        let gate = self.state.lock().unwrap().gate.clone();
+        if let Some(gate) = gate {
+            gate.hold().await;
+        }
+    }
+
+    /// Note a request and look up what to answer it with
+    fn receive(&self, request: ReceivedRequest) -> Result<Outcome, CommunicationError> {
+        let mut state = self.state.lock().unwrap();
+        let key = (request.method.clone(), request.path.clone());
+        state.received.push(request);
+        if let Some((remaining @ 1.., error)) = state.failures.get_mut(&key) {
+            *remaining -= 1;
+            return Err(error.clone());
+        }
+        match state.outcomes.get(&key) {
+            Some(Outcome::Respond(response)) => Ok(Outcome::Respond(response.clone())),
+            Some(Outcome::Envelope(envelope)) => Ok(Outcome::Envelope(envelope.clone())),
+            Some(Outcome::Fail(error)) => Err(error.clone()),
+            None => Err(CommunicationError::ConnectionFailed(format!(
+                "no mock response for {} {}",
+                key.0, key.1
+            ))),
+        }
+    }
+}
+
+#[async_trait]
+impl PipeCommunicationService for MockPipeCommunicationService {
+    async fn send_request(&self, address: &str, request: Vec<u8>) -> Result<Vec<u8>, CommunicationError> {
+        let format = Format::of(&request);
+        let request = replay::decode_request(&request).map_err(CommunicationError::SendFailed)?;
+        self.pass_gate().await;
+        let outcome = self.receive(ReceivedRequest {
+            address: address.to_string(),
+            method: request.method,
+            path: request.path,
+            headers: request.headers,
+            body: request.body,
+        })?;
+
+        match outcome {
+            Outcome::Respond(response) => {
+                let response = RecordedResponse {
+                    status: response.status_code,
+                    headers: response.headers,
+                    body: response.body,
+                };
+                replay::encode_response(response, format).map_err(CommunicationError::ReceiveFailed)
+            }
+            Outcome::Envelope(envelope) => Ok(envelope),
+            Outcome::Fail(error) => Err(error),
+        }
+    }
+
+    async fn send_streaming(
+        &self,
+        address: &str,
+        request: StreamingRequest,
+    ) -> Result<HttpResponse, CommunicationError> {
+        let body: Vec<bytes::Bytes> = request
+            .body
+            .try_collect()
+            .await
+            .map_err(|e| {
+                // Told apart the way the HTTP client does, so the use case
+                // sees the same errors it would from a real backend
+                if http_client::is_length_limit(&e) {
+                    CommunicationError::PayloadTooLarge(e.to_string())
+                } else {
+                    CommunicationError::RequestBodyFailed(e.to_string())
+                }
+            })?;
+        self.pass_gate().await;
+        let outcome = self.receive(ReceivedRequest {
+            address: address.to_string(),
+            method: request.method.as_str().to_string(),
+            path: request.path,
+            headers: request.headers,
+            body: body.concat(),
+        })?;
+
+        match outcome {
+            Outcome::Respond(response) => Ok(response),
+            Outcome::Envelope(_) => Err(CommunicationError::ReceiveFailed(
+                "raw requests are answered without an envelope".to_string(),
+            )),
+            Outcome::Fail(error) => Err(error),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::{Executable, PipeName, ProcessId, Route};
+    use axum::body::Body;
+    use axum::http::{Request, StatusCode};
+    use http_body_util::BodyExt;
+    use tower::Service;
+
+    fn api() -> Vec<Process> {
+        vec![Process::new(
+            ProcessId::new("api").unwrap(),
+            Executable::new("./api").unwrap(),
+            Route::new("/api/*").unwrap(),
+            PipeName::new("api_pipe").unwrap(),
+        )]
+    }
+
+    /// Send a request through the router in memory, with no socket involved
+    async fn send(router: &mut axum::Router, method: &str, path: &str, body: &str) -> (StatusCode, String) {
+        let request = Request::builder()
+            .method(method)
+            .uri(path)
+            .body(Body::from(body.to_string()))
+            .unwrap();
+        let response = router.call(request).await.unwrap();
+        let status = response.status();
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        (status, String::from_utf8_lossy(&body).into_owned())
+    }
+
+    #[tokio::test]
+    async fn test_programmed_response_is_proxied() {
+        let mock = MockPipeCommunicationService::new();
+        mock.respond("POST", "/api/users", 201, "created");
+        let mut router = mock.router(api());
+
+        let (status, body) = send(&mut router, "POST", "/api/users", r#"{"name":"a"}"#).await;
+
+        assert_eq!(status, StatusCode::CREATED);
+        assert_eq!(body, "created");
+        let received = mock.received();
+        assert_eq!(received.len(), 1);
+        assert_eq!(received[0].method, "POST");
+        assert_eq!(received[0].path, "/api/users");
+        assert_eq!(received[0].body, br#"{"name":"a"}"#);
+        assert!(received[0].header("x-request-id").is_some());
+    }
+
+    #[tokio::test]
+    async fn test_unrouted_path_is_not_found() {
+        let mock = MockPipeCommunicationService::new();
+        let mut router = mock.router(api());
+
+        let (status, _) = send(&mut router, "GET", "/other", "").await;
+
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert!(mock.received().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_injected_errors_map_to_statuses() {
+        let mock = MockPipeCommunicationService::new();
+        mock.fail("GET", "/api/slow", CommunicationError::Timeout("took too long".to_string()))
+            .fail("GET", "/api/down", CommunicationError::ConnectionFailed("refused".to_string()))
+            .respond_with_envelope("GET", "/api/garbled", "not an envelope")
+            .respond_with(
+                "GET",
+                "/api/bad-header",
+                HttpResponse {
+                    status_code: 200,
+                    headers: vec![("not a header name".to_string(), "x".to_string())],
+                    body: Vec::new(),
+                    trailers: Vec::new(),
+                },
+            );
+        let mut router = mock.router(api());
+
+        assert_eq!(send(&mut router, "GET", "/api/slow", "").await.0, StatusCode::GATEWAY_TIMEOUT);
+        assert_eq!(send(&mut router, "GET", "/api/down", "").await.0, StatusCode::BAD_GATEWAY);
+        assert_eq!(send(&mut router, "GET", "/api/garbled", "").await.0, StatusCode::BAD_GATEWAY);
+        assert_eq!(send(&mut router, "GET", "/api/unprogrammed", "").await.0, StatusCode::BAD_GATEWAY);
+        assert_eq!(
+            send(&mut router, "GET", "/api/bad-header", "").await.0,
+            StatusCode::INTERNAL_SERVER_ERROR
+        );
+        assert_eq!(mock.received().len(), 5);
+    }
+
+    #[tokio::test]
+    async fn test_first_requests_can_fail() {
+        let mock = MockPipeCommunicationService::new();
+        mock.respond("GET", "/api/flaky", 200, "ok").fail_first(
+            "GET",
+            "/api/flaky",
+            2,
+            CommunicationError::ConnectionFailed("refused".to_string()),
+        );
+        let mut router = mock.router(api());
+
+        assert_eq!(send(&mut router, "GET", "/api/flaky", "").await.0, StatusCode::BAD_GATEWAY);
+        assert_eq!(send(&mut router, "GET", "/api/flaky", "").await.0, StatusCode::BAD_GATEWAY);
+        assert_eq!(send(&mut router, "GET", "/api/flaky", "").await, (StatusCode::OK, "ok".to_string()));
+        assert_eq!(mock.received().len(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_gated_requests_wait_for_release() {
+        let mock = MockPipeCommunicationService::new();
+        mock.respond("GET", "/api/slow", 200, "done");
+        let gate = mock.gate();
+        let mut router = mock.router(api());
+
+        let held = tokio::spawn(async move { send(&mut router, "GET", "/api/slow", "").await });
+        gate.arrived(1).await;
+        assert!(!held.is_finished());
+
+        gate.release(1);
+        assert_eq!(held.await.unwrap(), (StatusCode::OK, "done".to_string()));
+    }
+}
@@ -0,0 +1,50 @@
+//! Rendering request and response bodies for debug logging
+
+/// Longest body prefix logged when no limit is configured, in bytes
+pub const DEFAULT_DEBUG_BODY_LIMIT: usize = 1024;
+
+/// Render up to `limit` bytes of `body` for a log line
+///
+/// UTF-8 text is shown as an escaped string; anything else as hex bytes.
+/// Truncated bodies say how much was left out.
+pub fn render(body: &[u8], limit: usize) -> String {
+    if body.is_empty() {
+        return "(empty)".to_string();
+    }
+
+    let rendered = match std::str::from_utf8(body) {
+        Ok(text) => {
+            let mut end = limit.min(text.len());
+            while !text.is_char_boundary(end) {
+                end -= 1;
+            }
+            format!("{:?}", &text[..end])
+        }
+        Err(_) => {
+            let hex: Vec<String> = body.iter().take(limit).map(|b| format!("{:02x}", b)).collect();
+            format!("hex[{}]", hex.join(" "))
+        }
+    };
+
+    if body.len() > limit {
+        format!("{} ... ({} of {} bytes)", rendered, limit, body.len())
+    } else {
+        rendered
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_text_binary_and_truncation() {
+        assert_eq!(render(b"", 10), "(empty)");
+        assert_eq!(render(b"{\"a\":1}\n", 100), r#""{\"a\":1}\n""#);
+        assert_eq!(render(&[0x89, 0x50, 0x4e, 0x47], 100), "hex[89 50 4e 47]");
+        assert_eq!(render(b"hello world", 5), r#""hello" ... (5 of 11 bytes)"#);
+        assert_eq!(render(&[0xff, 0x00, 0x01], 2), "hex[ff 00] ... (2 of 3 bytes)");
+        // Never splits a multi-byte character
+        assert_eq!(render("héllo".as_bytes(), 2), r#""h" ... (2 of 6 bytes)"#);
+    }
+}
@@ -0,0 +1,109 @@
+//! On-disk snapshot of the response cache, so a restarted proxy starts warm
+
+use crate::domain::HttpResponse;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::time::Duration;
+
+/// Bumped whenever the layout changes; files with another version are ignored
+const FORMAT_VERSION: u32 = 1;
+
+/// One cached response and how much longer it may be served for; `None`
+/// keeps it until evicted
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SavedEntry {
+    pub key: String,
+    pub response: HttpResponse,
+    pub ttl: Option<Duration>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct CacheFile {
+    version: u32,
+    entries: Vec<SavedEntry>,
+}
+
+/// Write `entries` to `path` as MessagePack
+///
+/// The file is written next to `path` and renamed into place, so a crash
+/// mid-write never leaves a truncated snapshot behind.
+pub fn save(path: &Path, entries: Vec<SavedEntry>) -> Result<(), String> {
+    let data = rmp_serde::to_vec_named(&CacheFile {
+        version: FORMAT_VERSION,
+        entries,
+    })
+    .map_err(|e| e.to_string())?;
+
+    let partial = path.with_extension("partial");
+    std::fs::write(&partial, data).map_err(|e| e.to_string())?;
+    std::fs::rename(&partial, path).map_err(|e| e.to_string())
+}
+
+/// Read the entries saved at `path`
+///
+/// A missing file is an empty cache; an unreadable, corrupt or incompatible
+/// one is an error.
+pub fn load(path: &Path) -> Result<Vec<SavedEntry>, String> {
+    let data = match std::fs::read(path) {
+        Ok(data) => data,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e.to_string()),
+    };
+
+    let file: CacheFile = rmp_serde::from_slice(&data).map_err(|e| format!("invalid cache file: {}", e))?;
+    if file.version != FORMAT_VERSION {
+        return Err(format!(
+            "cache file version {} is not supported (expected {})",
+            file.version, FORMAT_VERSION
+        ));
+    }
+    Ok(file.entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("cache.bin");
+        let entries = vec![SavedEntry {
+            key: "GET:/api/x".to_string(),
+            response: HttpResponse {
+                status_code: 200,
+                headers: vec![("content-type".to_string(), "text/plain".to_string())],
+                body: b"hello".to_vec(),
+                trailers: Vec::new(),
+            },
+            ttl: Some(Duration::from_secs(5)),
+        }];
+
+        save(&path, entries).unwrap();
+        let loaded = load(&path).unwrap();
+
+        assert_eq!(loaded.len(), 1);
+        assert_eq!(loaded[0].key, "GET:/api/x");
+        assert_eq!(loaded[0].response.body, b"hello");
+        assert_eq!(loaded[0].ttl, Some(Duration::from_secs(5)));
+        assert!(!path.with_extension("partial").exists());
+    }
+
+    #[test]
+    fn test_missing_file_is_empty_and_garbage_is_an_error() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("cache.bin");
+        assert!(load(&path).unwrap().is_empty());
+
+        std::fs::write(&path, b"not a cache").unwrap();
+        assert!(load(&path).is_err());
+
+        let other_version = rmp_serde::to_vec_named(&CacheFile {
+            version: FORMAT_VERSION + 1,
+            entries: vec![],
+        })
+        .unwrap();
+        std::fs::write(&path, other_version).unwrap();
+        assert!(load(&path).unwrap_err().contains("not supported"));
+    }
+}
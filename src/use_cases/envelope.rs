@@ -0,0 +1,103 @@
+//! JSON envelopes - how requests and responses are exchanged with processes
+//! using [`SerializationFormat::Json`](crate::domain::SerializationFormat::Json)
+//!
+//! A request is `{"method", "uri", "headers", "body"}`, where `uri` is the
+//! path followed by the query string if there is one, `headers` a list of
+//! name and value pairs and `body` base64. A response is `{"status",
+//! "headers", "body"}`, with `headers` an object. The legacy proxy speaks the
+//! same envelopes, so a backend works behind either entry point.
+
+use crate::domain::{HttpRequest, HttpResponse};
+use base64::{engine::general_purpose, Engine as _};
+
+/// Encode a request as a JSON object
+pub fn encode_request(request: &HttpRequest) -> Result<Vec<u8>, String> {
+    let json = serde_json::json!({
+        "method": request.method.as_str(),
+        "uri": request.path,
+        "headers": request.headers,
+        "body": general_purpose::STANDARD.encode(&request.body),
+    });
+    serde_json::to_vec(&json).map_err(|e| e.to_string())
+}
+
+/// Decode a JSON response envelope
+///
+/// In strict mode a missing or out-of-range `status`, a non-string `body`, or a
+/// body that isn't valid base64 is rejected. Lenient mode keeps the historical
+/// behaviour of defaulting the status to 200 and dropping an undecodable body.
+pub fn decode_response(data: &[u8], lenient: bool) -> Result<HttpResponse, String> {
+    let json: serde_json::Value = serde_json::from_slice(data)
+        .map_err(|e| format!("invalid JSON: {}", e))?;
+
+    let status_code = match json.get("status") {
+        Some(value) => match value.as_u64().filter(|s| (100..=599).contains(s)) {
+            Some(status) => status as u16,
+            None if lenient => 200,
+            None => return Err(format!("invalid status field: {}", value)),
+        },
+        None if lenient => 200,
+        None => return Err("missing status field".to_string()),
+    };
+
+    let headers = json["headers"]
+        .as_object()
+        .map(|obj| {
+            obj.iter()
+                .filter_map(|(k, v)| {
+                    v.as_str().map(|v| (k.clone(), v.to_string()))
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+
+    let body = match json.get("body") {
+        None | Some(serde_json::Value::Null) => Vec::new(),
+        Some(serde_json::Value::String(encoded)) => match general_purpose::STANDARD.decode(encoded) {
+            Ok(body) => body,
+            Err(_) if lenient => Vec::new(),
+            Err(e) => return Err(format!("body is not valid base64: {}", e)),
+        },
+        Some(_) if lenient => Vec::new(),
+        Some(other) => return Err(format!("body must be a base64 string, got {}", other)),
+    };
+
+    Ok(HttpResponse {
+        status_code,
+        headers,
+        body,
+        trailers: Vec::new(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::HttpMethod;
+
+    #[test]
+    fn test_round_trip() {
+        let request = HttpRequest {
+            method: HttpMethod::Patch,
+            path: "/api/items/7?fields=name".to_string(),
+            headers: vec![("content-type".to_string(), "application/json".to_string())],
+            body: b"{}".to_vec(),
+        };
+        let encoded: serde_json::Value = serde_json::from_slice(&encode_request(&request).unwrap()).unwrap();
+        assert_eq!(
+            encoded,
+            serde_json::json!({
+                "method": "PATCH",
+                "uri": "/api/items/7?fields=name",
+                "headers": [["content-type", "application/json"]],
+                "body": "e30=",
+            })
+        );
+
+        let response = br#"{"status": 204, "headers": {"x-id": "7"}, "body": null}"#;
+        let response = decode_response(response, false).unwrap();
+        assert_eq!(response.status_code, 204);
+        assert_eq!(response.headers, vec![("x-id".to_string(), "7".to_string())]);
+        assert!(response.body.is_empty());
+    }
+}
@@ -0,0 +1,105 @@
+//! Health tracking for processes with a configured health check
+
+use crate::domain::{HealthState, Process};
+use std::collections::HashMap;
+use std::sync::RwLock;
+
+/// Current health of every process, shared between the proxy and the
+/// background health checks
+///
+/// Processes without a health check are always considered healthy; those
+/// with one start out as `Starting` and only receive traffic once a check
+/// has passed.
+pub struct HealthRegistry {
+    states: RwLock<HashMap<String, HealthState>>,
+}
+
+impl HealthRegistry {
+    pub fn new(processes: &[Process]) -> Self {
+        let states = processes
+            .iter()
+            .map(|p| {
+                let state = if p.health_check.is_some() {
+                    HealthState::Starting
+                } else {
+                    HealthState::Healthy
+                };
+                (p.id.as_str().to_string(), state)
+            })
+            .collect();
+
+        Self {
+            states: RwLock::new(states),
+        }
+    }
+
+    pub fn get(&self, process: &str) -> HealthState {
+        self.states
+            .read()
+            .unwrap()
+            .get(process)
+            .copied()
+            .unwrap_or(HealthState::Healthy)
+    }
+
+    /// Record a new state, logging transitions
+    pub fn set(&self, process: &str, state: HealthState) {
+        let previous = self
+            .states
+            .write()
+            .unwrap()
+            .insert(process.to_string(), state);
+
+        if previous != Some(state) {
+            match state {
+                HealthState::Healthy => tracing::info!("Process '{}' is healthy", process),
+                HealthState::Unhealthy => tracing::warn!("Process '{}' is unhealthy", process),
+                HealthState::Starting => tracing::debug!("Process '{}' is starting", process),
+            }
+        }
+    }
+
+    pub fn is_healthy(&self, process: &str) -> bool {
+        self.get(process) == HealthState::Healthy
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::{Executable, HealthCheck, PipeName, ProcessId, Route};
+    use std::time::Duration;
+
+    fn process(id: &str, checked: bool) -> Process {
+        let mut process = Process::new(
+            ProcessId::new(id).unwrap(),
+            Executable::new("./x").unwrap(),
+            Route::new("/x/*").unwrap(),
+            PipeName::new(id).unwrap(),
+        );
+        if checked {
+            process.health_check = Some(HealthCheck {
+                path: "/healthz".to_string(),
+                interval: Duration::from_secs(1),
+            });
+        }
+        process
+    }
+
+    #[test]
+    fn test_initial_states() {
+        let registry = HealthRegistry::new(&[process("plain", false), process("checked", true)]);
+        assert_eq!(registry.get("plain"), HealthState::Healthy);
+        assert_eq!(registry.get("checked"), HealthState::Starting);
+        assert!(!registry.is_healthy("checked"));
+    }
+
+    #[test]
+    fn test_set_state() {
+        let registry = HealthRegistry::new(&[process("checked", true)]);
+        registry.set("checked", HealthState::Healthy);
+        assert!(registry.is_healthy("checked"));
+        registry.set("checked", HealthState::Unhealthy);
+        assert_eq!(registry.get("checked"), HealthState::Unhealthy);
+    }
+}
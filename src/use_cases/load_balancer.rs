@@ -0,0 +1,246 @@
+//! Instance selection for processes that run more than one copy
+
+use crate::domain::Process;
+use std::ops::Deref;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::Notify;
+
+/// How long an instance that refused a connection is skipped for
+const UNHEALTHY_COOLDOWN: Duration = Duration::from_secs(5);
+
+/// Weighted round-robin selection over the instances of one process
+///
+/// Selection is smooth weighted round-robin, as in nginx: with weights 5, 3
+/// and 1 every nine requests go 5, 3 and 1 to each instance, interleaved
+/// rather than in bursts. Equal weights give plain round-robin. Instances
+/// that fail to accept a connection are marked unhealthy and skipped until
+/// the cooldown expires or they answer successfully again.
+pub struct InstancePool {
+    addresses: Vec<String>,
+    /// HTTP address of each instance, used when its pipe is unreachable
+    http_addresses: Vec<String>,
+    weights: Vec<i64>,
+    /// Each instance's running score; the highest is picked next
+    current_weights: Mutex<Vec<i64>>,
+    unhealthy_until: Mutex<Vec<Option<Instant>>>,
+    /// Requests being sent to these instances, so a reload knows when the
+    /// ones it replaced are done with
+    in_flight: AtomicUsize,
+    /// Notified when the last request in flight finishes
+    idle: Notify,
+}
+
+impl InstancePool {
+    pub fn new(process: &Process) -> Self {
+        let addresses = process.instance_addresses();
+        let weights = match process.weights.len() {
+            0 => vec![1; addresses.len()],
+            _ => process.weights.iter().map(|&w| i64::from(w)).collect(),
+        };
+        Self {
+            current_weights: Mutex::new(vec![0; addresses.len()]),
+            unhealthy_until: Mutex::new(vec![None; addresses.len()]),
+            addresses,
+            http_addresses: process.instance_http_addresses(),
+            weights,
+            in_flight: AtomicUsize::new(0),
+            idle: Notify::new(),
+        }
+    }
+
+    /// Instance indices in the order they should be tried for one request:
+    /// the next healthy instance by weight, the other healthy ones after it,
+    /// then unhealthy ones as a last resort
+    pub fn candidates(&self) -> Vec<usize> {
+        let len = self.addresses.len();
+        let now = Instant::now();
+        let unhealthy_until = self.unhealthy_until.lock().unwrap();
+        let healthy = |i: usize| unhealthy_until[i].is_none_or(|until| until <= now);
+
+        // Unhealthy instances don't take part, so their share goes to the others
+        let mut eligible: Vec<usize> = (0..len).filter(|&i| healthy(i)).collect();
+        if eligible.is_empty() {
+            eligible = (0..len).collect();
+        }
+        let start = self.pick(&eligible);
+
+        let (healthy, unhealthy): (Vec<usize>, Vec<usize>) = (0..len)
+            .map(|offset| (start + offset) % len)
+            .partition(|&i| healthy(i));
+
+        healthy.into_iter().chain(unhealthy).collect()
+    }
+
+    /// Smooth weighted round-robin step over `eligible`: every instance's
+    /// score grows by its weight, and the highest scorer is picked and set
+    /// back by the total
+    fn pick(&self, eligible: &[usize]) -> usize {
+        let mut current = self.current_weights.lock().unwrap();
+        let mut total = 0;
+        let mut best = eligible[0];
+        for &i in eligible {
+            current[i] += self.weights[i];
+            total += self.weights[i];
+            if current[i] > current[best] {
+                best = i;
+            }
+        }
+        current[best] -= total;
+        best
+    }
+
+    pub fn addresses(&self) -> &[String] {
+        &self.addresses
+    }
+
+    pub fn address(&self, index: usize) -> &str {
+        &self.addresses[index]
+    }
+
+    pub fn http_address(&self, index: usize) -> &str {
+        &self.http_addresses[index]
+    }
+
+    pub fn len(&self) -> usize {
+        self.addresses.len()
+    }
+
+    pub fn mark_unhealthy(&self, index: usize) {
+        self.unhealthy_until.lock().unwrap()[index] = Some(Instant::now() + UNHEALTHY_COOLDOWN);
+    }
+
+    pub fn mark_healthy(&self, index: usize) {
+        self.unhealthy_until.lock().unwrap()[index] = None;
+    }
+
+    /// Count a request as in flight to these instances until the returned
+    /// guard is dropped
+    pub fn enter(self: Arc<Self>) -> InFlight {
+        self.in_flight.fetch_add(1, Ordering::SeqCst);
+        InFlight(self)
+    }
+
+    /// Wait until no request is in flight to these instances
+    pub async fn drained(&self) {
+        loop {
+            // Registered before checking, so a request finishing in between
+            // isn't missed
+            let idle = self.idle.notified();
+            if self.in_flight.load(Ordering::SeqCst) == 0 {
+                return;
+            }
+            idle.await;
+        }
+    }
+}
+
+/// A request in flight to a pool's instances, counted until it's dropped
+pub struct InFlight(Arc<InstancePool>);
+
+impl Deref for InFlight {
+    type Target = InstancePool;
+
+    fn deref(&self) -> &InstancePool {
+        &self.0
+    }
+}
+
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        if self.0.in_flight.fetch_sub(1, Ordering::SeqCst) == 1 {
+            self.0.idle.notify_waiters();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::{Executable, PipeName, ProcessId, Route};
+
+    fn weighted_pool(n: usize, weights: Vec<u32>) -> InstancePool {
+        let mut process = Process::new(
+            ProcessId::new("api").unwrap(),
+            Executable::new("./api").unwrap(),
+            Route::new("/api/*").unwrap(),
+            PipeName::new("api_pipe").unwrap(),
+        );
+        process.instances = n;
+        process.weights = weights;
+        InstancePool::new(&process)
+    }
+
+    fn pool(n: usize) -> InstancePool {
+        weighted_pool(n, Vec::new())
+    }
+
+    #[tokio::test]
+    async fn test_drained_waits_for_requests_in_flight() {
+        let pool = Arc::new(pool(1));
+        pool.drained().await;
+
+        let first = pool.clone().enter();
+        let second = pool.clone().enter();
+        let draining = tokio::spawn({
+            let pool = pool.clone();
+            async move { pool.drained().await }
+        });
+        drop(first);
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert!(!draining.is_finished());
+
+        drop(second);
+        tokio::time::timeout(Duration::from_secs(1), draining).await.unwrap().unwrap();
+    }
+
+    #[test]
+    fn test_round_robin_rotates_first_choice() {
+        let pool = pool(3);
+        let firsts: Vec<usize> = (0..6).map(|_| pool.candidates()[0]).collect();
+        assert_eq!(firsts, vec![0, 1, 2, 0, 1, 2]);
+    }
+
+    #[test]
+    fn test_unhealthy_instances_are_tried_last() {
+        let pool = pool(3);
+        pool.mark_unhealthy(1);
+
+        for _ in 0..3 {
+            let candidates = pool.candidates();
+            assert_ne!(candidates[0], 1);
+            assert_eq!(candidates[2], 1);
+        }
+
+        pool.mark_healthy(1);
+        let firsts: Vec<usize> = (0..3).map(|_| pool.candidates()[0]).collect();
+        assert!(firsts.contains(&1));
+    }
+
+    #[test]
+    fn test_requests_are_shared_by_weight() {
+        let pool = weighted_pool(3, vec![5, 3, 1]);
+        let mut counts = [0; 3];
+        for _ in 0..9000 {
+            counts[pool.candidates()[0]] += 1;
+        }
+        assert_eq!(counts, [5000, 3000, 1000]);
+
+        // Interleaved rather than five in a row to the first instance
+        let firsts: Vec<usize> = (0..9).map(|_| pool.candidates()[0]).collect();
+        assert_eq!(firsts, vec![0, 1, 0, 2, 0, 1, 0, 1, 0]);
+    }
+
+    #[test]
+    fn test_unhealthy_instance_share_goes_to_others() {
+        let pool = weighted_pool(3, vec![5, 3, 1]);
+        pool.mark_unhealthy(0);
+
+        let mut counts = [0; 3];
+        for _ in 0..400 {
+            counts[pool.candidates()[0]] += 1;
+        }
+        assert_eq!(counts, [0, 300, 100]);
+    }
+}
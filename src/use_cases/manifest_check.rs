@@ -0,0 +1,174 @@
+//! Consistency checks across a whole manifest, beyond what each process's
+//! own configuration can catch
+
+use crate::domain::{CommunicationMode, Process};
+use std::collections::{HashMap, HashSet};
+
+/// Describe every clash between processes: ids, routes and pipe names that
+/// are declared twice, and HTTP ports that two backends would listen on
+pub fn find_conflicts(processes: &[Process]) -> Vec<String> {
+    let mut conflicts = Vec::new();
+
+    let ids = processes.iter().map(|p| (p.id.as_str().to_string(), p));
+    conflicts.extend(duplicates(ids, "id"));
+
+    // Processes can share a route when they serve different methods
+    let serving_any: HashSet<&str> = processes
+        .iter()
+        .filter(|p| p.methods.is_empty())
+        .map(|p| p.route.as_str())
+        .collect();
+    let routes = processes.iter().flat_map(|p| {
+        let route = p.route.as_str();
+        let keys = if p.methods.is_empty() || serving_any.contains(route) {
+            vec![route.to_string()]
+        } else {
+            p.methods.iter().map(|m| format!("{} {}", m.as_str(), route)).collect()
+        };
+        keys.into_iter().map(move |key| (key, p))
+    });
+    conflicts.extend(duplicates(routes, "route"));
+
+    // Static routes serve files, so their placeholder pipe names are never used
+    let pipe_names = processes
+        .iter()
+        .filter(|p| p.static_dir.is_none())
+        .flat_map(|p| p.instance_pipe_names().into_iter().map(move |name| (name, p)));
+    conflicts.extend(duplicates(pipe_names, "pipe name"));
+
+    // Ports are derived from pipe names, so distinct names can still collide,
+    // with each other or with a port set in the manifest
+    let http_addresses = processes.iter().flat_map(|p| {
+        let listens_on_http = p.communication_mode != CommunicationMode::Pipe || p.http_fallback;
+        let addresses = if listens_on_http {
+            p.instance_http_addresses()
+        } else {
+            Vec::new()
+        };
+        addresses.into_iter().map(move |address| (address, p))
+    });
+    conflicts.extend(duplicates(http_addresses, "HTTP address"));
+
+    conflicts
+}
+
+/// One message per value claimed by more than one process, naming them
+fn duplicates<'a>(values: impl Iterator<Item = (String, &'a Process)>, what: &str) -> Vec<String> {
+    let mut owners: Vec<(String, Vec<&str>)> = Vec::new();
+    let mut index: HashMap<String, usize> = HashMap::new();
+
+    for (value, process) in values {
+        match index.get(&value) {
+            Some(&i) => owners[i].1.push(process.id.as_str()),
+            None => {
+                index.insert(value.clone(), owners.len());
+                owners.push((value, vec![process.id.as_str()]));
+            }
+        }
+    }
+
+    owners
+        .into_iter()
+        .filter(|(_, ids)| ids.len() > 1)
+        .map(|(value, ids)| format!("Duplicate {} '{}' used by processes: {}", what, value, ids.join(", ")))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::{Executable, PipeName, ProcessId, Route};
+
+    fn process(id: &str, route: &str, pipe_name: &str) -> Process {
+        Process::new(
+            ProcessId::new(id).unwrap(),
+            Executable::new("./x").unwrap(),
+            Route::new(route).unwrap(),
+            PipeName::new(pipe_name).unwrap(),
+        )
+    }
+
+    #[test]
+    fn test_distinct_processes_have_no_conflicts() {
+        let processes = vec![process("a", "/a/*", "a_pipe"), process("b", "/b/*", "b_pipe")];
+        assert!(find_conflicts(&processes).is_empty());
+    }
+
+    #[test]
+    fn test_duplicate_route_is_reported() {
+        let processes = vec![process("a", "/api/*", "a_pipe"), process("b", "/api/*", "b_pipe")];
+        assert_eq!(
+            find_conflicts(&processes),
+            vec!["Duplicate route '/api/*' used by processes: a, b".to_string()]
+        );
+    }
+
+    #[test]
+    fn test_route_shared_by_method_is_only_a_conflict_when_methods_overlap() {
+        use crate::domain::HttpMethod;
+
+        let mut reads = process("reads", "/api/*", "reads_pipe");
+        reads.methods = vec![HttpMethod::Get, HttpMethod::Head];
+        let mut writes = process("writes", "/api/*", "writes_pipe");
+        writes.methods = vec![HttpMethod::Post];
+        assert!(find_conflicts(&[reads.clone(), writes.clone()]).is_empty());
+
+        writes.methods.push(HttpMethod::Get);
+        assert_eq!(
+            find_conflicts(&[reads.clone(), writes]),
+            vec!["Duplicate route 'GET /api/*' used by processes: reads, writes".to_string()]
+        );
+
+        let any = process("any", "/api/*", "any_pipe");
+        assert_eq!(
+            find_conflicts(&[reads, any]),
+            vec!["Duplicate route '/api/*' used by processes: reads, any".to_string()]
+        );
+    }
+
+    #[test]
+    fn test_duplicate_pipe_name_and_address_are_reported() {
+        let mut a = process("a", "/a/*", "shared");
+        a.communication_mode = CommunicationMode::Http;
+        let mut b = process("b", "/b/*", "shared");
+        b.communication_mode = CommunicationMode::Http;
+
+        let conflicts = find_conflicts(&[a, b]);
+        assert_eq!(conflicts.len(), 2);
+        assert!(conflicts[0].starts_with("Duplicate pipe name 'shared'"));
+        assert!(conflicts[1].starts_with("Duplicate HTTP address"));
+    }
+
+    #[test]
+    fn test_instance_names_are_checked() {
+        // "svc" with two instances uses "svc_1", which "other" also claims
+        let mut multi = process("multi", "/m/*", "svc");
+        multi.instances = 2;
+        let other = process("other", "/o/*", "svc_1");
+
+        let conflicts = find_conflicts(&[multi, other]);
+        assert_eq!(conflicts, vec!["Duplicate pipe name 'svc_1' used by processes: multi, other".to_string()]);
+    }
+    #[test]
+    fn test_fixed_port_collides_with_derived_port() {
+        let mut derived = process("derived", "/d/*", "derived_pipe");
+        derived.communication_mode = CommunicationMode::Http;
+        let port = crate::domain::utils::get_http_port_from_name("derived_pipe");
+        let mut fixed = process("fixed", "/f/*", "fixed_pipe");
+        fixed.http_fallback = true;
+        fixed.http_port = Some(port);
+
+        let conflicts = find_conflicts(&[derived, fixed]);
+        assert_eq!(
+            conflicts,
+            vec![format!("Duplicate HTTP address '127.0.0.1:{}' used by processes: derived, fixed", port)]
+        );
+    }
+
+    #[test]
+    fn test_static_route_placeholder_pipe_name_is_not_a_conflict() {
+        let site = Process::new_static(ProcessId::new("site").unwrap(), Route::new("/site/*").unwrap(), "./public");
+        let backend = process("backend", "/b/*", "site");
+        assert!(find_conflicts(&[site, backend]).is_empty());
+    }
+}
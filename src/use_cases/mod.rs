@@ -1,11 +1,34 @@
 //! Use Cases - Application-specific business rules
 //! Uses domain entities and repository interfaces
 
-use crate::domain::{HttpRequest, HttpResponse, Process, ProcessRepository,  
-                    ProcessOrchestrationService, PipeCommunicationService};
-use moka::future::Cache;
+mod body_log;
+mod cache_file;
+pub(crate) mod envelope;
+mod health;
+mod load_balancer;
+mod manifest_check;
+mod msgpack;
+mod payload_metrics;
+mod response_cache;
+
+pub use body_log::DEFAULT_DEBUG_BODY_LIMIT;
+pub use health::HealthRegistry;
+pub use payload_metrics::PayloadMetrics;
+pub use response_cache::CacheStats;
+use response_cache::ResponseCache;
+use response_cache::CachedResponse;
+use load_balancer::InstancePool;
+use crate::domain::{HttpMethod, HttpRequest, HttpResponse, Process, ProcessRepository,  
+                    ProcessOrchestrationService, PipeCommunicationService, CommunicationError,
+                    CommunicationMode, ConcurrencyLimit, OverflowPolicy, HealthCheck, HealthState, SerializationFormat,
+                    OrchestrationError, ProcessId, ProcessState, ProcessStatus, SendOptions, StreamingRequest};
+use bytes::Bytes;
+use futures_util::TryStreamExt;
+use std::collections::HashMap;
+use std::path::PathBuf;
 use std::sync::Arc;
-use tokio::sync::RwLock;
+use std::time::{Duration, Instant, SystemTime};
+use tokio::sync::{Mutex, RwLock, Semaphore, SemaphorePermit};
 
 /// Use case for initializing the system
 pub struct InitializeSystemUseCase<R: ProcessRepository> {
@@ -46,188 +69,3156 @@ impl<O: ProcessOrchestrationService> StartAllProcessesUseCase<O> {
     }
 }
 
-/// Use case for stopping all processes
-pub struct StopAllProcessesUseCase<O: ProcessOrchestrationService> {
+/// Use case for checking all processes can be started before serving traffic
+pub struct ValidateProcessesUseCase<O: ProcessOrchestrationService> {
     orchestrator: Arc<RwLock<O>>,
 }
 
-impl<O: ProcessOrchestrationService> StopAllProcessesUseCase<O> {
+impl<O: ProcessOrchestrationService> ValidateProcessesUseCase<O> {
     pub fn new(orchestrator: Arc<RwLock<O>>) -> Self {
         Self { orchestrator }
     }
 
     pub async fn execute(&self) -> Result<(), UseCaseError> {
         self.orchestrator
-            .write()
-            .await
-            .stop_all()
+            .read()
             .await
+            .validate_all()
             .map_err(|e| UseCaseError::OrchestrationError(e.to_string()))
     }
 }
 
+/// Use case for validating a manifest without starting anything
+pub struct CheckManifestUseCase<O: ProcessOrchestrationService> {
+    orchestrator: Arc<RwLock<O>>,
+}
+
+impl<O: ProcessOrchestrationService> CheckManifestUseCase<O> {
+    pub fn new(orchestrator: Arc<RwLock<O>>) -> Self {
+        Self { orchestrator }
+    }
+
+    /// Every problem found with the registered processes; empty if the
+    /// manifest is ready to run
+    pub async fn execute(&self, processes: &[Process]) -> Vec<String> {
+        let mut problems = self.conflicts(processes);
+        if let Err(e) = self.orchestrator.read().await.validate_all() {
+            problems.push(e.to_string());
+        }
+        problems
+    }
+
+    /// The problems between the processes themselves, leaving their
+    /// executables unchecked
+    pub fn conflicts(&self, processes: &[Process]) -> Vec<String> {
+        manifest_check::find_conflicts(processes)
+    }
+}
+
+/// Use case for stopping all processes
+pub struct StopAllProcessesUseCase<O: ProcessOrchestrationService> {
+    orchestrator: Arc<RwLock<O>>,
+}
+
+impl<O: ProcessOrchestrationService> StopAllProcessesUseCase<O> {
+    pub fn new(orchestrator: Arc<RwLock<O>>) -> Self {
+        Self { orchestrator }
+    }
+
+    /// Stop every process, giving them `timeout` to exit before killing
+    /// them; returns the ones that had to be killed
+    pub async fn execute_within(&self, timeout: Duration) -> Vec<ProcessId> {
+        self.orchestrator.write().await.shutdown(timeout).await
+    }
+}
+
+/// Options controlling how requests are proxied
+#[derive(Debug, Clone, Default)]
+pub struct ProxyOptions {
+    /// Maximum number of cached responses; `None` disables caching
+    pub cache_size: Option<u64>,
+    /// Accept minimal or malformed response envelopes, defaulting a missing
+    /// status to 200 and an undecodable body to empty
+    pub lenient_responses: bool,
+    /// Match routes ignoring case and trailing slashes; see [`crate::domain::Route::matches_normalized`]
+    pub normalize_routes: bool,
+    /// Where the cache is saved on shutdown and restored from on startup
+    pub cache_file: Option<PathBuf>,
+    /// Refuse requests for a process that is still starting, telling clients
+    /// to retry after this long, rather than sending them to a backend that
+    /// isn't listening yet; requires an orchestrator
+    pub starting_retry_after: Option<Duration>,
+    /// Log request and response bodies for every process, not just those
+    /// with `debug_body` set
+    pub debug_bodies: bool,
+    /// Longest body prefix logged; `None` uses [`DEFAULT_DEBUG_BODY_LIMIT`]
+    pub debug_body_limit: Option<usize>,
+    /// How long a starting process has to become ready; `None` uses [`READY_TIMEOUT`]
+    pub ready_timeout: Option<Duration>,
+    /// Pause between readiness probes; `None` uses [`READY_POLL_INTERVAL`]
+    pub ready_poll_interval: Option<Duration>,
+    /// Largest response read from a backend, for processes without their
+    /// own limit; `None` uses [`MAX_RESPONSE_BYTES`]
+    pub max_response_bytes: Option<usize>,
+}
+
+/// Backend endpoint for a request that bypasses the envelope protocol: one
+/// that upgrades to a bidirectional stream, or a gRPC call
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UpgradeTarget {
+    /// Id of the process serving the route
+    pub process: String,
+    /// `host:port` of the chosen instance
+    pub address: String,
+}
+
+/// A file request on a route served from a directory
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StaticMount {
+    /// Id of the process the route belongs to
+    pub process: String,
+    /// Directory the files are served from
+    pub dir: String,
+    /// The request path below the route, as a path into `dir`
+    pub path: String,
+    /// Headers set on every response from the route
+    pub response_headers: Vec<(String, String)>,
+}
+
+/// Where the time went while proxying one request
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RequestTimings {
+    /// The response was served from the cache without contacting a backend
+    pub cache_hit: bool,
+    pub serialize: Option<Duration>,
+    /// Round trip to the backend, excluding any wait for a concurrency slot
+    pub backend: Option<Duration>,
+    pub deserialize: Option<Duration>,
+}
+
+/// A proxied response together with its timing breakdown
+#[derive(Debug, Clone)]
+pub struct TimedResponse {
+    pub response: HttpResponse,
+    pub timings: RequestTimings,
+    /// Id of the process the request was routed to; for a cache hit, the
+    /// one its route currently matches
+    pub process: Option<String>,
+    /// Length of the request body; `None` when it was streamed to the backend
+    pub request_bytes: Option<u64>,
+}
+
+/// How long a process without a health check is given to start up before
+/// it is sent traffic
+pub const STARTUP_GRACE: Duration = Duration::from_secs(2);
+
+/// How long a started or reloaded process has to become ready before it is
+/// given up on, unless configured otherwise
+pub const READY_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Pause between readiness probes of a starting process, unless configured
+/// otherwise
+pub const READY_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Largest response body read from a backend, unless configured otherwise;
+/// a bigger one fails the request instead of filling the proxy's memory
+pub const MAX_RESPONSE_BYTES: usize = 256 * 1024 * 1024;
+
+/// Longest the proxy takes to shut down, unless configured otherwise
+pub const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Longest a reload waits for requests to the replaced instances to finish
+const DRAIN_TIMEOUT: Duration = Duration::from_secs(30);
+
 /// Use case for proxying HTTP requests to processes
 pub struct ProxyHttpRequestUseCase<P: PipeCommunicationService> {
     pipe_service: Arc<P>,
+    /// Transport for HTTP-mode processes; `pipe_service` is used when unset
+    http_service: Option<Arc<dyn PipeCommunicationService>>,
     processes: Arc<Vec<Process>>,
-    cache: Option<Cache<String, HttpResponse>>,
+    cache: Option<Arc<ResponseCache>>,
+    options: ProxyOptions,
+    /// Per-process request slots, keyed by process id, for processes with a concurrency limit
+    limiters: HashMap<String, (Semaphore, OverflowPolicy)>,
+    /// Instance addresses and their health, keyed by process id; replaced
+    /// as a whole when a process is reloaded
+    pools: HashMap<String, std::sync::RwLock<Arc<InstancePool>>>,
+    /// Result of each process's health checks
+    health: Arc<HealthRegistry>,
+    /// Body sizes of every exchange answered, per process
+    payload_metrics: PayloadMetrics,
+    /// Restarts processes stopped for being idle; idle timeouts have no effect without it
+    orchestrator: Option<Arc<RwLock<dyn ProcessOrchestrationService>>>,
+    /// Held while waking or reloading a process so that only one of them
+    /// starts it at a time, keyed by process id
+    lifecycle_locks: HashMap<String, Mutex<()>>,
 }
 
 impl<P: PipeCommunicationService> ProxyHttpRequestUseCase<P> {
+    #[allow(dead_code)]
     pub fn new(pipe_service: Arc<P>, processes: Arc<Vec<Process>>) -> Self {
         Self::new_with_cache(pipe_service, processes, None)
     }
 
+    #[allow(dead_code)]
     pub fn new_with_cache(
         pipe_service: Arc<P>,
         processes: Arc<Vec<Process>>,
         cache_size: Option<u64>,
     ) -> Self {
-        let cache = cache_size.map(|size| {
-            Cache::builder()
-                .max_capacity(size)
-                .build()
-        });
+        Self::with_options(
+            pipe_service,
+            processes,
+            ProxyOptions {
+                cache_size,
+                ..ProxyOptions::default()
+            },
+        )
+    }
+
+    pub fn with_options(
+        pipe_service: Arc<P>,
+        processes: Arc<Vec<Process>>,
+        options: ProxyOptions,
+    ) -> Self {
+        let cache = options.cache_size.map(|size| Arc::new(ResponseCache::new(size)));
+
+        let limiters = processes
+            .iter()
+            .filter_map(|p| {
+                p.concurrency_limit.as_ref().map(|ConcurrencyLimit { max_concurrent, overflow }| {
+                    (p.id.as_str().to_string(), (Semaphore::new(*max_concurrent), overflow.clone()))
+                })
+            })
+            .collect();
+
+        let pools = processes
+            .iter()
+            .map(|p| (p.id.as_str().to_string(), std::sync::RwLock::new(Arc::new(InstancePool::new(p)))))
+            .collect();
+
+        let health = Arc::new(HealthRegistry::new(&processes));
+
+        let lifecycle_locks = processes
+            .iter()
+            .map(|p| (p.id.as_str().to_string(), Mutex::new(())))
+            .collect();
         
         Self {
             pipe_service,
+            http_service: None,
             processes,
             cache,
+            options,
+            limiters,
+            pools,
+            health,
+            payload_metrics: PayloadMetrics::default(),
+            orchestrator: None,
+            lifecycle_locks,
+        }
+    }
+
+    /// Use a separate transport for HTTP-mode processes and HTTP fallback
+    pub fn with_http_service(mut self, http_service: Arc<dyn PipeCommunicationService>) -> Self {
+        self.http_service = Some(http_service);
+        self
+    }
+
+    /// Track request activity through `orchestrator`, restarting processes
+    /// it stopped for being idle when a request arrives for them
+    pub fn with_orchestrator(mut self, orchestrator: Arc<RwLock<dyn ProcessOrchestrationService>>) -> Self {
+        self.orchestrator = Some(orchestrator);
+        self
+    }
+
+    fn transport(&self, mode: &CommunicationMode) -> &dyn PipeCommunicationService {
+        match (mode, &self.http_service) {
+            (CommunicationMode::Http | CommunicationMode::Grpc, Some(http_service)) => http_service.as_ref(),
+            _ => self.pipe_service.as_ref(),
         }
     }
 
     /// Execute the use case: route request to appropriate process
     /// Cache (if enabled) applies to both HTTP and named pipe communication modes
+    #[allow(dead_code)]
     pub async fn execute(&self, request: HttpRequest) -> Result<HttpResponse, UseCaseError> {
-        // Check cache if enabled (applies to both HTTP and pipe modes)
-        if let Some(cache) = &self.cache {
-            let cache_key = self.generate_cache_key(&request);
-            if let Some(cached_response) = cache.get(&cache_key).await {
+        self.execute_timed(request).await.map(|timed| timed.response)
+    }
+
+    /// Same as [`execute`](Self::execute), also reporting how long each
+    /// stage of the exchange took
+    pub async fn execute_timed(&self, request: HttpRequest) -> Result<TimedResponse, UseCaseError> {
+        let request_bytes = request.body.len() as u64;
+        let mut timed = self.respond(request).await?;
+        timed.request_bytes = Some(request_bytes);
+        self.record_sizes(&timed);
+        Ok(timed)
+    }
+
+    /// Answer a buffered request from the cache, or else from its process
+    async fn respond(&self, request: HttpRequest) -> Result<TimedResponse, UseCaseError> {
+        let Some(cache) = &self.cache else {
+            return self.forward(&request).await.map(|(_, timed)| timed);
+        };
+
+        // Concurrent misses for the same key are coalesced: only the first
+        // caller reaches the backend and the rest wait for its result. A
+        // failure is returned to every waiter and nothing is cached.
+        let cache_key = self.generate_cache_key(&request);
+        let mut fetched = None;
+        let entry = cache
+            .entries()
+            .entry(cache_key)
+            .or_try_insert_with(async {
+                tracing::debug!("Cache miss for {}", request.path);
+                cache.record_miss();
+                let (process, timed) = self.forward(&request).await?;
+                fetched = Some((timed.timings, timed.process));
+                let ttl = cache_ttl(process, &timed.response);
+                Ok::<_, UseCaseError>(CachedResponse::new(timed.response, ttl))
+            })
+            .await
+            .map_err(Arc::unwrap_or_clone)?;
+
+        let (timings, process) = match fetched {
+            Some(fetched) => {
+                tracing::debug!("Cached response for {}", request.path);
+                fetched
+            }
+            None => {
                 tracing::debug!("Cache hit for {} (no process communication needed)", request.path);
-                return Ok(cached_response);
+                cache.record_hit();
+                let timings = RequestTimings {
+                    cache_hit: true,
+                    ..RequestTimings::default()
+                };
+                let process = self
+                    .find_dispatched_process(&request.method, &request.path)
+                    .map(|p| p.id.as_str().to_string());
+                (timings, process)
+            }
+        };
+
+        Ok(TimedResponse {
+            response: entry.into_value().response,
+            timings,
+            process,
+            request_bytes: None,
+        })
+    }
+
+    /// How the cache has been used since startup; `None` when caching is disabled
+    pub async fn cache_stats(&self) -> Option<CacheStats> {
+        Some(self.cache.as_ref()?.stats().await)
+    }
+
+    /// Drop every cached response, returning how many there were; `None`
+    /// when caching is disabled
+    pub async fn clear_cache(&self) -> Option<u64> {
+        let cleared = self.cache.as_ref()?.clear().await;
+        tracing::info!("Cleared {} cached response(s)", cleared);
+        Some(cleared)
+    }
+
+    /// Load the responses saved by [`save_cache`](Self::save_cache), skipping
+    /// those that have expired since, and return how many were restored
+    ///
+    /// A corrupt or incompatible file is ignored with a warning.
+    pub async fn restore_cache(&self) -> usize {
+        let (Some(cache), Some(path)) = (&self.cache, &self.options.cache_file) else {
+            return 0;
+        };
+
+        let entries = match cache_file::load(path) {
+            Ok(entries) => entries,
+            Err(e) => {
+                tracing::warn!("Ignoring cache file {}: {}", path.display(), e);
+                return 0;
+            }
+        };
+
+        let mut restored = 0;
+        for entry in entries {
+            let cached = CachedResponse::new(entry.response, entry.ttl);
+            if cached.remaining_ttl().is_some() {
+                cache.entries().insert(entry.key, cached).await;
+                restored += 1;
             }
-            tracing::debug!("Cache miss for {}", request.path);
         }
+        tracing::info!("Restored {} cached response(s) from {}", restored, path.display());
+        restored
+    }
 
-        use crate::domain::entities::CommunicationMode;
-        use crate::domain::utils::{get_pipe_address_from_name, get_http_address_from_name};
-        
-        // Find matching process
+    /// Write the cache's unexpired entries to the configured cache file,
+    /// with their remaining TTLs, and return how many were saved
+    pub fn save_cache(&self) -> Result<usize, UseCaseError> {
+        let (Some(cache), Some(path)) = (&self.cache, &self.options.cache_file) else {
+            return Ok(0);
+        };
+
+        let entries: Vec<cache_file::SavedEntry> = cache
+            .entries()
+            .iter()
+            .filter_map(|(key, cached)| {
+                Some(cache_file::SavedEntry {
+                    key: key.as_ref().clone(),
+                    ttl: cached.remaining_ttl()?,
+                    response: cached.response,
+                })
+            })
+            .collect();
+
+        let saved = entries.len();
+        cache_file::save(path, entries)
+            .map_err(|e| UseCaseError::RepositoryError(format!("failed to save cache to {}: {}", path.display(), e)))?;
+        tracing::info!("Saved {} cached response(s) to {}", saved, path.display());
+        Ok(saved)
+    }
+
+    /// Send a request to the process serving its route, bypassing the cache,
+    /// and return the process that answered along with its response
+    async fn forward(&self, request: &HttpRequest) -> Result<(&Process, TimedResponse), UseCaseError> {
+        let mut timings = RequestTimings::default();
+
+        let process = self.route(&request.method, &request.path).await?;
+        let woken = self.wake(process).await?;
+
+        // Raw processes are sent plain HTTP, a buffered body being one chunk
+        if process.protocol == SerializationFormat::Raw {
+            let timed = self.exchange_streaming(process, request.clone().into(), woken).await?;
+            return Ok((process, timed));
+        }
+
+        // Processes without their own HEAD handling are sent a GET instead
+        let head_from_get = request.method == HttpMethod::Head && process.head_from_get;
+
+        // Hold a request slot for the duration of the exchange
+        let _permit = self.acquire_slot(process).await?;
+
+        // Serialize request, its deadline counted from now, as is the timeout
+        let started = Instant::now();
+        let request_data = if head_from_get || process.timeout.is_some() || process.rewrites_path() {
+            let mut outgoing = request.clone();
+            if head_from_get {
+                outgoing.method = HttpMethod::Get;
+            }
+            if process.rewrites_path() {
+                outgoing.path = process.rewrite_path(&request.path);
+            }
+            if let Some(timeout) = process.timeout {
+                set_deadline(&mut outgoing.headers, timeout);
+            }
+            self.serialize_request(&outgoing, process.protocol)?
+        } else {
+            self.serialize_request(request, process.protocol)?
+        };
+        timings.serialize = Some(started.elapsed());
+        self.log_body(process, request, "request", &request.body);
+        let started = Instant::now();
+
+        // Send request through the communication channel. Nothing here is
+        // spawned: if the client goes away this future is dropped and the
+        // backend connection with it
+        let response_data = self
+            .bounded(process, self.send_with_retries(process, &request.method, request_data, woken))
+            .await?;
+        timings.backend = Some(started.elapsed());
+        self.record_activity(process).await;
+        if woken {
+            self.mark_ready(process).await;
+        }
+
+        // Deserialize response
+        let started = Instant::now();
+        let mut response = self.deserialize_response(response_data, process.protocol)?;
+        if head_from_get {
+            response = response.into_head_response();
+        }
+        timings.deserialize = Some(started.elapsed());
+        self.log_body(process, request, "response", &response.body);
+        response.set_headers(&process.response_headers);
+
+        let timed = TimedResponse {
+            response,
+            timings,
+            process: Some(process.id.as_str().to_string()),
+            request_bytes: None,
+        };
+        Ok((process, timed))
+    }
+
+    /// Proxy a request to a raw-protocol process, streaming its body to the
+    /// backend instead of buffering it; the cache is bypassed
+    pub async fn execute_streaming(&self, request: StreamingRequest) -> Result<TimedResponse, UseCaseError> {
+        let process = self.route(&request.method, &request.path).await?;
+        if process.protocol != SerializationFormat::Raw {
+            // The route moved to a process that needs the whole body up front
+            let body: Vec<Bytes> = request.body.try_collect().await.map_err(|e| {
+                UseCaseError::CommunicationError {
+                    process: process.id.as_str().to_string(),
+                    source: CommunicationError::ReceiveFailed(e.to_string()),
+                }
+            })?;
+            let request = HttpRequest {
+                method: request.method,
+                path: request.path,
+                headers: request.headers,
+                body: body.concat(),
+            };
+            return self.execute_timed(request).await;
+        }
+
+        let woken = self.wake(process).await?;
+        let timed = self.exchange_streaming(process, request, woken).await?;
+        self.record_sizes(&timed);
+        Ok(timed)
+    }
+
+    /// Add an answered exchange's body sizes to its process's metrics
+    fn record_sizes(&self, timed: &TimedResponse) {
+        if let Some(process) = &timed.process {
+            self.payload_metrics
+                .record(process, timed.request_bytes, timed.response.body.len() as u64);
+        }
+    }
+
+    /// Request and response body sizes of every exchange answered so far
+    pub fn payload_metrics(&self) -> &PayloadMetrics {
+        &self.payload_metrics
+    }
+
+    /// Whether requests for `path` go to a raw-protocol process, and so should
+    /// be handed over with [`execute_streaming`](Self::execute_streaming)
+    ///
+    /// Only the path is considered; should the method pick a process of
+    /// another protocol, [`execute_streaming`](Self::execute_streaming)
+    /// buffers the body after all.
+    pub fn streams_requests(&self, path: &str) -> bool {
+        self.matching_processes(path)
+            .into_iter()
+            .find(|p| self.health.is_healthy(p.id.as_str()))
+            .is_some_and(|p| p.protocol == SerializationFormat::Raw)
+    }
+
+    /// Find the process to send a `method` request for `path` to, refusing
+    /// it while it is starting if configured to
+    async fn route(&self, method: &HttpMethod, path: &str) -> Result<&Process, UseCaseError> {
+        // Find matching process that is fit to take traffic
+        let process = match self.find_routable_process(method, path) {
+            Err(UseCaseError::ProcessUnavailable(id)) => {
+                if let Some(process) = self.processes.iter().find(|p| p.id.as_str() == id) {
+                    self.refuse_if_starting(process).await?;
+                }
+                return Err(UseCaseError::ProcessUnavailable(id));
+            }
+            result => result?,
+        };
+        self.refuse_if_starting(process).await?;
+        Ok(process)
+    }
+
+    /// Bound an exchange with a process by its timeout, so pipe and HTTP
+    /// backends behave the same
+    async fn bounded<T>(
+        &self,
+        process: &Process,
+        send: impl std::future::Future<Output = Result<T, CommunicationError>>,
+    ) -> Result<T, UseCaseError> {
+        match process.timeout {
+            Some(limit) => tokio::time::timeout(limit, send).await.unwrap_or_else(|_| {
+                Err(CommunicationError::Timeout(format!("no response within {:?}", limit)))
+            }),
+            None => send.await,
+        }
+        .map_err(|source| UseCaseError::CommunicationError {
+            process: process.id.as_str().to_string(),
+            source,
+        })
+    }
+
+    /// Stream a request to a raw-protocol process, holding a request slot
+    /// like any other exchange
+    async fn exchange_streaming(
+        &self,
+        process: &Process,
+        mut request: StreamingRequest,
+        woken: bool,
+    ) -> Result<TimedResponse, UseCaseError> {
+        let _permit = self.acquire_slot(process).await?;
+        if let Some(timeout) = process.timeout {
+            set_deadline(&mut request.headers, timeout);
+        }
+        if process.rewrites_path() {
+            request.path = process.rewrite_path(&request.path);
+        }
+        let started = Instant::now();
+
+        // Held for the whole exchange so a reload waits for it to finish
+        let pool = self.pool(process).enter();
+        let mut response = self
+            .bounded(process, self.send_streaming_when_ready(process, &pool, request, woken))
+            .await?;
+        // The transport has already dropped the backend's hop-by-hop headers
+        response.set_headers(&process.response_headers);
+        let timings = RequestTimings {
+            backend: Some(started.elapsed()),
+            ..RequestTimings::default()
+        };
+        self.record_activity(process).await;
+        if woken {
+            self.mark_ready(process).await;
+        }
+
+        Ok(TimedResponse {
+            response,
+            timings,
+            process: Some(process.id.as_str().to_string()),
+            request_bytes: None,
+        })
+    }
+
+    /// Log a decoded body at trace level if body logging is enabled for the
+    /// process; off by default since bodies can carry credentials
+    fn log_body(&self, process: &Process, request: &HttpRequest, kind: &str, body: &[u8]) {
+        if !(self.options.debug_bodies || process.debug_body) {
+            return;
+        }
+        let limit = self.options.debug_body_limit.unwrap_or(DEFAULT_DEBUG_BODY_LIMIT);
+        tracing::trace!(
+            "{} body for {} {} ('{}'): {}",
+            kind,
+            request.method.as_str(),
+            request.path,
+            process.id.as_str(),
+            body_log::render(body, limit)
+        );
+    }
+
+    /// Start a process that was stopped for being idle and wait for its
+    /// health check to pass, returning whether it had to be started
+    ///
+    /// Also records the request as activity, so a running process isn't
+    /// stopped while it is being used.
+    async fn wake(&self, process: &Process) -> Result<bool, UseCaseError> {
+        let (Some(orchestrator), Some(_)) = (&self.orchestrator, process.idle_timeout) else {
+            return Ok(false);
+        };
+        let _waking = self.lifecycle_locks[process.id.as_str()].lock().await;
+
+        {
+            let mut orchestrator = orchestrator.write().await;
+            orchestrator.record_activity(&process.id);
+            if orchestrator.is_running(&process.id) {
+                return Ok(false);
+            }
+            tracing::info!("Waking idle process '{}'", process.id.as_str());
+            orchestrator
+                .start_process(&process.id)
+                .await
+                .map_err(|e| UseCaseError::OrchestrationError(e.to_string()))?;
+        }
+
+        if let Some(check) = &process.health_check {
+            self.health.set(process.id.as_str(), HealthState::Starting);
+            if !self.wait_until_healthy(process, check, &self.pool(process)).await {
+                self.health.set(process.id.as_str(), HealthState::Unhealthy);
+                return Err(UseCaseError::ProcessUnavailable(process.id.as_str().to_string()));
+            }
+            self.health.set(process.id.as_str(), HealthState::Healthy);
+            self.mark_ready(process).await;
+        }
+
+        Ok(true)
+    }
+
+    /// Restart a process without dropping requests
+    ///
+    /// A replacement is started on fresh addresses next to the running
+    /// instances. Once it passes its health check (or, without one, accepts
+    /// connections) new requests are routed to it, and the old
+    /// instances are stopped when the requests already sent to them have
+    /// completed. If the replacement never becomes ready it is stopped and
+    /// the old instances keep serving.
+    pub async fn reload(&self, id: &str) -> Result<(), UseCaseError> {
         let process = self
-            .find_matching_process(&request.path)
-            .ok_or_else(|| UseCaseError::NoRouteFound(request.path.clone()))?;
+            .processes
+            .iter()
+            .find(|p| p.id.as_str() == id)
+            .ok_or_else(|| UseCaseError::ProcessNotFound(id.to_string()))?;
+        let orchestrator = self
+            .orchestrator
+            .as_ref()
+            .ok_or_else(|| UseCaseError::OrchestrationError("processes are not managed by this proxy".to_string()))?;
+        let orchestration_error = |e: OrchestrationError| UseCaseError::OrchestrationError(e.to_string());
+        let _reloading = self.lifecycle_locks[id].lock().await;
 
-        // Serialize request
-        let request_data = self.serialize_request(&request)?;
+        let generation = orchestrator
+            .write()
+            .await
+            .start_replacement(&process.id)
+            .await
+            .map_err(orchestration_error)?;
+        let replacement = process.for_generation(generation);
+        let pool = Arc::new(InstancePool::new(&replacement));
 
-        // Get address based on communication mode
-        let address = match process.communication_mode {
-            CommunicationMode::Pipe => get_pipe_address_from_name(process.pipe_name.as_str()),
-            CommunicationMode::Http => get_http_address_from_name(process.pipe_name.as_str()),
+        let ready = match &process.health_check {
+            Some(check) => self.wait_until_healthy(&replacement, check, &pool).await,
+            None => match self.wait_until_reachable(&replacement, &pool).await {
+                Ok(()) => true,
+                Err(e) => {
+                    tracing::warn!("{}", e);
+                    false
+                }
+            },
         };
+        let ready = ready && (self.warm_up(&replacement, &pool).await || !process.warmup_strict);
+        if !ready {
+            tracing::warn!("Replacement for process '{}' did not become healthy", id);
+            orchestrator
+                .write()
+                .await
+                .abandon_replacement(&process.id)
+                .await
+                .map_err(orchestration_error)?;
+            return Err(UseCaseError::ProcessUnavailable(id.to_string()));
+        }
 
-        tracing::debug!("Routing request to {} via {:?}: {}", 
-            process.id.as_str(), process.communication_mode, address);
+        let previous = std::mem::replace(&mut *self.pools[id].write().unwrap(), pool);
+        tracing::info!("Routing process '{}' to generation {}", id, generation);
+
+        if tokio::time::timeout(DRAIN_TIMEOUT, previous.drained()).await.is_err() {
+            tracing::warn!("Requests to the replaced instances of '{}' still running after {:?}", id, DRAIN_TIMEOUT);
+        }
+        orchestrator
+            .write()
+            .await
+            .stop_replaced(&process.id)
+            .await
+            .map_err(orchestration_error)?;
+
+        tracing::info!("Process '{}' reloaded", id);
+        Ok(())
+    }
+
+    /// Probe a starting process's instances until its health check passes,
+    /// giving up after the ready timeout
+    async fn wait_until_healthy(&self, process: &Process, check: &HealthCheck, pool: &InstancePool) -> bool {
+        let deadline = Instant::now() + self.ready_timeout();
+        loop {
+            if self.probe(process, check, pool).await {
+                return true;
+            }
+            if Instant::now() >= deadline {
+                return false;
+            }
+            tokio::time::sleep(self.ready_poll_interval()).await;
+        }
+    }
+
+    /// Wait until every instance of each process without a health check
+    /// accepts connections, marking those processes ready
+    ///
+    /// For pipe-mode processes that means the socket file exists and can be
+    /// connected to; for HTTP-mode ones that the port accepts connections.
+    /// Processes with a health check become ready when it first passes, and
+    /// static routes have nothing to wait for. Each process is sent its
+    /// warm-up requests before it's marked ready.
+    ///
+    /// The error is a failed strict warm-up if there was one, since that
+    /// should stop the proxy starting; otherwise it names the first socket
+    /// or address that never became reachable.
+    pub async fn wait_until_ready(&self) -> Result<(), UseCaseError> {
+        let waits = self
+            .processes
+            .iter()
+            .filter(|p| p.health_check.is_none() && p.static_dir.is_none())
+            .map(|process| async move {
+                let pool = self.pool(process);
+                self.wait_until_reachable(process, &pool).await?;
+                if !self.warm_up(process, &pool).await && process.warmup_strict {
+                    return Err(UseCaseError::WarmupFailed(process.id.as_str().to_string()));
+                }
+                self.mark_ready(process).await;
+                Ok(())
+            });
+        let errors: Vec<UseCaseError> = futures_util::future::join_all(waits)
+            .await
+            .into_iter()
+            .filter_map(Result::err)
+            .collect();
+        match errors.iter().position(|e| matches!(e, UseCaseError::WarmupFailed(_))) {
+            Some(index) => Err(errors.into_iter().nth(index).unwrap()),
+            None => errors.into_iter().next().map_or(Ok(()), Err),
+        }
+    }
+
+    /// Send a process's warm-up requests to each instance in `pool`,
+    /// returning whether every one was answered without a server error
+    ///
+    /// Failures are only logged here; the caller decides whether they keep
+    /// the process from taking traffic.
+    async fn warm_up(&self, process: &Process, pool: &InstancePool) -> bool {
+        let mut warmed = true;
+        for index in 0..pool.len() {
+            for warmup in &process.warmup {
+                let request = HttpRequest {
+                    method: warmup.method.clone(),
+                    path: warmup.path.clone(),
+                    headers: vec![],
+                    body: vec![],
+                };
+                let address = pool.address(index);
+                let exchange = self.exchange_with_instance(process, pool, index, request);
+                match tokio::time::timeout(self.ready_timeout(), exchange).await {
+                    Ok(Ok(response)) if response.status_code < 500 => {
+                        tracing::debug!(
+                            "Warmed up '{}' at {} with {} {}",
+                            process.id.as_str(), address, warmup.method.as_str(), warmup.path
+                        );
+                    }
+                    Ok(Ok(response)) => {
+                        tracing::warn!(
+                            "Warm-up {} {} for '{}' at {} answered {}",
+                            warmup.method.as_str(), warmup.path, process.id.as_str(), address, response.status_code
+                        );
+                        warmed = false;
+                    }
+                    Ok(Err(e)) => {
+                        tracing::warn!(
+                            "Warm-up {} {} for '{}' at {} failed: {}",
+                            warmup.method.as_str(), warmup.path, process.id.as_str(), address, e
+                        );
+                        warmed = false;
+                    }
+                    Err(_) => {
+                        tracing::warn!(
+                            "Warm-up {} {} for '{}' at {} got no answer within {:?}",
+                            warmup.method.as_str(), warmup.path, process.id.as_str(), address, self.ready_timeout()
+                        );
+                        warmed = false;
+                    }
+                }
+            }
+        }
+        warmed
+    }
 
-        // Send request through the communication channel
+    /// Send a request to instance `index` of `pool`, bypassing load
+    /// balancing, and decode its response
+    async fn exchange_with_instance(
+        &self,
+        process: &Process,
+        pool: &InstancePool,
+        index: usize,
+        request: HttpRequest,
+    ) -> Result<HttpResponse, UseCaseError> {
+        let communication_error = |source| UseCaseError::CommunicationError {
+            process: process.id.as_str().to_string(),
+            source,
+        };
+        if process.protocol == SerializationFormat::Raw {
+            return self
+                .transport(&process.communication_mode)
+                .send_streaming_with(pool.address(index), request.into(), &self.send_options(process))
+                .await
+                .map_err(communication_error);
+        }
+        let request_data = self.serialize_request(&request, process.protocol)?;
         let response_data = self
-            .pipe_service
-            .send_request(&address, request_data)
+            .send_to(process, pool, index, request_data)
             .await
-            .map_err(|e| UseCaseError::CommunicationError(e.to_string()))?;
+            .map_err(communication_error)?;
+        self.deserialize_response(response_data, process.protocol)
+    }
 
-        // Deserialize response
-        let response = self.deserialize_response(response_data)?;
+    /// Probe each of a starting process's instances until it accepts
+    /// connections, giving up after the ready timeout
+    async fn wait_until_reachable(&self, process: &Process, pool: &InstancePool) -> Result<(), UseCaseError> {
+        let timeout = self.ready_timeout();
+        let deadline = Instant::now() + timeout;
+        let transport = self.transport(&process.communication_mode);
 
-        // Store in cache if enabled
-        if let Some(cache) = &self.cache {
-            let cache_key = self.generate_cache_key(&request);
-            cache.insert(cache_key, response.clone()).await;
-            tracing::debug!("Cached response for {}", request.path);
+        for address in pool.addresses() {
+            while let Err(e) = transport.probe(address).await {
+                if Instant::now() >= deadline {
+                    let what = match process.communication_mode {
+                        CommunicationMode::Pipe => "socket",
+                        CommunicationMode::Http | CommunicationMode::Grpc => "address",
+                    };
+                    return Err(UseCaseError::CommunicationError {
+                        process: process.id.as_str().to_string(),
+                        source: CommunicationError::Timeout(format!(
+                            "{} {} not ready after {:?} ({})",
+                            what, address, timeout, e
+                        )),
+                    });
+                }
+                tokio::time::sleep(self.ready_poll_interval()).await;
+            }
         }
+        Ok(())
+    }
 
-        Ok(response)
+    /// How exchanges with `process` are carried out: the largest response
+    /// it may send before the request fails, the `Host` it's sent and the
+    /// envelope format it speaks
+    fn send_options(&self, process: &Process) -> SendOptions {
+        SendOptions {
+            max_response_bytes: process
+                .max_response_bytes
+                .or(self.options.max_response_bytes)
+                .unwrap_or(MAX_RESPONSE_BYTES),
+            host: process.host_header.clone(),
+            protocol: process.protocol,
+        }
     }
 
-    fn generate_cache_key(&self, request: &HttpRequest) -> String {
-        format!("{}:{}", request.method.as_str(), request.path)
+    fn ready_timeout(&self) -> Duration {
+        self.options.ready_timeout.unwrap_or(READY_TIMEOUT)
     }
 
-    fn find_matching_process(&self, path: &str) -> Option<&Process> {
-        self.processes
-            .iter()
-            .find(|p| p.route.matches(path))
+    fn ready_poll_interval(&self) -> Duration {
+        self.options.ready_poll_interval.unwrap_or(READY_POLL_INTERVAL)
     }
 
-    fn serialize_request(&self, request: &HttpRequest) -> Result<Vec<u8>, UseCaseError> {
-        use base64::{Engine as _, engine::general_purpose};
-        
-        let json = serde_json::json!({
-            "method": request.method.as_str(),
-            "uri": request.path,
-            "headers": request.headers,
-            "body": general_purpose::STANDARD.encode(&request.body),
-        });
+    /// Addresses of the instances currently serving a process, which change
+    /// when it is reloaded; none for a static route
+    pub fn addresses(&self, process: &Process) -> Vec<String> {
+        if process.static_dir.is_some() {
+            return Vec::new();
+        }
+        self.pool(process).addresses().to_vec()
+    }
 
-        serde_json::to_vec(&json)
-            .map_err(|e| UseCaseError::SerializationError(e.to_string()))
+    /// The instances currently serving a process
+    fn pool(&self, process: &Process) -> Arc<InstancePool> {
+        self.pools[process.id.as_str()].read().unwrap().clone()
     }
 
-    fn deserialize_response(&self, data: Vec<u8>) -> Result<HttpResponse, UseCaseError> {
-        use base64::{Engine as _, engine::general_purpose};
-        
-        let json: serde_json::Value = serde_json::from_slice(&data)
-            .map_err(|e| UseCaseError::DeserializationError(e.to_string()))?;
+    /// Postpone the idle timeout of a process that has one
+    async fn record_activity(&self, process: &Process) {
+        if let (Some(orchestrator), Some(_)) = (&self.orchestrator, process.idle_timeout) {
+            orchestrator.write().await.record_activity(&process.id);
+        }
+    }
 
-        let status_code = json["status"].as_u64().unwrap_or(200) as u16;
-        
-        let headers = json["headers"]
-            .as_object()
-            .map(|obj| {
-                obj.iter()
-                    .filter_map(|(k, v)| {
-                        v.as_str().map(|v| (k.clone(), v.to_string()))
-                    })
-                    .collect()
-            })
-            .unwrap_or_default();
+    /// Lifecycle stage of a process, when the proxy manages it
+    pub async fn state(&self, process: &Process) -> Option<ProcessState> {
+        self.orchestrator.as_ref()?.read().await.state(&process.id)
+    }
 
-        let body = json["body"]
-            .as_str()
-            .and_then(|s| general_purpose::STANDARD.decode(s).ok())
-            .unwrap_or_default();
+    /// Runtime status of a process, including how it last exited, when the
+    /// proxy manages it
+    pub async fn process_status(&self, process: &Process) -> Option<ProcessStatus> {
+        self.orchestrator.as_ref()?.read().await.status(&process.id)
+    }
 
-        Ok(HttpResponse {
-            status_code,
-            headers,
-            body,
-        })
+    /// Processes keeping the proxy from being ready, with why: `stopped`,
+    /// `starting`, `failed` or `unhealthy`
+    ///
+    /// Processes with an idle timeout are started on demand, so they never
+    /// hold up readiness.
+    pub async fn unready_processes(&self) -> Vec<(&Process, &'static str)> {
+        let mut unready = Vec::new();
+        for process in self.processes.iter().filter(|p| p.idle_timeout.is_none()) {
+            let reason = match (self.state(process).await, self.health.get(process.id.as_str())) {
+                (Some(ProcessState::Stopped), _) => "stopped",
+                (Some(ProcessState::Failed), _) => "failed",
+                (Some(ProcessState::Starting), _) | (_, HealthState::Starting) => "starting",
+                (_, HealthState::Unhealthy) => "unhealthy",
+                _ => continue,
+            };
+            unready.push((process, reason));
+        }
+        unready
     }
 
-}
+    /// Fail with [`UseCaseError::ProcessStarting`] if refusing requests to
+    /// starting processes is enabled and `process` is one
+    async fn refuse_if_starting(&self, process: &Process) -> Result<(), UseCaseError> {
+        let Some(retry_after) = self.options.starting_retry_after else {
+            return Ok(());
+        };
+        if self.state(process).await == Some(ProcessState::Starting) {
+            return Err(UseCaseError::ProcessStarting {
+                process: process.id.as_str().to_string(),
+                retry_after,
+            });
+        }
+        Ok(())
+    }
 
-/// Use case errors
-#[derive(Debug)]
-pub enum UseCaseError {
-    RepositoryError(String),
-    OrchestrationError(String),
-    CommunicationError(String),
-    NoRouteFound(String),
-    SerializationError(String),
-    DeserializationError(String),
-}
+    /// Tell the orchestrator a starting process has answered a request
+    async fn mark_ready(&self, process: &Process) {
+        if self.state(process).await == Some(ProcessState::Starting) {
+            if let Some(orchestrator) = &self.orchestrator {
+                orchestrator.write().await.mark_ready(&process.id);
+            }
+        }
+    }
 
-impl std::fmt::Display for UseCaseError {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self {
-            UseCaseError::RepositoryError(msg) => write!(f, "Repository error: {}", msg),
-            UseCaseError::OrchestrationError(msg) => write!(f, "Orchestration error: {}", msg),
-            UseCaseError::CommunicationError(msg) => write!(f, "Communication error: {}", msg),
-            UseCaseError::NoRouteFound(path) => write!(f, "No route found for path: {}", path),
-            UseCaseError::SerializationError(msg) => write!(f, "Serialization error: {}", msg),
-            UseCaseError::DeserializationError(msg) => write!(f, "Deserialization error: {}", msg),
+    /// Whether the process was stopped for being idle and is waiting for a
+    /// request to start it again
+    async fn is_asleep(&self, process: &Process) -> bool {
+        match (&self.orchestrator, process.idle_timeout) {
+            (Some(orchestrator), Some(_)) => !orchestrator.read().await.is_running(&process.id),
+            _ => false,
         }
     }
-}
 
-impl std::error::Error for UseCaseError {}
+    /// Send a request, and again after a pause while it fails to connect or
+    /// times out, as often as the process's retry policy allows for `method`
+    ///
+    /// The process's timeout covers every attempt together.
+    async fn send_with_retries(
+        &self,
+        process: &Process,
+        method: &HttpMethod,
+        request_data: Vec<u8>,
+        woken: bool,
+    ) -> Result<Vec<u8>, CommunicationError> {
+        let Some(policy) = process.retry.as_ref().filter(|policy| policy.covers(method)) else {
+            return self.send_when_ready(process, request_data, woken).await;
+        };
+
+        let mut attempt = 1;
+        loop {
+            match self.send_when_ready(process, request_data.clone(), woken).await {
+                Err(e @ (CommunicationError::ConnectionFailed(_) | CommunicationError::Timeout(_)))
+                    if attempt < policy.max_attempts =>
+                {
+                    let pause = policy.backoff(attempt);
+                    tracing::warn!(
+                        "{} request to '{}' failed ({}); retrying in {:?} (attempt {} of {})",
+                        method.as_str(),
+                        process.id.as_str(),
+                        e,
+                        pause,
+                        attempt + 1,
+                        policy.max_attempts
+                    );
+                    tokio::time::sleep(pause).await;
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    /// Send a request to one of the process's instances; a process that was
+    /// just woken may not be listening yet, so refused connections are
+    /// retried until it is
+    async fn send_when_ready(&self, process: &Process, request_data: Vec<u8>, woken: bool) -> Result<Vec<u8>, CommunicationError> {
+        // Held for the whole exchange so a reload waits for it to finish
+        let pool = self.pool(process).enter();
+        if !woken {
+            return self.send_to_instance(process, &pool, request_data).await;
+        }
+
+        let deadline = Instant::now() + self.ready_timeout();
+        loop {
+            match self.send_to_instance(process, &pool, request_data.clone()).await {
+                Err(CommunicationError::ConnectionFailed(_)) if Instant::now() < deadline => {
+                    tokio::time::sleep(self.ready_poll_interval()).await;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    /// Stream a request to one of the process's instances
+    ///
+    /// A body can only be sent once, so a process that was just woken is
+    /// probed until it accepts connections rather than sent the request
+    /// repeatedly, and an unreachable instance fails the request instead of
+    /// the next one being tried.
+    async fn send_streaming_when_ready(
+        &self,
+        process: &Process,
+        pool: &InstancePool,
+        request: StreamingRequest,
+        woken: bool,
+    ) -> Result<HttpResponse, CommunicationError> {
+        let index = pool.candidates()[0];
+        let address = pool.address(index);
+        let transport = self.transport(&process.communication_mode);
+        if woken {
+            let deadline = Instant::now() + self.ready_timeout();
+            while transport.probe(address).await.is_err() && Instant::now() < deadline {
+                tokio::time::sleep(self.ready_poll_interval()).await;
+            }
+        }
+
+        tracing::debug!("Streaming request to {}: {}", process.id.as_str(), address);
+        match transport.send_streaming_with(address, request, &self.send_options(process)).await {
+            Ok(response) => {
+                pool.mark_healthy(index);
+                Ok(response)
+            }
+            Err(CommunicationError::ConnectionFailed(e)) if pool.len() > 1 => {
+                tracing::warn!("Instance {} of '{}' is unreachable: {}", address, process.id.as_str(), e);
+                pool.mark_unhealthy(index);
+                Err(CommunicationError::ConnectionFailed(e))
+            }
+            result => result,
+        }
+    }
+
+    /// Send a request to one of the process's instances
+    ///
+    /// An instance that refuses the connection never saw the request, so it is
+    /// marked unhealthy and the next instance is tried.
+    async fn send_to_instance(
+        &self,
+        process: &Process,
+        pool: &InstancePool,
+        request_data: Vec<u8>,
+    ) -> Result<Vec<u8>, CommunicationError> {
+        let mut last_error = None;
+
+        for index in pool.candidates() {
+            match self.send_to(process, pool, index, request_data.clone()).await {
+                Ok(response_data) => {
+                    pool.mark_healthy(index);
+                    return Ok(response_data);
+                }
+                Err(CommunicationError::ConnectionFailed(e)) if pool.len() > 1 => {
+                    tracing::warn!("Instance {} of '{}' is unreachable: {}", pool.address(index), process.id.as_str(), e);
+                    pool.mark_unhealthy(index);
+                    last_error = Some(CommunicationError::ConnectionFailed(e));
+                }
+                Err(e) => return Err(e),
+            }
+        }
+
+        Err(last_error.expect("a process always has at least one instance"))
+    }
+
+    /// Send a request to a single instance, retrying over HTTP when the pipe
+    /// is unreachable and the process allows falling back
+    async fn send_to(
+        &self,
+        process: &Process,
+        pool: &InstancePool,
+        index: usize,
+        request_data: Vec<u8>,
+    ) -> Result<Vec<u8>, CommunicationError> {
+        let address = pool.address(index);
+        tracing::debug!("Routing request to {} via {:?}: {}", 
+            process.id.as_str(), process.communication_mode, address);
+
+        let transport = self.transport(&process.communication_mode);
+        let options = self.send_options(process);
+        if !(process.http_fallback && process.communication_mode == CommunicationMode::Pipe) {
+            return transport.send_request_with(address, request_data, &options).await;
+        }
+
+        match transport.send_request_with(address, request_data.clone(), &options).await {
+            Err(CommunicationError::ConnectionFailed(e)) => {
+                let http_address = pool.http_address(index);
+                tracing::warn!(
+                    "Pipe {} for '{}' is unreachable ({}); falling back to HTTP at {}",
+                    address, process.id.as_str(), e, http_address
+                );
+                self.transport(&CommunicationMode::Http)
+                    .send_request_with(http_address, request_data, &options)
+                    .await
+            }
+            result => result,
+        }
+    }
+
+    /// Wait for (or fail to get) a free request slot on a concurrency-limited process
+    async fn acquire_slot(&self, process: &Process) -> Result<Option<SemaphorePermit<'_>>, UseCaseError> {
+        let Some((semaphore, overflow)) = self.limiters.get(process.id.as_str()) else {
+            return Ok(None);
+        };
+
+        let busy = || UseCaseError::ConcurrencyLimitReached(process.id.as_str().to_string());
+        let permit = match overflow {
+            OverflowPolicy::Reject => semaphore.try_acquire().map_err(|_| busy())?,
+            OverflowPolicy::Queue(wait) => tokio::time::timeout(*wait, semaphore.acquire())
+                .await
+                .map_err(|_| busy())?
+                .map_err(|_| busy())?,
+        };
+
+        Ok(Some(permit))
+    }
+
+    /// Health of every process, as last seen by the health checks
+    pub fn health(&self) -> &HealthRegistry {
+        &self.health
+    }
+
+    /// The processes this use case routes to, in match order
+    pub fn processes(&self) -> &[Process] {
+        &self.processes
+    }
+
+    /// Run one health check against a process and record the result
+    pub async fn check_health(&self, process: &Process) -> HealthState {
+        let Some(check) = &process.health_check else {
+            return HealthState::Healthy;
+        };
+
+        // A process coming into service, whether starting or recovering, is
+        // warmed up before it's sent traffic
+        let pool = self.pool(process);
+        let previous = self.health.get(process.id.as_str());
+        let state = if !self.probe(process, check, &pool).await {
+            HealthState::Unhealthy
+        } else if previous != HealthState::Healthy
+            && !self.warm_up(process, &pool).await
+            && process.warmup_strict
+        {
+            // Warmed up again on the next check
+            previous
+        } else {
+            self.mark_ready(process).await;
+            HealthState::Healthy
+        };
+        self.health.set(process.id.as_str(), state);
+        state
+    }
+
+    /// Send a process's health check to `pool`, returning whether it passed
+    async fn probe(&self, process: &Process, check: &HealthCheck, pool: &InstancePool) -> bool {
+        let probe = HttpRequest {
+            method: HttpMethod::Get,
+            path: check.path.clone(),
+            headers: vec![],
+            body: vec![],
+        };
+        let exchange = async {
+            if process.protocol == SerializationFormat::Raw {
+                let response = self.send_streaming_when_ready(process, pool, probe.clone().into(), false).await;
+                return response.ok();
+            }
+            let request_data = self.serialize_request(&probe, process.protocol).ok()?;
+            let response_data = self.send_to_instance(process, pool, request_data).await.ok()?;
+            self.deserialize_response(response_data, process.protocol).ok()
+        };
+
+        matches!(
+            tokio::time::timeout(check.interval, exchange).await,
+            Ok(Some(response)) if (200..300).contains(&response.status_code)
+        )
+    }
+
+    /// Start a background task per process that runs its health check on
+    /// the configured interval
+    pub fn spawn_health_checks(self: &Arc<Self>) -> Vec<tokio::task::JoinHandle<()>>
+    where
+        P: 'static,
+    {
+        self.processes
+            .iter()
+            .filter_map(|process| {
+                let interval = process.health_check.as_ref()?.interval;
+                let use_case = Arc::clone(self);
+                let process = process.clone();
+                Some(tokio::spawn(async move {
+                    let mut ticker = tokio::time::interval(interval);
+                    loop {
+                        ticker.tick().await;
+                        // A stopped idle process keeps its last state until woken
+                        if !use_case.is_asleep(&process).await {
+                            use_case.check_health(&process).await;
+                        }
+                    }
+                }))
+            })
+            .collect()
+    }
+
+    /// Start a background task stopping processes that have gone without
+    /// requests for longer than their idle timeout
+    ///
+    /// `None` if no process has an idle timeout or there is no orchestrator
+    /// to stop them with.
+    pub fn spawn_idle_reaper(&self) -> Option<tokio::task::JoinHandle<()>> {
+        let orchestrator = self.orchestrator.clone()?;
+        let shortest = self.processes.iter().filter_map(|p| p.idle_timeout).min()?;
+
+        // Often enough that no process outlives its timeout by more than half
+        let period = (shortest / 2).max(Duration::from_millis(10));
+        Some(tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(period);
+            loop {
+                ticker.tick().await;
+                orchestrator.write().await.stop_idle().await;
+            }
+        }))
+    }
+
+    /// Whether some process, the default one included, serves `path`
+    pub fn has_route(&self, path: &str) -> bool {
+        !self.matching_processes(path).is_empty()
+    }
+
+    /// Request body limit configured for the process a `method` request for
+    /// `path` is dispatched to, if any
+    pub fn max_body_bytes(&self, method: &HttpMethod, path: &str) -> Option<usize> {
+        self.find_dispatched_process(method, path)?.max_body_bytes
+    }
+
+    /// Whether the process a `method` request for `path` is dispatched to
+    /// takes a body of `content_type`; a request no process serves is left
+    /// for routing to turn away
+    pub fn accepts_content_type(&self, method: &HttpMethod, path: &str, content_type: &str) -> bool {
+        self.find_dispatched_process(method, path)
+            .is_none_or(|process| process.accepts_content_type(content_type))
+    }
+
+    /// The directory and file path to serve a request for `path` from, if
+    /// it's for a static route; `None` means it goes to a process as usual
+    pub fn static_mount(&self, path: &str) -> Option<StaticMount> {
+        let process = self.find_matching_process(path)?;
+        let dir = process.static_dir.clone()?;
+        Some(StaticMount {
+            process: process.id.as_str().to_string(),
+            dir,
+            path: process.route.mount_path(path),
+            response_headers: process.response_headers.clone(),
+        })
+    }
+
+    /// Resolve the backend for a protocol upgrade (e.g. WebSocket) requested
+    /// by a `method` request on `path`
+    ///
+    /// Only HTTP-mode processes can carry an upgraded connection; `None` means
+    /// the request should be handled as a regular request instead.
+    pub fn upgrade_target(&self, method: &HttpMethod, path: &str) -> Option<UpgradeTarget> {
+        let process = self.find_dispatched_process(method, path)?;
+        if process.communication_mode != CommunicationMode::Http {
+            return None;
+        }
+
+        let pool = self.pool(process);
+        let index = pool.candidates()[0];
+        Some(UpgradeTarget {
+            process: process.id.as_str().to_string(),
+            address: pool.address(index).to_string(),
+        })
+    }
+
+    /// Whether `method` requests for `path` go to a gRPC-mode process, and so
+    /// should be forwarded as they are to the backend
+    /// [`grpc_target`](Self::grpc_target) resolves
+    pub fn serves_grpc(&self, method: &HttpMethod, path: &str) -> bool {
+        self.find_dispatched_process(method, path)
+            .is_some_and(|p| p.communication_mode == CommunicationMode::Grpc)
+    }
+
+    /// Resolve the backend for a gRPC call on `path`, waking its process if
+    /// it was stopped for being idle
+    ///
+    /// The call itself never passes through the use case, so the process's
+    /// concurrency limit and timeout don't apply to it.
+    pub async fn grpc_target(&self, path: &str) -> Result<UpgradeTarget, UseCaseError> {
+        // gRPC calls are always POSTs
+        let process = self.route(&HttpMethod::Post, path).await?;
+        if self.wake(process).await? {
+            self.wait_until_reachable(process, &self.pool(process)).await?;
+            self.mark_ready(process).await;
+        }
+        self.record_activity(process).await;
+
+        let pool = self.pool(process);
+        let index = pool.candidates()[0];
+        Ok(UpgradeTarget {
+            process: process.id.as_str().to_string(),
+            address: pool.address(index).to_string(),
+        })
+    }
+
+    /// Method, path and normalized query, plus the value of each header the
+    /// serving process varies its responses on
+    ///
+    /// A header that is absent is keyed differently from one that is sent
+    /// empty; repeated headers are joined in the order received. A process in
+    /// a shared cache group is keyed by its group and the path below its
+    /// route instead, so the group's routes to one resource share an entry;
+    /// a path with nothing below the route's prefix is keyed as usual.
+    /// A process that rewrites paths is keyed by the path it's sent, with its
+    /// id so that processes rewriting to the same path are kept apart.
+    fn generate_cache_key(&self, request: &HttpRequest) -> String {
+        let process = self.find_dispatched_process(&request.method, &request.path);
+        let (path, query) = split_query(&request.path);
+        let ignored = process.map_or(&[][..], |p| &p.cache_ignore_query[..]);
+        let shared = process.and_then(|p| Some((p.shared_cache.as_ref()?, p.route.path_below(path)?)));
+        let mut key = match shared {
+            Some((group, below)) => format!("{}@{}:{}", group, request.method.as_str(), below),
+            None => match process.filter(|p| p.rewrites_path()) {
+                Some(process) => format!(
+                    "{}>{}:{}",
+                    process.id.as_str(),
+                    request.method.as_str(),
+                    process.rewrite_path(path)
+                ),
+                None => format!("{}:{}", request.method.as_str(), path),
+            },
+        };
+        if let Some(query) = normalize_query(query, ignored) {
+            key.push('?');
+            key.push_str(&query);
+        }
+        let Some(process) = process else {
+            return key;
+        };
+
+        for name in &process.cache_vary {
+            let values: Vec<&str> = request
+                .headers
+                .iter()
+                .filter(|(k, _)| k.eq_ignore_ascii_case(name))
+                .map(|(_, v)| v.as_str())
+                .collect();
+            key.push('\n');
+            key.push_str(name);
+            if !values.is_empty() {
+                key.push('=');
+                key.push_str(&values.join(", "));
+            }
+        }
+        key
+    }
+
+    /// The first healthy process whose route matches `path`, or the default
+    /// process if no route does
+    ///
+    /// Unhealthy matches are skipped; if every match is unhealthy the first
+    /// one is reported as unavailable.
+    fn find_routable_process(&self, method: &HttpMethod, path: &str) -> Result<&Process, UseCaseError> {
+        let matches = self.matching_processes(path);
+        if matches.is_empty() {
+            return Err(UseCaseError::NoRouteFound(path.to_string()));
+        }
+
+        // A path that is routed, only not for this method, is told apart
+        // from one that isn't routed at all
+        let mut serving = matches.iter().copied().filter(|p| p.allows(method)).peekable();
+        let Some(first) = serving.peek().copied() else {
+            let mut allowed: Vec<HttpMethod> = Vec::new();
+            for method in matches.iter().flat_map(|p| &p.methods) {
+                if !allowed.contains(method) {
+                    allowed.push(method.clone());
+                }
+            }
+            return Err(UseCaseError::MethodNotAllowed {
+                path: path.to_string(),
+                allowed,
+            });
+        };
+
+        serving
+            .find(|p| self.health.is_healthy(p.id.as_str()))
+            .ok_or_else(|| UseCaseError::ProcessUnavailable(first.id.as_str().to_string()))
+    }
+
+    /// The process a `method` request for `path` is dispatched to, even one
+    /// that can't take it yet; `None` when no process serves the request
+    fn find_dispatched_process(&self, method: &HttpMethod, path: &str) -> Option<&Process> {
+        match self.find_routable_process(method, path) {
+            Ok(process) => Some(process),
+            // Still dispatched to it, once it's woken or recovers
+            Err(UseCaseError::ProcessUnavailable(id)) => self.processes.iter().find(|p| p.id.as_str() == id),
+            Err(_) => None,
+        }
+    }
+
+    fn find_matching_process(&self, path: &str) -> Option<&Process> {
+        self.matching_processes(path).into_iter().next()
+    }
+
+    /// Processes whose route matches `path`, in match order, falling back to
+    /// the default process only when none do
+    ///
+    /// Routes match the path alone, whatever query follows it.
+    fn matching_processes(&self, path: &str) -> Vec<&Process> {
+        let (path, _) = split_query(path);
+        let matches: Vec<&Process> = self.processes.iter().filter(|p| self.route_matches(p, path)).collect();
+        if !matches.is_empty() {
+            return matches;
+        }
+        self.processes.iter().filter(|p| p.is_default).collect()
+    }
+
+    fn route_matches(&self, process: &Process, path: &str) -> bool {
+        if self.options.normalize_routes {
+            process.route.matches_normalized(path)
+        } else {
+            process.route.matches(path)
+        }
+    }
+
+    fn serialize_request(&self, request: &HttpRequest, format: SerializationFormat) -> Result<Vec<u8>, UseCaseError> {
+        let encoded = match format {
+            SerializationFormat::MsgPack => msgpack::encode_request(request),
+            _ => envelope::encode_request(request),
+        };
+        encoded.map_err(UseCaseError::SerializationError)
+    }
+
+    fn deserialize_response(&self, data: Vec<u8>, format: SerializationFormat) -> Result<HttpResponse, UseCaseError> {
+        // A backend speaking the other envelope format is told apart from
+        // one sending a broken envelope
+        if let Some(answered) = SerializationFormat::of_envelope(&data).filter(|&f| f != format) {
+            return Err(UseCaseError::DeserializationError(format!(
+                "backend answered with a {} envelope, but the process's protocol is {}; \
+                 backends are told which to use in PIPE_PROTOCOL",
+                answered.as_str(),
+                format.as_str()
+            )));
+        }
+        let parsed = match format {
+            SerializationFormat::Json => envelope::decode_response(&data, self.options.lenient_responses),
+            SerializationFormat::MsgPack => msgpack::decode_response(&data, self.options.lenient_responses),
+            // Raw responses are passed through by the transport and never decoded here
+            SerializationFormat::Raw => Err("raw responses have no envelope to decode".to_string()),
+        };
+        parsed.map_err(|e| {
+            tracing::debug!(
+                "Malformed response envelope ({}): {}",
+                e,
+                String::from_utf8_lossy(&data)
+            );
+            UseCaseError::DeserializationError(e)
+        })
+    }
+
+}
+
+/// Header telling a backend when the proxy stops waiting for its response,
+/// in milliseconds since the Unix epoch, so it can abandon work it can't
+/// finish in time
+pub const DEADLINE_HEADER: &str = "x-request-deadline";
+
+/// Set the deadline for a request sent now to a process with `timeout`
+///
+/// A deadline from a proxy in front is kept if it's sooner.
+fn set_deadline(headers: &mut Vec<(String, String)>, timeout: Duration) {
+    let deadline = (SystemTime::now() + timeout)
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64;
+    let is_deadline = |name: &str| name.eq_ignore_ascii_case(DEADLINE_HEADER);
+    let deadline = headers
+        .iter()
+        .filter(|(name, _)| is_deadline(name))
+        .filter_map(|(_, value)| value.trim().parse::<u64>().ok())
+        .fold(deadline, u64::min);
+    headers.retain(|(name, _)| !is_deadline(name));
+    headers.push((DEADLINE_HEADER.to_string(), deadline.to_string()));
+}
+
+/// A request target's path and its query, if it has one
+fn split_query(target: &str) -> (&str, Option<&str>) {
+    match target.split_once('?') {
+        Some((path, query)) => (path, Some(query)),
+        None => (target, None),
+    }
+}
+
+/// A query as it identifies a cached response: without the `ignored`
+/// parameters, and with the rest sorted by name so their order doesn't
+/// matter
+///
+/// The sort is stable, so repeated parameters keep their relative order.
+/// `None` if no parameters are left.
+fn normalize_query(query: Option<&str>, ignored: &[String]) -> Option<String> {
+    fn name(param: &str) -> &str {
+        param.split_once('=').map_or(param, |(name, _)| name)
+    }
+    let mut params: Vec<&str> = query?
+        .split('&')
+        .filter(|param| !param.is_empty() && !ignored.iter().any(|i| i == name(param)))
+        .collect();
+    params.sort_by(|a, b| name(a).cmp(name(b)));
+    (!params.is_empty()).then(|| params.join("&"))
+}
+
+/// How long a response from `process` may be cached
+///
+/// Successful and redirect responses are kept until evicted. Error responses
+/// are only kept for the process's negative cache TTL, and only if its policy
+/// covers the status; anything else expires immediately, so it still reaches
+/// requests coalesced onto the same backend call but is never served again.
+///
+/// The backend's `Cache-Control` has the last word: a response it marks
+/// `no-store`, `no-cache` or `private` is never served again, and a
+/// `max-age` (or `s-maxage`, meant for shared caches like the proxy)
+/// replaces the TTL of a response that would otherwise be kept. `Expires`
+/// is ignored: backends that set it are expected to send `max-age` too,
+/// which takes precedence over it anyway.
+fn cache_ttl(process: &Process, response: &HttpResponse) -> Option<Duration> {
+    let ttl = if response.status_code < 400 {
+        None
+    } else {
+        match &process.negative_cache {
+            Some(policy) if policy.covers(response.status_code) => Some(policy.ttl),
+            _ => Some(Duration::ZERO),
+        }
+    };
+    match cache_control(&response.headers) {
+        CacheControl::NotStorable => Some(Duration::ZERO),
+        CacheControl::MaxAge(_) if ttl == Some(Duration::ZERO) => ttl,
+        CacheControl::MaxAge(max_age) => Some(max_age),
+        CacheControl::Unspecified => ttl,
+    }
+}
+
+/// What a response's `Cache-Control` allows a shared cache to do with it
+#[derive(Debug, PartialEq, Eq)]
+enum CacheControl {
+    NotStorable,
+    MaxAge(Duration),
+    Unspecified,
+}
+
+/// Read the `Cache-Control` directives among `headers`, which may be split
+/// over several headers; names are case-insensitive and values may be quoted
+fn cache_control(headers: &[(String, String)]) -> CacheControl {
+    let mut max_age = None;
+    let mut s_maxage = None;
+    let directives = headers
+        .iter()
+        .filter(|(name, _)| name.eq_ignore_ascii_case("cache-control"))
+        .flat_map(|(_, value)| value.split(','));
+    for directive in directives {
+        let (name, value) = match directive.split_once('=') {
+            Some((name, value)) => (name.trim(), Some(value.trim().trim_matches('"'))),
+            None => (directive.trim(), None),
+        };
+        let seconds = || value.and_then(|v| v.parse::<u64>().ok()).map(Duration::from_secs);
+        match name.to_ascii_lowercase().as_str() {
+            "no-store" | "no-cache" | "private" => return CacheControl::NotStorable,
+            "max-age" => max_age = max_age.or_else(seconds),
+            "s-maxage" => s_maxage = s_maxage.or_else(seconds),
+            _ => {}
+        }
+    }
+    s_maxage.or(max_age).map_or(CacheControl::Unspecified, CacheControl::MaxAge)
+}
+
+/// Use case errors
+#[derive(Debug, Clone)]
+pub enum UseCaseError {
+    RepositoryError(String),
+    OrchestrationError(String),
+    CommunicationError {
+        process: String,
+        source: CommunicationError,
+    },
+    NoRouteFound(String),
+    /// Routes match the path, but none of them serves the request's method
+    MethodNotAllowed {
+        path: String,
+        /// Every method the matching routes serve, in declaration order
+        allowed: Vec<HttpMethod>,
+    },
+    ProcessNotFound(String),
+    ProcessUnavailable(String),
+    /// The process has been started but isn't accepting requests yet
+    ProcessStarting {
+        process: String,
+        retry_after: Duration,
+    },
+    ConcurrencyLimitReached(String),
+    /// A process's warm-up failed and it is configured not to take traffic
+    /// until one succeeds
+    WarmupFailed(String),
+    SerializationError(String),
+    DeserializationError(String),
+}
+
+impl std::fmt::Display for UseCaseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            UseCaseError::RepositoryError(msg) => write!(f, "Repository error: {}", msg),
+            UseCaseError::OrchestrationError(msg) => write!(f, "Orchestration error: {}", msg),
+            UseCaseError::CommunicationError { process, source } => {
+                write!(f, "Communication error with process '{}': {}", process, source)
+            }
+            UseCaseError::NoRouteFound(path) => write!(f, "No route found for path: {}", path),
+            UseCaseError::MethodNotAllowed { path, allowed } => {
+                let allowed: Vec<&str> = allowed.iter().map(HttpMethod::as_str).collect();
+                write!(f, "Path {} only serves {}", path, allowed.join(", "))
+            }
+            UseCaseError::ProcessNotFound(id) => write!(f, "No process with id '{}'", id),
+            UseCaseError::ProcessUnavailable(process) => {
+                write!(f, "Process '{}' is not healthy", process)
+            }
+            UseCaseError::ProcessStarting { process, .. } => write!(f, "Process '{}' is starting", process),
+            UseCaseError::ConcurrencyLimitReached(process) => {
+                write!(f, "Concurrency limit reached for process '{}'", process)
+            }
+            UseCaseError::WarmupFailed(process) => write!(f, "Warm-up of process '{}' failed", process),
+            UseCaseError::SerializationError(msg) => write!(f, "Serialization error: {}", msg),
+            UseCaseError::DeserializationError(msg) => write!(f, "Deserialization error: {}", msg),
+        }
+    }
+}
+
+impl UseCaseError {
+    /// The process the failure is attributed to, if any
+    pub fn process(&self) -> Option<&str> {
+        match self {
+            UseCaseError::CommunicationError { process, .. }
+            | UseCaseError::ProcessUnavailable(process)
+            | UseCaseError::ProcessStarting { process, .. }
+            | UseCaseError::ConcurrencyLimitReached(process)
+            | UseCaseError::WarmupFailed(process) => Some(process),
+            _ => None,
+        }
+    }
+}
+
+impl std::error::Error for UseCaseError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::{Executable, PipeName, ProcessId, Route};
+    use crate::test_support::{CapturedLogs, MockPipeCommunicationService};
+    use async_trait::async_trait;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+
+    /// Communication service that answers every request with fixed bytes
+    struct StubService {
+        response: Vec<u8>,
+    }
+
+    #[async_trait]
+    impl PipeCommunicationService for StubService {
+        async fn send_request(&self, _address: &str, _request: Vec<u8>) -> Result<Vec<u8>, CommunicationError> {
+            Ok(self.response.clone())
+        }
+    }
+
+    /// Communication service that records how many requests are in flight at once
+    #[derive(Default)]
+    struct SlowService {
+        calls: AtomicUsize,
+        in_flight: AtomicUsize,
+        max_in_flight: AtomicUsize,
+        failing: std::sync::atomic::AtomicBool,
+    }
+
+    #[async_trait]
+    impl PipeCommunicationService for SlowService {
+        async fn send_request(&self, _address: &str, _request: Vec<u8>) -> Result<Vec<u8>, CommunicationError> {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            let current = self.in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+            self.max_in_flight.fetch_max(current, Ordering::SeqCst);
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            self.in_flight.fetch_sub(1, Ordering::SeqCst);
+            if self.failing.load(Ordering::SeqCst) {
+                return Err(CommunicationError::ReceiveFailed("backend crashed".to_string()));
+            }
+            Ok(br#"{"status": 200}"#.to_vec())
+        }
+    }
+
+    /// Communication service that records which address each request went to
+    /// and refuses connections to a configurable set of addresses
+    #[derive(Default)]
+    struct RecordingService {
+        seen: std::sync::Mutex<Vec<String>>,
+        down: Vec<String>,
+    }
+
+    #[async_trait]
+    impl PipeCommunicationService for RecordingService {
+        async fn send_request(&self, address: &str, _request: Vec<u8>) -> Result<Vec<u8>, CommunicationError> {
+            self.seen.lock().unwrap().push(address.to_string());
+            if self.down.iter().any(|d| d == address) {
+                return Err(CommunicationError::ConnectionFailed(address.to_string()));
+            }
+            Ok(br#"{"status": 200}"#.to_vec())
+        }
+    }
+
+    fn multi_instance_use_case(service: RecordingService) -> (ProxyHttpRequestUseCase<RecordingService>, Vec<String>) {
+        let mut process = test_process();
+        process.instances = 2;
+        let addresses = process.instance_addresses();
+        let use_case = ProxyHttpRequestUseCase::new(Arc::new(service), Arc::new(vec![process]));
+        (use_case, addresses)
+    }
+
+    fn test_process() -> Process {
+        Process::new(
+            ProcessId::new("api").unwrap(),
+            Executable::new("./api").unwrap(),
+            Route::new("/api/*").unwrap(),
+            PipeName::new("api_pipe").unwrap(),
+        )
+    }
+
+    fn limited_use_case(max_concurrent: usize, overflow: OverflowPolicy) -> Arc<ProxyHttpRequestUseCase<SlowService>> {
+        let mut process = test_process();
+        process.concurrency_limit = Some(ConcurrencyLimit { max_concurrent, overflow });
+        Arc::new(ProxyHttpRequestUseCase::new(
+            Arc::new(SlowService::default()),
+            Arc::new(vec![process]),
+        ))
+    }
+
+    async fn run_concurrently(
+        use_case: &Arc<ProxyHttpRequestUseCase<SlowService>>,
+        count: usize,
+    ) -> Vec<Result<HttpResponse, UseCaseError>> {
+        let handles: Vec<_> = (0..count)
+            .map(|_| {
+                let use_case = use_case.clone();
+                tokio::spawn(async move { use_case.execute(get("/api/x")).await })
+            })
+            .collect();
+
+        let mut results = Vec::new();
+        for handle in handles {
+            results.push(handle.await.unwrap());
+        }
+        results
+    }
+
+    fn use_case(response: serde_json::Value, lenient: bool) -> ProxyHttpRequestUseCase<StubService> {
+        let service = Arc::new(StubService {
+            response: serde_json::to_vec(&response).unwrap(),
+        });
+        let options = ProxyOptions {
+            lenient_responses: lenient,
+            ..ProxyOptions::default()
+        };
+        ProxyHttpRequestUseCase::with_options(service, Arc::new(vec![test_process()]), options)
+    }
+
+    fn get(path: &str) -> HttpRequest {
+        HttpRequest {
+            method: crate::domain::HttpMethod::Get,
+            path: path.to_string(),
+            headers: vec![],
+            body: vec![],
+        }
+    }
+
+    #[tokio::test]
+    async fn test_idle_process_is_stopped_and_woken() {
+        use crate::adapters::TokioProcessOrchestrator;
+
+        let mut process = test_process();
+        process.executable = Executable::new("sleep").unwrap();
+        process.arguments = vec!["30".to_string()];
+        process.idle_timeout = Some(Duration::from_millis(100));
+        let id = process.id.clone();
+
+        let mut orchestrator = TokioProcessOrchestrator::new();
+        orchestrator.register(process.clone());
+        orchestrator.start_process(&id).await.unwrap();
+        let orchestrator = Arc::new(RwLock::new(orchestrator));
+
+        let service = Arc::new(StubService {
+            response: br#"{"status": 200}"#.to_vec(),
+        });
+        let use_case = ProxyHttpRequestUseCase::new(service, Arc::new(vec![process]))
+            .with_orchestrator(orchestrator.clone());
+        let reaper = use_case.spawn_idle_reaper().unwrap();
+
+        assert_eq!(use_case.execute(get("/api/x")).await.unwrap().status_code, 200);
+        let first_pid = orchestrator.read().await.status(&id).unwrap().pid;
+
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        assert!(!orchestrator.read().await.is_running(&id));
+
+        assert_eq!(use_case.execute(get("/api/x")).await.unwrap().status_code, 200);
+        let status = orchestrator.read().await.status(&id).unwrap();
+        assert!(status.running);
+        assert_ne!(status.pid, first_pid);
+
+        reaper.abort();
+        orchestrator.write().await.stop_all().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_starting_process_is_refused_when_enabled() {
+        use crate::adapters::TokioProcessOrchestrator;
+
+        let mut process = test_process();
+        process.executable = Executable::new("sleep").unwrap();
+        process.arguments = vec!["30".to_string()];
+        process.health_check = Some(HealthCheck {
+            path: "/healthz".to_string(),
+            interval: Duration::from_secs(60),
+        });
+        let id = process.id.clone();
+
+        let mut orchestrator = TokioProcessOrchestrator::new();
+        orchestrator.register(process.clone());
+        orchestrator.start_process(&id).await.unwrap();
+        let orchestrator: Arc<RwLock<dyn ProcessOrchestrationService>> = Arc::new(RwLock::new(orchestrator));
+
+        let service = Arc::new(StubService {
+            response: br#"{"status": 200}"#.to_vec(),
+        });
+        let processes = Arc::new(vec![process.clone()]);
+        let options = ProxyOptions {
+            starting_retry_after: Some(Duration::from_secs(3)),
+            ..ProxyOptions::default()
+        };
+        let use_case = ProxyHttpRequestUseCase::with_options(service.clone(), processes.clone(), options)
+            .with_orchestrator(orchestrator.clone());
+        let eager = ProxyHttpRequestUseCase::new(service, processes).with_orchestrator(orchestrator.clone());
+
+        assert!(matches!(
+            use_case.execute(get("/api/x")).await,
+            Err(UseCaseError::ProcessStarting { ref process, retry_after }) if process == "api" && retry_after == Duration::from_secs(3)
+        ));
+        // Without the option the existing behaviour is kept
+        assert!(matches!(eager.execute(get("/api/x")).await, Err(UseCaseError::ProcessUnavailable(_))));
+
+        // Passing its health check makes the process ready
+        assert_eq!(use_case.check_health(&process).await, HealthState::Healthy);
+        assert_eq!(use_case.state(&process).await, Some(ProcessState::Running));
+        assert_eq!(use_case.execute(get("/api/x")).await.unwrap().status_code, 200);
+
+        orchestrator.write().await.stop_all().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_default_process_catches_unmatched_paths() {
+        let service = Arc::new(RecordingService::default());
+        let mut spa = test_process();
+        spa.id = ProcessId::new("spa").unwrap();
+        spa.route = Route::new("/app").unwrap();
+        spa.pipe_name = PipeName::new("spa_pipe").unwrap();
+        spa.is_default = true;
+        // Declared first, but specific routes still take precedence
+        let processes = Arc::new(vec![spa.clone(), test_process()]);
+        let use_case = ProxyHttpRequestUseCase::new(service.clone(), processes);
+
+        use_case.execute(get("/api/users")).await.unwrap();
+        use_case.execute(get("/dashboard/settings")).await.unwrap();
+        use_case.execute(get("/app")).await.unwrap();
+
+        let api_address = test_process().instance_addresses()[0].clone();
+        let spa_address = spa.instance_addresses()[0].clone();
+        assert_eq!(*service.seen.lock().unwrap(), vec![api_address, spa_address.clone(), spa_address]);
+    }
+
+    #[tokio::test]
+    async fn test_cache_survives_restart() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let options = ProxyOptions {
+            cache_size: Some(10),
+            cache_file: Some(dir.path().join("cache.bin")),
+            ..ProxyOptions::default()
+        };
+        let build = |service: &Arc<SlowService>| {
+            ProxyHttpRequestUseCase::with_options(service.clone(), Arc::new(vec![test_process()]), options.clone())
+        };
+
+        let service = Arc::new(SlowService::default());
+        let use_case = build(&service);
+        assert_eq!(use_case.restore_cache().await, 0);
+        use_case.execute(get("/api/x")).await.unwrap();
+        assert_eq!(use_case.save_cache().unwrap(), 1);
+
+        // A fresh use case, as after a restart, serves the saved response
+        let service = Arc::new(SlowService::default());
+        let use_case = build(&service);
+        assert_eq!(use_case.restore_cache().await, 1);
+        let timed = use_case.execute_timed(get("/api/x")).await.unwrap();
+        assert!(timed.timings.cache_hit);
+        assert_eq!(timed.response.status_code, 200);
+        assert_eq!(service.calls.load(Ordering::SeqCst), 0);
+
+        // A corrupt file is ignored rather than failing startup
+        std::fs::write(options.cache_file.as_ref().unwrap(), b"garbage").unwrap();
+        assert_eq!(build(&service).restore_cache().await, 0);
+    }
+
+    #[tokio::test]
+    async fn test_expired_entries_are_not_restored() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let options = ProxyOptions {
+            cache_size: Some(10),
+            cache_file: Some(dir.path().join("cache.bin")),
+            ..ProxyOptions::default()
+        };
+        let use_case = ProxyHttpRequestUseCase::with_options(
+            Arc::new(SlowService::default()),
+            Arc::new(vec![test_process()]),
+            options,
+        );
+
+        let cache = use_case.cache.as_ref().unwrap().entries();
+        let ok = || HttpResponse { status_code: 200, headers: vec![], body: vec![], trailers: vec![] };
+        cache.insert("fresh".to_string(), CachedResponse::new(ok(), Some(Duration::from_secs(60)))).await;
+        cache.insert("forever".to_string(), CachedResponse::new(ok(), None)).await;
+        let mut stale = CachedResponse::new(ok(), Some(Duration::from_secs(60)));
+        stale.stored_at -= Duration::from_secs(120);
+        cache.insert("stale".to_string(), stale).await;
+
+        assert_eq!(use_case.save_cache().unwrap(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_route_normalization() {
+        let service = Arc::new(StubService {
+            response: br#"{"status": 200}"#.to_vec(),
+        });
+        let mut users = test_process();
+        users.id = ProcessId::new("users").unwrap();
+        users.route = Route::new("/users/").unwrap();
+        let processes = Arc::new(vec![test_process(), users]);
+
+        let strict = ProxyHttpRequestUseCase::new(service.clone(), processes.clone());
+        for path in ["/API/Users", "/users"] {
+            assert!(matches!(strict.execute(get(path)).await, Err(UseCaseError::NoRouteFound(_))), "{}", path);
+        }
+
+        let options = ProxyOptions {
+            normalize_routes: true,
+            ..ProxyOptions::default()
+        };
+        let normalized = ProxyHttpRequestUseCase::with_options(service, processes, options);
+        for path in ["/API/Users", "/api", "/users", "/Users/", "/USERS/1"] {
+            assert_eq!(normalized.execute(get(path)).await.unwrap().status_code, 200, "{}", path);
+        }
+        assert!(matches!(normalized.execute(get("/other")).await, Err(UseCaseError::NoRouteFound(_))));
+    }
+
+    #[tokio::test]
+    async fn test_valid_envelope() {
+        let use_case = use_case(serde_json::json!({"status": 201, "headers": {"X-A": "1"}, "body": "aGk="}), false);
+        let response = use_case.execute(get("/api/x")).await.unwrap();
+
+        assert_eq!(response.status_code, 201);
+        assert_eq!(response.headers, vec![("X-A".to_string(), "1".to_string())]);
+        assert_eq!(response.body, b"hi");
+    }
+
+    #[tokio::test]
+    async fn test_missing_status_is_rejected() {
+        let use_case = use_case(serde_json::json!({"body": "aGk="}), false);
+        let result = use_case.execute(get("/api/x")).await;
+        assert!(matches!(result, Err(UseCaseError::DeserializationError(_))));
+    }
+
+    #[tokio::test]
+    async fn test_invalid_status_is_rejected() {
+        for status in [serde_json::json!("200"), serde_json::json!(42), serde_json::json!(1000)] {
+            let use_case = use_case(serde_json::json!({"status": status, "body": ""}), false);
+            let result = use_case.execute(get("/api/x")).await;
+            assert!(matches!(result, Err(UseCaseError::DeserializationError(_))), "status {} accepted", status);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_non_string_body_is_rejected() {
+        let use_case = use_case(serde_json::json!({"status": 200, "body": [1, 2, 3]}), false);
+        let result = use_case.execute(get("/api/x")).await;
+        assert!(matches!(result, Err(UseCaseError::DeserializationError(_))));
+    }
+
+    #[tokio::test]
+    async fn test_invalid_base64_body_is_rejected() {
+        let use_case = use_case(serde_json::json!({"status": 200, "body": "not base64!"}), false);
+        let result = use_case.execute(get("/api/x")).await;
+        assert!(matches!(result, Err(UseCaseError::DeserializationError(_))));
+    }
+
+    #[tokio::test]
+    async fn test_invalid_json_is_rejected() {
+        let service = Arc::new(StubService { response: b"not json".to_vec() });
+        let use_case = ProxyHttpRequestUseCase::new(service, Arc::new(vec![test_process()]));
+        let result = use_case.execute(get("/api/x")).await;
+        assert!(matches!(result, Err(UseCaseError::DeserializationError(_))));
+    }
+
+    #[tokio::test]
+    async fn test_lenient_mode_accepts_minimal_envelopes() {
+        let use_case = use_case(serde_json::json!({"body": "not base64!"}), true);
+        let response = use_case.execute(get("/api/x")).await.unwrap();
+
+        assert_eq!(response.status_code, 200);
+        assert!(response.body.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_queue_policy_caps_in_flight_requests() {
+        let use_case = limited_use_case(2, OverflowPolicy::Queue(Duration::from_secs(5)));
+        let results = run_concurrently(&use_case, 10).await;
+
+        assert!(results.iter().all(|r| r.is_ok()));
+        assert!(use_case.pipe_service.max_in_flight.load(Ordering::SeqCst) <= 2);
+    }
+
+    #[tokio::test]
+    async fn test_queue_policy_times_out() {
+        let use_case = limited_use_case(1, OverflowPolicy::Queue(Duration::from_millis(10)));
+        let results = run_concurrently(&use_case, 3).await;
+
+        assert!(results.iter().any(|r| matches!(r, Err(UseCaseError::ConcurrencyLimitReached(p)) if p == "api")));
+    }
+
+    #[tokio::test]
+    async fn test_reject_policy_fails_fast() {
+        let use_case = limited_use_case(1, OverflowPolicy::Reject);
+        let results = run_concurrently(&use_case, 5).await;
+
+        let rejected = results
+            .iter()
+            .filter(|r| matches!(r, Err(UseCaseError::ConcurrencyLimitReached(_))))
+            .count();
+        assert!(rejected > 0);
+        assert!(results.iter().any(|r| r.is_ok()));
+        assert_eq!(use_case.pipe_service.max_in_flight.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn test_requests_are_spread_across_instances() {
+        let (use_case, addresses) = multi_instance_use_case(RecordingService::default());
+
+        for _ in 0..4 {
+            use_case.execute(get("/api/x")).await.unwrap();
+        }
+
+        let seen = use_case.pipe_service.seen.lock().unwrap();
+        for address in &addresses {
+            assert_eq!(seen.iter().filter(|a| *a == address).count(), 2);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_unreachable_instance_is_skipped() {
+        let mut process = test_process();
+        process.instances = 2;
+        let dead = process.instance_addresses()[0].clone();
+        let (use_case, addresses) = multi_instance_use_case(RecordingService {
+            down: vec![dead.clone()],
+            ..RecordingService::default()
+        });
+
+        for _ in 0..4 {
+            use_case.execute(get("/api/x")).await.unwrap();
+        }
+
+        // The dead instance is tried once, then avoided
+        let seen = use_case.pipe_service.seen.lock().unwrap();
+        assert_eq!(seen.iter().filter(|a| **a == dead).count(), 1);
+        assert_eq!(seen.iter().filter(|a| **a == addresses[1]).count(), 4);
+    }
+
+    #[tokio::test]
+    async fn test_failed_upload_leaves_instances_healthy() {
+        let mock = MockPipeCommunicationService::new();
+        mock.respond("POST", "/api/upload", 200, "stored");
+        let mut process = test_process();
+        process.instances = 2;
+        process.communication_mode = CommunicationMode::Http;
+        process.protocol = SerializationFormat::Raw;
+        let addresses = process.instance_addresses();
+        let use_case = ProxyHttpRequestUseCase::new(Arc::new(mock.clone()), Arc::new(vec![process]));
+        let upload = |chunk: Result<Bytes, std::io::Error>| StreamingRequest {
+            method: HttpMethod::Post,
+            path: "/api/upload".to_string(),
+            headers: vec![],
+            body: Box::pin(futures_util::stream::iter([chunk])),
+        };
+
+        // The client hanging up mid-upload is no fault of the instance
+        let aborted = upload(Err(std::io::Error::from(std::io::ErrorKind::ConnectionReset)));
+        let result = use_case.execute_streaming(aborted).await;
+        assert!(matches!(
+            result,
+            Err(UseCaseError::CommunicationError { source: CommunicationError::RequestBodyFailed(_), .. })
+        ));
+
+        for _ in 0..4 {
+            use_case.execute_streaming(upload(Ok(Bytes::from("data")))).await.unwrap();
+        }
+        let received = mock.received();
+        for address in &addresses {
+            assert_eq!(received.iter().filter(|r| r.address == *address).count(), 2);
+        }
+    }
+
+    #[test]
+    fn test_content_type_is_checked_against_the_process_for_the_method() {
+        let mut uploads = test_process();
+        uploads.id = ProcessId::new("uploads").unwrap();
+        uploads.methods = vec![HttpMethod::Post];
+        uploads.accepted_content_types = vec!["multipart/form-data".to_string()];
+        let mut updates = test_process();
+        updates.methods = vec![HttpMethod::Put];
+        updates.accepted_content_types = vec!["application/json".to_string()];
+        let use_case = ProxyHttpRequestUseCase::new(Arc::new(SlowService::default()), Arc::new(vec![uploads, updates]));
+
+        assert!(use_case.accepts_content_type(&HttpMethod::Post, "/api/x", "multipart/form-data"));
+        assert!(!use_case.accepts_content_type(&HttpMethod::Post, "/api/x", "application/json"));
+        assert!(use_case.accepts_content_type(&HttpMethod::Put, "/api/x", "application/json"));
+        assert!(!use_case.accepts_content_type(&HttpMethod::Put, "/api/x", "multipart/form-data"));
+        // Neither serves it, so routing answers 405 instead
+        assert!(use_case.accepts_content_type(&HttpMethod::Delete, "/api/x", "text/plain"));
+    }
+
+    #[tokio::test]
+    async fn test_process_timeout() {
+        let mut process = test_process();
+        process.timeout = Some(Duration::from_millis(10));
+        let use_case = ProxyHttpRequestUseCase::new(Arc::new(SlowService::default()), Arc::new(vec![process]));
+
+        let result = use_case.execute(get("/api/x")).await;
+        assert!(matches!(
+            result,
+            Err(UseCaseError::CommunicationError { source: CommunicationError::Timeout(_), .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_deadline_header_reflects_process_timeout() {
+        let backend = MockPipeCommunicationService::new();
+        backend.respond("GET", "/api/x", 200, "ok");
+        backend.respond("GET", "/other/x", 200, "ok");
+        let mut timed = test_process();
+        timed.timeout = Some(Duration::from_secs(30));
+        let mut untimed = test_process();
+        untimed.id = ProcessId::new("other").unwrap();
+        untimed.route = Route::new("/other/*").unwrap();
+        let use_case = ProxyHttpRequestUseCase::new(Arc::new(backend.clone()), Arc::new(vec![timed, untimed]));
+        let now_millis = || SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).unwrap().as_millis() as u64;
+
+        let before = now_millis();
+        use_case.execute(get("/api/x")).await.unwrap();
+        let after = now_millis();
+        use_case.execute(get("/other/x")).await.unwrap();
+
+        // A sooner deadline set further up is passed on as is
+        let mut upstream = get("/api/x");
+        upstream.headers.push(("X-Request-Deadline".to_string(), (before + 1000).to_string()));
+        use_case.execute(upstream).await.unwrap();
+
+        let received = backend.received();
+        let deadline: u64 = received[0].header(DEADLINE_HEADER).unwrap().parse().unwrap();
+        assert!((before + 30_000..=after + 30_000).contains(&deadline), "{}", deadline);
+        assert_eq!(received[1].header(DEADLINE_HEADER), None);
+        let deadlines: Vec<_> = received[2]
+            .headers
+            .iter()
+            .filter(|(k, _)| k.eq_ignore_ascii_case(DEADLINE_HEADER))
+            .collect();
+        assert_eq!(deadlines, vec![&(DEADLINE_HEADER.to_string(), (before + 1000).to_string())]);
+    }
+
+    #[tokio::test]
+    async fn test_upgrade_target_requires_http_mode() {
+        let pipe = test_process();
+        let mut http = Process::new(
+            ProcessId::new("ws").unwrap(),
+            Executable::new("./ws").unwrap(),
+            Route::new("/ws/*").unwrap(),
+            PipeName::new("ws_http").unwrap(),
+        );
+        http.communication_mode = crate::domain::CommunicationMode::Http;
+        let expected_address = http.instance_addresses()[0].clone();
+        let use_case = ProxyHttpRequestUseCase::new(
+            Arc::new(RecordingService::default()),
+            Arc::new(vec![pipe, http]),
+        );
+
+        assert_eq!(use_case.upgrade_target(&HttpMethod::Get, "/api/x"), None);
+        assert_eq!(use_case.upgrade_target(&HttpMethod::Get, "/missing"), None);
+        assert_eq!(
+            use_case.upgrade_target(&HttpMethod::Get, "/ws/chat"),
+            Some(UpgradeTarget { process: "ws".to_string(), address: expected_address })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_http_fallback_when_pipe_is_down() {
+        let mut process = test_process();
+        process.http_fallback = true;
+        let pipe_address = process.instance_addresses()[0].clone();
+        let http_address = crate::domain::utils::get_http_address_from_name(process.pipe_name.as_str());
+
+        let pipe = Arc::new(RecordingService {
+            down: vec![pipe_address.clone()],
+            ..RecordingService::default()
+        });
+        let http = Arc::new(RecordingService::default());
+        let use_case = ProxyHttpRequestUseCase::new(pipe.clone(), Arc::new(vec![process]))
+            .with_http_service(http.clone());
+
+        let response = use_case.execute(get("/api/x")).await.unwrap();
+        assert_eq!(response.status_code, 200);
+        assert_eq!(*pipe.seen.lock().unwrap(), vec![pipe_address]);
+        assert_eq!(*http.seen.lock().unwrap(), vec![http_address]);
+    }
+
+    #[tokio::test]
+    async fn test_no_fallback_unless_enabled() {
+        let process = test_process();
+        let pipe = Arc::new(RecordingService {
+            down: process.instance_addresses(),
+            ..RecordingService::default()
+        });
+        let http = Arc::new(RecordingService::default());
+        let use_case = ProxyHttpRequestUseCase::new(pipe, Arc::new(vec![process]))
+            .with_http_service(http.clone());
+
+        let result = use_case.execute(get("/api/x")).await;
+        assert!(matches!(
+            result,
+            Err(UseCaseError::CommunicationError { source: CommunicationError::ConnectionFailed(_), .. })
+        ));
+        assert!(http.seen.lock().unwrap().is_empty());
+    }
+
+    /// Communication service that answers like a backend with no HEAD handler
+    struct GetOnlyService;
+
+    #[async_trait]
+    impl PipeCommunicationService for GetOnlyService {
+        async fn send_request(&self, _address: &str, request: Vec<u8>) -> Result<Vec<u8>, CommunicationError> {
+            let request: serde_json::Value = serde_json::from_slice(&request).unwrap();
+            let response = match request["method"].as_str() {
+                Some("GET") => serde_json::json!({"status": 200, "headers": {"X-Version": "7"}, "body": "aGVsbG8="}),
+                _ => serde_json::json!({"status": 405}),
+            };
+            Ok(serde_json::to_vec(&response).unwrap())
+        }
+    }
+
+    /// A use case retrying requests for `methods`, whose backend can't be
+    /// reached for the first `failures` of them
+    fn retrying_use_case(
+        failures: usize,
+        methods: Vec<HttpMethod>,
+    ) -> (ProxyHttpRequestUseCase<MockPipeCommunicationService>, MockPipeCommunicationService) {
+        let mut process = test_process();
+        process.retry = Some(crate::domain::RetryPolicy {
+            max_attempts: 3,
+            backoff: Duration::from_millis(1),
+            methods,
+        });
+        let mock = MockPipeCommunicationService::new();
+        for method in ["GET", "POST"] {
+            mock.respond(method, "/api/x", 200, "").fail_first(
+                method,
+                "/api/x",
+                failures,
+                CommunicationError::ConnectionFailed("refused".to_string()),
+            );
+        }
+        let use_case = ProxyHttpRequestUseCase::new(Arc::new(mock.clone()), Arc::new(vec![process]));
+        (use_case, mock)
+    }
+
+    #[tokio::test]
+    async fn test_idempotent_request_is_retried_after_transient_failure() {
+        let (use_case, mock) = retrying_use_case(2, vec![]);
+        assert_eq!(use_case.execute(get("/api/x")).await.unwrap().status_code, 200);
+        assert_eq!(mock.received().len(), 3);
+
+        // Attempts stop at the policy's maximum
+        let (use_case, mock) = retrying_use_case(5, vec![]);
+        assert!(use_case.execute(get("/api/x")).await.is_err());
+        assert_eq!(mock.received().len(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_post_is_not_retried_unless_opted_in() {
+        let post = || HttpRequest { method: HttpMethod::Post, ..get("/api/x") };
+
+        let (use_case, mock) = retrying_use_case(1, vec![]);
+        let result = use_case.execute(post()).await;
+        assert!(
+            matches!(result, Err(UseCaseError::CommunicationError { source: CommunicationError::ConnectionFailed(_), .. })),
+            "{:?}",
+            result
+        );
+        assert_eq!(mock.received().len(), 1);
+
+        let (use_case, mock) = retrying_use_case(1, vec![HttpMethod::Post]);
+        assert_eq!(use_case.execute(post()).await.unwrap().status_code, 200);
+        assert_eq!(mock.received().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_head_derived_from_get() {
+        let mut process = test_process();
+        process.head_from_get = true;
+        let use_case = ProxyHttpRequestUseCase::new(Arc::new(GetOnlyService), Arc::new(vec![process]));
+
+        let get_response = use_case.execute(get("/api/x")).await.unwrap();
+        let head_response = use_case
+            .execute(HttpRequest { method: HttpMethod::Head, ..get("/api/x") })
+            .await
+            .unwrap();
+
+        assert_eq!(head_response.status_code, get_response.status_code);
+        assert!(head_response.body.is_empty());
+        assert!(head_response.headers.contains(&("X-Version".to_string(), "7".to_string())));
+        assert!(head_response
+            .headers
+            .contains(&("content-length".to_string(), get_response.body.len().to_string())));
+    }
+
+    #[tokio::test]
+    async fn test_head_forwarded_unless_enabled() {
+        let use_case = ProxyHttpRequestUseCase::new(Arc::new(GetOnlyService), Arc::new(vec![test_process()]));
+
+        let response = use_case
+            .execute(HttpRequest { method: HttpMethod::Head, ..get("/api/x") })
+            .await
+            .unwrap();
+        assert_eq!(response.status_code, 405);
+    }
+
+    /// Backend whose health endpoint answers according to a switch
+    #[derive(Default)]
+    struct FlakyService {
+        healthy: std::sync::atomic::AtomicBool,
+    }
+
+    #[async_trait]
+    impl PipeCommunicationService for FlakyService {
+        async fn send_request(&self, _address: &str, request: Vec<u8>) -> Result<Vec<u8>, CommunicationError> {
+            let request: serde_json::Value = serde_json::from_slice(&request).unwrap();
+            let status = if request["uri"] == "/healthz" && !self.healthy.load(Ordering::SeqCst) {
+                503
+            } else {
+                200
+            };
+            Ok(serde_json::to_vec(&serde_json::json!({ "status": status })).unwrap())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_traffic_gated_on_health() {
+        let mut process = test_process();
+        process.health_check = Some(crate::domain::HealthCheck {
+            path: "/healthz".to_string(),
+            interval: Duration::from_millis(10),
+        });
+        let use_case = Arc::new(ProxyHttpRequestUseCase::new(
+            Arc::new(FlakyService::default()),
+            Arc::new(vec![process]),
+        ));
+        let checks = use_case.spawn_health_checks();
+
+        // Not ready until the first check passes, and the check keeps failing
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        let result = use_case.execute(get("/api/x")).await;
+        assert!(matches!(result, Err(UseCaseError::ProcessUnavailable(ref p)) if p == "api"));
+
+        use_case.pipe_service.healthy.store(true, Ordering::SeqCst);
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(use_case.execute(get("/api/x")).await.is_ok());
+
+        use_case.pipe_service.healthy.store(false, Ordering::SeqCst);
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(matches!(use_case.execute(get("/api/x")).await, Err(UseCaseError::ProcessUnavailable(_))));
+
+        checks.iter().for_each(|c| c.abort());
+    }
+
+    #[tokio::test]
+    async fn test_unhealthy_process_is_skipped_for_next_match() {
+        let mut checked = test_process();
+        checked.health_check = Some(crate::domain::HealthCheck {
+            path: "/healthz".to_string(),
+            interval: Duration::from_secs(60),
+        });
+        let mut fallback = test_process();
+        fallback.id = ProcessId::new("fallback").unwrap();
+        let use_case = ProxyHttpRequestUseCase::new(
+            Arc::new(RecordingService::default()),
+            Arc::new(vec![checked, fallback]),
+        );
+
+        // "api" has not passed a check yet, so "fallback" serves the route
+        use_case.execute(get("/api/x")).await.unwrap();
+        assert_eq!(use_case.health().get("api"), HealthState::Starting);
+    }
+
+    #[tokio::test]
+    async fn test_timings_report_cache_hits() {
+        let use_case = ProxyHttpRequestUseCase::new_with_cache(
+            Arc::new(RecordingService::default()),
+            Arc::new(vec![test_process()]),
+            Some(10),
+        );
+
+        let miss = use_case.execute_timed(get("/api/x")).await.unwrap().timings;
+        assert!(!miss.cache_hit);
+        assert!(miss.serialize.is_some() && miss.backend.is_some() && miss.deserialize.is_some());
+
+        let hit = use_case.execute_timed(get("/api/x")).await.unwrap().timings;
+        assert_eq!(hit, RequestTimings { cache_hit: true, ..RequestTimings::default() });
+    }
+
+    #[tokio::test]
+    async fn test_cache_stats_count_evictions() {
+        let use_case = ProxyHttpRequestUseCase::new_with_cache(
+            Arc::new(RecordingService::default()),
+            Arc::new(vec![test_process()]),
+            Some(1),
+        );
+        assert_eq!(use_case.cache_stats().await, Some(CacheStats::default()));
+
+        use_case.execute(get("/api/a")).await.unwrap();
+        use_case.execute(get("/api/b")).await.unwrap();
+        let stats = use_case.cache_stats().await.unwrap();
+        assert_eq!(stats, CacheStats { entries: 1, hits: 0, misses: 2, evictions: 1 });
+
+        // Explicitly cleared entries aren't evictions
+        assert_eq!(use_case.clear_cache().await, Some(1));
+        assert_eq!(use_case.cache_stats().await.unwrap().evictions, 1);
+
+        let uncached = ProxyHttpRequestUseCase::new(Arc::new(RecordingService::default()), Arc::new(vec![test_process()]));
+        assert_eq!(uncached.cache_stats().await, None);
+        assert_eq!(uncached.clear_cache().await, None);
+    }
+
+    fn cached_slow_use_case() -> Arc<ProxyHttpRequestUseCase<SlowService>> {
+        Arc::new(ProxyHttpRequestUseCase::new_with_cache(
+            Arc::new(SlowService::default()),
+            Arc::new(vec![test_process()]),
+            Some(10),
+        ))
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_misses_are_coalesced() {
+        let use_case = cached_slow_use_case();
+
+        let results = run_concurrently(&use_case, 10).await;
+        assert!(results.iter().all(|r| r.is_ok()));
+        assert_eq!(use_case.pipe_service.calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn test_coalesced_failure_reaches_every_waiter() {
+        let use_case = cached_slow_use_case();
+        use_case.pipe_service.failing.store(true, Ordering::SeqCst);
+
+        let results = run_concurrently(&use_case, 5).await;
+        assert!(results
+            .iter()
+            .all(|r| matches!(r, Err(UseCaseError::CommunicationError { source: CommunicationError::ReceiveFailed(_), .. }))));
+        assert_eq!(use_case.pipe_service.calls.load(Ordering::SeqCst), 1);
+
+        // The failure wasn't cached, so the next request tries again
+        use_case.pipe_service.failing.store(false, Ordering::SeqCst);
+        assert!(use_case.execute(get("/api/x")).await.is_ok());
+        assert_eq!(use_case.pipe_service.calls.load(Ordering::SeqCst), 2);
+    }
+
+    /// Communication service answering with a switchable status code
+    struct StatusService {
+        status: std::sync::atomic::AtomicU16,
+        calls: AtomicUsize,
+    }
+
+    #[async_trait]
+    impl PipeCommunicationService for StatusService {
+        async fn send_request(&self, _address: &str, _request: Vec<u8>) -> Result<Vec<u8>, CommunicationError> {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            let status = self.status.load(Ordering::SeqCst);
+            Ok(serde_json::to_vec(&serde_json::json!({ "status": status })).unwrap())
+        }
+    }
+
+    fn negative_cache_use_case(status: u16, policy: Option<crate::domain::NegativeCachePolicy>) -> ProxyHttpRequestUseCase<StatusService> {
+        let mut process = test_process();
+        process.negative_cache = policy;
+        let service = StatusService {
+            status: status.into(),
+            calls: AtomicUsize::new(0),
+        };
+        ProxyHttpRequestUseCase::new_with_cache(Arc::new(service), Arc::new(vec![process]), Some(10))
+    }
+
+    #[tokio::test]
+    async fn test_cache_varies_on_configured_headers() {
+        let mut process = test_process();
+        process.cache_vary = vec!["accept".to_string()];
+        let service = StatusService {
+            status: 200.into(),
+            calls: AtomicUsize::new(0),
+        };
+        let use_case = ProxyHttpRequestUseCase::new_with_cache(Arc::new(service), Arc::new(vec![process]), Some(10));
+        let calls = || use_case.pipe_service.calls.load(Ordering::SeqCst);
+        let with_accept = |name: &str, value: &str| HttpRequest {
+            headers: vec![(name.to_string(), value.to_string())],
+            ..get("/api/x")
+        };
+
+        use_case.execute(with_accept("accept", "application/json")).await.unwrap();
+        use_case.execute(with_accept("accept", "text/html")).await.unwrap();
+        assert_eq!(calls(), 2);
+
+        // Header names match regardless of case
+        use_case.execute(with_accept("Accept", "text/html")).await.unwrap();
+        assert_eq!(calls(), 2);
+
+        // No header and an empty header are distinct from each other and
+        // from any value; other headers don't matter
+        use_case.execute(get("/api/x")).await.unwrap();
+        use_case.execute(with_accept("accept", "")).await.unwrap();
+        use_case.execute(with_accept("user-agent", "curl")).await.unwrap();
+        assert_eq!(calls(), 4);
+    }
+
+    #[tokio::test]
+    async fn test_cache_ignores_configured_query_params() {
+        let mut process = test_process();
+        process.cache_ignore_query = vec!["_".to_string(), "utm_source".to_string()];
+        let service = StatusService {
+            status: 200.into(),
+            calls: AtomicUsize::new(0),
+        };
+        let use_case = ProxyHttpRequestUseCase::new_with_cache(Arc::new(service), Arc::new(vec![process]), Some(10));
+        let calls = || use_case.pipe_service.calls.load(Ordering::SeqCst);
+
+        use_case.execute(get("/api/x?page=2&_=169999")).await.unwrap();
+        use_case.execute(get("/api/x?_=170000&page=2&utm_source=mail")).await.unwrap();
+        use_case.execute(get("/api/x?page=2")).await.unwrap();
+        assert_eq!(calls(), 1);
+
+        // Parameters that aren't ignored still get their own entries
+        use_case.execute(get("/api/x?page=3&_=1")).await.unwrap();
+        use_case.execute(get("/api/x")).await.unwrap();
+        use_case.execute(get("/api/x?_=1")).await.unwrap();
+        assert_eq!(calls(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_shared_cache_serves_a_resource_once_across_routes() {
+        let process = |id: &str, route: &str, group: Option<&str>| {
+            let mut process = Process::new(
+                ProcessId::new(id).unwrap(),
+                Executable::new("./cdn").unwrap(),
+                Route::new(route).unwrap(),
+                PipeName::new(format!("{}_pipe", id)).unwrap(),
+            );
+            process.shared_cache = group.map(str::to_string);
+            process
+        };
+        let processes = vec![
+            process("cdn-a", "/cdn-a/*", Some("assets")),
+            process("cdn-b", "/v2/cdn-b/*", Some("assets")),
+            process("tenant", "/tenant/*", None),
+        ];
+        let service = StatusService {
+            status: 200.into(),
+            calls: AtomicUsize::new(0),
+        };
+        let use_case = ProxyHttpRequestUseCase::new_with_cache(Arc::new(service), Arc::new(processes), Some(10));
+        let calls = || use_case.pipe_service.calls.load(Ordering::SeqCst);
+
+        use_case.execute(get("/cdn-a/img/logo.png?v=1")).await.unwrap();
+        let timed = use_case.execute_timed(get("/v2/cdn-b/img/logo.png?v=1")).await.unwrap();
+        assert!(timed.timings.cache_hit);
+        assert_eq!(calls(), 1);
+        assert_eq!(use_case.cache_stats().await.unwrap().hits, 1);
+
+        // A process outside the group never gets the group's responses
+        use_case.execute(get("/tenant/img/logo.png?v=1")).await.unwrap();
+        assert_eq!(calls(), 2);
+        use_case.execute(get("/v2/cdn-b/img/logo.png?v=2")).await.unwrap();
+        assert_eq!(calls(), 3);
+
+        // Only the part of a path below a route's prefix is shared: `/cdn-ax`
+        // isn't `/x` under `/cdn-a/*`, and an exact route has no part below it
+        let processes = vec![
+            process("favicon", "/favicon.ico", Some("assets")),
+            process("robots", "/robots.txt", Some("assets")),
+            process("cdn-a", "/cdn-a/*", Some("assets")),
+        ];
+        let use_case = ProxyHttpRequestUseCase::new(Arc::new(SlowService::default()), Arc::new(processes));
+        assert_ne!(
+            use_case.generate_cache_key(&get("/cdn-ax")),
+            use_case.generate_cache_key(&get("/cdn-a/x"))
+        );
+        assert_ne!(
+            use_case.generate_cache_key(&get("/favicon.ico")),
+            use_case.generate_cache_key(&get("/robots.txt"))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_prefix_is_stripped_before_forwarding() {
+        let mock = MockPipeCommunicationService::new();
+        mock.respond("GET", "/users", 200, "")
+            .respond("GET", "/users?page=2", 200, "")
+            .respond("GET", "/internal/users", 200, "");
+        let paths = |mock: &MockPipeCommunicationService| -> Vec<String> {
+            mock.received().into_iter().map(|request| request.path).collect()
+        };
+        let mut process = Process::new(
+            ProcessId::new("users").unwrap(),
+            Executable::new("./users").unwrap(),
+            Route::new("/api/v1/*").unwrap(),
+            PipeName::new("users_pipe").unwrap(),
+        );
+        process.strip_prefix = Some("/api/v1".to_string());
+        let use_case = ProxyHttpRequestUseCase::new_with_cache(Arc::new(mock.clone()), Arc::new(vec![process.clone()]), Some(10));
+
+        use_case.execute(get("/api/v1/users")).await.unwrap();
+        use_case.execute(get("/api/v1/users?page=2")).await.unwrap();
+        assert_eq!(paths(&mock), vec!["/users", "/users?page=2"]);
+
+        // Cached under the path the backend was sent
+        assert!(use_case.execute_timed(get("/api/v1/users")).await.unwrap().timings.cache_hit);
+        assert_eq!(use_case.generate_cache_key(&get("/api/v1/users")), "users>GET:/users");
+
+        process.prepend_path = Some("/internal".to_string());
+        let use_case = ProxyHttpRequestUseCase::new(Arc::new(mock.clone()), Arc::new(vec![process]));
+        use_case.execute(get("/api/v1/users")).await.unwrap();
+        assert_eq!(paths(&mock)[2..], ["/internal/users"]);
+    }
+
+    #[tokio::test]
+    async fn test_cache_key_ignores_query_param_order() {
+        let service = StatusService {
+            status: 200.into(),
+            calls: AtomicUsize::new(0),
+        };
+        let use_case = ProxyHttpRequestUseCase::new_with_cache(Arc::new(service), Arc::new(vec![test_process()]), Some(10));
+        let calls = || use_case.pipe_service.calls.load(Ordering::SeqCst);
+
+        use_case.execute(get("/api/x?a=1&b=2")).await.unwrap();
+        use_case.execute(get("/api/x?b=2&a=1")).await.unwrap();
+        assert_eq!(calls(), 1);
+
+        // Repeated parameters keep their order, which can matter
+        use_case.execute(get("/api/x?a=1&a=2")).await.unwrap();
+        use_case.execute(get("/api/x?a=2&a=1")).await.unwrap();
+        assert_eq!(calls(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_cached_404_expires_and_is_refreshed() {
+        let use_case = negative_cache_use_case(
+            404,
+            Some(crate::domain::NegativeCachePolicy {
+                ttl: Duration::from_millis(100),
+                server_errors: vec![],
+            }),
+        );
+        let calls = || use_case.pipe_service.calls.load(Ordering::SeqCst);
+
+        assert_eq!(use_case.execute(get("/api/x")).await.unwrap().status_code, 404);
+        use_case.pipe_service.status.store(200, Ordering::SeqCst);
+        assert_eq!(use_case.execute(get("/api/x")).await.unwrap().status_code, 404);
+        assert_eq!(calls(), 1);
+
+        // Once the negative entry expires the success replaces it for good
+        tokio::time::sleep(Duration::from_millis(150)).await;
+        assert_eq!(use_case.execute(get("/api/x")).await.unwrap().status_code, 200);
+        tokio::time::sleep(Duration::from_millis(150)).await;
+        assert_eq!(use_case.execute(get("/api/x")).await.unwrap().status_code, 200);
+        assert_eq!(calls(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_configured_server_errors_are_cached() {
+        let policy = crate::domain::NegativeCachePolicy {
+            ttl: Duration::from_secs(60),
+            server_errors: vec![503],
+        };
+
+        let use_case = negative_cache_use_case(503, Some(policy.clone()));
+        use_case.execute(get("/api/x")).await.unwrap();
+        use_case.execute(get("/api/x")).await.unwrap();
+        assert_eq!(use_case.pipe_service.calls.load(Ordering::SeqCst), 1);
+
+        let use_case = negative_cache_use_case(500, Some(policy));
+        use_case.execute(get("/api/x")).await.unwrap();
+        use_case.execute(get("/api/x")).await.unwrap();
+        assert_eq!(use_case.pipe_service.calls.load(Ordering::SeqCst), 2);
+    }
+
+    /// How many of two identical requests reach a backend answering with
+    /// `cache_control`, then how many of two more after `pause`
+    async fn backend_calls_with(cache_control: &'static str, pause: Duration) -> (usize, usize) {
+        let backend = MockPipeCommunicationService::new();
+        backend.respond_with(
+            "GET",
+            "/api/x",
+            HttpResponse {
+                status_code: 200,
+                headers: vec![("Cache-Control".to_string(), cache_control.to_string())],
+                body: Vec::new(),
+                trailers: Vec::new(),
+            },
+        );
+        let use_case = ProxyHttpRequestUseCase::new_with_cache(Arc::new(backend), Arc::new(vec![test_process()]), Some(10));
+        let calls = || use_case.pipe_service.received().len();
+
+        use_case.execute(get("/api/x")).await.unwrap();
+        use_case.execute(get("/api/x")).await.unwrap();
+        let before = calls();
+        tokio::time::sleep(pause).await;
+        use_case.execute(get("/api/x")).await.unwrap();
+        use_case.execute(get("/api/x")).await.unwrap();
+        (before, calls())
+    }
+
+    #[tokio::test]
+    async fn test_payload_sizes_are_recorded_per_process() {
+        let backend = MockPipeCommunicationService::new();
+        backend.respond("POST", "/api/upload", 201, vec![b'x'; 300]);
+        let use_case = ProxyHttpRequestUseCase::new(Arc::new(backend), Arc::new(vec![test_process()]));
+
+        let request = HttpRequest {
+            method: HttpMethod::Post,
+            body: vec![b'y'; 2000],
+            ..get("/api/upload")
+        };
+        let timed = use_case.execute_timed(request).await.unwrap();
+        assert_eq!(timed.request_bytes, Some(2000));
+
+        let metrics = use_case.payload_metrics().to_prometheus();
+        assert!(metrics.contains("request_bytes_sum{process=\"api\"} 2000\n"), "{}", metrics);
+        assert!(metrics.contains("request_bytes_bucket{process=\"api\",le=\"1024\"} 0\n"), "{}", metrics);
+        assert!(metrics.contains("request_bytes_bucket{process=\"api\",le=\"4096\"} 1\n"), "{}", metrics);
+        assert!(metrics.contains("response_bytes_sum{process=\"api\"} 300\n"), "{}", metrics);
+        assert!(metrics.contains("response_bytes_count{process=\"api\"} 1\n"), "{}", metrics);
+    }
+
+    #[tokio::test]
+    async fn test_envelope_in_another_format_is_reported() {
+        let backend = MockPipeCommunicationService::new();
+        backend.respond_with_envelope("GET", "/api/x", r#"{"status": 200, "body": ""}"#);
+        let mut process = test_process();
+        process.protocol = SerializationFormat::MsgPack;
+        let use_case = ProxyHttpRequestUseCase::new(Arc::new(backend), Arc::new(vec![process]));
+
+        match use_case.execute(get("/api/x")).await {
+            Err(UseCaseError::DeserializationError(e)) => {
+                assert!(e.contains("a json envelope, but the process's protocol is msgpack"), "{}", e)
+            }
+            other => panic!("expected a deserialization error, got {:?}", other.map(|r| r.status_code)),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_no_store_response_is_never_cached() {
+        assert_eq!(backend_calls_with("no-store", Duration::ZERO).await, (2, 4));
+        assert_eq!(backend_calls_with("public, No-Store", Duration::ZERO).await, (2, 4));
+        assert_eq!(backend_calls_with("private, max-age=60", Duration::ZERO).await, (2, 4));
+    }
+
+    #[tokio::test]
+    async fn test_max_age_sets_entry_expiry() {
+        // Kept for its max-age, then fetched again and kept anew
+        assert_eq!(backend_calls_with("max-age=1", Duration::from_millis(1100)).await, (1, 2));
+        assert_eq!(backend_calls_with("public, max-age=\"60\"", Duration::ZERO).await, (1, 1));
+        // s-maxage is meant for shared caches, so it wins
+        assert_eq!(backend_calls_with("max-age=60, s-maxage=0", Duration::ZERO).await, (2, 4));
+    }
+
+    #[test]
+    fn test_cache_control_parsing() {
+        let headers = |values: &[&str]| -> Vec<(String, String)> {
+            values.iter().map(|v| ("cache-control".to_string(), v.to_string())).collect()
+        };
+        assert_eq!(cache_control(&headers(&[])), CacheControl::Unspecified);
+        assert_eq!(cache_control(&headers(&["public", "max-age=30"])), CacheControl::MaxAge(Duration::from_secs(30)));
+        assert_eq!(cache_control(&headers(&["max-age=soon"])), CacheControl::Unspecified);
+        assert_eq!(cache_control(&headers(&["max-age=30", "no-cache"])), CacheControl::NotStorable);
+    }
+
+    #[tokio::test]
+    async fn test_errors_not_cached_without_policy() {
+        let use_case = negative_cache_use_case(404, None);
+        use_case.execute(get("/api/x")).await.unwrap();
+        use_case.execute(get("/api/x")).await.unwrap();
+        assert_eq!(use_case.pipe_service.calls.load(Ordering::SeqCst), 2);
+    }
+
+    /// Backend speaking MessagePack that echoes the request body back
+    struct MsgPackEchoService;
+
+    #[async_trait]
+    impl PipeCommunicationService for MsgPackEchoService {
+        async fn send_request(&self, _address: &str, request: Vec<u8>) -> Result<Vec<u8>, CommunicationError> {
+            #[derive(serde::Serialize, serde::Deserialize)]
+            struct Envelope {
+                #[serde(default)]
+                status: u16,
+                #[serde(with = "serde_bytes")]
+                body: Vec<u8>,
+            }
+
+            let request: Envelope = rmp_serde::from_slice(&request).unwrap();
+            let response = Envelope { status: 200, body: request.body };
+            Ok(rmp_serde::to_vec_named(&response).unwrap())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_msgpack_protocol() {
+        let mut process = test_process();
+        process.protocol = SerializationFormat::MsgPack;
+        let use_case = ProxyHttpRequestUseCase::new(Arc::new(MsgPackEchoService), Arc::new(vec![process]));
+
+        let body: Vec<u8> = (0..=255).collect();
+        let response = use_case
+            .execute(HttpRequest {
+                method: HttpMethod::Post,
+                body: body.clone(),
+                ..get("/api/x")
+            })
+            .await
+            .unwrap();
+        assert_eq!(response.status_code, 200);
+        assert_eq!(response.body, body);
+    }
+
+    /// Proxy one POST and return the body log lines it produced
+    async fn body_log_lines(process: Process, options: ProxyOptions) -> Vec<String> {
+        let (captured, _guard) = CapturedLogs::capture();
+
+        // "hello" in base64
+        let service = StubService { response: br#"{"status": 200, "body": "aGVsbG8="}"#.to_vec() };
+        let use_case = ProxyHttpRequestUseCase::with_options(Arc::new(service), Arc::new(vec![process]), options);
+        let request = HttpRequest {
+            method: HttpMethod::Post,
+            body: b"token=s3cret".to_vec(),
+            ..get("/api/login")
+        };
+        use_case.execute(request).await.unwrap();
+
+        captured.lines().into_iter().filter(|line| line.contains(" body for ")).collect()
+    }
+
+    // The subscriber is thread-local, so the use case has to run on this thread
+    #[tokio::test(flavor = "current_thread")]
+    async fn test_bodies_are_logged_only_when_enabled() {
+        assert!(body_log_lines(test_process(), ProxyOptions::default()).await.is_empty());
+
+        let mut process = test_process();
+        process.debug_body = true;
+        let lines = body_log_lines(process, ProxyOptions::default()).await;
+        assert_eq!(lines.len(), 2, "{:?}", lines);
+        assert!(lines[0].contains(r#"request body for POST /api/login ('api'): "token=s3cret""#), "{}", lines[0]);
+        assert!(lines[1].contains(r#"response body for POST /api/login ('api'): "hello""#), "{}", lines[1]);
+
+        let options = ProxyOptions {
+            debug_bodies: true,
+            debug_body_limit: Some(5),
+            ..ProxyOptions::default()
+        };
+        let lines = body_log_lines(test_process(), options).await;
+        assert_eq!(lines.len(), 2, "{:?}", lines);
+        assert!(lines[0].ends_with(r#""token" ... (5 of 12 bytes)"#), "{}", lines[0]);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_wait_until_ready_waits_for_socket() {
+        use crate::infrastructure::NamedPipeClient;
+
+        let pipe_name = format!("ready_test_{}", std::process::id());
+        let mut process = test_process();
+        process.pipe_name = PipeName::new(pipe_name.clone()).unwrap();
+        let socket_path = crate::domain::utils::get_pipe_address_from_name(&pipe_name);
+        let _ = std::fs::remove_file(&socket_path);
+
+        // The backend only binds its socket some time after starting
+        let backend_path = socket_path.clone();
+        let backend = tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(300)).await;
+            let listener = tokio::net::UnixListener::bind(&backend_path).unwrap();
+            let _ = listener.accept().await;
+        });
+
+        let options = ProxyOptions {
+            ready_timeout: Some(Duration::from_secs(5)),
+            ready_poll_interval: Some(Duration::from_millis(20)),
+            ..ProxyOptions::default()
+        };
+        let use_case =
+            ProxyHttpRequestUseCase::with_options(Arc::new(NamedPipeClient::new()), Arc::new(vec![process]), options);
+
+        let started = Instant::now();
+        use_case.wait_until_ready().await.unwrap();
+        assert!(started.elapsed() >= Duration::from_millis(300));
+
+        backend.await.unwrap();
+        let _ = std::fs::remove_file(&socket_path);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_wait_until_ready_names_missing_socket() {
+        use crate::infrastructure::NamedPipeClient;
+
+        let pipe_name = format!("never_ready_test_{}", std::process::id());
+        let mut process = test_process();
+        process.pipe_name = PipeName::new(pipe_name.clone()).unwrap();
+        let options = ProxyOptions {
+            ready_timeout: Some(Duration::from_millis(100)),
+            ready_poll_interval: Some(Duration::from_millis(20)),
+            ..ProxyOptions::default()
+        };
+        let use_case =
+            ProxyHttpRequestUseCase::with_options(Arc::new(NamedPipeClient::new()), Arc::new(vec![process]), options);
+
+        let error = use_case.wait_until_ready().await.unwrap_err();
+        let message = error.to_string();
+        assert_eq!(error.process(), Some("api"));
+        assert!(message.contains(&format!("socket /tmp/{} not ready", pipe_name)), "{}", message);
+    }
+
+    /// Backend answering 500 for `failing_path` and 200 for the other
+    /// warm-up, health check and client paths
+    fn warmup_backend(failing_path: Option<&str>) -> MockPipeCommunicationService {
+        let backend = MockPipeCommunicationService::new();
+        for (method, path) in [("GET", "/healthz"), ("POST", "/prime"), ("GET", "/ping"), ("GET", "/api/x")] {
+            let status = if failing_path == Some(path) { 500 } else { 200 };
+            backend.respond(method, path, status, "");
+        }
+        backend
+    }
+
+    /// Method and path of every request `backend` received, in order
+    fn seen(backend: &MockPipeCommunicationService) -> Vec<String> {
+        backend.received().iter().map(|r| format!("{} {}", r.method, r.path)).collect()
+    }
+
+    fn warmed_process() -> Process {
+        let mut process = test_process();
+        process.instances = 2;
+        process.warmup = vec![
+            crate::domain::WarmupRequest {
+                method: crate::domain::HttpMethod::Get,
+                path: "/healthz".to_string(),
+            },
+            crate::domain::WarmupRequest {
+                method: crate::domain::HttpMethod::Post,
+                path: "/prime".to_string(),
+            },
+        ];
+        process
+    }
+
+    #[tokio::test]
+    async fn test_warmup_reaches_each_instance_before_client_requests() {
+        let backend = warmup_backend(None);
+        let use_case = ProxyHttpRequestUseCase::new(Arc::new(backend.clone()), Arc::new(vec![warmed_process()]));
+
+        use_case.wait_until_ready().await.unwrap();
+        use_case.execute(get("/api/x")).await.unwrap();
+
+        assert_eq!(
+            seen(&backend),
+            vec!["GET /healthz", "POST /prime", "GET /healthz", "POST /prime", "GET /api/x"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_failed_warmup_only_blocks_strict_processes() {
+        let backend = Arc::new(warmup_backend(Some("/prime")));
+        let use_case = ProxyHttpRequestUseCase::new(backend.clone(), Arc::new(vec![warmed_process()]));
+        use_case.wait_until_ready().await.unwrap();
+
+        let mut process = warmed_process();
+        process.warmup_strict = true;
+        let use_case = ProxyHttpRequestUseCase::new(backend, Arc::new(vec![process]));
+        let error = use_case.wait_until_ready().await.unwrap_err();
+        assert!(matches!(error, UseCaseError::WarmupFailed(ref id) if id == "api"), "{}", error);
+    }
+
+    #[tokio::test]
+    async fn test_health_checked_process_is_warmed_up_before_it_is_healthy() {
+        let backend = warmup_backend(Some("/prime"));
+        let mut process = warmed_process();
+        process.instances = 1;
+        process.warmup_strict = true;
+        process.health_check = Some(HealthCheck {
+            path: "/ping".to_string(),
+            interval: Duration::from_secs(1),
+        });
+        let use_case = ProxyHttpRequestUseCase::new(Arc::new(backend.clone()), Arc::new(vec![process.clone()]));
+
+        // A strict warm-up that fails keeps the process out of service
+        assert_eq!(use_case.check_health(&process).await, HealthState::Starting);
+        assert_eq!(seen(&backend), vec!["GET /ping", "GET /healthz", "POST /prime"]);
+
+        // Warm-ups stop once the process is healthy
+        let backend = warmup_backend(None);
+        let use_case = ProxyHttpRequestUseCase::new(Arc::new(backend.clone()), Arc::new(vec![process.clone()]));
+        assert_eq!(use_case.check_health(&process).await, HealthState::Healthy);
+        assert_eq!(use_case.check_health(&process).await, HealthState::Healthy);
+        assert_eq!(seen(&backend), vec!["GET /ping", "GET /healthz", "POST /prime", "GET /ping"]);
+    }
+
+    /// Backend speaking plain HTTP, answering with the number of body chunks
+    /// it was streamed and their contents
+    struct RawService;
+
+    #[async_trait]
+    impl PipeCommunicationService for RawService {
+        async fn send_request(&self, _address: &str, _request: Vec<u8>) -> Result<Vec<u8>, CommunicationError> {
+            panic!("raw processes are never sent an envelope");
+        }
+
+        async fn send_streaming(
+            &self,
+            _address: &str,
+            request: StreamingRequest,
+        ) -> Result<HttpResponse, CommunicationError> {
+            let chunks: Vec<Bytes> = request.body.try_collect().await.unwrap();
+            Ok(HttpResponse {
+                status_code: 202,
+                headers: vec![("x-chunks".to_string(), chunks.len().to_string())],
+                body: format!("{} {}", request.path, String::from_utf8(chunks.concat()).unwrap()).into_bytes(),
+                trailers: Vec::new(),
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_raw_process_is_streamed_to() {
+        let mut raw = test_process();
+        raw.communication_mode = CommunicationMode::Http;
+        raw.protocol = SerializationFormat::Raw;
+        let mut enveloped = test_process();
+        enveloped.id = ProcessId::new("web").unwrap();
+        enveloped.route = Route::new("/web/*").unwrap();
+        let use_case = ProxyHttpRequestUseCase::new(Arc::new(RawService), Arc::new(vec![raw, enveloped]));
+
+        assert!(use_case.streams_requests("/api/upload"));
+        assert!(!use_case.streams_requests("/web/x"));
+
+        let chunks = ["one ", "two ", "three"].map(|c| Ok(Bytes::from(c)));
+        let request = StreamingRequest {
+            method: HttpMethod::Post,
+            path: "/api/upload".to_string(),
+            headers: vec![],
+            body: Box::pin(futures_util::stream::iter(chunks)),
+        };
+        let timed = use_case.execute_streaming(request).await.unwrap();
+        assert_eq!(timed.process.as_deref(), Some("api"));
+        assert_eq!(timed.response.status_code, 202);
+        assert_eq!(timed.response.headers, vec![("x-chunks".to_string(), "3".to_string())]);
+        assert_eq!(timed.response.body, b"/api/upload one two three");
+
+        // A buffered request reaches it the same way, as a single chunk
+        let mut request = get("/api/x");
+        request.body = b"buffered".to_vec();
+        let response = use_case.execute(request).await.unwrap();
+        assert_eq!(response.headers, vec![("x-chunks".to_string(), "1".to_string())]);
+        assert_eq!(response.body, b"/api/x buffered");
+    }
+}
@@ -0,0 +1,139 @@
+//! MessagePack envelopes - the same fields as the JSON envelopes, with the
+//! body carried as raw bytes instead of base64
+
+use crate::domain::{HttpRequest, HttpResponse};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+#[derive(Serialize)]
+struct RequestEnvelope<'a> {
+    method: &'a str,
+    uri: &'a str,
+    headers: &'a [(String, String)],
+    #[serde(with = "serde_bytes")]
+    body: &'a [u8],
+}
+
+#[derive(Deserialize)]
+struct ResponseEnvelope {
+    status: Option<u64>,
+    #[serde(default)]
+    headers: HashMap<String, String>,
+    #[serde(default, with = "serde_bytes")]
+    body: Vec<u8>,
+}
+
+/// Encode a request as a MessagePack map
+pub fn encode_request(request: &HttpRequest) -> Result<Vec<u8>, String> {
+    let envelope = RequestEnvelope {
+        method: request.method.as_str(),
+        uri: &request.path,
+        headers: &request.headers,
+        body: &request.body,
+    };
+    rmp_serde::to_vec_named(&envelope).map_err(|e| e.to_string())
+}
+
+/// Decode a MessagePack response envelope
+///
+/// As with JSON, a missing or out-of-range status is an error unless
+/// `lenient` is set, in which case it defaults to 200.
+pub fn decode_response(data: &[u8], lenient: bool) -> Result<HttpResponse, String> {
+    let envelope: ResponseEnvelope =
+        rmp_serde::from_slice(data).map_err(|e| format!("invalid MessagePack: {}", e))?;
+
+    let status_code = match envelope.status {
+        Some(status) if (100..=599).contains(&status) => status as u16,
+        _ if lenient => 200,
+        Some(status) => return Err(format!("invalid status field: {}", status)),
+        None => return Err("missing status field".to_string()),
+    };
+
+    Ok(HttpResponse {
+        status_code,
+        headers: envelope.headers.into_iter().collect(),
+        body: envelope.body,
+        trailers: Vec::new(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::HttpMethod;
+
+    fn request(body: Vec<u8>) -> HttpRequest {
+        HttpRequest {
+            method: HttpMethod::Post,
+            path: "/api/upload".to_string(),
+            headers: vec![("content-type".to_string(), "application/octet-stream".to_string())],
+            body,
+        }
+    }
+
+    #[derive(Deserialize)]
+    struct DecodedRequest {
+        method: String,
+        uri: String,
+        headers: Vec<(String, String)>,
+        #[serde(with = "serde_bytes")]
+        body: Vec<u8>,
+    }
+
+    #[derive(Serialize)]
+    struct EncodedResponse<'a> {
+        status: u16,
+        headers: HashMap<&'a str, &'a str>,
+        #[serde(with = "serde_bytes")]
+        body: &'a [u8],
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let body: Vec<u8> = (0..=255).collect();
+        let decoded: DecodedRequest = rmp_serde::from_slice(&encode_request(&request(body.clone())).unwrap()).unwrap();
+        assert_eq!(decoded.method, "POST");
+        assert_eq!(decoded.uri, "/api/upload");
+        assert_eq!(decoded.headers, request(vec![]).headers);
+        assert_eq!(decoded.body, body);
+
+        let response = rmp_serde::to_vec_named(&EncodedResponse {
+            status: 201,
+            headers: HashMap::from([("x-id", "7")]),
+            body: &body,
+        })
+        .unwrap();
+        let response = decode_response(&response, false).unwrap();
+        assert_eq!(response.status_code, 201);
+        assert_eq!(response.headers, vec![("x-id".to_string(), "7".to_string())]);
+        assert_eq!(response.body, body);
+    }
+
+    #[test]
+    fn test_missing_status_is_rejected_unless_lenient() {
+        #[derive(Serialize)]
+        struct Empty {}
+
+        let data = rmp_serde::to_vec_named(&Empty {}).unwrap();
+        assert!(decode_response(&data, false).is_err());
+        assert_eq!(decode_response(&data, true).unwrap().status_code, 200);
+    }
+
+    #[test]
+    fn test_smaller_than_json() {
+        use base64::{engine::general_purpose, Engine as _};
+
+        let request = request(vec![0xAB; 64 * 1024]);
+        let json = serde_json::to_vec(&serde_json::json!({
+            "method": request.method.as_str(),
+            "uri": request.path,
+            "headers": request.headers,
+            "body": general_purpose::STANDARD.encode(&request.body),
+        }))
+        .unwrap();
+        let msgpack = encode_request(&request).unwrap();
+
+        // base64 alone inflates the body by a third
+        assert!(msgpack.len() * 4 < json.len() * 3 + 64, "{} vs {}", msgpack.len(), json.len());
+    }
+}
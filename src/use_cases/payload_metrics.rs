@@ -0,0 +1,140 @@
+//! Sizes of the bodies flowing through the proxy, per process, exposed as
+//! Prometheus histograms for capacity planning
+
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::sync::Mutex;
+
+/// Upper bounds of the histogram buckets, in bytes: 64 B up to 16 MiB
+const SIZE_BUCKETS: [u64; 10] = [
+    64,
+    256,
+    1024,
+    4 * 1024,
+    16 * 1024,
+    64 * 1024,
+    256 * 1024,
+    1024 * 1024,
+    4 * 1024 * 1024,
+    16 * 1024 * 1024,
+];
+
+/// Counts of observed sizes, one per bucket plus one for larger sizes
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+struct SizeHistogram {
+    buckets: [u64; SIZE_BUCKETS.len() + 1],
+    sum: u64,
+    count: u64,
+}
+
+impl SizeHistogram {
+    fn observe(&mut self, bytes: u64) {
+        let bucket = SIZE_BUCKETS.iter().position(|&bound| bytes <= bound).unwrap_or(SIZE_BUCKETS.len());
+        self.buckets[bucket] += 1;
+        self.sum += bytes;
+        self.count += 1;
+    }
+}
+
+#[derive(Debug, Default)]
+struct ProcessSizes {
+    request: SizeHistogram,
+    response: SizeHistogram,
+}
+
+/// Request and response body sizes, keyed by the id of the process that
+/// served them
+#[derive(Debug, Default)]
+pub struct PayloadMetrics {
+    processes: Mutex<BTreeMap<String, ProcessSizes>>,
+}
+
+impl PayloadMetrics {
+    /// Record one exchange with `process`; a streamed request has no size
+    pub fn record(&self, process: &str, request_bytes: Option<u64>, response_bytes: u64) {
+        let mut processes = self.processes.lock().unwrap();
+        let sizes = processes.entry(process.to_string()).or_default();
+        if let Some(bytes) = request_bytes {
+            sizes.request.observe(bytes);
+        }
+        sizes.response.observe(response_bytes);
+    }
+
+    /// The `request_bytes` and `response_bytes` histograms in the
+    /// Prometheus text exposition format
+    pub fn to_prometheus(&self) -> String {
+        let processes = self.processes.lock().unwrap();
+        let mut out = String::new();
+        write_histogram(
+            &mut out,
+            "request_bytes",
+            "Size of proxied request bodies in bytes",
+            processes.iter().map(|(process, sizes)| (process, &sizes.request)),
+        );
+        write_histogram(
+            &mut out,
+            "response_bytes",
+            "Size of proxied response bodies in bytes",
+            processes.iter().map(|(process, sizes)| (process, &sizes.response)),
+        );
+        out
+    }
+}
+
+/// Write the histogram `name` with one series per process
+fn write_histogram<'a>(
+    out: &mut String,
+    name: &str,
+    help: &str,
+    series: impl Iterator<Item = (&'a String, &'a SizeHistogram)>,
+) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} histogram", name);
+    for (process, histogram) in series {
+        let label = escape_label(process);
+        let mut cumulative = 0;
+        for (bound, count) in SIZE_BUCKETS.iter().zip(&histogram.buckets) {
+            cumulative += count;
+            let _ = writeln!(out, "{}_bucket{{process=\"{}\",le=\"{}\"}} {}", name, label, bound, cumulative);
+        }
+        let _ = writeln!(out, "{}_bucket{{process=\"{}\",le=\"+Inf\"}} {}", name, label, histogram.count);
+        let _ = writeln!(out, "{}_sum{{process=\"{}\"}} {}", name, label, histogram.sum);
+        let _ = writeln!(out, "{}_count{{process=\"{}\"}} {}", name, label, histogram.count);
+    }
+}
+
+/// A label value with the characters Prometheus requires escaped
+fn escape_label(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sizes_are_bucketed_cumulatively() {
+        let metrics = PayloadMetrics::default();
+        metrics.record("api", Some(10), 100);
+        metrics.record("api", Some(2000), 64);
+        metrics.record("api", None, 20 * 1024 * 1024);
+
+        let text = metrics.to_prometheus();
+        assert!(text.contains("# TYPE request_bytes histogram\n"), "{}", text);
+        assert!(text.contains("request_bytes_bucket{process=\"api\",le=\"64\"} 1\n"), "{}", text);
+        assert!(text.contains("request_bytes_bucket{process=\"api\",le=\"4096\"} 2\n"), "{}", text);
+        assert!(text.contains("request_bytes_sum{process=\"api\"} 2010\n"), "{}", text);
+        assert!(text.contains("request_bytes_count{process=\"api\"} 2\n"), "{}", text);
+
+        assert!(text.contains("response_bytes_bucket{process=\"api\",le=\"64\"} 1\n"), "{}", text);
+        assert!(text.contains("response_bytes_bucket{process=\"api\",le=\"256\"} 2\n"), "{}", text);
+        assert!(text.contains("response_bytes_bucket{process=\"api\",le=\"16777216\"} 2\n"), "{}", text);
+        assert!(text.contains("response_bytes_bucket{process=\"api\",le=\"+Inf\"} 3\n"), "{}", text);
+        assert!(text.contains("response_bytes_count{process=\"api\"} 3\n"), "{}", text);
+    }
+
+    #[test]
+    fn test_label_values_are_escaped() {
+        assert_eq!(escape_label(r#"a"b\c"#), r#"a\"b\\c"#);
+    }
+}
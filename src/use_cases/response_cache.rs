@@ -0,0 +1,144 @@
+//! The response cache: responses keyed by the request they answer, each
+//! expiring after its own TTL
+
+use crate::domain::HttpResponse;
+use moka::future::Cache;
+use moka::Expiry;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime};
+
+/// A cached response and how long it may be served for; `None` keeps it
+/// until evicted
+#[derive(Debug, Clone)]
+pub(super) struct CachedResponse {
+    pub(super) response: HttpResponse,
+    pub(super) ttl: Option<Duration>,
+    pub(super) stored_at: SystemTime,
+}
+
+impl CachedResponse {
+    pub(super) fn new(response: HttpResponse, ttl: Option<Duration>) -> Self {
+        Self {
+            response,
+            ttl,
+            stored_at: SystemTime::now(),
+        }
+    }
+
+    /// How much longer the response may be served for: `Some(None)` if it
+    /// doesn't expire, `None` if it already has
+    pub(super) fn remaining_ttl(&self) -> Option<Option<Duration>> {
+        match self.ttl {
+            None => Some(None),
+            Some(ttl) => {
+                let age = self.stored_at.elapsed().unwrap_or_default();
+                ttl.checked_sub(age).filter(|left| !left.is_zero()).map(Some)
+            }
+        }
+    }
+}
+
+/// Counts of how the response cache has been used since startup
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    /// Responses currently cached
+    pub entries: u64,
+    /// Requests answered from the cache
+    pub hits: u64,
+    /// Requests that had to go to a backend
+    pub misses: u64,
+    /// Entries removed because they expired or the cache was full
+    pub evictions: u64,
+}
+
+/// Running totals behind [`CacheStats`], shared with the cache's eviction listener
+#[derive(Debug, Default)]
+struct CacheCounters {
+    hits: AtomicU64,
+    misses: AtomicU64,
+    evictions: AtomicU64,
+}
+
+/// Expiry standing in for "never" when replacing an entry that had a TTL
+const UNEXPIRING: Duration = Duration::from_secs(365 * 24 * 60 * 60);
+
+/// Expires each cache entry according to its own TTL, so a newer response
+/// for the same key replaces both the value and its expiry
+struct CachedResponseExpiry;
+
+impl Expiry<String, CachedResponse> for CachedResponseExpiry {
+    fn expire_after_create(&self, _key: &String, value: &CachedResponse, _created_at: Instant) -> Option<Duration> {
+        value.ttl
+    }
+
+    fn expire_after_update(
+        &self,
+        _key: &String,
+        value: &CachedResponse,
+        _updated_at: Instant,
+        _duration_until_expiry: Option<Duration>,
+    ) -> Option<Duration> {
+        // Clearing the expiry of an entry that already had one doesn't stop
+        // moka from evicting it at the old time, so use a far-off expiry instead
+        value.ttl.or(Some(UNEXPIRING))
+    }
+}
+
+/// Cached responses and the counts of how they've been used, built by a
+/// proxy from its `cache_size`
+pub struct ResponseCache {
+    entries: Cache<String, CachedResponse>,
+    counters: Arc<CacheCounters>,
+}
+
+impl ResponseCache {
+    /// A cache holding up to `max_entries` responses
+    pub fn new(max_entries: u64) -> Self {
+        let counters = Arc::new(CacheCounters::default());
+        let evictions = counters.clone();
+        let entries = Cache::builder()
+            .max_capacity(max_entries)
+            .expire_after(CachedResponseExpiry)
+            .eviction_listener(move |_key, _value, cause| {
+                if cause.was_evicted() {
+                    evictions.evictions.fetch_add(1, Ordering::Relaxed);
+                }
+            })
+            .build();
+        Self { entries, counters }
+    }
+
+    pub(super) fn entries(&self) -> &Cache<String, CachedResponse> {
+        &self.entries
+    }
+
+    pub(super) fn record_hit(&self) {
+        self.counters.hits.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(super) fn record_miss(&self) {
+        self.counters.misses.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// How the cache has been used since it was created
+    pub async fn stats(&self) -> CacheStats {
+        // Settle pending inserts and expirations so the entry count is current
+        self.entries.run_pending_tasks().await;
+        CacheStats {
+            entries: self.entries.entry_count(),
+            hits: self.counters.hits.load(Ordering::Relaxed),
+            misses: self.counters.misses.load(Ordering::Relaxed),
+            evictions: self.counters.evictions.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Drop every cached response, returning how many there were
+    pub async fn clear(&self) -> u64 {
+        self.entries.run_pending_tasks().await;
+        let cleared = self.entries.entry_count();
+        self.entries.invalidate_all();
+        self.entries.run_pending_tasks().await;
+        cleared
+    }
+}
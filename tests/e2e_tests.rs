@@ -4,9 +4,10 @@
 
 use assert_cmd::cargo::CommandCargoExt;
 use std::fs::File;
-use std::io::Write;
-use std::path::PathBuf;
-use std::process::Command;
+use std::io::{BufRead, BufReader, Write};
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
 use std::time::Duration;
 use tempfile::TempDir;
 
@@ -18,6 +19,56 @@ fn create_test_manifest(dir: &TempDir, content: &str) -> PathBuf {
     manifest_path
 }
 
+/// Build a proxy command that binds to an OS-assigned port
+fn proxy_command(manifest_path: &Path) -> Command {
+    let mut cmd = Command::cargo_bin("local_lambdas").unwrap();
+    cmd.arg(manifest_path)
+        .env("BIND_ADDRESS", "127.0.0.1:0")
+        .env("NO_COLOR", "1")
+        // The test backends never open their pipes, so don't wait long for them
+        .env("READY_TIMEOUT_MS", "500");
+    cmd
+}
+
+/// Extract the address from the proxy's "Listening on http://..." log line
+fn parse_listening_address(line: &str) -> Option<SocketAddr> {
+    let (_, rest) = line.split_once("Listening on http://")?;
+    let addr: String = rest
+        .chars()
+        .take_while(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | ':' | '[' | ']'))
+        .collect();
+    addr.parse().ok()
+}
+
+/// Spawn the proxy on an ephemeral port and wait until it reports where it is listening
+fn spawn_proxy(manifest_path: &Path) -> (Child, SocketAddr) {
+    spawn_proxy_command(proxy_command(manifest_path))
+}
+
+/// Spawn a proxy command built by `proxy_command` and wait for its address
+fn spawn_proxy_command(mut command: Command) -> (Child, SocketAddr) {
+    let mut child = command
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+
+    let mut lines = BufReader::new(child.stdout.take().unwrap()).lines();
+    let addr = loop {
+        let line = lines
+            .next()
+            .expect("proxy exited before reporting its address")
+            .unwrap();
+        if let Some(addr) = parse_listening_address(&line) {
+            break addr;
+        }
+    };
+
+    // Keep draining stdout so the proxy never blocks on a full pipe
+    std::thread::spawn(move || lines.for_each(drop));
+
+    (child, addr)
+}
+
 #[test]
 fn test_binary_exists() {
     // Test that the binary can be found and constructed
@@ -33,14 +84,14 @@ fn test_with_valid_empty_manifest() {
     
     let manifest_path = create_test_manifest(&temp_dir, xml);
     
-    let mut cmd = Command::cargo_bin("local_lambdas").unwrap();
-    cmd.arg(manifest_path.to_str().unwrap());
-    
+    let mut cmd = proxy_command(&manifest_path);
+
     // Just verify it doesn't crash immediately
     // We use spawn with a timeout to avoid waiting indefinitely
     let mut child = cmd.spawn().unwrap();
     std::thread::sleep(Duration::from_millis(500));
     let _ = child.kill();
+    let _ = child.wait();
 }
 
 #[test]
@@ -50,9 +101,8 @@ fn test_with_invalid_xml() {
     
     let manifest_path = create_test_manifest(&temp_dir, invalid_xml);
     
-    let mut cmd = Command::cargo_bin("local_lambdas").unwrap();
-    cmd.arg(manifest_path.to_str().unwrap());
-    
+    let mut cmd = proxy_command(&manifest_path);
+
     let mut child = cmd.spawn().unwrap();
     std::thread::sleep(Duration::from_secs(1));
     
@@ -64,6 +114,7 @@ fn test_with_invalid_xml() {
         }
         _ => {
             let _ = child.kill();
+            let _ = child.wait();
         }
     }
 }
@@ -84,11 +135,984 @@ fn test_with_process_manifest() {
     
     let manifest_path = create_test_manifest(&temp_dir, xml);
     
-    let mut cmd = Command::cargo_bin("local_lambdas").unwrap();
-    cmd.arg(manifest_path.to_str().unwrap());
-    
+    let mut cmd = proxy_command(&manifest_path);
+
     // Just verify it starts without crashing
     let mut child = cmd.spawn().unwrap();
     std::thread::sleep(Duration::from_millis(500));
     let _ = child.kill();
+    let _ = child.wait();
+}
+
+/// Answer every proxied request on `listener` with `body`, the way an
+/// HTTP-mode backend would; connections closed without a request (readiness
+/// probes) are skipped
+fn serve_http_backend(listener: std::net::TcpListener, body: &'static str) {
+    use std::io::Read;
+
+    std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            let mut stream = stream.unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut content_length = None;
+            loop {
+                let mut line = String::new();
+                if reader.read_line(&mut line).unwrap_or(0) == 0 || line == "\r\n" {
+                    break;
+                }
+                if let Some((name, value)) = line.split_once(':') {
+                    if name.eq_ignore_ascii_case("content-length") {
+                        content_length = value.trim().parse::<usize>().ok();
+                    }
+                }
+            }
+            let Some(content_length) = content_length else {
+                continue;
+            };
+            let mut request = vec![0; content_length];
+            reader.read_exact(&mut request).unwrap();
+
+            use base64::Engine as _;
+            let envelope = format!(
+                r#"{{"status": 200, "body": "{}"}}"#,
+                base64::engine::general_purpose::STANDARD.encode(body)
+            );
+            write!(
+                stream,
+                "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                envelope.len(),
+                envelope
+            )
+            .unwrap();
+        }
+    });
+}
+
+#[cfg(unix)]
+#[test]
+fn test_fixed_http_port_is_used() {
+    let temp_dir = TempDir::new().unwrap();
+    let backend = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = backend.local_addr().unwrap().port();
+    serve_http_backend(backend, "fixed port");
+
+    // The spawned process reports the address it was told to listen on
+    let reported = temp_dir.path().join("address");
+    let xml = format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<manifest>
+    <process>
+        <id>fixed</id>
+        <executable>sh</executable>
+        <arg>-c</arg>
+        <arg>echo "$HTTP_ADDRESS" > {}; sleep 5</arg>
+        <route>/fixed/*</route>
+        <pipe_name>fixed_port_pipe</pipe_name>
+        <communication_mode>http</communication_mode>
+        <http_port>{}</http_port>
+    </process>
+</manifest>"#,
+        reported.display(),
+        port
+    );
+
+    let manifest_path = create_test_manifest(&temp_dir, &xml);
+    let (mut child, addr) = spawn_proxy(&manifest_path);
+
+    let response = reqwest::blocking::get(format!("http://{}/fixed/x", addr)).unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::OK);
+    assert_eq!(response.text().unwrap(), "fixed port");
+
+    let mut address = String::new();
+    for _ in 0..50 {
+        address = std::fs::read_to_string(&reported).unwrap_or_default();
+        if !address.is_empty() {
+            break;
+        }
+        std::thread::sleep(Duration::from_millis(100));
+    }
+    assert_eq!(address.trim(), format!("127.0.0.1:{}", port));
+
+    let _ = child.kill();
+    let _ = child.wait();
+}
+
+#[cfg(unix)]
+#[test]
+fn test_recorded_session_is_replayed_without_backends() {
+    let temp_dir = TempDir::new().unwrap();
+    let recording = temp_dir.path().join("session.jsonl");
+    let backend = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = backend.local_addr().unwrap().port();
+    serve_http_backend(backend, "recorded");
+
+    let manifest = |executable: &str| {
+        format!(
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<manifest>
+    <process>
+        <id>api</id>
+        <executable>{}</executable>
+        <arg>5</arg>
+        <route>/api/*</route>
+        <pipe_name>replay_pipe</pipe_name>
+        <communication_mode>http</communication_mode>
+        <http_port>{}</http_port>
+    </process>
+</manifest>"#,
+            executable, port
+        )
+    };
+
+    let manifest_path = create_test_manifest(&temp_dir, &manifest("sleep"));
+    let mut command = proxy_command(&manifest_path);
+    command.arg("--record").arg(&recording);
+    let (mut child, addr) = spawn_proxy_command(command);
+    let recorded = reqwest::blocking::get(format!("http://{}/api/users", addr)).unwrap();
+    assert_eq!(recorded.status(), reqwest::StatusCode::OK);
+    assert_eq!(recorded.text().unwrap(), "recorded");
+    let _ = child.kill();
+    let _ = child.wait();
+
+    // Nothing is started, so an executable that doesn't exist is never noticed
+    let manifest_path = create_test_manifest(&temp_dir, &manifest("./no/such/executable"));
+    let mut command = proxy_command(&manifest_path);
+    command.arg("--replay").arg(&recording);
+    let (mut child, addr) = spawn_proxy_command(command);
+    let replayed = reqwest::blocking::get(format!("http://{}/api/users", addr)).unwrap();
+    let unrecorded = reqwest::blocking::get(format!("http://{}/api/orders", addr)).unwrap();
+    let _ = child.kill();
+    let _ = child.wait();
+
+    assert_eq!(replayed.status(), reqwest::StatusCode::OK);
+    assert_eq!(replayed.text().unwrap(), "recorded");
+    assert_eq!(unrecorded.status(), reqwest::StatusCode::BAD_GATEWAY);
+}
+
+/// Answer each request on `listener` with the size of its body, as plain
+/// HTTP, reading the body as it arrives without keeping it
+fn serve_upload_counting_backend(listener: std::net::TcpListener) {
+    use std::io::Read;
+
+    std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            let mut stream = stream.unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut content_length = None;
+            loop {
+                let mut line = String::new();
+                if reader.read_line(&mut line).unwrap_or(0) == 0 || line == "\r\n" {
+                    break;
+                }
+                if let Some((name, value)) = line.split_once(':') {
+                    if name.eq_ignore_ascii_case("content-length") {
+                        content_length = value.trim().parse::<u64>().ok();
+                    }
+                }
+            }
+            // Readiness probes connect without sending a request
+            let Some(content_length) = content_length else {
+                continue;
+            };
+            let received = std::io::copy(&mut reader.by_ref().take(content_length), &mut std::io::sink()).unwrap();
+            let body = received.to_string();
+            write!(
+                stream,
+                "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                body.len(),
+                body
+            )
+            .unwrap();
+        }
+    });
+}
+
+/// Peak resident memory of a process, in bytes
+#[cfg(target_os = "linux")]
+fn peak_memory(pid: u32) -> u64 {
+    let status = std::fs::read_to_string(format!("/proc/{}/status", pid)).unwrap();
+    let kilobytes = status
+        .lines()
+        .find_map(|line| line.strip_prefix("VmHWM:"))
+        .and_then(|value| value.trim().trim_end_matches("kB").trim().parse::<u64>().ok())
+        .unwrap();
+    kilobytes * 1024
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn test_raw_upload_is_streamed_with_bounded_memory() {
+    const UPLOAD_BYTES: u64 = 32 * 1024 * 1024;
+
+    let temp_dir = TempDir::new().unwrap();
+    let backend = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = backend.local_addr().unwrap().port();
+    serve_upload_counting_backend(backend);
+
+    let xml = format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<manifest>
+    <process>
+        <id>upload</id>
+        <executable>sleep</executable>
+        <arg>30</arg>
+        <route>/upload/*</route>
+        <pipe_name>raw_upload_pipe</pipe_name>
+        <communication_mode>http</communication_mode>
+        <http_port>{}</http_port>
+        <protocol>raw</protocol>
+    </process>
+</manifest>"#,
+        port
+    );
+
+    let manifest_path = create_test_manifest(&temp_dir, &xml);
+    let mut command = proxy_command(&manifest_path);
+    command.arg("--max-body-bytes").arg(UPLOAD_BYTES.to_string());
+    let (mut child, addr) = spawn_proxy_command(command);
+    let before = peak_memory(child.id());
+
+    let upload = std::io::Read::take(std::io::repeat(b'x'), UPLOAD_BYTES);
+    let body = reqwest::blocking::Body::sized(upload, UPLOAD_BYTES);
+    let response = reqwest::blocking::Client::builder()
+        .timeout(Duration::from_secs(120))
+        .build()
+        .unwrap()
+        .post(format!("http://{}/upload/file", addr))
+        .body(body)
+        .send()
+        .unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::OK);
+    assert_eq!(response.text().unwrap(), UPLOAD_BYTES.to_string());
+
+    // Buffering the upload would have needed at least its whole size
+    let growth = peak_memory(child.id()) - before;
+    assert!(growth < UPLOAD_BYTES / 4, "proxy grew by {} bytes", growth);
+
+    let _ = child.kill();
+    let _ = child.wait();
+}
+
+/// Answer every request on `listener` with a chunked body followed by an
+/// `x-checksum` trailer, the way a raw-protocol backend might; connections
+/// closed without a request (readiness probes) are skipped
+fn serve_trailer_backend(listener: std::net::TcpListener) {
+    std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            let mut stream = stream.unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut lines = 0;
+            loop {
+                let mut line = String::new();
+                if reader.read_line(&mut line).unwrap_or(0) == 0 || line == "\r\n" {
+                    break;
+                }
+                lines += 1;
+            }
+            if lines == 0 {
+                continue;
+            }
+            stream
+                .write_all(
+                    b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\nTrailer: x-checksum\r\nConnection: close\r\n\r\n\
+                      5\r\nhello\r\n0\r\nx-checksum: abc123\r\n\r\n",
+                )
+                .unwrap();
+        }
+    });
+}
+
+#[test]
+fn test_raw_backend_trailers_reach_client() {
+    use std::io::Read;
+
+    let temp_dir = TempDir::new().unwrap();
+    let backend = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = backend.local_addr().unwrap().port();
+    serve_trailer_backend(backend);
+
+    let xml = format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<manifest>
+    <process>
+        <id>files</id>
+        <executable>sleep</executable>
+        <arg>30</arg>
+        <route>/files/*</route>
+        <pipe_name>raw_trailer_pipe</pipe_name>
+        <communication_mode>http</communication_mode>
+        <http_port>{}</http_port>
+        <protocol>raw</protocol>
+    </process>
+</manifest>"#,
+        port
+    );
+
+    let manifest_path = create_test_manifest(&temp_dir, &xml);
+    let (mut child, addr) = spawn_proxy(&manifest_path);
+
+    // An HTTP/1.1 client only gets trailers when it says it accepts them
+    let mut stream = std::net::TcpStream::connect(addr).unwrap();
+    stream.set_read_timeout(Some(Duration::from_secs(10))).unwrap();
+    stream
+        .write_all(b"GET /files/a HTTP/1.1\r\nHost: localhost\r\nTE: trailers\r\nConnection: close\r\n\r\n")
+        .unwrap();
+    let mut raw = String::new();
+    stream.read_to_string(&mut raw).unwrap();
+    let raw = raw.to_ascii_lowercase();
+
+    assert!(raw.starts_with("http/1.1 200"), "{}", raw);
+    assert!(raw.contains("trailer: x-checksum\r\n"), "{}", raw);
+    let (_, body) = raw.split_once("\r\n\r\n").unwrap();
+    assert!(body.contains("hello"), "{}", body);
+    assert!(body.ends_with("0\r\nx-checksum: abc123\r\n\r\n"), "{}", body);
+
+    let _ = child.kill();
+    let _ = child.wait();
+}
+
+#[test]
+fn test_binds_ephemeral_port() {
+    let temp_dir = TempDir::new().unwrap();
+    let xml = r#"<?xml version="1.0" encoding="UTF-8"?>
+<manifest>
+</manifest>"#;
+
+    let manifest_path = create_test_manifest(&temp_dir, xml);
+    let (mut child, addr) = spawn_proxy(&manifest_path);
+
+    assert_ne!(addr.port(), 0, "The reported port should be the one chosen by the OS");
+
+    // The proxy answers on the discovered port; nothing is routed so expect a 404
+    let response = reqwest::blocking::get(format!("http://{}/unrouted", addr)).unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::NOT_FOUND);
+
+    let _ = child.kill();
+    let _ = child.wait();
+}
+
+#[test]
+fn test_binds_several_addresses() {
+    let temp_dir = TempDir::new().unwrap();
+    let backend = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = backend.local_addr().unwrap().port();
+    serve_http_backend(backend, "from backend");
+    let xml = format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<manifest>
+    <process>
+        <id>api</id>
+        <executable>sleep</executable>
+        <arg>5</arg>
+        <route>/api/*</route>
+        <pipe_name>multi_bind_pipe</pipe_name>
+        <communication_mode>http</communication_mode>
+        <http_port>{}</http_port>
+    </process>
+</manifest>"#,
+        port
+    );
+
+    let manifest_path = create_test_manifest(&temp_dir, &xml);
+    let mut child = proxy_command(&manifest_path)
+        .env("BIND_ADDRESS", "127.0.0.1:0, 127.0.0.1:0")
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let mut lines = BufReader::new(child.stdout.take().unwrap()).lines();
+    let addrs: Vec<SocketAddr> = lines
+        .by_ref()
+        .map_while(Result::ok)
+        .filter_map(|line| parse_listening_address(&line))
+        .take(2)
+        .collect();
+    std::thread::spawn(move || lines.for_each(drop));
+
+    let responses: Vec<_> = addrs
+        .iter()
+        .map(|addr| reqwest::blocking::get(format!("http://{}/api/x", addr)).map(|r| r.text()))
+        .collect();
+    let _ = child.kill();
+    let _ = child.wait();
+
+    assert_eq!(addrs.len(), 2);
+    assert_ne!(addrs[0], addrs[1]);
+    for response in responses {
+        assert_eq!(response.unwrap().unwrap(), "from backend");
+    }
+}
+
+#[test]
+fn test_serves_with_tcp_options() {
+    let temp_dir = TempDir::new().unwrap();
+    let backend = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = backend.local_addr().unwrap().port();
+    serve_http_backend(backend, "tuned");
+    let xml = format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<manifest>
+    <process>
+        <id>api</id>
+        <executable>sleep</executable>
+        <arg>5</arg>
+        <route>/api/*</route>
+        <pipe_name>tcp_options_pipe</pipe_name>
+        <communication_mode>http</communication_mode>
+        <http_port>{}</http_port>
+    </process>
+</manifest>"#,
+        port
+    );
+
+    let manifest_path = create_test_manifest(&temp_dir, &xml);
+    let mut command = proxy_command(&manifest_path);
+    command.args(["--listen-backlog", "16"]);
+    let (mut child, addr) = spawn_proxy_command(command);
+
+    // Several requests over one kept-alive connection
+    let client = reqwest::blocking::Client::new();
+    for _ in 0..3 {
+        let response = client.get(format!("http://{}/api/x", addr)).send().unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::OK);
+        assert_eq!(response.text().unwrap(), "tuned");
+    }
+
+    let _ = child.kill();
+    let _ = child.wait();
+}
+
+#[test]
+fn test_unavailable_bind_address_fails_startup() {
+    let temp_dir = TempDir::new().unwrap();
+    let taken = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let taken_addr = taken.local_addr().unwrap();
+    let xml = r#"<?xml version="1.0" encoding="UTF-8"?>
+<manifest>
+</manifest>"#;
+
+    let manifest_path = create_test_manifest(&temp_dir, xml);
+    let output = proxy_command(&manifest_path)
+        .env("BIND_ADDRESS", format!("127.0.0.1:0,{}", taken_addr))
+        .output()
+        .unwrap();
+
+    assert!(!output.status.success());
+    assert!(!String::from_utf8_lossy(&output.stdout).contains("Listening on"));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains(&format!("Failed to bind {}", taken_addr)), "{}", stderr);
+    assert!(stderr.contains("already in use"), "{}", stderr);
+    assert!(stderr.contains("--bind"), "{}", stderr);
+}
+
+#[test]
+fn test_taken_backend_port_is_reported() {
+    let temp_dir = TempDir::new().unwrap();
+    let taken = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let taken_addr = taken.local_addr().unwrap();
+    let xml = format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<manifest>
+    <process>
+        <id>squatted</id>
+        <executable>sleep</executable>
+        <arg>30</arg>
+        <route>/squatted/*</route>
+        <pipe_name>squatted_pipe</pipe_name>
+        <communication_mode>http</communication_mode>
+        <http_port>{}</http_port>
+    </process>
+</manifest>"#,
+        taken_addr.port()
+    );
+
+    let manifest_path = create_test_manifest(&temp_dir, &xml);
+    let mut child = proxy_command(&manifest_path).stdout(Stdio::piped()).spawn().unwrap();
+    let lines = BufReader::new(child.stdout.take().unwrap()).lines();
+    let warning = lines
+        .map_while(Result::ok)
+        .take_while(|line| parse_listening_address(line).is_none())
+        .find(|line| line.contains("already in use"));
+    let _ = child.kill();
+    let _ = child.wait();
+
+    let warning = warning.expect("no warning about the taken port");
+    assert!(warning.contains(&taken_addr.to_string()), "{}", warning);
+    assert!(warning.contains("'squatted'"), "{}", warning);
+}
+
+#[cfg(unix)]
+#[test]
+fn test_shutdown_kills_process_ignoring_sigterm_at_deadline() {
+    let temp_dir = TempDir::new().unwrap();
+    let pid_file = temp_dir.path().join("pid");
+    let xml = format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<manifest>
+    <process>
+        <id>stubborn</id>
+        <executable>sh</executable>
+        <arg>-c</arg>
+        <arg>trap '' TERM; echo $$ > {}; exec sleep 30</arg>
+        <route>/stubborn/*</route>
+        <pipe_name>stubborn_shutdown_pipe</pipe_name>
+    </process>
+</manifest>"#,
+        pid_file.display()
+    );
+
+    let manifest_path = create_test_manifest(&temp_dir, &xml);
+    let mut command = proxy_command(&manifest_path);
+    command.arg("--shutdown-timeout").arg("1");
+    let (mut child, _) = spawn_proxy_command(command);
+
+    let mut pid = String::new();
+    for _ in 0..50 {
+        pid = std::fs::read_to_string(&pid_file).unwrap_or_default();
+        if !pid.trim().is_empty() {
+            break;
+        }
+        std::thread::sleep(Duration::from_millis(100));
+    }
+    let pid = pid.trim().to_string();
+    assert!(!pid.is_empty(), "process never started");
+
+    let signalled_at = std::time::Instant::now();
+    let sent = Command::new("kill").arg("-TERM").arg(child.id().to_string()).status().unwrap();
+    assert!(sent.success());
+    let status = loop {
+        if let Some(status) = child.try_wait().unwrap() {
+            break status;
+        }
+        if signalled_at.elapsed() > Duration::from_secs(10) {
+            let _ = child.kill();
+            panic!("proxy did not exit within its shutdown timeout");
+        }
+        std::thread::sleep(Duration::from_millis(50));
+    };
+
+    assert!(status.success(), "{:?}", status);
+    assert!(signalled_at.elapsed() >= Duration::from_secs(1));
+    let alive = Command::new("kill").arg("-0").arg(&pid).stderr(Stdio::null()).status().unwrap();
+    assert!(!alive.success(), "process {} survived shutdown", pid);
+}
+
+#[cfg(unix)]
+#[test]
+fn test_interrupt_during_startup_stops_started_processes() {
+    let temp_dir = TempDir::new().unwrap();
+    let pid_file = temp_dir.path().join("pid");
+    // Never opens its pipe, so the proxy is still waiting for it when interrupted
+    let xml = format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<manifest>
+    <process>
+        <id>slow</id>
+        <executable>sh</executable>
+        <arg>-c</arg>
+        <arg>echo $$ > {}; exec sleep 30</arg>
+        <route>/slow/*</route>
+        <pipe_name>slow_startup_pipe</pipe_name>
+    </process>
+</manifest>"#,
+        pid_file.display()
+    );
+
+    let manifest_path = create_test_manifest(&temp_dir, &xml);
+    let mut child = proxy_command(&manifest_path)
+        .env("READY_TIMEOUT_MS", "30000")
+        .stdout(Stdio::null())
+        .spawn()
+        .unwrap();
+
+    let mut pid = String::new();
+    for _ in 0..50 {
+        pid = std::fs::read_to_string(&pid_file).unwrap_or_default();
+        if !pid.trim().is_empty() {
+            break;
+        }
+        std::thread::sleep(Duration::from_millis(100));
+    }
+    let pid = pid.trim().to_string();
+    assert!(!pid.is_empty(), "process never started");
+
+    let signalled_at = std::time::Instant::now();
+    let sent = Command::new("kill").arg("-INT").arg(child.id().to_string()).status().unwrap();
+    assert!(sent.success());
+    let status = loop {
+        if let Some(status) = child.try_wait().unwrap() {
+            break status;
+        }
+        if signalled_at.elapsed() > Duration::from_secs(10) {
+            let _ = child.kill();
+            panic!("proxy did not exit after being interrupted");
+        }
+        std::thread::sleep(Duration::from_millis(50));
+    };
+
+    assert!(status.success(), "{:?}", status);
+    let alive = Command::new("kill").arg("-0").arg(&pid).stderr(Stdio::null()).status().unwrap();
+    assert!(!alive.success(), "process {} outlived the interrupted proxy", pid);
+}
+
+#[test]
+fn test_starts_with_single_worker_thread() {
+    let temp_dir = TempDir::new().unwrap();
+    let xml = r#"<?xml version="1.0" encoding="UTF-8"?>
+<manifest>
+</manifest>"#;
+
+    let manifest_path = create_test_manifest(&temp_dir, xml);
+    let mut command = proxy_command(&manifest_path);
+    command.arg("--worker-threads").arg("1");
+    let (mut child, addr) = spawn_proxy_command(command);
+
+    let response = reqwest::blocking::get(format!("http://{}/unrouted", addr)).unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::NOT_FOUND);
+
+    let _ = child.kill();
+    let _ = child.wait();
+}
+
+#[test]
+fn test_json_log_format() {
+    let temp_dir = TempDir::new().unwrap();
+    let xml = r#"<?xml version="1.0" encoding="UTF-8"?>
+<manifest>
+</manifest>"#;
+
+    let manifest_path = create_test_manifest(&temp_dir, xml);
+    let mut child = proxy_command(&manifest_path)
+        .arg("--log-format")
+        .arg("json")
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+
+    // Every line up to the listening message is a JSON object
+    let lines = BufReader::new(child.stdout.take().unwrap()).lines();
+    let mut seen = 0;
+    for line in lines {
+        let line = line.unwrap();
+        let entry: serde_json::Value =
+            serde_json::from_str(&line).unwrap_or_else(|e| panic!("not JSON ({}): {}", e, line));
+        assert!(entry["level"].is_string(), "{}", line);
+        seen += 1;
+        if parse_listening_address(&line).is_some() {
+            break;
+        }
+    }
+    assert!(seen > 1);
+
+    let _ = child.kill();
+    let _ = child.wait();
+}
+
+#[test]
+fn test_logs_to_file() {
+    let temp_dir = TempDir::new().unwrap();
+    let xml = r#"<?xml version="1.0" encoding="UTF-8"?>
+<manifest>
+</manifest>"#;
+
+    let manifest_path = create_test_manifest(&temp_dir, xml);
+    let log_dir = temp_dir.path().join("logs");
+    std::fs::create_dir(&log_dir).unwrap();
+    let mut child = proxy_command(&manifest_path)
+        .arg("--log-file")
+        .arg(log_dir.join("proxy.log"))
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+
+    // The file name gets the date appended, so look for whatever was created
+    let read_logs = || -> String {
+        std::fs::read_dir(&log_dir)
+            .unwrap()
+            .map(|entry| std::fs::read_to_string(entry.unwrap().path()).unwrap())
+            .collect()
+    };
+    let mut logs = String::new();
+    for _ in 0..100 {
+        logs = read_logs();
+        if logs.contains("Listening on http://") {
+            break;
+        }
+        std::thread::sleep(Duration::from_millis(100));
+    }
+
+    let _ = child.kill();
+    let _ = child.wait();
+
+    assert!(logs.contains("Listening on http://"), "{}", logs);
+    let mut stdout = String::new();
+    std::io::Read::read_to_string(&mut child.stdout.take().unwrap(), &mut stdout).unwrap();
+    assert!(stdout.is_empty(), "{}", stdout);
+}
+
+#[cfg(unix)]
+#[test]
+fn test_binds_unix_socket() {
+    use std::io::Read;
+    use std::os::unix::net::UnixStream;
+
+    let temp_dir = TempDir::new().unwrap();
+    let xml = r#"<?xml version="1.0" encoding="UTF-8"?>
+<manifest>
+</manifest>"#;
+
+    let manifest_path = create_test_manifest(&temp_dir, xml);
+    let socket_path = temp_dir.path().join("proxy.sock");
+    let mut child = proxy_command(&manifest_path)
+        .env("BIND_ADDRESS", format!("unix:{}", socket_path.display()))
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+
+    let mut lines = BufReader::new(child.stdout.take().unwrap()).lines();
+    loop {
+        let line = lines
+            .next()
+            .expect("proxy exited before reporting its socket")
+            .unwrap();
+        if line.contains("Listening on unix:") {
+            break;
+        }
+    }
+    std::thread::spawn(move || lines.for_each(drop));
+
+    // Nothing is routed so expect a 404, answered over the socket
+    let mut stream = UnixStream::connect(&socket_path).unwrap();
+    stream
+        .write_all(b"GET /unrouted HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n")
+        .unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    assert!(response.starts_with("HTTP/1.1 404"), "{}", response);
+
+    let _ = child.kill();
+    let _ = child.wait();
+}
+
+#[test]
+fn test_unreadable_tls_certificate_fails_startup() {
+    let temp_dir = TempDir::new().unwrap();
+    let xml = r#"<?xml version="1.0" encoding="UTF-8"?>
+<manifest>
+</manifest>"#;
+
+    let manifest_path = create_test_manifest(&temp_dir, xml);
+    let cert_path = temp_dir.path().join("missing-cert.pem");
+    let output = proxy_command(&manifest_path)
+        .arg("--tls-cert")
+        .arg(&cert_path)
+        .arg("--tls-key")
+        .arg(temp_dir.path().join("missing-key.pem"))
+        .output()
+        .unwrap();
+
+    assert!(!output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(!stdout.contains("Listening on"), "proxy should not start serving");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains(&format!("Failed to read TLS certificate {}", cert_path.display())),
+        "{}",
+        stderr
+    );
+}
+
+#[test]
+fn test_missing_executable_fails_startup() {
+    let temp_dir = TempDir::new().unwrap();
+    let xml = r#"<?xml version="1.0" encoding="UTF-8"?>
+<manifest>
+    <process>
+        <id>bogus</id>
+        <executable>./no/such/executable</executable>
+        <route>/bogus/*</route>
+        <pipe_name>bogus_pipe</pipe_name>
+    </process>
+</manifest>"#;
+
+    let manifest_path = create_test_manifest(&temp_dir, xml);
+    let output = proxy_command(&manifest_path).output().unwrap();
+
+    assert!(!output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(!stdout.contains("Listening on"), "proxy should not start serving");
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Executable not found"));
+}
+
+#[test]
+fn test_skip_exec_check_allows_startup() {
+    let temp_dir = TempDir::new().unwrap();
+    let xml = r#"<?xml version="1.0" encoding="UTF-8"?>
+<manifest>
+    <process>
+        <id>bogus</id>
+        <executable>./no/such/executable</executable>
+        <route>/bogus/*</route>
+        <pipe_name>bogus_pipe</pipe_name>
+    </process>
+</manifest>"#;
+
+    let manifest_path = create_test_manifest(&temp_dir, xml);
+    let mut cmd = proxy_command(&manifest_path);
+    cmd.arg("--skip-exec-check");
+    let mut child = cmd.stdout(Stdio::piped()).spawn().unwrap();
+
+    let lines = BufReader::new(child.stdout.take().unwrap()).lines();
+    let started = lines.map_while(Result::ok).any(|line| parse_listening_address(&line).is_some());
+
+    let _ = child.kill();
+    let _ = child.wait();
+    assert!(started);
+}
+
+#[test]
+fn test_check_valid_manifest_prints_routing_table() {
+    let temp_dir = TempDir::new().unwrap();
+    let xml = r#"<?xml version="1.0" encoding="UTF-8"?>
+<manifest>
+    <process>
+        <id>sleeper</id>
+        <executable>sleep</executable>
+        <arg>60</arg>
+        <route>/sleep/*</route>
+        <pipe_name>check_sleep_pipe</pipe_name>
+    </process>
+</manifest>"#;
+
+    let manifest_path = create_test_manifest(&temp_dir, xml);
+    let output = proxy_command(&manifest_path).arg("--check").output().unwrap();
+
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("ROUTE"));
+    assert!(stdout.lines().any(|line| line.starts_with("sleeper") && line.contains("/sleep/*")));
+    assert!(!stdout.contains("Listening on"), "check mode should not start serving");
+    assert!(!stdout.contains("Starting process"), "check mode should not spawn processes");
+}
+
+#[test]
+fn test_manifest_read_from_stdin() {
+    let xml = r#"<?xml version="1.0" encoding="UTF-8"?>
+<manifest>
+    <process>
+        <id>piped</id>
+        <executable>sleep</executable>
+        <route>/piped/*</route>
+        <pipe_name>stdin_piped_pipe</pipe_name>
+    </process>
+</manifest>"#;
+
+    let mut child = proxy_command(Path::new("-"))
+        .arg("--check")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child.stdin.take().unwrap().write_all(xml.as_bytes()).unwrap();
+    let output = child.wait_with_output().unwrap();
+
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("Loading manifest from standard input"), "{}", stdout);
+    assert!(stdout.lines().any(|line| line.starts_with("piped") && line.contains("/piped/*")), "{}", stdout);
+    assert!(stdout.contains("Manifest OK: 1 process(es)"), "{}", stdout);
+}
+
+#[test]
+fn test_check_reports_duplicate_route() {
+    let temp_dir = TempDir::new().unwrap();
+    let xml = r#"<?xml version="1.0" encoding="UTF-8"?>
+<manifest>
+    <process>
+        <id>first</id>
+        <executable>sleep</executable>
+        <route>/api/*</route>
+        <pipe_name>check_first_pipe</pipe_name>
+    </process>
+    <process>
+        <id>second</id>
+        <executable>sleep</executable>
+        <route>/api/*</route>
+        <pipe_name>check_second_pipe</pipe_name>
+    </process>
+</manifest>"#;
+
+    let manifest_path = create_test_manifest(&temp_dir, xml);
+    let output = proxy_command(&manifest_path).arg("--check").output().unwrap();
+
+    assert!(!output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("Duplicate route '/api/*' used by processes: first, second"));
+}
+
+#[test]
+fn test_emits_startup_json() {
+    let temp_dir = TempDir::new().unwrap();
+    let backend = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = backend.local_addr().unwrap().port();
+    serve_http_backend(backend, "ok");
+    let xml = format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<manifest>
+    <process>
+        <id>api</id>
+        <executable>sleep</executable>
+        <arg>5</arg>
+        <route>/api/*</route>
+        <pipe_name>startup_json_api</pipe_name>
+        <communication_mode>http</communication_mode>
+        <http_port>{}</http_port>
+    </process>
+    <process>
+        <id>worker</id>
+        <executable>sleep</executable>
+        <arg>5</arg>
+        <route>/worker/*</route>
+        <pipe_name>startup_json_worker</pipe_name>
+    </process>
+</manifest>"#,
+        port
+    );
+
+    let manifest_path = create_test_manifest(&temp_dir, &xml);
+    let mut child = proxy_command(&manifest_path)
+        .arg("--emit-startup-json")
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .unwrap();
+
+    let mut lines = BufReader::new(child.stdout.take().unwrap()).lines();
+    let line = lines.next().expect("proxy exited without a startup summary").unwrap();
+    let summary: serde_json::Value = serde_json::from_str(&line).unwrap();
+
+    let listening = summary["listening"][0].as_str().unwrap();
+    let addr: SocketAddr = listening.strip_prefix("http://").unwrap().parse().unwrap();
+    assert_ne!(addr.port(), 0);
+    assert_eq!(
+        summary["processes"][0],
+        serde_json::json!({
+            "id": "api",
+            "route": "/api/*",
+            "mode": "http",
+            "addresses": [format!("127.0.0.1:{}", port)],
+            "status": "ready",
+        })
+    );
+    assert_eq!(summary["processes"][1]["id"], "worker");
+    assert_eq!(summary["processes"][1]["mode"], "pipe");
+    assert_eq!(summary["processes"].as_array().unwrap().len(), 2);
+
+    // The proxy really is listening where it says
+    let response = reqwest::blocking::get(format!("{}/api/x", listening)).unwrap();
+    assert_eq!(response.text().unwrap(), "ok");
+
+    // Nothing else is printed, logs included
+    let _ = child.kill();
+    let _ = child.wait();
+    assert_eq!(lines.map(Result::unwrap).collect::<Vec<_>>(), Vec::<String>::new());
 }
@@ -1,5 +1,5 @@
-/// Integration tests for the local_lambdas HTTP proxy
-/// These tests verify the interaction between multiple components
+//! Integration tests for the local_lambdas HTTP proxy
+//! These tests verify the interaction between multiple components
 
 use std::fs::File;
 use std::io::Write;
@@ -242,3 +242,466 @@ fn test_empty_manifest_handling() {
     
     assert_eq!(orchestrator.get_configs().len(), 0);
 }
+
+/// A minimal gRPC echo service, written out by hand in place of generated code
+// tonic's traits dictate its large `Status` error type
+#[allow(clippy::result_large_err)]
+mod echo {
+    use futures_util::Stream;
+    use std::pin::Pin;
+    use std::task::{Context, Poll};
+    use tonic::codec::ProstCodec;
+    use tonic::codegen::{http, Body, BoxFuture, Service, StdError};
+    use tonic::server::{Grpc, NamedService, ServerStreamingService};
+    use tonic::{Code, Request, Response, Status};
+
+    pub const REPEAT: &str = "/echo.Echo/Repeat";
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct RepeatRequest {
+        #[prost(string, tag = "1")]
+        pub text: String,
+        #[prost(uint32, tag = "2")]
+        pub times: u32,
+        /// Status code to end the call with after the replies; 0 for success
+        #[prost(int32, tag = "3")]
+        pub fail_with: i32,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct Reply {
+        #[prost(string, tag = "1")]
+        pub text: String,
+    }
+
+    type ReplyStream = Pin<Box<dyn Stream<Item = Result<Reply, Status>> + Send>>;
+
+    /// Replies with `text` `times` times, then ends the call with `fail_with`
+    #[derive(Clone)]
+    struct Repeat;
+
+    impl ServerStreamingService<RepeatRequest> for Repeat {
+        type Response = Reply;
+        type ResponseStream = ReplyStream;
+        type Future = BoxFuture<Response<ReplyStream>, Status>;
+
+        fn call(&mut self, request: Request<RepeatRequest>) -> Self::Future {
+            let request = request.into_inner();
+            let replies = (0..request.times).map(move |i| Ok(Reply { text: format!("{} {}", request.text, i) }));
+            let end = match request.fail_with {
+                0 => None,
+                code => Some(Err(Status::new(Code::from(code), "told to fail"))),
+            };
+            let stream: ReplyStream = Box::pin(futures_util::stream::iter(replies.chain(end)));
+            Box::pin(async move { Ok(Response::new(stream)) })
+        }
+    }
+
+    #[derive(Clone)]
+    pub struct EchoServer;
+
+    impl NamedService for EchoServer {
+        const NAME: &'static str = "echo.Echo";
+    }
+
+    impl<B> Service<http::Request<B>> for EchoServer
+    where
+        B: Body + Send + 'static,
+        B::Error: Into<StdError> + Send + 'static,
+    {
+        type Response = http::Response<tonic::body::BoxBody>;
+        type Error = std::convert::Infallible;
+        type Future = BoxFuture<Self::Response, Self::Error>;
+
+        fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn call(&mut self, request: http::Request<B>) -> Self::Future {
+            if request.uri().path() != REPEAT {
+                return Box::pin(async { Ok(Status::unimplemented("no such method").into_http()) });
+            }
+            Box::pin(async move {
+                let mut grpc = Grpc::new(ProstCodec::<Reply, RepeatRequest>::default());
+                Ok(grpc.server_streaming(Repeat, request).await)
+            })
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_grpc_calls_are_proxied_with_trailers() {
+    use echo::{EchoServer, Reply, RepeatRequest, REPEAT};
+    use local_lambdas::adapters::HttpServerState;
+    use local_lambdas::domain::{CommunicationMode, Executable, PipeName, Process, ProcessId, Route};
+    use local_lambdas::infrastructure::HttpClient;
+    use local_lambdas::use_cases::ProxyHttpRequestUseCase;
+    use std::sync::Arc;
+    use tonic::codec::ProstCodec;
+    use tonic::codegen::http::uri::PathAndQuery;
+    use tonic::Code;
+
+    let mut process = Process::new(
+        ProcessId::new("echo").unwrap(),
+        Executable::new("./echo").unwrap(),
+        Route::new("/echo.Echo/*").unwrap(),
+        PipeName::new("grpc_echo_test").unwrap(),
+    );
+    process.communication_mode = CommunicationMode::Grpc;
+    // Nothing listens for this one
+    let mut down = Process::new(
+        ProcessId::new("down").unwrap(),
+        Executable::new("./down").unwrap(),
+        Route::new("/down.Down/*").unwrap(),
+        PipeName::new("grpc_down_test").unwrap(),
+    );
+    down.communication_mode = CommunicationMode::Grpc;
+
+    // The backend listens where the process would have been told to
+    let backend = tokio::net::TcpListener::bind(&process.instance_addresses()[0]).await.unwrap();
+    tokio::spawn(
+        tonic::transport::Server::builder()
+            .add_service(EchoServer)
+            .serve_with_incoming(tonic::transport::server::TcpIncoming::from_listener(backend, true, None).unwrap()),
+    );
+
+    let use_case = ProxyHttpRequestUseCase::new(Arc::new(HttpClient::new()), Arc::new(vec![process, down]));
+    let app = HttpServerState::new(Arc::new(use_case)).create_router();
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let proxy = listener.local_addr().unwrap();
+    tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+    let channel = tonic::transport::Channel::from_shared(format!("http://{}", proxy))
+        .unwrap()
+        .connect()
+        .await
+        .unwrap();
+    let mut client = tonic::client::Grpc::new(channel);
+    let repeat = |request: RepeatRequest| {
+        let mut client = client.clone();
+        async move {
+            client.ready().await.unwrap();
+            let codec = ProstCodec::<RepeatRequest, Reply>::default();
+            client
+                .server_streaming(tonic::Request::new(request), PathAndQuery::from_static(REPEAT), codec)
+                .await
+        }
+    };
+
+    // Every streamed reply arrives, and the call ends cleanly
+    let mut replies = repeat(RepeatRequest { text: "hi".to_string(), times: 3, fail_with: 0 })
+        .await
+        .unwrap()
+        .into_inner();
+    for i in 0..3 {
+        assert_eq!(replies.message().await.unwrap().unwrap().text, format!("hi {}", i));
+    }
+    assert_eq!(replies.message().await.unwrap(), None);
+
+    // A status sent in the trailers after some replies reaches the client
+    let mut replies = repeat(RepeatRequest {
+        text: "hi".to_string(),
+        times: 2,
+        fail_with: Code::FailedPrecondition as i32,
+    })
+    .await
+    .unwrap()
+    .into_inner();
+    assert!(replies.message().await.unwrap().is_some());
+    assert!(replies.message().await.unwrap().is_some());
+    let status = replies.message().await.unwrap_err();
+    assert_eq!(status.code(), Code::FailedPrecondition);
+    assert_eq!(status.message(), "told to fail");
+
+    // A backend that can't be reached fails the call as unavailable
+    client.ready().await.unwrap();
+    let status = client
+        .unary(
+            tonic::Request::new(RepeatRequest::default()),
+            PathAndQuery::from_static("/down.Down/Call"),
+            ProstCodec::<RepeatRequest, Reply>::default(),
+        )
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::Unavailable);
+}
+
+#[tokio::test]
+async fn test_embedded_proxy_serves_in_memory_processes() {
+    use local_lambdas::domain::{CommunicationMode, Executable, PipeName, Process, ProcessId, Route, SerializationFormat};
+    use local_lambdas::LocalLambdas;
+    use std::time::Duration;
+
+    // Plain HTTP backend standing in for the process, which only sleeps
+    let backend = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = backend.local_addr().unwrap().port();
+    let app = axum::Router::new().route("/api/hello", axum::routing::get(|| async { "hello from backend" }));
+    tokio::spawn(async move { axum::serve(backend, app).await });
+
+    let mut process = Process::new(
+        ProcessId::new("api").unwrap(),
+        Executable::new("sleep").unwrap(),
+        Route::new("/api/*").unwrap(),
+        PipeName::new("embedded_api_pipe").unwrap(),
+    );
+    process.arguments = vec!["30".to_string()];
+    process.communication_mode = CommunicationMode::Http;
+    process.protocol = SerializationFormat::Raw;
+    process.http_port = Some(port);
+
+    let proxy = LocalLambdas::builder()
+        .processes(vec![process])
+        .cache_size(10)
+        .bind("127.0.0.1:0")
+        .shutdown_timeout(Duration::from_secs(5))
+        .build()
+        .start()
+        .await
+        .unwrap();
+
+    let response = reqwest::get(format!("http://{}/api/hello", proxy.local_addr())).await.unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::OK);
+    assert_eq!(response.text().await.unwrap(), "hello from backend");
+
+    // The process stops when asked, and the proxy stops listening
+    let addr = proxy.local_addr();
+    assert!(proxy.shutdown().await.unwrap().is_empty());
+    assert!(tokio::net::TcpStream::connect(addr).await.is_err());
+}
+
+#[tokio::test]
+async fn test_embedded_proxy_refuses_clashing_processes() {
+    use local_lambdas::domain::{Executable, PipeName, Process, ProcessId, Route};
+    use local_lambdas::LocalLambdas;
+
+    let process = |id: &str| {
+        Process::new(
+            ProcessId::new(id).unwrap(),
+            Executable::new("sleep").unwrap(),
+            Route::new("/api/*").unwrap(),
+            PipeName::new(format!("embedded_{}_pipe", id)).unwrap(),
+        )
+    };
+
+    // Checked the way `--check` checks a manifest, before anything starts
+    let error = LocalLambdas::builder()
+        .processes(vec![process("first"), process("second")])
+        .bind("127.0.0.1:0")
+        .build()
+        .start()
+        .await
+        .err()
+        .unwrap();
+    assert!(error.to_string().contains("Duplicate route '/api/*'"), "{}", error);
+}
+
+#[tokio::test]
+async fn test_each_protocol_round_trips_with_a_matching_backend() {
+    use local_lambdas::domain::{
+        CommunicationMode, Executable, HttpMethod, HttpRequest, PipeName, Process, ProcessId, Route, SerializationFormat,
+    };
+    use local_lambdas::infrastructure::{HttpClient, NamedPipeClient};
+    use local_lambdas::use_cases::ProxyHttpRequestUseCase;
+    use mockito::Matcher;
+    use std::sync::Arc;
+
+    #[derive(serde::Serialize)]
+    struct MsgPackResponse<'a> {
+        status: u16,
+        headers: std::collections::HashMap<&'a str, &'a str>,
+        #[serde(with = "serde_bytes")]
+        body: &'a [u8],
+    }
+
+    // One backend answering each format only when told to expect it
+    let mut backend = mockito::Server::new_async().await;
+    let json = backend
+        .mock("POST", "/")
+        .match_header("x-proxy-protocol", "json")
+        .match_header("content-type", "application/json")
+        .match_body(Matcher::PartialJson(serde_json::json!({"method": "GET", "uri": "/json/x"})))
+        .with_body(r#"{"status": 200, "headers": {}, "body": "ZnJvbSBqc29u"}"#)
+        .create_async()
+        .await;
+    let msgpack_response = rmp_serde::to_vec_named(&MsgPackResponse {
+        status: 200,
+        headers: Default::default(),
+        body: b"from msgpack",
+    })
+    .unwrap();
+    let msgpack = backend
+        .mock("POST", "/")
+        .match_header("x-proxy-protocol", "msgpack")
+        .match_header("content-type", "application/msgpack")
+        .with_body(msgpack_response)
+        .create_async()
+        .await;
+    let raw = backend
+        .mock("GET", "/raw/x")
+        .match_header("x-proxy-protocol", "raw")
+        .with_body("from raw")
+        .create_async()
+        .await;
+
+    let port = backend.socket_address().port();
+    let processes = [SerializationFormat::Json, SerializationFormat::MsgPack, SerializationFormat::Raw]
+        .into_iter()
+        .map(|format| {
+            let mut process = Process::new(
+                ProcessId::new(format.as_str()).unwrap(),
+                Executable::new("./backend").unwrap(),
+                Route::new(format!("/{}/*", format.as_str())).unwrap(),
+                PipeName::new(format!("{}_pipe", format.as_str())).unwrap(),
+            );
+            process.communication_mode = CommunicationMode::Http;
+            process.protocol = format;
+            process.http_port = Some(port);
+            process
+        })
+        .collect();
+    let use_case = ProxyHttpRequestUseCase::new(Arc::new(NamedPipeClient::new()), Arc::new(processes))
+        .with_http_service(Arc::new(HttpClient::new()));
+
+    for format in ["json", "msgpack", "raw"] {
+        let request = HttpRequest {
+            method: HttpMethod::Get,
+            path: format!("/{}/x", format),
+            headers: vec![],
+            body: vec![],
+        };
+        let response = use_case.execute(request).await.unwrap();
+        assert_eq!(response.status_code, 200);
+        assert_eq!(String::from_utf8(response.body).unwrap(), format!("from {}", format));
+    }
+    json.assert_async().await;
+    msgpack.assert_async().await;
+    raw.assert_async().await;
+}
+
+#[tokio::test]
+async fn test_response_over_the_limit_answers_502() {
+    use axum::body::Body;
+    use local_lambdas::adapters::HttpServerState;
+    use local_lambdas::domain::{CommunicationMode, Executable, PipeName, Process, ProcessId, Route, SerializationFormat};
+    use local_lambdas::infrastructure::{HttpClient, NamedPipeClient};
+    use local_lambdas::use_cases::{ProxyHttpRequestUseCase, ProxyOptions};
+    use std::sync::Arc;
+    use tower::Service;
+
+    // A backend answering far more than either process is allowed
+    let mut backend = mockito::Server::new_async().await;
+    let envelope = format!(r#"{{"status": 200, "headers": {{}}, "body": "{}"}}"#, "A".repeat(64 * 1024));
+    backend.mock("POST", "/").with_body(envelope).create_async().await;
+    backend.mock("GET", "/raw/x").with_body(vec![b'x'; 64 * 1024]).create_async().await;
+
+    let port = backend.socket_address().port();
+    let process = |format: SerializationFormat| {
+        let mut process = Process::new(
+            ProcessId::new(format.as_str()).unwrap(),
+            Executable::new("./backend").unwrap(),
+            Route::new(format!("/{}/*", format.as_str())).unwrap(),
+            PipeName::new(format!("{}_pipe", format.as_str())).unwrap(),
+        );
+        process.communication_mode = CommunicationMode::Http;
+        process.protocol = format;
+        process.http_port = Some(port);
+        process
+    };
+    // The raw process sets its own limit; the JSON one gets the proxy's
+    let mut raw = process(SerializationFormat::Raw);
+    raw.max_response_bytes = Some(1024);
+    let options = ProxyOptions {
+        max_response_bytes: Some(4096),
+        ..ProxyOptions::default()
+    };
+    let processes = vec![process(SerializationFormat::Json), raw];
+    let use_case = ProxyHttpRequestUseCase::with_options(Arc::new(NamedPipeClient::new()), Arc::new(processes), options)
+        .with_http_service(Arc::new(HttpClient::new()));
+    let router = HttpServerState::new(Arc::new(use_case)).create_router();
+
+    for path in ["/json/x", "/raw/x"] {
+        let request = axum::http::Request::get(path).body(Body::empty()).unwrap();
+        let response = router.clone().call(request).await.unwrap();
+        assert_eq!(response.status(), axum::http::StatusCode::BAD_GATEWAY, "{}", path);
+    }
+}
+
+#[tokio::test]
+async fn test_host_header_override_reaches_the_backend() {
+    use local_lambdas::domain::{CommunicationMode, Executable, HttpMethod, HttpRequest, PipeName, Process, ProcessId, Route};
+    use local_lambdas::infrastructure::{HttpClient, NamedPipeClient};
+    use local_lambdas::use_cases::ProxyHttpRequestUseCase;
+    use std::sync::Arc;
+
+    // A backend that only answers requests for its virtual host
+    let mut backend = mockito::Server::new_async().await;
+    let mock = backend
+        .mock("POST", "/")
+        .match_header("host", "api.internal")
+        .with_body(r#"{"status": 200, "headers": {}, "body": ""}"#)
+        .create_async()
+        .await;
+
+    let mut process = Process::new(
+        ProcessId::new("api").unwrap(),
+        Executable::new("./backend").unwrap(),
+        Route::new("/api/*").unwrap(),
+        PipeName::new("api_pipe").unwrap(),
+    );
+    process.communication_mode = CommunicationMode::Http;
+    process.http_port = Some(backend.socket_address().port());
+    process.host_header = Some("api.internal".to_string());
+    let use_case = ProxyHttpRequestUseCase::new(Arc::new(NamedPipeClient::new()), Arc::new(vec![process]))
+        .with_http_service(Arc::new(HttpClient::new()));
+
+    let request = HttpRequest {
+        method: HttpMethod::Get,
+        path: "/api/users".to_string(),
+        headers: vec![("host".to_string(), "localhost:3000".to_string())],
+        body: vec![],
+    };
+    assert_eq!(use_case.execute(request).await.unwrap().status_code, 200);
+    mock.assert_async().await;
+}
+
+#[tokio::test]
+async fn test_stripped_prefix_is_not_sent_to_the_backend() {
+    use local_lambdas::domain::{
+        CommunicationMode, Executable, HttpMethod, HttpRequest, PipeName, Process, ProcessId, Route, SerializationFormat,
+    };
+    use local_lambdas::infrastructure::{HttpClient, NamedPipeClient};
+    use local_lambdas::use_cases::ProxyHttpRequestUseCase;
+    use std::sync::Arc;
+
+    // A backend that knows nothing of the /api/v1 it's mounted under
+    let mut backend = mockito::Server::new_async().await;
+    let mock = backend
+        .mock("GET", "/users")
+        .with_body("[]")
+        .create_async()
+        .await;
+
+    let mut process = Process::new(
+        ProcessId::new("users").unwrap(),
+        Executable::new("./backend").unwrap(),
+        Route::new("/api/v1/*").unwrap(),
+        PipeName::new("users_pipe").unwrap(),
+    );
+    process.communication_mode = CommunicationMode::Http;
+    process.protocol = SerializationFormat::Raw;
+    process.http_port = Some(backend.socket_address().port());
+    process.strip_prefix = Some("/api/v1".to_string());
+    let use_case = ProxyHttpRequestUseCase::new(Arc::new(NamedPipeClient::new()), Arc::new(vec![process]))
+        .with_http_service(Arc::new(HttpClient::new()));
+
+    let request = HttpRequest {
+        method: HttpMethod::Get,
+        path: "/api/v1/users".to_string(),
+        headers: vec![],
+        body: vec![],
+    };
+    let response = use_case.execute(request).await.unwrap();
+    assert_eq!(response.status_code, 200);
+    assert_eq!(response.body, b"[]");
+    mock.assert_async().await;
+}
@@ -235,6 +235,51 @@ fn test_performance_comparison_pipe_vs_http() {
     cmd.wait().ok();
 }
 
+#[tokio::test]
+#[ignore] // Run manually: cargo test --test perf_comparison_tests -- --ignored
+async fn test_performance_reused_vs_per_request_http_client() {
+    use local_lambdas::domain::PipeCommunicationService;
+    use local_lambdas::infrastructure::HttpClient;
+
+    let mut server = mockito::Server::new_async().await;
+    server
+        .mock("POST", "/")
+        .with_body(r#"{"status": 200}"#)
+        .expect_at_least(1)
+        .create_async()
+        .await;
+    let address = server.host_with_port();
+    let url = server.url();
+    let num_requests = 500;
+
+    println!("\n=== HTTP client: per-request vs reused ({} requests) ===", num_requests);
+
+    // Previous behaviour: a fresh client, and so a fresh connection, per request
+    let per_request_start = Instant::now();
+    for _ in 0..num_requests {
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(30))
+            .build()
+            .unwrap();
+        let response = client.post(&url).body("{}").send().await.unwrap();
+        response.bytes().await.unwrap();
+    }
+    let per_request_duration = per_request_start.elapsed();
+
+    let client = HttpClient::new();
+    let reused_start = Instant::now();
+    for _ in 0..num_requests {
+        client.send_request(&address, b"{}".to_vec()).await.unwrap();
+    }
+    let reused_duration = reused_start.elapsed();
+
+    println!("  Per-request client: {:?} ({:.3}ms/request)",
+        per_request_duration, per_request_duration.as_secs_f64() * 1000.0 / num_requests as f64);
+    println!("  Reused client:      {:?} ({:.3}ms/request)",
+        reused_duration, reused_duration.as_secs_f64() * 1000.0 / num_requests as f64);
+    println!("  Speedup: {:.2}x\n", per_request_duration.as_secs_f64() / reused_duration.as_secs_f64());
+}
+
 #[test]
 fn test_manifest_with_http_mode() {
     let temp_dir = TempDir::new().unwrap();